@@ -0,0 +1,48 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use indoor_map_lib::bounding_box::BoundingBox;
+use indoor_map_lib::svg_path_parser::{PathData, SimpleSvgPath};
+
+/// Path with about as many commands as the longest corridor in our floor plans, mixing absolute
+/// and relative commands
+fn corridor_path(commands: usize) -> PathData {
+    let mut d = String::from("M 0 0");
+    for i in 0..commands {
+        match i % 4 {
+            0 => d.push_str(&format!(" L {} {}", i % 97, i % 89)),
+            1 => d.push_str(" l 1.5 -2.25"),
+            2 => d.push_str(" h 3 v -1"),
+            _ => d.push_str(" c 1 1 2 2 3 3"),
+        }
+    }
+    PathData::parse(&d).unwrap()
+}
+
+/// Bounding box found by collecting every command first, as it was before it was streamed
+fn collected_bounding_box(data: &PathData) -> (f64, f64, f64, f64) {
+    SimpleSvgPath::from(data).into_iter().fold(
+        (f64::MAX, f64::MAX, f64::MIN, f64::MIN),
+        |(min_x, min_y, max_x, max_y), command| {
+            (
+                min_x.min(command.0),
+                min_y.min(command.1),
+                max_x.max(command.0),
+                max_y.max(command.1),
+            )
+        },
+    )
+}
+
+fn bounding_box(c: &mut Criterion) {
+    let data = corridor_path(40_000);
+    let mut group = c.benchmark_group("bounding box of a 40k command path");
+    group.bench_function("streamed", |b| {
+        b.iter(|| BoundingBox::from(black_box(&data)))
+    });
+    group.bench_function("collected", |b| {
+        b.iter(|| collected_bounding_box(black_box(&data)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bounding_box);
+criterion_main!(benches);
@@ -1,36 +1,445 @@
-use std::fs;
+use std::collections::HashMap;
 use std::fs::File;
-use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
+use regex::Regex;
+use structopt::clap::AppSettings;
 use structopt::StructOpt;
 
-use indoor_map_lib::map_data::uncompiled;
+use indoor_map_lib::cli::{self, CliError, ErrorFormat, ErrorKind};
+use indoor_map_lib::map_data::compile::{Compiler, OrphanRoom};
+use indoor_map_lib::map_data::metadata::MetadataSchema;
+use indoor_map_lib::map_data::overrides::Overrides;
+use indoor_map_lib::map_data::uncompiled::CompileOptions;
+use indoor_map_lib::map_data::{compiled, uncompiled};
+use indoor_map_lib::routing::RoutingOptions;
+use indoor_map_lib::search::SearchIndex;
+use indoor_map_lib::transform::CoordinateSystem;
 
 #[derive(StructOpt, Debug)]
-#[structopt(name = "compile_map_json")]
+#[structopt(
+    name = "compile_map_json",
+    setting = AppSettings::ArgsNegateSubcommands
+)]
 struct Opt {
+    #[structopt(subcommand)]
+    command: Option<Command>,
     #[structopt(name = "INPUT JSON", parse(from_os_str))]
-    input: PathBuf,
+    input: Option<PathBuf>,
     #[structopt(name = "OUTPUT JSON", parse(from_os_str))]
-    output: PathBuf,
+    output: Option<PathBuf>,
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "JSON file of changes to rooms, such as closures, to apply to the input"
+    )]
+    overrides: Option<PathBuf>,
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "JSON file giving the type of known room metadata keys, which are then checked"
+    )]
+    metadata_schema: Option<PathBuf>,
+    #[structopt(
+        long,
+        help = "Warn instead of failing when a room appears on more than one floor, like a stairwell"
+    )]
+    allow_rooms_on_multiple_floors: bool,
+    #[structopt(
+        long,
+        help = "Warn instead of failing when floor images are missing, and compile the rooms in the rest"
+    )]
+    allow_missing_images: bool,
+    #[structopt(
+        long,
+        help = "Compile rooms without vertices with the no-access tag instead of failing, so they're drawn but can't be routed to"
+    )]
+    allow_unrouted_rooms: bool,
+    #[structopt(
+        long,
+        help = "Warn about vertices outside of the element in their floor's images with the same ID"
+    )]
+    check_vertex_elements: bool,
+    #[structopt(
+        long,
+        help = "Fail instead of warning when a room's shape has no width or height, such as a rect scaled to nothing"
+    )]
+    strict: bool,
+    #[structopt(
+        long,
+        help = "Warn about vertices on the same floor that are closer than this, which were probably meant to be the same vertex"
+    )]
+    dedupe_epsilon: Option<f64>,
+    #[structopt(
+        long,
+        help = "Compute floor offsets so that the element with the ID `registration` lands in the same place on every floor"
+    )]
+    auto_offsets: bool,
+    #[structopt(
+        long,
+        help = "Print the rooms in the floor images that aren't in the JSON, by floor, with the center of each"
+    )]
+    report_orphans: bool,
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "Write JSON of skeleton entries for the rooms in the floor images that aren't in the JSON, to merge into it"
+    )]
+    scaffold_rooms: Option<PathBuf>,
+    #[structopt(
+        long,
+        help = "Give each room written by --scaffold-rooms a vertex at its center, named <room>_auto"
+    )]
+    scaffold_vertices: bool,
+    #[structopt(
+        long = "id-prefix",
+        alias = "room-id-prefix",
+        default_value = "room",
+        number_of_values = 1,
+        help = "Prefix of the IDs of room elements in the floor images, followed by the room number; repeat to allow several, and the longest one an ID starts with is used; replaced by the id_prefixes of a floor or of the map data"
+    )]
+    id_prefixes: Vec<String>,
+    #[structopt(
+        long,
+        parse(try_from_str = Regex::new),
+        help = "Regular expression for the IDs of room elements whose first capture group, or group named number, is the room number; tried before the prefixes, and replaced by the id_pattern of a floor or of the map data"
+    )]
+    id_pattern: Option<Regex>,
+    #[structopt(
+        long,
+        help = "Don't look for room numbers in the inkscape:label of elements without a room ID"
+    )]
+    no_inkscape_labels: bool,
+    #[structopt(
+        long,
+        default_value = "y-up-with-offsets",
+        possible_values = &["y-up-with-offsets", "svg-native"],
+        help = "Convention for map coordinates: shifted by floor offsets with the y-axis up, or unchanged SVG coordinates for floors without offsets"
+    )]
+    coordinate_system: CoordinateSystem,
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "Also write a CSV directory of every room, with its floor, names, tags, area, and center"
+    )]
+    csv: Option<PathBuf>,
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "Also write a CSV matrix of the walking distance between every pair of rooms, which is `inf` between rooms that can't reach each other"
+    )]
+    distance_matrix: Option<PathBuf>,
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "Also write JSON of only the routing graph and the vertices of each room, for clients that only route"
+    )]
+    emit_graph: Option<PathBuf>,
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "CSV file with `room` and `name` columns of names to add to rooms before compiling"
+    )]
+    import_names: Option<PathBuf>,
+    #[structopt(
+        long,
+        help = "Embed a search index of the rooms in the output, for clients that can't build one"
+    )]
+    search_index: bool,
+    #[structopt(
+        long,
+        help = "Leave out points of room outlines within this distance of the line between the points around them"
+    )]
+    simplify: Option<f64>,
+    #[structopt(
+        long,
+        help = "Round locations, outlines, bounds, and areas in the output to this many digits after the decimal point; 15 or more keeps full precision"
+    )]
+    precision: Option<usize>,
+    #[structopt(
+        long,
+        default_value = "warn",
+        help = "Level of messages and timings to write to standard error: error, warn, info, debug, or trace"
+    )]
+    log_level: tracing::Level,
+    #[structopt(long, help = "Write messages and timings as JSON lines")]
+    log_json: bool,
+    #[structopt(
+        long,
+        default_value = "text",
+        possible_values = &["text", "json"],
+        help = "Write an error that stops compiling as text or as a JSON object with its kind, message, file, and id"
+    )]
+    error_format: ErrorFormat,
 }
 
-fn main() {
+#[derive(StructOpt, Debug)]
+enum Command {
+    /// Checks already compiled map data for room areas that no longer match their outlines
+    Maintain {
+        #[structopt(name = "COMPILED JSON", parse(from_os_str))]
+        input: PathBuf,
+        #[structopt(
+            long,
+            help = "Write recomputed areas back to the file instead of only reporting stale ones"
+        )]
+        recompute: bool,
+        #[structopt(
+            long,
+            default_value = "1",
+            help = "Largest difference from the recomputed area that isn't reported"
+        )]
+        tolerance: f64,
+    },
+}
+
+fn main() -> ExitCode {
     let opt: Opt = Opt::from_args();
+    indoor_map_lib::logging::init(opt.log_level, opt.log_json);
+
+    cli::run(opt.error_format, || match &opt.command {
+        Some(Command::Maintain {
+            input,
+            recompute,
+            tolerance,
+        }) => maintain(input, *recompute, *tolerance),
+        None => compile(&opt),
+    })
+}
+
+/// Creates an I/O error about the file at `path`
+fn io_error(context: &str, path: &Path, error: impl std::fmt::Display) -> CliError {
+    CliError::with_source(ErrorKind::Io, context, error).file(path)
+}
+
+fn maintain(input: &Path, recompute: bool, tolerance: f64) -> Result<(), CliError> {
+    let input_json = cli::read_to_string(input)?;
+    let mut map_data: compiled::MapData = cli::from_json(&input_json, input)?;
+
+    for (number, stored, recomputed) in map_data.stale_areas(tolerance) {
+        println!(
+            "Room {} has area {} but its outline has area {}",
+            number, stored, recomputed
+        );
+    }
+
+    if recompute {
+        map_data.recompute_derived();
+        let output_data = serde_json::to_string(&map_data)
+            .map_err(|error| io_error("Error serializing map data", input, error))?;
+        cli::write(input, output_data)?;
+    }
+    Ok(())
+}
+
+fn compile(opt: &Opt) -> Result<(), CliError> {
+    let (input, output) = match (&opt.input, &opt.output) {
+        (Some(input), Some(output)) => (input, output),
+        _ => {
+            return Err(CliError::new(
+                ErrorKind::Usage,
+                "The input and output JSON files are required",
+            ))
+        }
+    };
+
+    let input_json = cli::read_to_string(input)?;
 
-    let input_json = fs::read_to_string(&opt.input).expect("Error reading input file");
+    let base_path = input.parent().ok_or_else(|| {
+        CliError::new(ErrorKind::Usage, "The input path should be a file").file(input)
+    })?;
 
-    let base_path = opt.input.parent().expect("Input path should be a file");
+    let mut map_data: uncompiled::MapData = cli::from_json(&input_json, input)?;
+
+    if let Some(overrides_path) = &opt.overrides {
+        let overrides_json = cli::read_to_string(overrides_path)?;
+        let overrides = Overrides::new(&overrides_json).map_err(|error| {
+            CliError::with_source(ErrorKind::Parse, "Invalid overrides JSON", error)
+                .file(overrides_path)
+        })?;
+        map_data
+            .apply_overrides(overrides)
+            .map_err(|error| CliError::from(error).file(overrides_path))?;
+    }
+
+    if let Some(names_path) = &opt.import_names {
+        let names_file = File::open(names_path)
+            .map_err(|error| io_error("Error reading file", names_path, error))?;
+        let report = map_data
+            .import_names_csv(names_file)
+            .map_err(|error| CliError::from(error).file(names_path))?;
+        for number in report.unknown_rooms {
+            println!("Room in the names CSV does not exist: {}", number);
+        }
+        for number in report.missing_rooms {
+            println!("Room is not in the names CSV: {}", number);
+        }
+    }
+
+    let verify_options = CompileOptions {
+        allow_unrouted_rooms: opt.allow_unrouted_rooms,
+        ..CompileOptions::default()
+    };
+    let map_data = map_data
+        .verify_with_options(&verify_options)
+        .map_err(|error| CliError::from(error).file(input))?;
+
+    if let Some(epsilon) = opt.dedupe_epsilon {
+        for (id, other_id, distance) in map_data.near_duplicate_vertices(epsilon) {
+            println!(
+                "Vertices {} and {} are only {} apart, and may be meant to be the same vertex",
+                id, other_id, distance
+            );
+        }
+    }
+
+    if let Some(schema_path) = &opt.metadata_schema {
+        let schema_json = cli::read_to_string(schema_path)?;
+        let schema = MetadataSchema::new(&schema_json).map_err(|error| {
+            CliError::with_source(ErrorKind::Parse, "Invalid metadata schema JSON", error)
+                .file(schema_path)
+        })?;
+        map_data
+            .verify_metadata(&schema)
+            .map_err(|error| CliError::from(error).file(input))?;
+    }
+
+    let mut compiler = Compiler::new()
+        .base_path(base_path)
+        .allow_rooms_on_multiple_floors(opt.allow_rooms_on_multiple_floors)
+        .allow_missing_images(opt.allow_missing_images)
+        .allow_unrouted_rooms(opt.allow_unrouted_rooms)
+        .check_vertex_elements(opt.check_vertex_elements)
+        .strict(opt.strict)
+        .auto_offsets(opt.auto_offsets)
+        .id_prefixes(opt.id_prefixes.clone())
+        .use_inkscape_label(!opt.no_inkscape_labels)
+        .coordinate_system(opt.coordinate_system);
+    if let Some(id_pattern) = &opt.id_pattern {
+        compiler = compiler.id_pattern(id_pattern.clone());
+    }
+    if let Some(tolerance) = opt.simplify {
+        compiler = compiler.simplify(tolerance);
+    }
+    if opt.report_orphans {
+        let orphans = compiler.orphan_svg_rooms(base_path, &map_data)?;
+        print_orphans(&map_data, &orphans);
+    }
+    if let Some(scaffold_path) = &opt.scaffold_rooms {
+        let scaffold = compiler.scaffold_rooms(base_path, &map_data, opt.scaffold_vertices)?;
+        let scaffold_json = serde_json::to_string_pretty(&scaffold).map_err(|error| {
+            io_error("Error serializing scaffolded rooms", scaffold_path, error)
+        })?;
+        cli::write(scaffold_path, scaffold_json)?;
+    }
+    let compile_output = compiler.compile(map_data)?;
+    for (floor, (x, y)) in &compile_output.computed_offsets {
+        println!("Computed offsets of floor {}: ({}, {})", floor, x, y);
+    }
+    let mut compiled = compile_output.map_data;
+    if opt.search_index {
+        compiled.index = Some(SearchIndex::build(&compiled));
+    }
+    print_stats(&compiled);
+
+    if let Some(csv_path) = &opt.csv {
+        let csv_file = File::create(csv_path)
+            .map_err(|error| io_error("Error writing file", csv_path, error))?;
+        compiled
+            .to_csv(csv_file)
+            .map_err(|error| io_error("Error writing file", csv_path, error))?;
+    }
+
+    if let Some(matrix_path) = &opt.distance_matrix {
+        let matrix_file = File::create(matrix_path)
+            .map_err(|error| io_error("Error writing file", matrix_path, error))?;
+        compiled
+            .distance_matrix(&RoutingOptions::default())
+            .to_csv(matrix_file)
+            .map_err(|error| io_error("Error writing file", matrix_path, error))?;
+    }
+
+    if let Some(graph_path) = &opt.emit_graph {
+        let graph_json = serde_json::to_string(&compiled.to_compact_graph())
+            .map_err(|error| io_error("Error serializing the routing graph", graph_path, error))?;
+        cli::write(graph_path, graph_json)?;
+    }
+
+    let output_data = match opt.precision {
+        Some(decimals) => compiled.to_json_with_precision(decimals),
+        None => serde_json::to_string(&compiled),
+    }
+    .map_err(|error| io_error("Error serializing map data", output, error))?;
+    cli::write(output, output_data)
+}
+
+fn print_orphans(map_data: &uncompiled::MapData, orphans: &HashMap<String, Vec<OrphanRoom>>) {
+    for floor in &map_data.floors {
+        if let Some(rooms) = orphans.get(floor.get_number()) {
+            println!(
+                "Floor {} has rooms that aren't in the JSON:",
+                floor.get_number()
+            );
+            for room in rooms {
+                println!(
+                    "  {} at ({}, {})",
+                    room.number, room.center.0, room.center.1
+                );
+            }
+        }
+    }
+}
 
-    let map_data = uncompiled::MapData::new(&input_json).expect("Error in the JSON file");
+fn print_stats(map_data: &compiled::MapData) {
+    println!(
+        "Compiled {} floors, {} vertices, {} edges, and {} rooms",
+        map_data.floors.len(),
+        map_data.vertices.len(),
+        map_data.edges.len(),
+        map_data.rooms.len()
+    );
+    for floor in &map_data.floors {
+        if let Some((min_x, min_y, max_x, max_y)) = floor.get_bounds() {
+            println!(
+                "Floor {} covers ({}, {}) to ({}, {})",
+                floor.get_number(),
+                min_x,
+                min_y,
+                max_x,
+                max_y
+            );
+        }
+    }
 
-    let output_data = serde_json::to_string(
-        &map_data
-            .compile(base_path)
-            .expect("Error compiling map data"),
-    )
-    .expect("Error serializing map data");
-    let mut output = File::create(opt.output).expect("Error before writing to output file");
-    write!(output, "{}", output_data).expect("Error while writing to output file");
+    let mut room_tags: Vec<_> = map_data
+        .rooms
+        .values()
+        .flat_map(|room| &room.tags)
+        .collect();
+    room_tags.sort_unstable_by_key(|tag| tag.as_str());
+    room_tags.dedup();
+    for tag in room_tags {
+        let numbers: Vec<_> = map_data
+            .rooms_with_tag(tag)
+            .into_iter()
+            .map(|(number, _room)| number)
+            .collect();
+        println!("Rooms tagged {}: {}", tag, numbers.join(", "));
+    }
+    let mut vertex_tags: Vec<_> = map_data
+        .vertices
+        .values()
+        .flat_map(|vertex| vertex.get_tags())
+        .collect();
+    vertex_tags.sort_unstable_by_key(|tag| tag.as_str());
+    vertex_tags.dedup();
+    for tag in vertex_tags {
+        let ids: Vec<_> = map_data
+            .vertices_with_tag(tag)
+            .into_iter()
+            .map(|(id, _vertex)| id)
+            .collect();
+        println!("Vertices tagged {}: {}", tag, ids.join(", "));
+    }
 }
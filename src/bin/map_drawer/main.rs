@@ -1,15 +1,22 @@
+use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
+use nalgebra::Vector2;
 use structopt::StructOpt;
-use svg::node::element::Group;
-use svg::node::element::Path;
-use svg::Document;
 
+use indoor_map_lib::bounding_box::{BoundingBox, BoundingSquare};
+use indoor_map_lib::cli::{self, CliError, ErrorFormat, ErrorKind};
+use indoor_map_lib::draw::{
+    default_tag_fills, draw_floor, render_schematic, DrawOptions, LegendPosition, SchematicOptions,
+};
 use indoor_map_lib::map_data::compiled;
-use indoor_map_lib::map_data::compiled::Room;
-use std::collections::HashMap;
-use svg::node::element::path::Data;
+use indoor_map_lib::map_data::VertexTag;
+use indoor_map_lib::numbers;
+use indoor_map_lib::routing::{ClosedRooms, RoutingOptions};
+use indoor_map_lib::svg_parser::{ParseOptions, SvgElement};
+use indoor_map_lib::tiles::{self, Layer, Manifest, SplitOptions, TileCoords, MAX_ZOOM};
 
 #[derive(StructOpt, Debug)]
 #[structopt(name = "map_drawer")]
@@ -32,46 +39,133 @@ struct Opt {
         short = "m",
         long,
         default_value = "0",
-        help = "minimum zoom level to create tiles for (no less than 0)"
+        help = "minimum zoom level to create tiles for with --tile (no less than 0)"
     )]
     min_zoom_level: u32,
+    #[structopt(
+        long,
+        default_value = "0",
+        help = "maximum zoom level to create tiles for with --tile"
+    )]
+    max_zoom: u32,
+    #[structopt(
+        long,
+        help = "split the drawing into tiles in <OUTPUT DIRECTORY>/<FLOOR>/ with a manifest.json \
+                of them, where the zoom level 0 tile covers the whole drawing, instead of writing \
+                base.svg"
+    )]
+    tile: bool,
+    #[structopt(
+        long,
+        help = "strip attributes only used by editors, such as inkscape:*, sodipodi:*, and data-*, \
+                from the drawing"
+    )]
+    strip_metadata: bool,
+    #[structopt(
+        long,
+        help = "shade the rooms reachable from this vertex by how far away they are, instead of \
+                shading every room the same"
+    )]
+    reachable_from: Option<String>,
+    #[structopt(
+        long,
+        default_value = "50",
+        help = "farthest distance from the --reachable-from vertex to shade rooms within"
+    )]
+    within: f64,
+    #[structopt(long, help = "don't use elevators when finding reachable rooms")]
+    avoid_elevators: bool,
+    #[structopt(
+        long,
+        help = "don't go through rooms tagged closed when finding reachable rooms"
+    )]
+    avoid_closed_rooms: bool,
+    #[structopt(
+        long,
+        help = "draw the rooms, edges, and vertices from the compiled JSON alone, without reading \
+                the floor image"
+    )]
+    schematic: bool,
+    #[structopt(
+        long,
+        help = "fill rooms by their tags, such as AEDs and bathrooms, as the schematic does, and \
+                add a legend of the tags"
+    )]
+    tag_colors: bool,
+    #[structopt(
+        long,
+        default_value = "top-right",
+        possible_values = &["top-left", "top-right", "bottom-left", "bottom-right"],
+        help = "corner to put the legend of tag colors by, just outside of the floor"
+    )]
+    legend_position: LegendPosition,
+    #[structopt(
+        long,
+        default_value = "warn",
+        help = "level of messages and timings to write to standard error: error, warn, info, debug, or trace"
+    )]
+    log_level: tracing::Level,
+    #[structopt(long, help = "write messages and timings as JSON lines")]
+    log_json: bool,
+    #[structopt(
+        long,
+        default_value = "text",
+        possible_values = &["text", "json"],
+        help = "write an error that stops drawing as text or as a JSON object with its kind, \
+                message, file, and id"
+    )]
+    error_format: ErrorFormat,
 }
 
-fn get_compiled_map_data(opt: &Opt) -> compiled::MapData {
-    let input_compiled_json =
-        fs::read_to_string(&opt.input_compiled_json).expect("Error reading input file");
-    serde_json::from_str::<compiled::MapData>(&input_compiled_json).expect("Error in the JSON file")
+/// Fills of rooms reachable from the `--reachable-from` vertex, from the closest rooms to the
+/// farthest
+const DISTANCE_FILLS: [&str; 4] = [
+    "rgb(26, 152, 80)",
+    "rgb(145, 207, 96)",
+    "rgb(254, 224, 139)",
+    "rgb(252, 141, 89)",
+];
+
+/// Finds the fill of each room reachable from the `--reachable-from` vertex, if one was given
+fn get_reachable_fills(
+    opt: &Opt,
+    compiled_map_data: &compiled::MapData,
+) -> Option<HashMap<String, String>> {
+    let start = opt.reachable_from.as_ref()?;
+    let mut options = RoutingOptions::default();
+    if opt.avoid_elevators {
+        options.excluded_vertex_tags.insert(VertexTag::Elevator);
+    }
+    if opt.avoid_closed_rooms {
+        options.closed_rooms = ClosedRooms::Excluded;
+    }
+    let fills = compiled_map_data
+        .reachable_within(start, opt.within, &options)
+        .into_iter()
+        .map(|(number, distance)| {
+            let bucket = (distance / opt.within * DISTANCE_FILLS.len() as f64) as usize;
+            let fill = DISTANCE_FILLS[bucket.min(DISTANCE_FILLS.len() - 1)];
+            (number, fill.to_owned())
+        })
+        .collect();
+    Some(fills)
 }
 
-fn get_input_svg_path(opt: &Opt, compiled_map_data: &compiled::MapData) -> PathBuf {
-    let relative_input_svg_path = compiled_map_data
-        .floors
-        .iter()
-        .find(|room| room.get_number() == opt.floor)
-        .unwrap()
-        .get_image();
-    opt.input_compiled_json
-        .parent()
-        .unwrap()
-        .join(relative_input_svg_path)
+fn get_compiled_map_data(opt: &Opt) -> Result<compiled::MapData, CliError> {
+    let input_compiled_json = cli::read_to_string(&opt.input_compiled_json)?;
+    cli::from_json(&input_compiled_json, &opt.input_compiled_json)
 }
 
-fn get_input_svg_document<'a>(
+fn get_input_svg_path(
     opt: &Opt,
     compiled_map_data: &compiled::MapData,
-    contents_owner: &'a mut String,
-) -> Document<'a> {
-    let svg_path = get_input_svg_path(opt, compiled_map_data);
-    let svg_parser = svg::open(svg_path, contents_owner).expect("Error parsing SVG");
-    Document::from_event_parser(svg_parser).unwrap()
-}
-
-fn get_floors_for_vertices(compiled_map_data: &compiled::MapData) -> HashMap<&str, &str> {
-    compiled_map_data
-        .vertices
-        .iter()
-        .map(|(name, vertex)| (name.as_str(), vertex.get_floor()))
-        .collect()
+) -> Result<PathBuf, CliError> {
+    let relative_input_svg_path = compiled_map_data
+        .require_floor(&opt.floor)
+        .map_err(|error| CliError::from(error).file(&opt.input_compiled_json))?
+        .get_image();
+    let base_path = opt.input_compiled_json.parent().unwrap_or(Path::new(""));
+    Ok(base_path.join(relative_input_svg_path))
 }
 
 fn get_output_file_path(opt: &Opt) -> PathBuf {
@@ -80,46 +174,112 @@ fn get_output_file_path(opt: &Opt) -> PathBuf {
     output_file
 }
 
-fn room_on_floor(room: &Room, floor: &str, vertex_floors: &HashMap<&str, &str>) -> bool {
-    vertex_floors
-        .get(room.vertices.iter().next().unwrap().as_str())
-        .map(|room_floor| *room_floor == floor)
-        .unwrap_or(false)
+/// Writes the drawing to `base.svg` in the output directory, or as tiles with `--tile`
+fn save(opt: &Opt, document: &svg::Document) -> Result<(), CliError> {
+    if opt.tile {
+        return save_tiles(opt, &document.to_string());
+    }
+    let path = get_output_file_path(opt);
+    svg::save(&path, document).map_err(|error| {
+        CliError::with_source(ErrorKind::Io, "Error writing file", error).file(path)
+    })
+}
+
+/// Splits the drawing into tiles in a directory named after the floor, with the zoom level 0 tile
+/// covering the drawing's view box, and writes a manifest of them alongside
+fn save_tiles(opt: &Opt, svg_data: &str) -> Result<(), CliError> {
+    TileCoords::new(Vector2::new(0, 0), opt.max_zoom).validate(MAX_ZOOM)?;
+    if opt.min_zoom_level > opt.max_zoom {
+        return Err(CliError::new(
+            ErrorKind::Usage,
+            format!(
+                "The minimum zoom level {} is greater than the maximum of {}",
+                opt.min_zoom_level, opt.max_zoom
+            ),
+        ));
+    }
+
+    // Drawings of floor images without a view box fall back to the bounds of their content
+    let root = SvgElement::from_svg_data(svg_data)?;
+    let view_box = root
+        .get_attr("viewBox")
+        .and_then(|view_box| numbers::parse_number_list(view_box).ok());
+    let view_box = match view_box.as_deref() {
+        Some(&[x, y, width, height]) => {
+            BoundingBox::new(Vector2::new(x, y), Vector2::new(width, height))
+        }
+        _ => root.get_bounding_box(),
+    };
+    let bounds = BoundingSquare::contain_bounding_box(&view_box);
+    let layer = Layer::new(svg_data, bounds.clone(), &ParseOptions::default())?;
+    let directory = opt.output_directory.join(&opt.floor);
+    fs::create_dir_all(&directory).map_err(|error| {
+        CliError::with_source(ErrorKind::Io, "Error creating directory", error).file(&directory)
+    })?;
+
+    let top_left = bounds.as_bounding_box().get_top_left();
+    let mut manifest = Manifest {
+        top_left: (top_left[0], top_left[1]),
+        size: bounds.edge_length(),
+        min_zoom: opt.min_zoom_level,
+        max_zoom: opt.max_zoom,
+        tile_path: Manifest::TILE_PATH.to_owned(),
+        tiles: 0,
+    };
+    let split_options = SplitOptions {
+        zoom_levels: opt.min_zoom_level..=opt.max_zoom,
+        region: None,
+    };
+    tiles::split(&layer, &split_options, |coords, tile| {
+        manifest.tiles += 1;
+        cli::write(
+            &directory.join(manifest.path_of(&coords)),
+            tile.to_document(),
+        )
+    })?;
+
+    let manifest_path = directory.join("manifest.json");
+    let manifest_json = serde_json::to_string_pretty(&manifest).map_err(|error| {
+        CliError::with_source(ErrorKind::Io, "Error serializing the manifest", error)
+            .file(&manifest_path)
+    })?;
+    cli::write(&manifest_path, manifest_json)
 }
 
-fn main() {
+fn main() -> ExitCode {
     let opt: Opt = Opt::from_args();
+    indoor_map_lib::logging::init(opt.log_level, opt.log_json);
 
-    let compiled_map_data = get_compiled_map_data(&opt);
+    cli::run(opt.error_format, || draw(&opt))
+}
 
-    let mut svg_contents = String::new();
-    let mut document = get_input_svg_document(&opt, &compiled_map_data, &mut svg_contents);
+fn draw(opt: &Opt) -> Result<(), CliError> {
+    let compiled_map_data = get_compiled_map_data(opt)?;
 
-    let vertex_floors = get_floors_for_vertices(&compiled_map_data);
+    if opt.schematic {
+        let options = SchematicOptions {
+            legend: Some(opt.legend_position),
+            ..SchematicOptions::default()
+        };
+        let document = render_schematic(&compiled_map_data, &opt.floor, &options)
+            .map_err(|error| CliError::from(error).file(&opt.input_compiled_json))?;
+        return save(opt, &document);
+    }
 
-    let outlines = compiled_map_data
-        .rooms
-        .values()
-        .filter(|room| room_on_floor(room, &opt.floor, &vertex_floors))
-        .map(|room| &room.outline);
+    let svg_path = get_input_svg_path(opt, &compiled_map_data)?;
+    let svg_contents = cli::read_to_string(&svg_path)?;
 
-    let mut outlines_element =
-        Group::new().set("transform", "scale(1, -1) translate(-4.5, -465.5)");
-    for outline in outlines {
-        let mut points = outline.iter();
-        let mut data = Data::new().move_to(*points.next().unwrap());
-        for point in points {
-            data = data.line_to(*point);
-        }
-        let data = data.close();
-        let path = Path::new()
-            .set("fill", "rgb(125, 181, 52)")
-            .set("fill-opacity", "0.2")
-            .set("d", data);
-        outlines_element = outlines_element.add(path);
+    let mut options = DrawOptions {
+        room_fills: get_reachable_fills(opt, &compiled_map_data),
+        strip_metadata: opt.strip_metadata,
+        ..DrawOptions::default()
+    };
+    if opt.tag_colors {
+        options.tag_fills = default_tag_fills();
+        options.legend = Some(opt.legend_position);
     }
-    let children = document.get_mut_svg().get_mut_children();
-    children.push(outlines_element.into());
+    let document = draw_floor(&compiled_map_data, &opt.floor, &svg_contents, &options)
+        .map_err(|error| CliError::from(error).file(&svg_path))?;
 
-    svg::save(get_output_file_path(&opt), &document).unwrap();
+    save(opt, &document)
 }
@@ -1,19 +1,23 @@
-use std::error::Error;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
 use nalgebra::Vector2;
 use structopt::StructOpt;
 
-use indoor_map_lib::bounding_box::BoundingSquare;
+use indoor_map_lib::bounding_box::{BoundingBox, BoundingSquare};
+use indoor_map_lib::cli::{self, CliError, ErrorFormat, ErrorKind};
+use indoor_map_lib::numbers;
+use indoor_map_lib::svg_parser::ParseOptions;
+use indoor_map_lib::tiles::{
+    self, Layer, Overlap, SplitOptions, StrokeOptions, TileCoords, MAX_ZOOM,
+};
 
-use crate::layer::Layer;
-use crate::tile_iterator::TileIterator;
-use svg::Document;
+use crate::progress::Progress;
+use crate::summary::Summary;
 
-mod layer;
-mod tile;
-mod tile_iterator;
+mod progress;
+mod summary;
 
 #[derive(StructOpt, Debug)]
 #[structopt(name = "svg_splitter")]
@@ -33,6 +37,11 @@ struct Opt {
         help = "zoom level to create tiles for (no less than 0)"
     )]
     zoom_level: u32,
+    #[structopt(
+        long,
+        help = "also create tiles for every zoom level from this one up to --zoom-level"
+    )]
+    min_zoom_level: Option<u32>,
     #[structopt(
         short = "x",
         long,
@@ -54,24 +63,212 @@ struct Opt {
         help = "length of the edge of the zoom level 0 tile"
     )]
     size: f64,
+    #[structopt(
+        long,
+        help = "skip elements with namespaced tags, such as Inkscape metadata, and their children"
+    )]
+    skip_namespaced: bool,
+    #[structopt(
+        long,
+        help = "include elements hidden with display: none or visibility: hidden in tiles"
+    )]
+    include_hidden: bool,
+    #[structopt(
+        long,
+        default_value = "16",
+        help = "font size in SVG units that em lengths are relative to"
+    )]
+    base_font_size: f64,
+    #[structopt(
+        long,
+        parse(try_from_str = parse_region),
+        help = "only write tiles overlapping this rectangle, given as x,y,width,height in SVG units"
+    )]
+    region: Option<BoundingBox>,
+    #[structopt(
+        long,
+        default_value = "0",
+        help = "margin around each tile within which elements are also included in it, in SVG \
+                units or as a percentage of the tile size (such as 5%)"
+    )]
+    overlap: Overlap,
+    #[structopt(
+        long,
+        help = "leave out elements without children whose width and height are both smaller than \
+                this fraction of the tile size (such as 0.01), so low zoom level tiles skip \
+                details too small to see"
+    )]
+    min_feature_size: Option<f64>,
+    #[structopt(
+        long,
+        help = "strip attributes only used by editors, such as inkscape:*, sodipodi:*, and data-*, \
+                from tiles"
+    )]
+    strip_metadata: bool,
+    #[structopt(
+        long,
+        help = "round numbers in path data, transforms, viewBoxes, and other geometry of tiles to \
+                this many digits after the decimal point"
+    )]
+    precision: Option<usize>,
+    #[structopt(
+        long,
+        help = "multiply stroke widths in tiles by this once for each zoom level (such as 0.5), so \
+                strokes keep the same width on screen as tiles are scaled up"
+    )]
+    stroke_scale: Option<f64>,
+    #[structopt(
+        long,
+        help = "set vector-effect=\"non-scaling-stroke\" on shapes in tiles, so their strokes are \
+                drawn the same width at every zoom level"
+    )]
+    non_scaling_strokes: bool,
+    #[structopt(
+        long,
+        help = "parse the input SVG again for each tile, keeping only that tile's elements, so that \
+                memory use depends on the size of the tiles rather than the input (much slower)"
+    )]
+    low_memory: bool,
+    #[structopt(
+        long,
+        help = "select the tiles without writing them, then print a summary"
+    )]
+    dry_run: bool,
+    #[structopt(
+        long,
+        help = "don't rewrite tiles whose file already has the same contents, so their modification \
+                times are kept"
+    )]
+    skip_unchanged: bool,
+    #[structopt(long, help = "print the summary as JSON")]
+    json: bool,
+    #[structopt(
+        short = "v",
+        long,
+        help = "print every warning from parsing the input SVG"
+    )]
+    verbose: bool,
+    #[structopt(
+        long,
+        default_value = "warn",
+        help = "level of messages and timings to write to standard error: error, warn, info, debug, or trace"
+    )]
+    log_level: tracing::Level,
+    #[structopt(long, help = "write messages and timings as JSON lines")]
+    log_json: bool,
+    #[structopt(
+        long,
+        default_value = "text",
+        possible_values = &["text", "json"],
+        help = "write an error that stops splitting as text or as a JSON object with its kind, \
+                message, file, and id"
+    )]
+    error_format: ErrorFormat,
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
+fn parse_region(region: &str) -> anyhow::Result<BoundingBox> {
+    match numbers::parse_number_list(region)?.as_slice() {
+        &[x, y, width, height] if width >= 0. && height >= 0. => Ok(BoundingBox::new(
+            Vector2::new(x, y),
+            Vector2::new(width, height),
+        )),
+        _ => Err(anyhow::anyhow!(
+            "Expected a region as x,y,width,height but got {:?}",
+            region
+        )),
+    }
+}
+
+/// Returns `true` if the file at `path` exists and contains exactly `contents`
+fn is_unchanged(path: &Path, contents: &str) -> bool {
+    // Check the size first so that changed tiles usually don't need to be read
+    let same_size =
+        fs::metadata(path).is_ok_and(|metadata| metadata.len() == contents.len() as u64);
+    same_size && fs::read(path).is_ok_and(|existing| existing == contents.as_bytes())
+}
+
+fn main() -> ExitCode {
     let opt: Opt = Opt::from_args();
+    indoor_map_lib::logging::init(opt.log_level, opt.log_json);
+
+    cli::run(opt.error_format, || split(&opt))
+}
 
-    let svg_data = fs::read_to_string(opt.input)?;
+fn split(opt: &Opt) -> Result<(), CliError> {
+    let svg_data = cli::read_to_string(&opt.input)?;
     let layer_bounds = BoundingSquare::new(Vector2::new(opt.top_left_x, opt.top_left_y), opt.size);
-    let layer = Layer::new(&svg_data, layer_bounds)?;
+    let parse_options = ParseOptions {
+        skip_namespaced: opt.skip_namespaced,
+        include_hidden: opt.include_hidden,
+        base_font_size: opt.base_font_size,
+        ..ParseOptions::default()
+    };
+    let layer = if opt.low_memory {
+        Layer::new_low_memory(&svg_data, layer_bounds.clone(), &parse_options)
+    } else {
+        Layer::new(&svg_data, layer_bounds.clone(), &parse_options)
+    }
+    .map_err(|error| CliError::from(error).file(&opt.input))?;
+    let layer = layer
+        .with_overlap(opt.overlap)
+        .with_min_feature_size(opt.min_feature_size)
+        .with_stripped_metadata(opt.strip_metadata)
+        .with_precision(opt.precision)
+        .with_strokes(StrokeOptions {
+            scale: opt.stroke_scale,
+            non_scaling: opt.non_scaling_strokes,
+        });
+
+    let warnings = &layer.report().warnings;
+    if !warnings.is_empty() {
+        eprintln!("{} warnings while parsing the input SVG", warnings.len());
+        if opt.verbose {
+            for warning in warnings {
+                eprintln!("  {}", warning);
+            }
+        } else {
+            eprintln!("Run with --verbose to see them");
+        }
+    }
+
+    let min_zoom_level = opt.min_zoom_level.unwrap_or(opt.zoom_level);
+    TileCoords::new(Vector2::new(0, 0), opt.zoom_level).validate(MAX_ZOOM)?;
+    let split_options = SplitOptions {
+        zoom_levels: min_zoom_level..=opt.zoom_level,
+        region: opt.region.clone(),
+    };
+    let mut progress = Progress::new(split_options.tile_coords(&layer).len());
+    let mut summary = Summary {
+        dry_run: opt.dry_run,
+        ..Summary::default()
+    };
+    tiles::split(&layer, &split_options, |coords, tile| {
+        let document = tile.to_document();
+        summary.add_tile(tile.is_empty(), document.len());
+        if !opt.dry_run {
+            let mut file_path = opt.output.clone();
+            file_path.push(format!(
+                "{}.{}.{}.svg",
+                coords.zoom, coords.location[0], coords.location[1]
+            ));
+            if opt.skip_unchanged && is_unchanged(&file_path, &document) {
+                summary.unchanged_tiles += 1;
+            } else {
+                cli::write(&file_path, document)?;
+            }
+        }
+        progress.tick();
+        Ok::<_, CliError>(())
+    })?;
+    progress.finish();
 
-    for coords in TileIterator::new(opt.zoom_level) {
-        let tile = layer.tile(&coords);
-        let mut file_path = opt.output.clone();
-        file_path.push(format!(
-            "{}.{}.{}.svg",
-            coords.zoom, coords.location[0], coords.location[1]
-        ));
-        let document = Document::new().add(tile.as_element());
-        svg::save(file_path, document)?;
+    if opt.json {
+        let json = serde_json::to_string(&summary).map_err(|error| {
+            CliError::with_source(ErrorKind::Io, "Error serializing the summary", error)
+        })?;
+        println!("{}", json);
+    } else {
+        println!("{}", summary);
     }
 
     Ok(())
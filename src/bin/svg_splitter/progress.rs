@@ -0,0 +1,35 @@
+use std::io::{self, Write};
+
+/// Reports how many of a known number of tiles have been written as a percentage on stderr,
+/// rewriting the same line as it changes.
+pub struct Progress {
+    total: usize,
+    done: usize,
+    last_percent: Option<usize>,
+}
+
+impl Progress {
+    pub fn new(total: usize) -> Self {
+        Self {
+            total,
+            done: 0,
+            last_percent: None,
+        }
+    }
+
+    pub fn tick(&mut self) {
+        self.done += 1;
+        let percent = self.done * 100 / self.total.max(1);
+        if self.last_percent != Some(percent) {
+            self.last_percent = Some(percent);
+            eprint!("\rTiling: {:>3}% ({}/{})", percent, self.done, self.total);
+            let _ = io::stderr().flush();
+        }
+    }
+
+    pub fn finish(&self) {
+        if self.last_percent.is_some() {
+            eprintln!();
+        }
+    }
+}
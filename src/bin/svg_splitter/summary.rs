@@ -0,0 +1,44 @@
+use std::fmt;
+
+use serde::Serialize;
+
+/// Statistics about one run of the splitter, printed when it's done
+#[derive(Debug, Default, Serialize)]
+pub struct Summary {
+    pub dry_run: bool,
+    pub tiles: usize,
+    /// Tiles containing at least one element
+    pub non_empty_tiles: usize,
+    /// Size of all tiles once serialized, whether or not they were written
+    pub bytes: usize,
+    /// Tiles that weren't written because the existing file already had the same contents
+    pub unchanged_tiles: usize,
+}
+
+impl Summary {
+    pub fn add_tile(&mut self, is_empty: bool, bytes: usize) {
+        self.tiles += 1;
+        if !is_empty {
+            self.non_empty_tiles += 1;
+        }
+        self.bytes += bytes;
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let action = if self.dry_run { "Would write" } else { "Wrote" };
+        write!(
+            f,
+            "{} {} tiles ({} non-empty), {} bytes in total",
+            action,
+            self.tiles - self.unchanged_tiles,
+            self.non_empty_tiles,
+            self.bytes
+        )?;
+        if self.unchanged_tiles > 0 {
+            write!(f, "; skipped {} unchanged tiles", self.unchanged_tiles)?;
+        }
+        Ok(())
+    }
+}
@@ -1,7 +1,7 @@
 use nalgebra::Vector2;
-use svg::node::element::path::Data;
 
-use crate::svg_path_parser::SimpleSvgPath;
+use crate::svg_path_parser::{PathData, PathIter};
+use crate::tiles::TileCoords;
 
 #[derive(Clone, Debug)]
 pub struct BoundingBox {
@@ -22,6 +22,31 @@ impl BoundingBox {
         !(self_left_of_other || other_left_of_self || self_below_other || other_below_self)
     }
 
+    /// Returns `true` if `other` lies entirely within this bounding box, including its edges.
+    pub fn contains(&self, other: &Self) -> bool {
+        let self_bottom_right = self.get_bottom_right();
+        let other_bottom_right = other.get_bottom_right();
+        self.top_left[0] <= other.top_left[0]
+            && self.top_left[1] <= other.top_left[1]
+            && other_bottom_right[0] <= self_bottom_right[0]
+            && other_bottom_right[1] <= self_bottom_right[1]
+    }
+
+    /// Returns the smallest bounding box containing both this bounding box and `other`.
+    pub fn union(&self, other: &Self) -> Self {
+        let top_left = self.top_left.inf(&other.top_left);
+        let bottom_right = self.get_bottom_right().sup(&other.get_bottom_right());
+        Self::new(top_left, bottom_right - top_left)
+    }
+
+    /// Returns this bounding box grown by `margin` on every side.
+    pub fn expand(&self, margin: f64) -> Self {
+        Self::new(
+            self.top_left.add_scalar(-margin),
+            self.size.add_scalar(2. * margin),
+        )
+    }
+
     pub fn get_top_left(&self) -> Vector2<f64> {
         self.top_left
     }
@@ -42,16 +67,14 @@ impl BoundingBox {
     }
 }
 
-impl From<&Data> for BoundingBox {
-    fn from(data: &Data) -> Self {
-        let path = SimpleSvgPath::from(data);
-
-        let mut min_x = f32::MAX;
-        let mut max_x = f32::MIN;
-        let mut min_y = f32::MAX;
-        let mut max_y = f32::MIN;
+impl From<&PathData> for BoundingBox {
+    fn from(data: &PathData) -> Self {
+        let mut min_x = f64::MAX;
+        let mut max_x = f64::MIN;
+        let mut min_y = f64::MAX;
+        let mut max_y = f64::MIN;
 
-        for command in path.into_iter() {
+        for command in PathIter::new(data) {
             if command.0 < min_x {
                 min_x = command.0;
             }
@@ -66,8 +89,8 @@ impl From<&Data> for BoundingBox {
             }
         }
 
-        let top_left = Vector2::new(min_x as f64, min_y as f64);
-        let bottom_right = Vector2::new(max_x as f64, max_y as f64);
+        let top_left = Vector2::new(min_x, min_y);
+        let bottom_right = Vector2::new(max_x, max_y);
         let size = bottom_right - top_left;
 
         Self::new(top_left, size)
@@ -99,4 +122,119 @@ impl BoundingSquare {
     pub fn as_bounding_box(&self) -> BoundingBox {
         BoundingBox::new(self.top_left, Vector2::new(self.size, self.size))
     }
+
+    /// Returns the length of the edges of the tiles at `zoom`, when this square is the tile at
+    /// zoom level 0
+    pub fn tile_edge_length(&self, zoom: u32) -> f64 {
+        self.size / 2_f64.powi(zoom as i32)
+    }
+
+    /// Returns the area covered by the tile at `coords`, when this square is the tile at zoom
+    /// level 0. Each edge is found straight from the square as `top_left + size * index / 2^zoom`
+    /// rather than by adding up the sizes of the tiles before it, so adjacent tiles share exactly
+    /// the same edge instead of leaving hairline gaps or overlaps at deep zoom levels. Because of
+    /// that, the width and height can differ from [`Self::tile_edge_length`] by rounding error.
+    pub fn subdivide(&self, coords: &TileCoords) -> BoundingBox {
+        let tiles_per_side = 2_f64.powi(coords.zoom as i32);
+        let edge =
+            |axis: usize, index: f64| self.top_left[axis] + self.size * index / tiles_per_side;
+        let location = coords.location.map(f64::from);
+        let top_left = Vector2::new(edge(0, location[0]), edge(1, location[1]));
+        let bottom_right = Vector2::new(edge(0, location[0] + 1.), edge(1, location[1] + 1.));
+        BoundingBox::new(top_left, bottom_right - top_left)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use svg::events::Event;
+
+    use super::*;
+    use crate::svg_path_parser::SimpleSvgPath;
+
+    /// Bounding box found by collecting every command of the path first
+    fn collected_bounding_box(data: &PathData) -> (Vector2<f64>, Vector2<f64>) {
+        let (min_x, min_y, max_x, max_y) = SimpleSvgPath::from(data).into_iter().fold(
+            (f64::MAX, f64::MAX, f64::MIN, f64::MIN),
+            |(min_x, min_y, max_x, max_y), command| {
+                (
+                    min_x.min(command.0),
+                    min_y.min(command.1),
+                    max_x.max(command.0),
+                    max_y.max(command.1),
+                )
+            },
+        );
+        let top_left = Vector2::new(min_x, min_y);
+        (top_left, Vector2::new(max_x, max_y) - top_left)
+    }
+
+    #[test]
+    fn adjacent_tiles_share_edges() {
+        let squares = [
+            BoundingSquare::new(Vector2::new(0., 0.), 100.),
+            BoundingSquare::new(Vector2::new(-37.3, 123.456), 333.3),
+            BoundingSquare::new(Vector2::new(1000.1, 2000.7), 0.7),
+        ];
+        for square in &squares {
+            for zoom in [0, 1, 10, 16, 20, 24] {
+                let last = TileCoords::tiles_per_side(zoom).unwrap() - 1;
+                let mut locations = vec![0, last / 3, last / 2, last];
+                locations.dedup();
+                for &x in &locations {
+                    for &y in &locations {
+                        let tile = square.subdivide(&TileCoords::new(Vector2::new(x, y), zoom));
+                        if x < last {
+                            let right =
+                                square.subdivide(&TileCoords::new(Vector2::new(x + 1, y), zoom));
+                            assert_eq!(
+                                tile.get_bottom_right()[0],
+                                right.get_top_left()[0],
+                                "{square:?} at ({x}, {y}) zoom {zoom}"
+                            );
+                        }
+                        if y < last {
+                            let below =
+                                square.subdivide(&TileCoords::new(Vector2::new(x, y + 1), zoom));
+                            assert_eq!(
+                                tile.get_bottom_right()[1],
+                                below.get_top_left()[1],
+                                "{square:?} at ({x}, {y}) zoom {zoom}"
+                            );
+                        }
+                    }
+                }
+            }
+            // The outer edges of the grid are the edges of the square
+            let zoom = 20;
+            let last = TileCoords::tiles_per_side(zoom).unwrap() - 1;
+            let top_left = square.subdivide(&TileCoords::new(Vector2::new(0, 0), zoom));
+            let bottom_right = square.subdivide(&TileCoords::new(Vector2::new(last, last), zoom));
+            let bounds = square.as_bounding_box();
+            assert_eq!(bounds.get_top_left(), top_left.get_top_left());
+            assert_eq!(bounds.get_bottom_right(), bottom_right.get_bottom_right());
+        }
+    }
+
+    #[test]
+    fn streamed_path_bounds_match_collected() {
+        let mut paths = 0;
+        for entry in fs::read_dir("tests/svg").unwrap() {
+            let svg_data = fs::read_to_string(entry.unwrap().path()).unwrap();
+            for event in svg::read(&svg_data).unwrap() {
+                if let Ok(Event::Tag("path", _, attributes)) = event {
+                    let data = PathData::parse(&attributes["d"]).unwrap();
+                    let bounds = BoundingBox::from(&data);
+                    assert_eq!(
+                        collected_bounding_box(&data),
+                        (bounds.get_top_left(), bounds.get_size())
+                    );
+                    paths += 1;
+                }
+            }
+        }
+        assert!(paths > 0);
+    }
 }
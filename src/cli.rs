@@ -0,0 +1,269 @@
+//! Errors of the binaries, with exit codes that tell what went wrong apart, so that scripts and
+//! build pipelines can react to bad input differently than to a missing file
+
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::str::FromStr;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::draw::DrawError;
+use crate::map_data::uncompiled::{CompileError, MapDataError};
+use crate::map_data::UnknownFloor;
+use crate::svg_parser::SvgParseError;
+use crate::tiles::TileCoordsError;
+
+/// What went wrong, which decides the exit code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorKind {
+    /// The arguments don't make sense, such as a floor that doesn't exist. Exits with 1, like
+    /// arguments that can't be parsed at all.
+    Usage,
+    /// An input file isn't valid JSON, SVG, or CSV, or doesn't have the expected structure.
+    /// Exits with 2.
+    Parse,
+    /// The input parsed, but breaks a rule of the map data, such as a room without vertices.
+    /// Exits with 3.
+    Validation,
+    /// A file couldn't be read or written, including floor images that don't exist. Exits with 4.
+    Io,
+}
+
+impl ErrorKind {
+    pub fn exit_code(self) -> u8 {
+        match self {
+            Self::Usage => 1,
+            Self::Parse => 2,
+            Self::Validation => 3,
+            Self::Io => 4,
+        }
+    }
+}
+
+/// Error that stops a binary, along with the file and the room, vertex, or floor it's about
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq, Serialize)]
+#[error("{message}")]
+pub struct CliError {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub file: Option<PathBuf>,
+    pub id: Option<String>,
+}
+
+impl CliError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            file: None,
+            id: None,
+        }
+    }
+
+    /// Creates an error whose message is `context` followed by the error that caused it
+    pub fn with_source(kind: ErrorKind, context: &str, source: impl Display) -> Self {
+        Self::new(kind, format!("{}: {}", context, source))
+    }
+
+    /// Sets the file the error is about, unless it's already set
+    pub fn file(mut self, file: impl Into<PathBuf>) -> Self {
+        self.file.get_or_insert_with(|| file.into());
+        self
+    }
+
+    /// Sets the room, vertex, or floor the error is about
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    /// Writes the error to standard error in `format`, and returns the exit code for it
+    pub fn report(&self, format: ErrorFormat) -> ExitCode {
+        match format {
+            ErrorFormat::Text => match &self.file {
+                Some(file) => eprintln!("Error in `{}`: {}", file.display(), self),
+                None => eprintln!("Error: {}", self),
+            },
+            ErrorFormat::Json => eprintln!(
+                "{}",
+                serde_json::to_string(self).expect("errors serialize to JSON")
+            ),
+        }
+        ExitCode::from(self.kind.exit_code())
+    }
+}
+
+impl From<MapDataError> for CliError {
+    fn from(error: MapDataError) -> Self {
+        let id = error.id().map(str::to_owned);
+        Self {
+            id,
+            ..Self::new(ErrorKind::Validation, error.to_string())
+        }
+    }
+}
+
+impl From<CompileError> for CliError {
+    fn from(error: CompileError) -> Self {
+        let kind = match &error {
+            CompileError::ReadImage { .. } | CompileError::MissingFloorImage(_) => ErrorKind::Io,
+            CompileError::InvalidImage { .. } => ErrorKind::Parse,
+            _ => ErrorKind::Validation,
+        };
+        let file = match &error {
+            CompileError::ReadImage { path, .. } => Some(path.clone()),
+            CompileError::MissingFloorImage(images) => match images.as_slice() {
+                [image] => Some(image.path.clone()),
+                _ => None,
+            },
+            _ => None,
+        };
+        let id = error.id().map(str::to_owned);
+        Self {
+            file,
+            id,
+            ..Self::new(kind, error.to_string())
+        }
+    }
+}
+
+impl From<UnknownFloor> for CliError {
+    fn from(error: UnknownFloor) -> Self {
+        let id = error.floor.clone();
+        Self::new(ErrorKind::Usage, error.to_string()).id(id)
+    }
+}
+
+impl From<DrawError> for CliError {
+    fn from(error: DrawError) -> Self {
+        let message = error.to_string();
+        match error {
+            DrawError::UndefinedFloorNumber(floor) => {
+                Self::new(ErrorKind::Usage, message).id(floor)
+            }
+            DrawError::Io(_) => Self::new(ErrorKind::Io, message),
+            DrawError::InvalidImage(_) => Self::new(ErrorKind::Parse, message),
+        }
+    }
+}
+
+impl From<SvgParseError> for CliError {
+    fn from(error: SvgParseError) -> Self {
+        let kind = match error {
+            SvgParseError::Io(_) => ErrorKind::Io,
+            _ => ErrorKind::Parse,
+        };
+        Self::new(kind, error.to_string())
+    }
+}
+
+impl From<TileCoordsError> for CliError {
+    fn from(error: TileCoordsError) -> Self {
+        Self::new(ErrorKind::Usage, error.to_string())
+    }
+}
+
+#[cfg(feature = "csv")]
+impl From<crate::map_data::uncompiled::ImportError> for CliError {
+    fn from(error: crate::map_data::uncompiled::ImportError) -> Self {
+        Self::new(ErrorKind::Parse, error.to_string())
+    }
+}
+
+/// Reads the file at `path`, giving an I/O error about it if it can't be read
+pub fn read_to_string(path: &Path) -> Result<String, CliError> {
+    fs::read_to_string(path).map_err(|error| {
+        CliError::with_source(ErrorKind::Io, "Error reading file", error).file(path)
+    })
+}
+
+/// Writes `contents` to the file at `path`, giving an I/O error about it if it can't be written
+pub fn write(path: &Path, contents: impl AsRef<[u8]>) -> Result<(), CliError> {
+    fs::write(path, contents).map_err(|error| {
+        CliError::with_source(ErrorKind::Io, "Error writing file", error).file(path)
+    })
+}
+
+/// Deserializes `json`, read from the file at `path`, giving a parse error about it if it's
+/// invalid
+pub fn from_json<T: DeserializeOwned>(json: &str, path: &Path) -> Result<T, CliError> {
+    serde_json::from_str(json)
+        .map_err(|error| CliError::with_source(ErrorKind::Parse, "Invalid JSON", error).file(path))
+}
+
+/// How errors are written to standard error
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ErrorFormat {
+    /// A line of text for people to read
+    #[default]
+    Text,
+    /// A JSON object with the `kind`, `message`, `file`, and `id` of the error, for tools such as
+    /// CI annotations
+    Json,
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[error("Unknown error format `{0}`; expected text or json")]
+pub struct UnknownErrorFormat(String);
+
+impl FromStr for ErrorFormat {
+    type Err = UnknownErrorFormat;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(UnknownErrorFormat(s.to_owned())),
+        }
+    }
+}
+
+/// Runs the body of a binary's `main`, reporting the error it returns in `format`
+pub fn run(format: ErrorFormat, body: impl FnOnce() -> Result<(), CliError>) -> ExitCode {
+    match body() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => error.report(format),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn error_json() {
+        let error = CliError::from(MapDataError::UndefinedVertexId("hall".to_owned()))
+            .file("map.json")
+            .file("ignored.json");
+        assert_eq!(3, error.kind.exit_code());
+        assert_eq!(
+            serde_json::json!({
+                "kind": "validation",
+                "message": "The vertex ID `hall` is undefined",
+                "file": "map.json",
+                "id": "hall"
+            }),
+            serde_json::to_value(&error).unwrap()
+        );
+
+        let error = CliError::from(CompileError::ReadImage {
+            path: PathBuf::from("floor.svg"),
+            reason: "No such file".to_owned(),
+        })
+        .file("map.json");
+        assert_eq!(ErrorKind::Io, error.kind);
+        assert_eq!(Some(PathBuf::from("floor.svg")), error.file);
+        assert_eq!(None, error.id);
+    }
+
+    #[test]
+    fn parse_error_format() {
+        assert_eq!(Ok(ErrorFormat::Text), "text".parse());
+        assert_eq!(Ok(ErrorFormat::Json), "json".parse());
+        assert!("yaml".parse::<ErrorFormat>().is_err());
+    }
+}
@@ -0,0 +1,672 @@
+//! Drawing the outlines of rooms from compiled map data over the images of floors
+
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::str::FromStr;
+
+use nalgebra::Vector2;
+use svg::node::element::path::Data;
+use svg::node::element::{Circle, Group, Line, Path, Rectangle, Text};
+use svg::node::Attributes;
+use svg::Document;
+
+use crate::bounding_box::BoundingBox;
+use crate::geometry::Polygon;
+use crate::map_data::compiled::{MapData, Room};
+use crate::map_data::RoomTag;
+use crate::numbers;
+use crate::svg_parser::{is_editor_metadata, strip_node_attributes};
+use crate::util::natural_cmp;
+
+#[derive(thiserror::Error, Debug)]
+pub enum DrawError {
+    #[error("The floor number `{0}` is undefined")]
+    UndefinedFloorNumber(String),
+    #[error("Error reading the floor image: {0}")]
+    Io(#[from] io::Error),
+    #[error("Error in the floor image: {0}")]
+    InvalidImage(#[from] svg::parser::Error),
+}
+
+/// How [`draw_floor`] draws room outlines
+#[derive(Debug, Clone)]
+pub struct DrawOptions {
+    /// Fill of every room outline, unless `room_fills` is given
+    pub fill: String,
+    /// If given, only the rooms in it are drawn, each with its own fill
+    pub room_fills: Option<HashMap<String, String>>,
+    /// Fill of rooms with each tag when `room_fills` isn't given, where a room with several of the
+    /// tags gets the fill of the first one listed. Rooms without any of them get `fill`.
+    pub tag_fills: Vec<(RoomTag, String)>,
+    /// Where to put a [`legend`] of the tags in `tag_fills` that the drawn rooms have, if
+    /// anywhere. The drawing's view box, or the one implied by its size in user units, is grown
+    /// to fit it. Images without either get the legend drawn inside of them instead.
+    pub legend: Option<LegendPosition>,
+    /// Strip attributes only used by editors, such as `inkscape:*`, `sodipodi:*`, and `data-*`,
+    /// from the floor image
+    pub strip_metadata: bool,
+}
+
+impl Default for DrawOptions {
+    fn default() -> Self {
+        Self {
+            fill: "rgb(125, 181, 52)".to_owned(),
+            room_fills: None,
+            tag_fills: vec![],
+            legend: None,
+            strip_metadata: false,
+        }
+    }
+}
+
+/// Draws the outlines of the rooms on `floor` over `floor_image`, the SVG data of the floor's
+/// image. Rooms are drawn in order of their numbers, and are on the floors given by
+/// [`MapData::rooms_on_floor`]. Outlines are converted back to the coordinates of the image with
+/// [`Floor::map_to_svg_coords`](crate::map_data::Floor::map_to_svg_coords), so they line up
+/// with it whatever the floor's offsets.
+pub fn draw_floor<'a>(
+    compiled: &MapData,
+    floor: &str,
+    floor_image: &'a str,
+    options: &DrawOptions,
+) -> Result<Document<'a>, DrawError> {
+    let Some(floor_data) = compiled.floor(floor) else {
+        return Err(DrawError::UndefinedFloorNumber(floor.to_owned()));
+    };
+    let to_svg =
+        |point: &(f64, f64)| floor_data.map_to_svg_coords(*point, compiled.coordinate_system);
+    let mut document = Document::from_event_parser(svg::read(floor_image)?)?;
+    if options.strip_metadata {
+        strip_node_attributes(document.get_mut_svg(), &is_editor_metadata);
+    }
+
+    let mut rooms: Vec<_> = compiled.rooms_on_floor(floor).collect();
+    rooms.sort_unstable_by(|(a, _), (b, _)| natural_cmp(a, b));
+
+    let mut outlines_element = Group::new();
+    for (number, room) in &rooms {
+        let fill = match &options.room_fills {
+            Some(fills) => match fills.get(*number) {
+                Some(fill) => fill,
+                None => continue,
+            },
+            None => tag_fill(room, &options.tag_fills).unwrap_or(&options.fill),
+        };
+        let path = Path::new()
+            .set("fill", fill.as_str())
+            .set("fill-opacity", OUTLINE_OPACITY)
+            .set("fill-rule", "evenodd")
+            .set("d", outline_data(room.outline_on_floor(floor), to_svg));
+        outlines_element = outlines_element.add(path);
+    }
+    let children = document.get_mut_svg().get_mut_children();
+    children.push(outlines_element.into());
+
+    let position = match options.legend {
+        Some(position) if options.room_fills.is_none() => position,
+        _ => return Ok(document),
+    };
+    // The legend goes outside of the floor's images, or the rooms if their bounds aren't known
+    let content = match floor_data.get_bounds() {
+        Some((min_x, min_y, max_x, max_y)) => points_bounds(&[(min_x, min_y), (max_x, max_y)]),
+        None => points_bounds(
+            rooms
+                .iter()
+                .flat_map(|(_number, room)| room.outline_on_floor(floor).0.iter()),
+        ),
+    }
+    .map(|bounds| map_bounds_to_svg(&bounds, to_svg));
+    let Some(content) = content else {
+        return Ok(document);
+    };
+    let font_size = content.get_size().max() / LEGEND_SCALE;
+    let rooms = || rooms.iter().map(|(_number, room)| *room);
+    let legend_around = |content: &BoundingBox| {
+        legend(
+            rooms(),
+            &options.tag_fills,
+            content,
+            position,
+            font_size,
+            OUTLINE_OPACITY,
+        )
+    };
+    let Some(mut legend) = legend_around(&content) else {
+        return Ok(document);
+    };
+    let svg = document.get_mut_svg();
+    let attributes = svg.get_attributes_mut();
+    let view_box = attributes
+        .get("viewBox")
+        .and_then(|view_box| numbers::parse_number_list(view_box).ok())
+        .and_then(|view_box| match view_box[..] {
+            [x, y, width, height] => Some(BoundingBox::new(
+                Vector2::new(x, y),
+                Vector2::new(width, height),
+            )),
+            _ => None,
+        })
+        .or_else(|| implicit_view_box(attributes));
+    match view_box {
+        Some(view_box) => {
+            let grown = view_box.union(&legend.bounds);
+            attributes.insert("viewBox".to_owned(), grown.as_view_box().into());
+            // Keep the scale the image is rendered at by growing its size along with its view box
+            let factors = grown.get_size().component_div(&view_box.get_size());
+            for (name, factor) in [("width", factors.x), ("height", factors.y)] {
+                let scaled = attributes
+                    .get(name)
+                    .filter(|length| !length.trim_end().ends_with('%'))
+                    .map(|length| numbers::scale_length(length, factor));
+                match scaled {
+                    Some(Ok(length)) => {
+                        attributes.insert(name.to_owned(), length.into());
+                    }
+                    // A size that can't be scaled would squash the image to fit the legend in
+                    Some(Err(_)) => {
+                        attributes.remove(name);
+                    }
+                    None => {}
+                }
+            }
+        }
+        // Without knowing what the image shows, the legend can only be moved inside the content
+        // instead of growing the image to fit it
+        None => {
+            let inset = inset_for_legend(&content, &legend.bounds, position, font_size);
+            legend = legend_around(&inset).unwrap_or(legend);
+        }
+    }
+    svg.get_mut_children().push(legend.group.into());
+    Ok(document)
+}
+
+/// Finds the view box of an `svg` element without one from its `width` and `height`, which is
+/// only known when they're in user units
+fn implicit_view_box(attributes: &Attributes) -> Option<BoundingBox> {
+    let length = |name| match numbers::parse_length(attributes.get(name)?) {
+        Ok((length, "" | "px")) => Some(length),
+        _ => None,
+    };
+    Some(BoundingBox::new(
+        Vector2::zeros(),
+        Vector2::new(length("width")?, length("height")?),
+    ))
+}
+
+/// Shrinks `content` so that a legend of the size of `legend` put outside of it at `position`
+/// lands inside of `content` instead
+fn inset_for_legend(
+    content: &BoundingBox,
+    legend: &BoundingBox,
+    position: LegendPosition,
+    font_size: f64,
+) -> BoundingBox {
+    let inset = legend.get_size()[1] + 2. * font_size;
+    let mut top_left = content.get_top_left();
+    let mut size = content.get_size();
+    size[1] -= inset;
+    if let LegendPosition::TopLeft | LegendPosition::TopRight = position {
+        top_left[1] += inset;
+    }
+    BoundingBox::new(top_left, size)
+}
+
+/// Opacity of the fills of room outlines drawn over floor images, so that the images show through
+const OUTLINE_OPACITY: f64 = 0.2;
+
+/// Ratio of the size of a floor's images to the font size of the legend drawn over them
+const LEGEND_SCALE: f64 = 40.;
+
+/// Fills of the tags usually called out on printed maps, such as AEDs and bathrooms, along with
+/// closed rooms, which [`SchematicOptions`] uses by default
+pub fn default_tag_fills() -> Vec<(RoomTag, String)> {
+    vec![
+        (RoomTag::Closed, "rgb(189, 189, 189)".to_owned()),
+        (RoomTag::NoAccess, "rgb(189, 189, 189)".to_owned()),
+        (RoomTag::Aed, "rgb(215, 48, 39)".to_owned()),
+        (RoomTag::BleedControl, "rgb(215, 48, 39)".to_owned()),
+        (RoomTag::WomenBathroom, "rgb(69, 117, 180)".to_owned()),
+        (RoomTag::MenBathroom, "rgb(69, 117, 180)".to_owned()),
+        (RoomTag::UnknownBathroom, "rgb(69, 117, 180)".to_owned()),
+    ]
+}
+
+/// Finds the fill of the first tag in `tag_fills` that `room` has
+fn tag_fill<'a>(room: &Room, tag_fills: &'a [(RoomTag, String)]) -> Option<&'a String> {
+    tag_fills
+        .iter()
+        .find(|(tag, _fill)| room.tags.contains(tag))
+        .map(|(_tag, fill)| fill)
+}
+
+/// Corner of a drawing that its [`legend`] is put by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LegendPosition {
+    TopLeft,
+    #[default]
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error(
+    "Unknown legend position `{0}`; expected `top-left`, `top-right`, `bottom-left`, or \
+     `bottom-right`"
+)]
+pub struct UnknownLegendPosition(String);
+
+impl FromStr for LegendPosition {
+    type Err = UnknownLegendPosition;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "top-left" => Ok(LegendPosition::TopLeft),
+            "top-right" => Ok(LegendPosition::TopRight),
+            "bottom-left" => Ok(LegendPosition::BottomLeft),
+            "bottom-right" => Ok(LegendPosition::BottomRight),
+            other => Err(UnknownLegendPosition(other.to_owned())),
+        }
+    }
+}
+
+/// Key to the fills of room tags in a drawing, made by [`legend`]
+#[derive(Debug, Clone)]
+pub struct Legend {
+    pub group: Group,
+    /// Area the legend covers, which the view box of the drawing has to include
+    pub bounds: BoundingBox,
+}
+
+/// Width of a character of a label in the legend relative to its font size, for estimating how
+/// wide the legend is, since text isn't measured until it's rendered
+const CHARACTER_WIDTH: f64 = 0.6;
+
+/// Draws a swatch and label for each tag in `tag_fills` that one of `rooms` has, in the order of
+/// `tag_fills`. The legend is put just outside of `content`, above it for the top positions and
+/// below it for the bottom ones, and lined up with its left or right edge, so that it doesn't
+/// cover anything. Swatches are filled with `fill_opacity` to match the rooms they stand for.
+/// Returns `None` if none of the rooms have any of the tags.
+pub fn legend<'a>(
+    rooms: impl IntoIterator<Item = &'a Room>,
+    tag_fills: &[(RoomTag, String)],
+    content: &BoundingBox,
+    position: LegendPosition,
+    font_size: f64,
+    fill_opacity: f64,
+) -> Option<Legend> {
+    let present: HashSet<&RoomTag> = rooms.into_iter().flat_map(|room| &room.tags).collect();
+    let mut entries: Vec<(&RoomTag, &String)> = vec![];
+    for (tag, fill) in tag_fills {
+        if present.contains(tag) && !entries.iter().any(|(listed, _fill)| *listed == tag) {
+            entries.push((tag, fill));
+        }
+    }
+    let longest = entries
+        .iter()
+        .map(|(tag, _fill)| tag.as_str().chars().count())
+        .max()?;
+
+    let row_height = font_size * 1.5;
+    let label_x = font_size * 1.5;
+    let size = Vector2::new(
+        label_x + longest as f64 * font_size * CHARACTER_WIDTH,
+        row_height * entries.len() as f64,
+    );
+    let x = match position {
+        LegendPosition::TopLeft | LegendPosition::BottomLeft => content.get_top_left()[0],
+        LegendPosition::TopRight | LegendPosition::BottomRight => {
+            content.get_bottom_right()[0] - size[0]
+        }
+    };
+    let y = match position {
+        LegendPosition::TopLeft | LegendPosition::TopRight => {
+            content.get_top_left()[1] - font_size - size[1]
+        }
+        LegendPosition::BottomLeft | LegendPosition::BottomRight => {
+            content.get_bottom_right()[1] + font_size
+        }
+    };
+
+    let mut group = Group::new()
+        .set("id", "legend")
+        .set("font-size", font_size)
+        .set("dominant-baseline", "central");
+    for (row, (tag, fill)) in entries.into_iter().enumerate() {
+        let top = y + row as f64 * row_height;
+        let swatch = Rectangle::new()
+            .set("x", x)
+            .set("y", top + (row_height - font_size) / 2.)
+            .set("width", font_size)
+            .set("height", font_size)
+            .set("fill", fill.as_str())
+            .set("fill-opacity", fill_opacity)
+            .set("stroke", "black")
+            .set("stroke-width", font_size / 10.);
+        let label = Text::new(tag.as_str())
+            .set("x", x + label_x)
+            .set("y", top + row_height / 2.);
+        group = group.add(swatch).add(label);
+    }
+    Some(Legend {
+        group,
+        bounds: BoundingBox::new(Vector2::new(x, y), size),
+    })
+}
+
+/// Finds the smallest bounding box containing `points`, or `None` if there aren't any
+fn points_bounds<'a>(points: impl IntoIterator<Item = &'a (f64, f64)>) -> Option<BoundingBox> {
+    points
+        .into_iter()
+        .map(|&(x, y)| BoundingBox::new(Vector2::new(x, y), Vector2::zeros()))
+        .reduce(|bounds, point| bounds.union(&point))
+}
+
+/// Converts bounds in map coordinates to SVG coordinates, which can flip them
+fn map_bounds_to_svg(
+    bounds: &BoundingBox,
+    to_svg: impl Fn(&(f64, f64)) -> (f64, f64),
+) -> BoundingBox {
+    let corner = |corner: Vector2<f64>| to_svg(&(corner[0], corner[1]));
+    points_bounds(&[
+        corner(bounds.get_top_left()),
+        corner(bounds.get_bottom_right()),
+    ])
+    .expect("there are two corners")
+}
+
+/// How [`render_schematic`] draws a floor
+#[derive(Debug, Clone)]
+pub struct SchematicOptions {
+    /// Fill of rooms with each tag, where a room with several of the tags gets the fill of the
+    /// first one listed
+    pub tag_fills: Vec<(RoomTag, String)>,
+    /// Fill of rooms without any of the tags in `tag_fills`
+    pub fill: String,
+    /// Radius of the dot drawn at each vertex
+    pub vertex_radius: f64,
+    /// Font size of the room numbers
+    pub font_size: f64,
+    /// Space left around the rooms and vertices on every side
+    pub margin: f64,
+    /// Where to put a [`legend`] of the tags in `tag_fills` that the drawn rooms have, if anywhere
+    pub legend: Option<LegendPosition>,
+}
+
+impl Default for SchematicOptions {
+    fn default() -> Self {
+        Self {
+            tag_fills: default_tag_fills(),
+            fill: "rgb(255, 255, 191)".to_owned(),
+            vertex_radius: 1.,
+            font_size: 4.,
+            margin: 5.,
+            legend: None,
+        }
+    }
+}
+
+/// Draws `floor` from the compiled map data alone, without its images, for previewing a map whose
+/// images aren't at hand. Rooms are drawn with their fills from `options` and their numbers at
+/// their centers, along with the edges and vertices on the floor. Everything is drawn in the
+/// coordinates of the floor's images, so the drawing is the same way up as they are, and the
+/// view box fits the rooms and vertices on the floor.
+pub fn render_schematic(
+    compiled: &MapData,
+    floor: &str,
+    options: &SchematicOptions,
+) -> Result<Document<'static>, DrawError> {
+    let Some(floor_data) = compiled.floor(floor) else {
+        return Err(DrawError::UndefinedFloorNumber(floor.to_owned()));
+    };
+    let to_svg =
+        |point: &(f64, f64)| floor_data.map_to_svg_coords(*point, compiled.coordinate_system);
+
+    let mut rooms: Vec<_> = compiled.rooms_on_floor(floor).collect();
+    rooms.sort_unstable_by(|(a, _), (b, _)| natural_cmp(a, b));
+    let mut vertices: Vec<_> = compiled
+        .vertices
+        .iter()
+        .filter(|(_id, vertex)| vertex.get_floor() == floor)
+        .collect();
+    vertices.sort_unstable_by_key(|(id, _vertex)| *id);
+
+    let points: Vec<_> = rooms
+        .iter()
+        .flat_map(|(_number, room)| room.outline_on_floor(floor).0.iter().copied())
+        .chain(vertices.iter().map(|(_id, vertex)| vertex.get_location()))
+        .map(|point| to_svg(&point))
+        .collect();
+    let content = points_bounds(&points)
+        .unwrap_or_else(|| BoundingBox::new(Vector2::zeros(), Vector2::zeros()));
+    let legend = options.legend.and_then(|position| {
+        let rooms = rooms.iter().map(|(_number, room)| *room);
+        legend(
+            rooms,
+            &options.tag_fills,
+            &content,
+            position,
+            options.font_size,
+            1.,
+        )
+    });
+    let bounds = match &legend {
+        Some(legend) => content.union(&legend.bounds),
+        None => content,
+    }
+    .expand(options.margin);
+    let (top_left, size) = (bounds.get_top_left(), bounds.get_size());
+
+    let mut rooms_element = Group::new()
+        .set("stroke", "black")
+        .set("stroke-width", options.vertex_radius / 2.);
+    let mut labels_element = Group::new()
+        .set("font-size", options.font_size)
+        .set("text-anchor", "middle")
+        .set("dominant-baseline", "central");
+    for (number, room) in &rooms {
+        let fill = tag_fill(room, &options.tag_fills).unwrap_or(&options.fill);
+        let path = Path::new()
+            .set("id", format!("room{}", number))
+            .set("fill", fill.as_str())
+            .set("fill-rule", "evenodd")
+            .set("d", outline_data(room.outline_on_floor(floor), to_svg));
+        rooms_element = rooms_element.add(path);
+        let (x, y) = to_svg(&room.center);
+        labels_element = labels_element.add(Text::new(number.as_str()).set("x", x).set("y", y));
+    }
+
+    let mut edges_element = Group::new()
+        .set("stroke", "gray")
+        .set("stroke-width", options.vertex_radius / 2.);
+    for edge in compiled.edges_on_floor(floor) {
+        let (x1, y1) = to_svg(&compiled.vertices[edge.get_from()].get_location());
+        let (x2, y2) = to_svg(&compiled.vertices[edge.get_to()].get_location());
+        let line = Line::new()
+            .set("x1", x1)
+            .set("y1", y1)
+            .set("x2", x2)
+            .set("y2", y2);
+        edges_element = edges_element.add(line);
+    }
+
+    let mut vertices_element = Group::new().set("fill", "black");
+    for (id, vertex) in &vertices {
+        let (x, y) = to_svg(&vertex.get_location());
+        let dot = Circle::new()
+            .set("id", id.as_str())
+            .set("cx", x)
+            .set("cy", y)
+            .set("r", options.vertex_radius);
+        vertices_element = vertices_element.add(dot);
+    }
+
+    let mut document = Document::new()
+        .set("viewBox", (top_left[0], top_left[1], size[0], size[1]))
+        .add(rooms_element)
+        .add(edges_element)
+        .add(vertices_element)
+        .add(labels_element);
+    if let Some(legend) = legend {
+        document = document.add(legend.group);
+    }
+    Ok(document)
+}
+
+/// Path data of a room's outline and holes, with each point converted by `to_svg`
+fn outline_data(
+    (outline, holes): (&Polygon, &[Polygon]),
+    to_svg: impl Fn(&(f64, f64)) -> (f64, f64),
+) -> Data {
+    let mut data = Data::new();
+    for ring in std::iter::once(outline).chain(holes) {
+        let mut points = ring.iter();
+        let Some(first) = points.next() else {
+            continue;
+        };
+        data = data.move_to(to_svg(first));
+        for point in points {
+            data = data.line_to(to_svg(point));
+        }
+        data = data.close();
+    }
+    data
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::path::Path;
+
+    use super::*;
+    use crate::map_data::uncompiled;
+    use crate::snapshot::assert_snapshot;
+
+    fn compiled() -> MapData {
+        let json = fs::read_to_string("tests/json/compile.json").unwrap();
+        uncompiled::MapData::new(&json)
+            .unwrap()
+            .compile(Path::new("tests/json"))
+            .unwrap()
+    }
+
+    #[test]
+    fn outline_snapshots() {
+        let compiled = compiled();
+        let svg_data = fs::read_to_string("tests/svg/rooms.svg").unwrap();
+
+        let document = draw_floor(&compiled, "1", &svg_data, &DrawOptions::default()).unwrap();
+        assert_snapshot("outlines.svg", &document.to_string());
+
+        let options = DrawOptions {
+            room_fills: Some(HashMap::from([(
+                "107".to_owned(),
+                "rgb(26, 152, 80)".to_owned(),
+            )])),
+            ..DrawOptions::default()
+        };
+        let document = draw_floor(&compiled, "1", &svg_data, &options).unwrap();
+        assert_snapshot("reachable_outlines.svg", &document.to_string());
+    }
+
+    #[test]
+    fn schematic_snapshot() {
+        let mut compiled = compiled();
+        compiled
+            .rooms
+            .get_mut("107")
+            .unwrap()
+            .tags
+            .insert(RoomTag::Aed);
+
+        let document = render_schematic(&compiled, "1", &SchematicOptions::default()).unwrap();
+        assert_snapshot("schematic.svg", &document.to_string());
+        assert!(matches!(
+            render_schematic(&compiled, "2", &SchematicOptions::default()),
+            Err(DrawError::UndefinedFloorNumber(floor)) if floor == "2"
+        ));
+    }
+
+    #[test]
+    fn legend_snapshots() {
+        let mut compiled = compiled();
+        let room = compiled.rooms.get_mut("107").unwrap();
+        room.tags.insert(RoomTag::Aed);
+        room.tags.insert(RoomTag::WomenBathroom);
+
+        let options = SchematicOptions {
+            legend: Some(LegendPosition::BottomLeft),
+            ..SchematicOptions::default()
+        };
+        let document = render_schematic(&compiled, "1", &options).unwrap();
+        assert_snapshot("schematic_legend.svg", &document.to_string());
+
+        let svg_data = fs::read_to_string("tests/svg/rooms.svg").unwrap();
+        let options = DrawOptions {
+            tag_fills: default_tag_fills(),
+            legend: Some(LegendPosition::TopRight),
+            ..DrawOptions::default()
+        };
+        let document = draw_floor(&compiled, "1", &svg_data, &options).unwrap();
+        let drawn = document.to_string();
+        assert_snapshot("legend_outlines.svg", &drawn);
+        // The view box grows to fit the legend above the image, and the size grows with it
+        assert!(
+            drawn.contains(r#"viewBox="0 -13.75 100 113.75""#),
+            "{}",
+            drawn
+        );
+        assert!(drawn.contains(r#"height="113.75""#), "{}", drawn);
+        assert!(drawn.contains(r#"width="100""#), "{}", drawn);
+
+        // Without a view box, the one implied by the size in user units grows instead
+        let without_view_box = svg_data.replace(r#" viewBox="0 0 100 100""#, "");
+        let document = draw_floor(&compiled, "1", &without_view_box, &options).unwrap();
+        let drawn = document.to_string();
+        assert!(
+            drawn.contains(r#"viewBox="0 -13.75 100 113.75""#),
+            "{}",
+            drawn
+        );
+        assert!(drawn.contains(r#"height="113.75""#), "{}", drawn);
+
+        // If the size isn't in user units either, the legend goes inside of the image
+        let without_view_box = without_view_box
+            .replace(r#"width="100""#, r#"width="100mm""#)
+            .replace(r#"height="100""#, r#"height="100mm""#);
+        let document = draw_floor(&compiled, "1", &without_view_box, &options).unwrap();
+        let drawn = document.to_string();
+        assert_snapshot("legend_inside.svg", &drawn);
+        assert!(!drawn.contains("viewBox"), "{}", drawn);
+
+        // Without any of the tags, there's nothing to explain
+        let untagged = compiled.rooms.values().filter(|room| room.tags.is_empty());
+        let bounds = BoundingBox::new(Vector2::zeros(), Vector2::new(10., 10.));
+        let tag_fills = default_tag_fills();
+        assert!(legend(
+            untagged,
+            &tag_fills,
+            &bounds,
+            LegendPosition::TopLeft,
+            1.,
+            1.
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn legend_positions() {
+        assert_eq!(LegendPosition::BottomRight, "bottom-right".parse().unwrap());
+        assert!("middle".parse::<LegendPosition>().is_err());
+    }
+
+    #[test]
+    fn undefined_floor() {
+        let svg_data = fs::read_to_string("tests/svg/rooms.svg").unwrap();
+        assert!(matches!(
+            draw_floor(&compiled(), "2", &svg_data, &DrawOptions::default()),
+            Err(DrawError::UndefinedFloorNumber(floor)) if floor == "2"
+        ));
+    }
+}
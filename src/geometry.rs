@@ -0,0 +1,242 @@
+//! Polygons, such as the outlines of rooms and of their holes
+
+use std::ops::Deref;
+
+use nalgebra::Vector2;
+use serde::{Deserialize, Serialize};
+
+use crate::bounding_box::BoundingBox;
+
+/// A polygon given by its points in order, where the last point connects back to the first. It's
+/// written as an array of points, the same as a `Vec<(f64, f64)>`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(transparent)]
+pub struct Polygon(Vec<(f64, f64)>);
+
+impl Polygon {
+    pub fn new(points: Vec<(f64, f64)>) -> Self {
+        Self(points)
+    }
+
+    pub fn points(&self) -> &[(f64, f64)] {
+        &self.0
+    }
+
+    pub fn points_mut(&mut self) -> &mut Vec<(f64, f64)> {
+        &mut self.0
+    }
+
+    pub fn into_points(self) -> Vec<(f64, f64)> {
+        self.0
+    }
+
+    /// Iterates over the edges of the polygon as pairs of points, including the edge from the last
+    /// point back to the first
+    pub fn edges(&self) -> impl Iterator<Item = ((f64, f64), (f64, f64))> + '_ {
+        ring_edges(&self.0)
+    }
+
+    /// Finds the area of the polygon with the shoelace formula, which is positive if its points go
+    /// counterclockwise when the y axis points up, and negative if they go clockwise
+    pub fn signed_area(&self) -> f64 {
+        ring_signed_area(&self.0)
+    }
+
+    pub fn area(&self) -> f64 {
+        self.signed_area().abs()
+    }
+
+    pub fn centroid(&self) -> (f64, f64) {
+        ring_centroid(&self.0)
+    }
+
+    /// Determines if `point` is inside of the polygon using the even-odd rule
+    pub fn contains(&self, point: (f64, f64)) -> bool {
+        ring_contains(&self.0, point)
+    }
+
+    /// Finds the smallest bounding box containing every point, or `None` if there are no points
+    pub fn bounding_box(&self) -> Option<BoundingBox> {
+        let (&first, rest) = self.0.split_first()?;
+        let (min, max) = rest.iter().fold((first, first), |(min, max), &(x, y)| {
+            ((min.0.min(x), min.1.min(y)), (max.0.max(x), max.1.max(y)))
+        });
+        Some(BoundingBox::new(
+            Vector2::new(min.0, min.1),
+            Vector2::new(max.0 - min.0, max.1 - min.1),
+        ))
+    }
+}
+
+impl Deref for Polygon {
+    type Target = [(f64, f64)];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl AsRef<[(f64, f64)]> for Polygon {
+    fn as_ref(&self) -> &[(f64, f64)] {
+        &self.0
+    }
+}
+
+impl From<Vec<(f64, f64)>> for Polygon {
+    fn from(points: Vec<(f64, f64)>) -> Self {
+        Self(points)
+    }
+}
+
+impl FromIterator<(f64, f64)> for Polygon {
+    fn from_iter<I: IntoIterator<Item = (f64, f64)>>(points: I) -> Self {
+        Self(points.into_iter().collect())
+    }
+}
+
+pub(crate) fn ring_edges(
+    points: &[(f64, f64)],
+) -> impl Iterator<Item = ((f64, f64), (f64, f64))> + '_ {
+    let this = points.iter().copied();
+    let next = points.iter().copied().cycle().skip(1);
+    this.zip(next)
+}
+
+pub(crate) fn ring_signed_area(points: &[(f64, f64)]) -> f64 {
+    let double_area: f64 = ring_edges(points)
+        .map(|((this_x, this_y), (next_x, next_y))| this_x * next_y - (next_x * this_y))
+        .sum();
+    0.5 * double_area
+}
+
+pub(crate) fn ring_centroid(points: &[(f64, f64)]) -> (f64, f64) {
+    let (center_x, center_y) = ring_edges(points)
+        .map(|((this_x, this_y), (next_x, next_y))| {
+            let diff = (this_x * next_y) - (next_x * this_y);
+            let x = (this_x + next_x) * diff;
+            let y = (this_y + next_y) * diff;
+            (x, y)
+        })
+        .fold((0.0, 0.0), |(acc_x, acc_y), (x, y)| (acc_x + x, acc_y + y));
+
+    let coefficient = 1.0 / (6.0 * ring_signed_area(points));
+
+    (coefficient * center_x, coefficient * center_y)
+}
+
+/// Longest that the point of a miter join can reach from its corner, as a multiple of half of the
+/// width, the same as the default `stroke-miterlimit` of SVG
+const MITER_LIMIT: f64 = 4.;
+
+/// Expands an open polyline into a ring around it, offset by `half_width` on each side, with miter
+/// joins at its corners and square ends at its first and last points. Returns an empty ring if the
+/// polyline has fewer than two distinct points.
+pub(crate) fn offset_polyline(points: &[(f64, f64)], half_width: f64) -> Vec<(f64, f64)> {
+    let mut points = points.to_vec();
+    points.dedup();
+    // Unit normal to the left of each segment
+    let normals: Vec<_> = points
+        .windows(2)
+        .map(|segment| {
+            let ((x1, y1), (x2, y2)) = (segment[0], segment[1]);
+            let length = (x2 - x1).hypot(y2 - y1);
+            (-(y2 - y1) / length, (x2 - x1) / length)
+        })
+        .collect();
+    if normals.is_empty() {
+        return vec![];
+    }
+
+    let offsets: Vec<_> = (0..points.len())
+        .map(|i| {
+            let before = normals[i.saturating_sub(1)];
+            let after = normals[i.min(normals.len() - 1)];
+            let (x, y) = (before.0 + after.0, before.1 + after.1);
+            let length = x.hypot(y);
+            if length < 1e-9 {
+                // The polyline turns back on itself
+                return (before.0 * half_width, before.1 * half_width);
+            }
+            let miter = (x / length, y / length);
+            let cos = miter.0 * after.0 + miter.1 * after.1;
+            let distance = (half_width / cos).min(half_width * MITER_LIMIT);
+            (miter.0 * distance, miter.1 * distance)
+        })
+        .collect();
+    let left = points
+        .iter()
+        .zip(&offsets)
+        .map(|((x, y), (dx, dy))| (x + dx, y + dy));
+    let right = points
+        .iter()
+        .zip(&offsets)
+        .rev()
+        .map(|((x, y), (dx, dy))| (x - dx, y - dy));
+    left.chain(right).collect()
+}
+
+pub(crate) fn ring_contains(points: &[(f64, f64)], point: (f64, f64)) -> bool {
+    let (x, y) = point;
+    ring_edges(points)
+        .filter(|((_, this_y), (_, next_y))| (*this_y > y) != (*next_y > y))
+        .filter(|((this_x, this_y), (next_x, next_y))| {
+            x < (next_x - this_x) * (y - this_y) / (next_y - this_y) + this_x
+        })
+        .count()
+        % 2
+        == 1
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn polygon_geometry() {
+        let polygon = Polygon::new(vec![(0., 0.), (4., 0.), (4., 2.), (0., 2.)]);
+        assert_eq!(
+            vec![
+                ((0., 0.), (4., 0.)),
+                ((4., 0.), (4., 2.)),
+                ((4., 2.), (0., 2.)),
+                ((0., 2.), (0., 0.)),
+            ],
+            polygon.edges().collect::<Vec<_>>()
+        );
+        assert_eq!(8., polygon.signed_area());
+        let clockwise: Polygon = polygon.iter().rev().copied().collect();
+        assert_eq!(-8., clockwise.signed_area());
+        assert_eq!(8., clockwise.area());
+        assert_eq!((2., 1.), polygon.centroid());
+        assert!(polygon.contains((1., 1.)));
+        assert!(!polygon.contains((5., 1.)));
+
+        let bounding_box = polygon.bounding_box().unwrap();
+        assert_eq!(Vector2::new(0., 0.), bounding_box.get_top_left());
+        assert_eq!(Vector2::new(4., 2.), bounding_box.get_size());
+        assert!(Polygon::default().bounding_box().is_none());
+        assert_eq!(0, Polygon::default().edges().count());
+
+        // Written the same as a list of points
+        let json = serde_json::to_string(&polygon).unwrap();
+        assert_eq!("[[0.0,0.0],[4.0,0.0],[4.0,2.0],[0.0,2.0]]", json);
+        assert_eq!(polygon, serde_json::from_str(&json).unwrap());
+    }
+
+    #[test]
+    fn offset_polylines() {
+        assert_eq!(
+            vec![(0., 1.), (4., 1.), (4., -1.), (0., -1.)],
+            offset_polyline(&[(0., 0.), (4., 0.), (4., 0.)], 1.)
+        );
+        // The outer corner of a right angle is the miter
+        let ring = offset_polyline(&[(0., 0.), (4., 0.), (4., 4.)], 1.);
+        assert_eq!((3., 1.), ring[1]);
+        assert_eq!((5., -1.), ring[4]);
+        assert_eq!(8. * 2., ring_signed_area(&ring).abs());
+        // Sharp turns are cut off at the miter limit
+        let ring = offset_polyline(&[(0., 0.), (10., 0.), (0., 0.1)], 1.);
+        assert!(ring.iter().all(|(x, _y)| *x <= 10. + MITER_LIMIT));
+        assert!(offset_polyline(&[(1., 1.), (1., 1.)], 1.).is_empty());
+    }
+}
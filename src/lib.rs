@@ -1,7 +1,22 @@
 pub mod bounding_box;
+pub mod cli;
+pub mod draw;
+pub mod geometry;
+#[cfg(feature = "logging")]
+pub mod logging;
 pub mod map_data;
+pub mod numbers;
+pub mod prelude;
+pub mod quad_tree;
+pub mod routing;
+pub mod search;
+#[cfg(test)]
+mod snapshot;
+pub mod style;
 pub mod svg_parser;
 pub mod svg_path_parser;
 pub mod svg_room;
+pub mod tiles;
+mod trace;
 pub mod transform;
 pub mod util;
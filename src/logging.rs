@@ -0,0 +1,18 @@
+//! Output for the spans of the library in the binaries
+
+use tracing::Level;
+use tracing_subscriber::fmt::format::FmtSpan;
+
+/// Writes events and spans at `level` or above to standard error, as JSON lines if `json` is set.
+/// Spans are written when they close, along with how long they took.
+pub fn init(level: Level, json: bool) {
+    let subscriber = tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_span_events(FmtSpan::CLOSE)
+        .with_writer(std::io::stderr);
+    if json {
+        subscriber.json().init();
+    } else {
+        subscriber.init();
+    }
+}
@@ -0,0 +1,397 @@
+//! Strategies for generating arbitrary map data in property tests
+
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+use proptest::collection::{hash_map, hash_set, vec};
+use proptest::prelude::*;
+use serde_json::Value;
+
+use crate::geometry::Polygon;
+use crate::map_data::{
+    compiled, uncompiled, Edge, EdgeKind, EdgeTag, Floor, RoomTag, Vertex, VertexTag,
+};
+use crate::search::SearchIndex;
+use crate::transform::CoordinateSystem;
+
+fn id() -> impl Strategy<Value = String> {
+    "[a-z0-9_-]{1,6}"
+}
+
+fn coordinate() -> impl Strategy<Value = f64> {
+    prop::num::f64::NORMAL | prop::num::f64::ZERO
+}
+
+fn point() -> impl Strategy<Value = (f64, f64)> {
+    (coordinate(), coordinate())
+}
+
+/// Known tags, along with strings that aren't tags, which become `Other`
+fn tag_string() -> impl Strategy<Value = String> {
+    prop_oneof![
+        prop::sample::select(vec![
+            "stairs",
+            "elevator",
+            "closed",
+            "women-bathroom",
+            "aed"
+        ])
+        .prop_map(str::to_owned),
+        "[a-z-]{1,12}",
+    ]
+}
+
+fn metadata_value() -> impl Strategy<Value = Value> {
+    // Floats are left out since serde_json doesn't promise to read them back exactly
+    let leaf = prop_oneof![
+        Just(Value::Null),
+        any::<bool>().prop_map(Value::from),
+        any::<i64>().prop_map(Value::from),
+        ".{0,8}".prop_map(Value::from),
+    ];
+    leaf.prop_recursive(2, 8, 4, |inner| {
+        prop_oneof![
+            vec(inner.clone(), 0..4).prop_map(Value::from),
+            hash_map(id(), inner, 0..4)
+                .prop_map(|object| Value::Object(object.into_iter().collect())),
+        ]
+    })
+}
+
+fn metadata() -> impl Strategy<Value = HashMap<String, Value>> {
+    hash_map(id(), metadata_value(), 0..3)
+}
+
+impl Arbitrary for VertexTag {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_args: Self::Parameters) -> Self::Strategy {
+        tag_string().prop_map(|tag| tag.parse().unwrap()).boxed()
+    }
+}
+
+impl Arbitrary for RoomTag {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_args: Self::Parameters) -> Self::Strategy {
+        tag_string().prop_map(|tag| tag.parse().unwrap()).boxed()
+    }
+}
+
+impl Arbitrary for EdgeKind {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_args: Self::Parameters) -> Self::Strategy {
+        prop_oneof![
+            prop::sample::select(vec!["door", "corridor", "stairs"]).prop_map(str::to_owned),
+            "[a-z-]{1,12}",
+        ]
+        .prop_map(|kind| kind.parse().unwrap())
+        .boxed()
+    }
+}
+
+impl Arbitrary for EdgeTag {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_args: Self::Parameters) -> Self::Strategy {
+        prop_oneof![
+            prop::sample::select(vec!["staff-only", "inaccessible"]).prop_map(str::to_owned),
+            "[a-z-]{1,12}",
+        ]
+        .prop_map(|tag| tag.parse().unwrap())
+        .boxed()
+    }
+}
+
+impl Arbitrary for Floor {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_args: Self::Parameters) -> Self::Strategy {
+        (
+            id(),
+            vec("[a-z/]{1,12}\\.svg", 1..4),
+            point(),
+            prop::option::of((point(), point())),
+            prop::option::of("[a-z]{1,4}\\(\\.\\+\\)"),
+            vec("[a-z]{1,4}", 0..3),
+        )
+            .prop_map(
+                |(number, images, offsets, bounds, id_pattern, id_prefixes)| Floor {
+                    number,
+                    images: images.into_iter().map(PathBuf::from).collect(),
+                    offsets,
+                    bounds: bounds
+                        .map(|((min_x, min_y), (max_x, max_y))| (min_x, min_y, max_x, max_y)),
+                    id_pattern,
+                    id_prefixes,
+                },
+            )
+            .boxed()
+    }
+}
+
+impl Arbitrary for Vertex {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_args: Self::Parameters) -> Self::Strategy {
+        (
+            id(),
+            point(),
+            hash_set(any::<VertexTag>(), 0..3),
+            prop::option::of(id()),
+        )
+            .prop_map(|(floor, location, tags, group)| Vertex {
+                floor,
+                location,
+                tags,
+                group,
+            })
+            .boxed()
+    }
+}
+
+impl Arbitrary for Edge {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_args: Self::Parameters) -> Self::Strategy {
+        (
+            id(),
+            id(),
+            any::<bool>(),
+            prop::option::of(any::<EdgeKind>()),
+            hash_set(any::<EdgeTag>(), 0..3),
+        )
+            .prop_map(|(from, to, directed, kind, tags)| Edge {
+                from,
+                to,
+                directed,
+                kind,
+                tags,
+            })
+            .boxed()
+    }
+}
+
+fn room_vertices() -> impl Strategy<Value = HashSet<String>> {
+    hash_set(id(), 0..3)
+}
+
+impl Arbitrary for uncompiled::Room {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_args: Self::Parameters) -> Self::Strategy {
+        (
+            room_vertices(),
+            vec(".{0,8}", 0..3),
+            prop::option::of(point()),
+            hash_set(any::<RoomTag>(), 0..3),
+            prop::option::of(any::<i32>()),
+            metadata(),
+        )
+            .prop_map(
+                |(vertices, names, center, tags, priority, metadata)| uncompiled::Room {
+                    vertices,
+                    names,
+                    center,
+                    tags,
+                    priority,
+                    metadata,
+                },
+            )
+            .boxed()
+    }
+}
+
+impl Arbitrary for compiled::Room {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_args: Self::Parameters) -> Self::Strategy {
+        (
+            room_vertices(),
+            vec(".{0,8}", 0..3),
+            point(),
+            vec(point(), 0..6),
+            vec(vec(point(), 3..5), 0..2),
+            coordinate(),
+            prop::option::of(id()),
+            hash_set(any::<RoomTag>(), 0..3),
+            prop::option::of(any::<i32>()),
+            metadata(),
+        )
+            .prop_map(
+                |(
+                    vertices,
+                    names,
+                    center,
+                    outline,
+                    holes,
+                    area,
+                    floor,
+                    tags,
+                    priority,
+                    metadata,
+                )| {
+                    compiled::Room {
+                        vertices,
+                        names,
+                        center,
+                        outline: outline.into(),
+                        holes: holes.into_iter().map(Polygon::from).collect(),
+                        area,
+                        floor,
+                        outlines: HashMap::new(),
+                        tags,
+                        priority,
+                        metadata,
+                    }
+                },
+            )
+            .boxed()
+    }
+}
+
+impl Arbitrary for uncompiled::MapData {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_args: Self::Parameters) -> Self::Strategy {
+        (
+            vec(any::<Floor>(), 0..3),
+            hash_map(id(), any::<Vertex>(), 0..4),
+            vec(any::<Edge>(), 0..4),
+            hash_map(id(), any::<uncompiled::Room>(), 0..4),
+            vec("[a-z]{1,4}", 0..3),
+            prop::option::of("[a-z]{1,4}\\(\\.\\+\\)"),
+        )
+            .prop_map(
+                |(floors, vertices, edges, rooms, id_prefixes, id_pattern)| uncompiled::MapData {
+                    floors,
+                    vertices,
+                    edges,
+                    rooms,
+                    id_prefixes,
+                    id_pattern,
+                    ..uncompiled::MapData::default()
+                },
+            )
+            .boxed()
+    }
+}
+
+impl Arbitrary for compiled::MapData {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_args: Self::Parameters) -> Self::Strategy {
+        (
+            vec(any::<Floor>(), 0..3),
+            hash_map(id(), any::<Vertex>(), 0..4),
+            vec(any::<Edge>(), 0..4),
+            hash_map(id(), any::<compiled::Room>(), 0..4),
+            prop_oneof![
+                Just(CoordinateSystem::SvgNative),
+                Just(CoordinateSystem::YUpWithOffsets)
+            ],
+            any::<bool>(),
+        )
+            .prop_map(
+                |(floors, vertices, edges, rooms, coordinate_system, with_index)| {
+                    let mut map_data = compiled::MapData {
+                        floors,
+                        vertices,
+                        edges,
+                        rooms,
+                        coordinate_system,
+                        index: None,
+                        ..compiled::MapData::default()
+                    };
+                    if with_index {
+                        map_data.index = Some(SearchIndex::build(&map_data));
+                    }
+                    map_data
+                },
+            )
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fmt::Debug;
+
+    use serde::de::DeserializeOwned;
+    use serde::Serialize;
+
+    use super::*;
+
+    fn assert_round_trip<T: Serialize + DeserializeOwned + Debug + PartialEq>(value: &T) {
+        let json = serde_json::to_string(value).unwrap();
+        let read: T = serde_json::from_str(&json).unwrap();
+        assert_eq!(value, &read, "{}", json);
+    }
+
+    proptest! {
+        #[test]
+        fn coordinates_round_trip(
+            point in (-1e5_f64..1e5, -1e5_f64..1e5),
+            offsets in (-1e5_f64..1e5, -1e5_f64..1e5),
+            coordinate_system in prop_oneof![
+                Just(CoordinateSystem::SvgNative),
+                Just(CoordinateSystem::YUpWithOffsets)
+            ],
+        ) {
+            let floor = Floor::new("1".to_owned(), PathBuf::from("1.svg"), offsets);
+            let map = floor.svg_to_map_coords(point, coordinate_system);
+            let svg = floor.map_to_svg_coords(map, coordinate_system);
+            // Only rounding separates the point from where it started
+            let tolerance = 4. * f64::EPSILON * (offsets.0.abs() + offsets.1.abs() + 1e5);
+            prop_assert!((svg.0 - point.0).abs() <= tolerance, "{:?} became {:?}", point, svg);
+            prop_assert!((svg.1 - point.1).abs() <= tolerance, "{:?} became {:?}", point, svg);
+        }
+
+        #[test]
+        fn floors_round_trip(floor in any::<Floor>()) {
+            assert_round_trip(&floor);
+        }
+
+        #[test]
+        fn vertices_round_trip(vertex in any::<Vertex>()) {
+            assert_round_trip(&vertex);
+        }
+
+        #[test]
+        fn edges_round_trip(edge in any::<Edge>()) {
+            assert_round_trip(&edge);
+        }
+
+        #[test]
+        fn uncompiled_rooms_round_trip(room in any::<uncompiled::Room>()) {
+            assert_round_trip(&room);
+        }
+
+        #[test]
+        fn compiled_rooms_round_trip(room in any::<compiled::Room>()) {
+            assert_round_trip(&room);
+        }
+
+        #[test]
+        fn uncompiled_map_data_round_trips(map_data in any::<uncompiled::MapData>()) {
+            assert_round_trip(&map_data);
+        }
+
+        #[test]
+        fn compiled_map_data_round_trips(map_data in any::<compiled::MapData>()) {
+            assert_round_trip(&map_data);
+        }
+    }
+}
@@ -0,0 +1,1842 @@
+//! Compiling map data along with the SVG images of its floors
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use nalgebra::Vector2;
+use regex::Regex;
+use serde::Serialize;
+
+use crate::bounding_box::BoundingBox;
+use crate::geometry::Polygon;
+use crate::map_data::uncompiled::{CompileError, CompileOptions, Dimension, MissingImage};
+use crate::map_data::{compiled, computed_center, uncompiled, Floor, RoomTag, Vertex};
+use crate::svg_parser::SvgElement;
+use crate::svg_room::{
+    extract_rooms_with_holes, extract_rooms_with_report, AmbiguousId, ExtractError, LabelConflict,
+    OutlineWithHoles, SvgRoomConfig,
+};
+use crate::trace::span;
+use crate::transform::CoordinateSystem;
+use crate::util::{natural_cmp, simplify_ring};
+
+/// Number, image contents, and offsets of each floor
+type FloorImages = Vec<(String, Vec<String>, (f64, f64))>;
+
+/// Rooms in a floor image with their outlines, along with what was reported about the elements
+/// of the image while finding them
+struct ImageRooms {
+    /// Rooms sorted by number
+    rooms: Vec<(String, OutlineWithHoles)>,
+    /// IDs of elements that start with a room ID prefix without being rooms
+    invalid_ids: Vec<String>,
+    ambiguous_ids: Vec<AmbiguousId>,
+    label_conflicts: Vec<LabelConflict>,
+    bounds: Option<BoundingBox>,
+}
+
+/// Number and offsets of floors
+pub type FloorOffsets = Vec<(String, (f64, f64))>;
+
+/// ID of the element in each floor image that [`Compiler::auto_offsets`] aligns floors by
+pub const REGISTRATION_ID: &str = "registration";
+
+/// Distance in map units that a floor's offsets can be from those computed from its registration
+/// element without a warning
+const OFFSETS_TOLERANCE: f64 = 0.5;
+
+/// Width or height in map units below which a room's outline has collapsed, usually because it was
+/// scaled to nothing by accident
+pub const COLLAPSED_ROOM_EPSILON: f64 = 1e-6;
+
+/// Thresholds for warning that the images of a floor don't fit the floor's vertices, which
+/// usually means that the floor was given the image of another floor. Bounds are compared in map
+/// coordinates, after the floor's offsets are applied.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImageExtentCheck {
+    /// Floors with fewer vertices than this aren't checked, since a few vertices say little about
+    /// how much of a floor there is. `usize::MAX` turns the check off.
+    pub min_vertices: usize,
+    /// Farthest the vertices can reach past an edge of the images, as a fraction of the images'
+    /// width or height
+    pub max_outside: f64,
+    /// Smallest fraction of the images' width or height, whichever the vertices cover more of,
+    /// that the vertices have to span. Comparing each direction on its own allows floors whose
+    /// vertices run along a single corridor.
+    pub min_coverage: f64,
+}
+
+impl Default for ImageExtentCheck {
+    fn default() -> Self {
+        Self {
+            min_vertices: 4,
+            max_outside: 0.1,
+            min_coverage: 0.25,
+        }
+    }
+}
+
+/// Something noteworthy found while compiling, which didn't stop the map data from compiling
+#[derive(Debug, Clone, PartialEq)]
+pub enum CompileWarning {
+    /// A vertex is so far outside of every floor image that its location is probably a mistake
+    DistantVertex { vertex: String },
+    /// A room appears on more than one floor, and its outline was taken from the first one
+    RoomOnMultipleFloors {
+        room: String,
+        first_floor: String,
+        first_bounds: (f64, f64, f64, f64),
+        second_floor: String,
+        second_bounds: (f64, f64, f64, f64),
+    },
+    /// A room in a floor image isn't in the map data, so it was skipped
+    UnknownRoom { room: String },
+    /// A floor image is missing, so it was left out and its rooms weren't compiled
+    MissingFloorImage(MissingImage),
+    /// Offsets were computed from registration elements, but a floor has none, so its offsets
+    /// were left as they were
+    MissingRegistration { floor: String },
+    /// The offsets computed for a floor from its registration element are far from the offsets
+    /// it was given, which were replaced
+    OffsetsMismatch {
+        floor: String,
+        given: (f64, f64),
+        computed: (f64, f64),
+    },
+    /// An element in a floor image has an ID that starts with the room ID prefix, but isn't
+    /// followed by a valid room number, so it was skipped
+    InvalidRoomId { floor: String, id: String },
+    /// An element in a floor image has an ID that gives different room numbers for the room ID
+    /// pattern and the longest room ID prefix it starts with, so the pattern's was used
+    AmbiguousRoomId { floor: String, id: AmbiguousId },
+    /// An element in a floor image has an ID and an `inkscape:label` that give different room
+    /// numbers, so the ID's was used
+    RoomLabelConflict {
+        floor: String,
+        conflict: LabelConflict,
+    },
+    /// A vertex has the same ID as an element in an image of its floor, but lies outside of it,
+    /// whose bounds in map coordinates are `(min x, min y, max x, max y)`
+    VertexOutsideElement {
+        vertex: String,
+        location: (f64, f64),
+        bounds: (f64, f64, f64, f64),
+    },
+    /// A room's outline is narrower than [`COLLAPSED_ROOM_EPSILON`] in one direction, so it has no
+    /// area. `size` is its size in that direction.
+    CollapsedRoom {
+        room: String,
+        floor: String,
+        dimension: Dimension,
+        size: f64,
+    },
+    /// Directed edges with the same kind and tags go both ways between two vertices, and could be
+    /// one undirected edge, as found by [`uncompiled::MapData::directed_edge_twins`]
+    DirectedEdgeTwins { from: String, to: String },
+    /// A stairs or elevator vertex has no edge to another floor and no group, as found by
+    /// [`uncompiled::MapData::unlinked_vertical_vertices`]
+    UnlinkedVerticalVertex { vertex: String },
+    /// An up or down vertex has no edge to another floor, which is all the tags affect, as found
+    /// by [`uncompiled::MapData::unlinked_up_down_vertices`]
+    UnlinkedUpDownVertex { vertex: String },
+    /// The vertices of a floor reach too far past its images or cover too little of them, as set
+    /// by [`ImageExtentCheck`], so the floor may have been given the wrong image. Bounds are
+    /// `(min x, min y, max x, max y)` in map coordinates.
+    ImageExtentMismatch {
+        floor: String,
+        image_bounds: (f64, f64, f64, f64),
+        vertex_bounds: (f64, f64, f64, f64),
+    },
+}
+
+impl fmt::Display for CompileWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DistantVertex { vertex } => {
+                write!(f, "Vertex is far outside of the floor images: {}", vertex)
+            }
+            Self::RoomOnMultipleFloors {
+                room,
+                first_floor,
+                first_bounds,
+                second_floor,
+                second_bounds,
+            } => write!(
+                f,
+                "Room {} appears on floor {} within {:?} and floor {} within {:?}",
+                room, first_floor, first_bounds, second_floor, second_bounds
+            ),
+            Self::UnknownRoom { room } => write!(f, "Room does not exist: {}", room),
+            Self::MissingFloorImage(missing) => write!(f, "Left out floor image {}", missing),
+            Self::MissingRegistration { floor } => write!(
+                f,
+                "Floor {} has no `{}` element, so its offsets weren't computed",
+                floor, REGISTRATION_ID
+            ),
+            Self::OffsetsMismatch {
+                floor,
+                given,
+                computed,
+            } => write!(
+                f,
+                "Floor {} was given offsets {:?}, but its registration element needs {:?}",
+                floor, given, computed
+            ),
+            Self::InvalidRoomId { floor, id } => write!(
+                f,
+                "Element {:?} on floor {} isn't a room, since it has no valid room number",
+                id, floor
+            ),
+            Self::AmbiguousRoomId { floor, id } => {
+                write!(
+                    f,
+                    "Element on floor {} has an ambiguous room ID: {}",
+                    floor, id
+                )
+            }
+            Self::RoomLabelConflict { floor, conflict } => write!(
+                f,
+                "Element on floor {} has an ID and a label for different rooms: {}",
+                floor, conflict
+            ),
+            Self::VertexOutsideElement {
+                vertex,
+                location,
+                bounds,
+            } => write!(
+                f,
+                "Vertex {} at {:?} is outside of the element with the same ID, within {:?}",
+                vertex, location, bounds
+            ),
+            Self::CollapsedRoom {
+                room,
+                floor,
+                dimension,
+                size,
+            } => write!(
+                f,
+                "Room {} on floor {} has a {} of {}, so it has no area",
+                room, floor, dimension, size
+            ),
+            Self::DirectedEdgeTwins { from, to } => write!(
+                f,
+                "Directed edges go both ways between {} and {}, and could be one undirected edge",
+                from, to
+            ),
+            Self::UnlinkedVerticalVertex { vertex } => write!(
+                f,
+                "Stairs or elevator vertex has no edge to another floor and no group: {}",
+                vertex
+            ),
+            Self::UnlinkedUpDownVertex { vertex } => write!(
+                f,
+                "Up or down vertex has no edge to another floor: {}",
+                vertex
+            ),
+            Self::ImageExtentMismatch {
+                floor,
+                image_bounds,
+                vertex_bounds,
+            } => write!(
+                f,
+                "Floor {} has vertices within {:?}, which don't fit its images within {:?}, so it \
+                 may have the image of another floor",
+                floor, vertex_bounds, image_bounds
+            ),
+        }
+    }
+}
+
+/// Room in a floor image that isn't in the map data
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrphanRoom {
+    pub number: String,
+    /// Center of the room's outline, in map coordinates
+    pub center: (f64, f64),
+}
+
+/// Counts of what was compiled
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompileStats {
+    pub floors: usize,
+    pub vertices: usize,
+    pub edges: usize,
+    pub rooms: usize,
+    /// Rooms in the map data that weren't found in any floor image, which were left out
+    pub rooms_without_outlines: usize,
+    /// Points left out of room outlines and holes by simplification
+    pub simplified_points: usize,
+}
+
+/// Compiled map data, along with what was found while compiling it
+#[derive(Debug, Clone)]
+pub struct CompileOutput {
+    pub map_data: compiled::MapData,
+    /// Warnings in the order they were found, which are only collected if the compiler was asked
+    /// to with [`Compiler::collect_warnings`]
+    pub warnings: Vec<CompileWarning>,
+    pub stats: CompileStats,
+    /// Number and offsets of every floor whose offsets were computed by
+    /// [`Compiler::auto_offsets`], in the order of the floors
+    pub computed_offsets: FloorOffsets,
+}
+
+/// Compiles map data, configured with a builder:
+///
+/// ```no_run
+/// # use indoor_map_lib::map_data::compile::Compiler;
+/// # use indoor_map_lib::map_data::uncompiled::MapData;
+/// # let map_data = MapData::empty();
+/// let output = Compiler::new()
+///     .base_path("maps")
+///     .simplify(0.5)
+///     .id_prefix("room")
+///     .collect_warnings(true)
+///     .compile(map_data)?;
+/// # Ok::<(), indoor_map_lib::map_data::uncompiled::CompileError>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Compiler {
+    base_path: PathBuf,
+    options: CompileOptions,
+    simplify_tolerance: Option<f64>,
+    collect_warnings: bool,
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Directory that the paths of floor images are relative to, which is usually the directory
+    /// of the map data's JSON file
+    pub fn base_path(self, base_path: impl Into<PathBuf>) -> Self {
+        Self {
+            base_path: base_path.into(),
+            ..self
+        }
+    }
+
+    /// Replaces every option set so far, other than the base path, simplification, and warning
+    /// collection
+    pub fn options(self, options: CompileOptions) -> Self {
+        Self { options, ..self }
+    }
+
+    /// Simplifies room outlines and holes, leaving out points within `tolerance` map units of the
+    /// line between the points around them, such as the extra nodes editors leave along walls
+    pub fn simplify(self, tolerance: f64) -> Self {
+        Self {
+            simplify_tolerance: Some(tolerance),
+            ..self
+        }
+    }
+
+    /// Prefix of the IDs of room elements in floor images, which is followed by the room number,
+    /// replacing any other prefixes
+    pub fn id_prefix(mut self, id_prefix: impl Into<String>) -> Self {
+        self.options.svg_room.id_prefixes = vec![id_prefix.into()];
+        self
+    }
+
+    /// Prefixes of the IDs of room elements in floor images, for images drawn with different
+    /// conventions. An ID that starts with more than one of them uses the longest. Floors or map
+    /// data with `id_prefixes` of their own use them instead.
+    pub fn id_prefixes(mut self, id_prefixes: Vec<String>) -> Self {
+        self.options.svg_room.id_prefixes = id_prefixes;
+        self
+    }
+
+    /// See [`SvgRoomConfig::id_pattern`]. Floors or map data with an `id_pattern` of their own use
+    /// it instead.
+    pub fn id_pattern(mut self, id_pattern: Regex) -> Self {
+        self.options.svg_room.id_pattern = Some(id_pattern);
+        self
+    }
+
+    /// Whether to also look for room numbers in the `inkscape:label` of elements
+    pub fn use_inkscape_label(mut self, use_inkscape_label: bool) -> Self {
+        self.options.svg_room.use_inkscape_label = use_inkscape_label;
+        self
+    }
+
+    /// Determines if the rest of a room element's ID after the prefix is a room number, which by
+    /// default is [`is_room_number`](crate::svg_room::is_room_number). Use
+    /// [`is_numbered_room`](crate::svg_room::is_numbered_room) to only find rooms whose numbers
+    /// have digits.
+    pub fn valid_room_number(mut self, valid_number: fn(&str) -> bool) -> Self {
+        self.options.svg_room.valid_number = valid_number;
+        self
+    }
+
+    pub fn coordinate_system(mut self, coordinate_system: CoordinateSystem) -> Self {
+        self.options.svg_room.coordinate_system = coordinate_system;
+        self
+    }
+
+    /// See [`CompileOptions::allow_rooms_on_multiple_floors`]
+    pub fn allow_rooms_on_multiple_floors(mut self, allow: bool) -> Self {
+        self.options.allow_rooms_on_multiple_floors = allow;
+        self
+    }
+
+    /// See [`CompileOptions::allow_missing_images`]
+    pub fn allow_missing_images(mut self, allow: bool) -> Self {
+        self.options.allow_missing_images = allow;
+        self
+    }
+
+    /// See [`CompileOptions::allow_unrouted_rooms`]
+    pub fn allow_unrouted_rooms(mut self, allow: bool) -> Self {
+        self.options.allow_unrouted_rooms = allow;
+        self
+    }
+
+    /// See [`CompileOptions::check_vertex_elements`]
+    pub fn check_vertex_elements(mut self, check: bool) -> Self {
+        self.options.check_vertex_elements = check;
+        self
+    }
+
+    /// See [`CompileOptions::strict`]
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.options.strict = strict;
+        self
+    }
+
+    /// See [`CompileOptions::auto_offsets`]
+    pub fn auto_offsets(mut self, auto_offsets: bool) -> Self {
+        self.options.auto_offsets = auto_offsets;
+        self
+    }
+
+    /// See [`CompileOptions::image_extent`]
+    pub fn image_extent_check(mut self, check: ImageExtentCheck) -> Self {
+        self.options.image_extent = check;
+        self
+    }
+
+    /// Return warnings in the [`CompileOutput`] instead of printing them as they are found
+    pub fn collect_warnings(self, collect_warnings: bool) -> Self {
+        Self {
+            collect_warnings,
+            ..self
+        }
+    }
+
+    /// Compiles `map_data`, reading the floor images relative to the base path
+    pub fn compile(
+        &self,
+        mut map_data: uncompiled::MapData,
+    ) -> Result<CompileOutput, CompileError> {
+        span!("compile", rooms = map_data.rooms.len());
+        let mut warnings = Warnings {
+            collect: self.collect_warnings,
+            warnings: vec![],
+        };
+        let mut stats = CompileStats::default();
+        for (from, to) in map_data.directed_edge_twins() {
+            warnings.warn(CompileWarning::DirectedEdgeTwins {
+                from: from.to_owned(),
+                to: to.to_owned(),
+            });
+        }
+        for vertex in map_data.unlinked_vertical_vertices() {
+            warnings.warn(CompileWarning::UnlinkedVerticalVertex {
+                vertex: vertex.to_owned(),
+            });
+        }
+        for vertex in map_data.unlinked_up_down_vertices() {
+            warnings.warn(CompileWarning::UnlinkedUpDownVertex {
+                vertex: vertex.to_owned(),
+            });
+        }
+        let coordinate_system = self.options.svg_room.coordinate_system;
+        if coordinate_system == CoordinateSystem::SvgNative {
+            if let Some(floor) = map_data
+                .floors
+                .iter()
+                .find(|floor| floor.get_offsets() != (0., 0.))
+            {
+                return Err(CompileError::OffsetsWithSvgNative(
+                    floor.get_number().to_owned(),
+                ));
+            }
+        }
+        let missing_images = map_data.missing_floor_images(&self.base_path);
+        if !missing_images.is_empty() && !self.options.allow_missing_images {
+            return Err(CompileError::MissingFloorImage(missing_images));
+        }
+        let skipped_paths: HashSet<_> = missing_images
+            .iter()
+            .map(|missing| missing.path.clone())
+            .collect();
+        for missing in missing_images {
+            warnings.warn(CompileWarning::MissingFloorImage(missing));
+        }
+        let mut floor_images =
+            self.read_floor_images(&self.base_path, &map_data, &skipped_paths)?;
+        let mut computed_offsets = vec![];
+        if self.options.auto_offsets && coordinate_system == CoordinateSystem::YUpWithOffsets {
+            computed_offsets = align_floors(&mut floor_images, &mut warnings)?;
+            for (floor, (_number, _images, offsets)) in
+                map_data.floors.iter_mut().zip(&floor_images)
+            {
+                floor.set_offsets(*offsets);
+            }
+        }
+
+        if self.options.check_vertex_elements {
+            for ((floor_number, images, _offsets), floor) in
+                floor_images.iter().zip(&map_data.floors)
+            {
+                for image_content in images {
+                    let image = SvgElement::from_svg_data(image_content).map_err(|err| {
+                        CompileError::InvalidImage {
+                            floor: floor_number.clone(),
+                            reason: err.to_string(),
+                        }
+                    })?;
+                    warn_vertices_outside_elements(
+                        floor_number,
+                        &map_data.vertices,
+                        &image,
+                        |bounds| map_bounds(bounds, floor, coordinate_system),
+                        &mut warnings,
+                    );
+                }
+            }
+        }
+
+        let room_configs = self.room_configs(&map_data)?;
+        let mut compiled_rooms = HashMap::with_capacity(map_data.rooms.len());
+        // Floor and bounds of each room in the images so far
+        let mut seen_rooms: HashMap<String, (String, _)> = HashMap::new();
+        // Bounds of the images of each floor, from the same pass that finds their rooms
+        let mut floors_bounds: HashMap<String, BoundingBox> = HashMap::new();
+        for (floor_number, images, offsets) in floor_images {
+            for image_content in &images {
+                let ImageRooms {
+                    rooms: image_rooms,
+                    invalid_ids,
+                    ambiguous_ids,
+                    label_conflicts,
+                    bounds: image_bounds,
+                } = self.extract_image_rooms(
+                    &floor_number,
+                    image_content,
+                    offsets,
+                    &room_configs[&floor_number],
+                )?;
+                if let Some(image_bounds) = image_bounds {
+                    let floor_bounds = match floors_bounds.remove(&floor_number) {
+                        Some(floor_bounds) => floor_bounds.union(&image_bounds),
+                        None => image_bounds,
+                    };
+                    floors_bounds.insert(floor_number.clone(), floor_bounds);
+                }
+                for id in invalid_ids {
+                    warnings.warn(CompileWarning::InvalidRoomId {
+                        floor: floor_number.clone(),
+                        id,
+                    });
+                }
+                for id in ambiguous_ids {
+                    warnings.warn(CompileWarning::AmbiguousRoomId {
+                        floor: floor_number.clone(),
+                        id,
+                    });
+                }
+                for conflict in label_conflicts {
+                    warnings.warn(CompileWarning::RoomLabelConflict {
+                        floor: floor_number.clone(),
+                        conflict,
+                    });
+                }
+                for (number, (outline, holes)) in image_rooms {
+                    span!("compile_room", room = %number);
+                    let bounds = outline_bounds(&outline);
+
+                    if let Some((seen_floor, seen_bounds)) = seen_rooms.get(&number) {
+                        let multi_floor_room =
+                            compiled_rooms
+                                .get_mut(&number)
+                                .filter(|room: &&mut compiled::Room| {
+                                    room.tags.contains(&RoomTag::MultiFloor)
+                                });
+                        if *seen_floor == floor_number
+                            || multi_floor_room
+                                .as_ref()
+                                .is_some_and(|room| room.outlines.contains_key(&floor_number))
+                        {
+                            return Err(CompileError::DuplicateSvgRoom {
+                                room: number,
+                                floor: floor_number,
+                            });
+                        }
+                        if let Some(room) = multi_floor_room {
+                            let (outline, holes) =
+                                self.simplify_outline(outline, holes, &mut stats);
+                            let holes = holes.into_iter().map(Polygon::from).collect();
+                            room.outlines
+                                .insert(floor_number.clone(), (outline.into(), holes));
+                            continue;
+                        }
+                        if !self.options.allow_rooms_on_multiple_floors {
+                            return Err(CompileError::RoomOnMultipleFloors {
+                                room: number,
+                                first_floor: seen_floor.clone(),
+                                second_floor: floor_number,
+                            });
+                        }
+                        warnings.warn(CompileWarning::RoomOnMultipleFloors {
+                            room: number,
+                            first_floor: seen_floor.clone(),
+                            first_bounds: *seen_bounds,
+                            second_floor: floor_number.clone(),
+                            second_bounds: bounds,
+                        });
+                        continue;
+                    }
+                    seen_rooms.insert(number.clone(), (floor_number.clone(), bounds));
+
+                    let uncompiled_room = match map_data.rooms.remove(&number) {
+                        Some(old_room) => old_room,
+                        None => {
+                            warnings.warn(CompileWarning::UnknownRoom { room: number });
+                            continue;
+                        }
+                    };
+
+                    let (min_x, min_y, max_x, max_y) = bounds;
+                    let collapsed = [
+                        (Dimension::Width, max_x - min_x),
+                        (Dimension::Height, max_y - min_y),
+                    ]
+                    .into_iter()
+                    .find(|&(_, size)| size < COLLAPSED_ROOM_EPSILON);
+                    if let Some((dimension, size)) = collapsed {
+                        if self.options.strict {
+                            return Err(CompileError::CollapsedRoom {
+                                room: number,
+                                floor: floor_number,
+                                dimension,
+                            });
+                        }
+                        warnings.warn(CompileWarning::CollapsedRoom {
+                            room: number.clone(),
+                            floor: floor_number.clone(),
+                            dimension,
+                            size,
+                        });
+                    }
+
+                    let (outline, holes) = self.simplify_outline(outline, holes, &mut stats);
+                    let mut compiled_room = uncompiled_room.compile(outline, holes);
+                    compiled_room.floor = Some(floor_number.clone());
+                    if compiled_room.vertices.is_empty() {
+                        if !self.options.allow_unrouted_rooms {
+                            return Err(CompileError::RoomWithoutVertices(number));
+                        }
+                        compiled_room.tags.insert(RoomTag::NoAccess);
+                    }
+                    compiled_rooms.insert(number, compiled_room);
+                }
+            }
+        }
+
+        let mut images_bounds: Option<BoundingBox> = None;
+        for floor in &mut map_data.floors {
+            let Some(floor_bounds) = floors_bounds.remove(floor.get_number()) else {
+                continue;
+            };
+            warn_image_extent(
+                floor.get_number(),
+                &floor_bounds,
+                &map_data.vertices,
+                &self.options.image_extent,
+                &mut warnings,
+            );
+            let min = floor_bounds.get_top_left();
+            let max = floor_bounds.get_bottom_right();
+            floor.set_bounds((min[0], min[1], max[0], max[1]));
+            images_bounds = Some(match images_bounds {
+                Some(images_bounds) => images_bounds.union(&floor_bounds),
+                None => floor_bounds,
+            });
+        }
+        if let Some(images_bounds) = images_bounds {
+            warn_distant_vertices(&map_data.vertices, &images_bounds, &mut warnings);
+        }
+
+        stats.floors = map_data.floors.len();
+        stats.vertices = map_data.vertices.len();
+        stats.edges = map_data.edges.len();
+        stats.rooms = compiled_rooms.len();
+        stats.rooms_without_outlines = map_data.rooms.len();
+        let mut pois = map_data.pois;
+        for poi in pois.values_mut() {
+            if poi.vertex.is_none() {
+                poi.vertex = poi.nearest_vertex(&map_data.vertices).map(str::to_owned);
+            }
+        }
+        let map_data = compiled::MapData {
+            floors: map_data.floors,
+            vertices: map_data.vertices,
+            rooms: compiled_rooms,
+            pois,
+            edges: map_data.edges,
+            coordinate_system,
+            index: None,
+            ..compiled::MapData::default()
+        };
+        Ok(CompileOutput {
+            map_data,
+            warnings: warnings.warnings,
+            stats,
+            computed_offsets,
+        })
+    }
+
+    /// Finds the rooms in the floor images that aren't in `map_data`, such as rooms drawn in a floor
+    /// plan before being added to the map data, by floor number. Rooms are found the same way as
+    /// when compiling, and are sorted by number with the center of their outlines.
+    pub fn orphan_svg_rooms(
+        &self,
+        base_path: &Path,
+        map_data: &uncompiled::MapData,
+    ) -> Result<HashMap<String, Vec<OrphanRoom>>, CompileError> {
+        map_data.verify_floor_images(base_path)?;
+        let floor_images = self.read_floor_images(base_path, map_data, &HashSet::new())?;
+        let room_configs = self.room_configs(map_data)?;
+        let mut orphans: HashMap<String, Vec<OrphanRoom>> = HashMap::new();
+        for (floor_number, images, offsets) in floor_images {
+            for image_content in &images {
+                let image_rooms = self.extract_image_rooms(
+                    &floor_number,
+                    image_content,
+                    offsets,
+                    &room_configs[&floor_number],
+                )?;
+                let floor_orphans = image_rooms
+                    .rooms
+                    .into_iter()
+                    .filter(|(number, _)| !map_data.rooms.contains_key(number))
+                    .map(|(number, (outline, holes))| OrphanRoom {
+                        number,
+                        center: computed_center(&outline, &holes),
+                    });
+                orphans
+                    .entry(floor_number.clone())
+                    .or_default()
+                    .extend(floor_orphans);
+            }
+        }
+        orphans.retain(|_floor, rooms| !rooms.is_empty());
+        for rooms in orphans.values_mut() {
+            rooms.sort_unstable_by(|a, b| natural_cmp(&a.number, &b.number));
+        }
+        Ok(orphans)
+    }
+
+    /// Generates skeleton entries for the rooms found by [`Self::orphan_svg_rooms`], as map data
+    /// that can be added to `map_data` with [`uncompiled::MapData::merge`]. The rooms have no
+    /// vertices or names, unless `add_vertices` is set, in which case each room gets a vertex
+    /// `<room>_auto` at the center of its outline on the floor it was found on.
+    pub fn scaffold_rooms(
+        &self,
+        base_path: &Path,
+        map_data: &uncompiled::MapData,
+        add_vertices: bool,
+    ) -> Result<uncompiled::MapData, CompileError> {
+        let mut scaffold = uncompiled::MapData::empty();
+        for (floor, orphans) in self.orphan_svg_rooms(base_path, map_data)? {
+            for orphan in orphans {
+                let mut vertices = HashSet::new();
+                if add_vertices {
+                    let id = format!("{}_auto", orphan.number);
+                    scaffold
+                        .vertices
+                        .insert(id.clone(), Vertex::new(floor.clone(), orphan.center));
+                    vertices.insert(id);
+                }
+                scaffold
+                    .rooms
+                    .insert(orphan.number, uncompiled::Room::new(vertices));
+            }
+        }
+        Ok(scaffold)
+    }
+
+    /// Simplifies `outline` and `holes` if the compiler was asked to, counting the points left out
+    /// in `stats`
+    fn simplify_outline(
+        &self,
+        outline: Vec<(f64, f64)>,
+        holes: Vec<Vec<(f64, f64)>>,
+        stats: &mut CompileStats,
+    ) -> OutlineWithHoles {
+        let Some(tolerance) = self.simplify_tolerance else {
+            return (outline, holes);
+        };
+        let mut simplify = |ring: Vec<(f64, f64)>| {
+            let simplified = simplify_ring(&ring, tolerance);
+            stats.simplified_points += ring.len() - simplified.len();
+            simplified
+        };
+        let outline = simplify(outline);
+        let holes = holes.into_iter().map(&mut simplify).collect();
+        (outline, holes)
+    }
+
+    /// Finds how room elements are recognized on each floor, by floor number. The `id_prefixes`
+    /// and `id_pattern` of a floor are used in place of the compiler's, and then those of the map
+    /// data.
+    fn room_configs(
+        &self,
+        map_data: &uncompiled::MapData,
+    ) -> Result<HashMap<String, SvgRoomConfig>, CompileError> {
+        map_data
+            .floors
+            .iter()
+            .map(|floor| {
+                let mut config = self.options.svg_room.clone();
+                let id_prefixes = match floor.get_id_prefixes() {
+                    [] => &map_data.id_prefixes,
+                    id_prefixes => id_prefixes,
+                };
+                if !id_prefixes.is_empty() {
+                    config.id_prefixes = id_prefixes.to_vec();
+                }
+                if let Some(id_pattern) = floor.get_id_pattern().or(map_data.id_pattern.as_deref())
+                {
+                    let id_pattern =
+                        Regex::new(id_pattern).map_err(|err| CompileError::InvalidIdPattern {
+                            floor: floor.get_number().to_owned(),
+                            reason: err.to_string(),
+                        })?;
+                    config.id_pattern = Some(id_pattern);
+                }
+                Ok((floor.get_number().to_owned(), config))
+            })
+            .collect()
+    }
+
+    /// Finds the rooms in an image of a floor, sorted by number, along with what was reported
+    /// about its elements
+    fn extract_image_rooms(
+        &self,
+        floor_number: &str,
+        image_content: &str,
+        offsets: (f64, f64),
+        config: &SvgRoomConfig,
+    ) -> Result<ImageRooms, CompileError> {
+        span!("extract_rooms", floor = %floor_number);
+        let extracted =
+            extract_rooms_with_report(image_content, offsets, config).map_err(|err| match err {
+                ExtractError::DuplicateRoom(room) => CompileError::DuplicateSvgRoom {
+                    room,
+                    floor: floor_number.to_owned(),
+                },
+                err => CompileError::InvalidImage {
+                    floor: floor_number.to_owned(),
+                    reason: err.to_string(),
+                },
+            })?;
+        let mut image_rooms: Vec<_> = extracted.rooms.into_iter().collect();
+        image_rooms.sort_unstable_by(|(a, _), (b, _)| natural_cmp(a, b));
+        let bounds = extracted.bounds.map(|(min_x, min_y, max_x, max_y)| {
+            BoundingBox::new(
+                Vector2::new(min_x, min_y),
+                Vector2::new(max_x - min_x, max_y - min_y),
+            )
+        });
+        Ok(ImageRooms {
+            rooms: image_rooms,
+            invalid_ids: extracted.invalid_ids,
+            ambiguous_ids: extracted.ambiguous_ids,
+            label_conflicts: extracted.label_conflicts,
+            bounds,
+        })
+    }
+
+    /// Reads the images of each floor other than `skipped_paths`, relative to `base_path`, along
+    /// with the floor's number and offsets
+    fn read_floor_images(
+        &self,
+        base_path: &Path,
+        map_data: &uncompiled::MapData,
+        skipped_paths: &HashSet<PathBuf>,
+    ) -> Result<FloorImages, CompileError> {
+        map_data
+            .floors
+            .iter()
+            .map(|floor| {
+                let images = floor
+                    .get_images()
+                    .iter()
+                    .map(|image_rel_path| base_path.join(image_rel_path))
+                    .filter(|image_path| !skipped_paths.contains(image_path))
+                    .map(|image_path| {
+                        fs::read_to_string(&image_path).map_err(|err| CompileError::ReadImage {
+                            path: image_path,
+                            reason: err.to_string(),
+                        })
+                    })
+                    .collect::<Result<_, _>>()?;
+                Ok((floor.get_number().to_owned(), images, floor.get_offsets()))
+            })
+            .collect()
+    }
+}
+
+/// Warnings found while compiling, which are either collected or printed as they are found
+struct Warnings {
+    collect: bool,
+    warnings: Vec<CompileWarning>,
+}
+
+impl Warnings {
+    fn warn(&mut self, warning: CompileWarning) {
+        if self.collect {
+            self.warnings.push(warning);
+        } else {
+            println!("{}", warning);
+        }
+    }
+}
+
+/// Converts bounds in the SVG coordinates of an image of `floor` to map coordinates
+fn map_bounds(
+    svg_bounds: &BoundingBox,
+    floor: &Floor,
+    coordinate_system: CoordinateSystem,
+) -> BoundingBox {
+    let to_map = |corner: Vector2<f64>| {
+        let (x, y) = floor.svg_to_map_coords((corner[0], corner[1]), coordinate_system);
+        Vector2::new(x, y)
+    };
+    let first = to_map(svg_bounds.get_top_left());
+    let second = to_map(svg_bounds.get_bottom_right());
+    let top_left = first.inf(&second);
+    BoundingBox::new(top_left, first.sup(&second) - top_left)
+}
+
+/// Computes the offsets of each floor with a registration element so that the element lands where
+/// it does on the first floor with one, returning the floors whose offsets were computed
+fn align_floors(
+    floor_images: &mut FloorImages,
+    warnings: &mut Warnings,
+) -> Result<FloorOffsets, CompileError> {
+    let config = SvgRoomConfig {
+        id_prefixes: vec![REGISTRATION_ID.to_owned()],
+        id_pattern: None,
+        use_inkscape_label: false,
+        coordinate_system: CoordinateSystem::SvgNative,
+        // Other IDs starting with the registration ID aren't registration elements
+        valid_number: str::is_empty,
+    };
+    let coordinate_system = CoordinateSystem::YUpWithOffsets;
+    // Map coordinates of the registration element
+    let mut target = None;
+    let mut computed = vec![];
+    for (floor_number, images, offsets) in floor_images {
+        let mut registration = None;
+        for image_content in images.iter() {
+            let elements =
+                extract_rooms_with_holes(image_content, (0., 0.), &config).map_err(|err| {
+                    CompileError::InvalidImage {
+                        floor: floor_number.clone(),
+                        reason: err.to_string(),
+                    }
+                })?;
+            if let Some((outline, _holes)) = elements.get("") {
+                let (min_x, min_y, max_x, max_y) = outline_bounds(outline);
+                registration = Some(((min_x + max_x) / 2., (min_y + max_y) / 2.));
+                break;
+            }
+        }
+        let Some(registration) = registration else {
+            warnings.warn(CompileWarning::MissingRegistration {
+                floor: floor_number.clone(),
+            });
+            continue;
+        };
+
+        let (target_x, target_y) =
+            *target.get_or_insert_with(|| coordinate_system.svg_to_map(registration, *offsets));
+        let floor_offsets = (registration.0 - target_x, target_y + registration.1);
+        let (given_x, given_y) = *offsets;
+        if (floor_offsets.0 - given_x).hypot(floor_offsets.1 - given_y) > OFFSETS_TOLERANCE {
+            warnings.warn(CompileWarning::OffsetsMismatch {
+                floor: floor_number.clone(),
+                given: *offsets,
+                computed: floor_offsets,
+            });
+        }
+        *offsets = floor_offsets;
+        computed.push((floor_number.clone(), floor_offsets));
+    }
+    Ok(computed)
+}
+
+/// Warns about vertices so far outside of every floor image that their locations are probably
+/// mistakes, such as vertices more than a whole map's width to the side of it
+fn warn_distant_vertices(
+    vertices: &HashMap<String, Vertex>,
+    images_bounds: &BoundingBox,
+    warnings: &mut Warnings,
+) {
+    let size = images_bounds.get_size();
+    let allowed_bounds = images_bounds.expand(size[0].max(size[1]));
+    let mut distant: Vec<_> = vertices
+        .iter()
+        .filter(|(_id, vertex)| {
+            let (x, y) = vertex.get_location();
+            let location = BoundingBox::new(Vector2::new(x, y), Vector2::zeros());
+            !allowed_bounds.contains(&location)
+        })
+        .map(|(id, _vertex)| id.clone())
+        .collect();
+    distant.sort_unstable();
+    for vertex in distant {
+        warnings.warn(CompileWarning::DistantVertex { vertex });
+    }
+}
+
+/// Warns if the vertices on `floor` don't fit `image_bounds`, the bounds of its images in map
+/// coordinates, as set by `check`
+fn warn_image_extent(
+    floor: &str,
+    image_bounds: &BoundingBox,
+    vertices: &HashMap<String, Vertex>,
+    check: &ImageExtentCheck,
+    warnings: &mut Warnings,
+) {
+    let locations: Vec<_> = vertices
+        .values()
+        .filter(|vertex| vertex.get_floor() == floor)
+        .map(Vertex::get_location)
+        .collect();
+    let image_size = image_bounds.get_size();
+    if locations.len() < check.min_vertices || image_size[0] <= 0. || image_size[1] <= 0. {
+        return;
+    }
+    let vertex_bounds = outline_bounds(&locations);
+    let (min_x, min_y, max_x, max_y) = vertex_bounds;
+    let (image_min, image_max) = (image_bounds.get_top_left(), image_bounds.get_bottom_right());
+
+    let outside = [
+        (image_min[0] - min_x) / image_size[0],
+        (max_x - image_max[0]) / image_size[0],
+        (image_min[1] - min_y) / image_size[1],
+        (max_y - image_max[1]) / image_size[1],
+    ]
+    .into_iter()
+    .fold(0., f64::max);
+    let coverage = ((max_x - min_x) / image_size[0]).max((max_y - min_y) / image_size[1]);
+    if outside > check.max_outside || coverage < check.min_coverage {
+        warnings.warn(CompileWarning::ImageExtentMismatch {
+            floor: floor.to_owned(),
+            image_bounds: (image_min[0], image_min[1], image_max[0], image_max[1]),
+            vertex_bounds,
+        });
+    }
+}
+
+/// Warns about vertices on `floor` that lie outside of the element of `image` with the same ID,
+/// where `to_map` converts the bounds of elements to map coordinates
+fn warn_vertices_outside_elements(
+    floor: &str,
+    vertices: &HashMap<String, Vertex>,
+    image: &SvgElement,
+    to_map: impl Fn(&BoundingBox) -> BoundingBox,
+    warnings: &mut Warnings,
+) {
+    let mut ids: Vec<_> = vertices
+        .iter()
+        .filter(|(_id, vertex)| vertex.get_floor() == floor)
+        .map(|(id, _vertex)| id)
+        .collect();
+    ids.sort_unstable();
+    for id in ids {
+        let Some(element) = image.find_by_id(id) else {
+            continue;
+        };
+        let bounds = to_map(&element.get_bounding_box());
+        let (x, y) = vertices[id].get_location();
+        let location = BoundingBox::new(Vector2::new(x, y), Vector2::zeros());
+        if !bounds.contains(&location) {
+            let min = bounds.get_top_left();
+            let max = bounds.get_bottom_right();
+            warnings.warn(CompileWarning::VertexOutsideElement {
+                vertex: id.clone(),
+                location: (x, y),
+                bounds: (min[0], min[1], max[0], max[1]),
+            });
+        }
+    }
+}
+
+/// Finds the bounds of an outline as `(min x, min y, max x, max y)`
+fn outline_bounds(outline: &[(f64, f64)]) -> (f64, f64, f64, f64) {
+    outline.iter().fold(
+        (
+            f64::INFINITY,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            f64::NEG_INFINITY,
+        ),
+        |(min_x, min_y, max_x, max_y), &(x, y)| {
+            (min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y))
+        },
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::map_data::uncompiled::{MapDataDeserializeError, MapDataError, MissingImageReason};
+    use crate::map_data::Poi;
+    use crate::svg_room::IdMatch;
+
+    fn map_data(path: &str) -> uncompiled::MapData {
+        uncompiled::MapData::new(&fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    /// Leaves out the warning about vertex `a` of `tests/json/compile.json`, which is tagged as
+    /// stairs without leading to another floor
+    fn without_unlinked_stairs(warnings: Vec<CompileWarning>) -> Vec<CompileWarning> {
+        let unlinked = CompileWarning::UnlinkedVerticalVertex {
+            vertex: "a".to_owned(),
+        };
+        warnings
+            .into_iter()
+            .filter(|warning| *warning != unlinked)
+            .collect()
+    }
+
+    /// Map data from `tests/json/compile.json` with a second floor drawn by the same image
+    fn room_on_two_floors() -> uncompiled::MapData {
+        let mut map_data = map_data("tests/json/compile.json");
+        let first = &map_data.floors[0];
+        let second = crate::map_data::Floor::new(
+            "2".to_owned(),
+            first.get_image().clone(),
+            first.get_offsets(),
+        );
+        map_data.floors.push(second);
+        map_data
+    }
+
+    #[test]
+    fn collect_warnings_and_stats() {
+        let output = Compiler::new()
+            .base_path("tests/json")
+            .allow_rooms_on_multiple_floors(true)
+            .collect_warnings(true)
+            .compile(room_on_two_floors())
+            .unwrap();
+        let rooms: Vec<_> = without_unlinked_stairs(output.warnings)
+            .into_iter()
+            .map(|warning| match warning {
+                CompileWarning::RoomOnMultipleFloors { room, .. } => room,
+                other => panic!("Should be a room on multiple floors, was {:?}", other),
+            })
+            .collect();
+        assert_eq!(vec!["106", "107"], rooms);
+        assert_eq!(output.map_data.rooms.len(), output.stats.rooms);
+        assert_eq!(output.map_data.floors.len(), output.stats.floors);
+        assert_eq!(0, output.stats.simplified_points);
+    }
+
+    #[test]
+    fn warn_room_label_conflicts() {
+        let mut map_data = map_data("tests/json/compile.json");
+        map_data.floors[0].add_image("../svg/labels.svg".into());
+        let output = Compiler::new()
+            .base_path("tests/json")
+            .collect_warnings(true)
+            .compile(map_data)
+            .unwrap();
+        let conflicts: Vec<_> = output
+            .warnings
+            .iter()
+            .filter(|warning| matches!(warning, CompileWarning::RoomLabelConflict { .. }))
+            .collect();
+        assert_eq!(
+            vec![&CompileWarning::RoomLabelConflict {
+                floor: "1".to_owned(),
+                conflict: LabelConflict {
+                    id: "room215".to_owned(),
+                    label: "room216".to_owned(),
+                    numbers: ("215".to_owned(), "216".to_owned()),
+                },
+            }],
+            conflicts
+        );
+    }
+
+    #[test]
+    fn collapsed_rooms() {
+        let compiler = Compiler::new()
+            .base_path("tests/json")
+            .collect_warnings(true);
+        let output = compiler
+            .compile(map_data("tests/json/collapsed_room.json"))
+            .unwrap();
+        assert_eq!(
+            vec![CompileWarning::CollapsedRoom {
+                room: "102".to_owned(),
+                floor: "1".to_owned(),
+                dimension: Dimension::Width,
+                size: 0.,
+            }],
+            output.warnings
+        );
+        // It's still compiled, as it was before it was warned about
+        assert_eq!(0., output.map_data.rooms["102"].area);
+
+        let error = compiler
+            .strict(true)
+            .compile(map_data("tests/json/collapsed_room.json"))
+            .unwrap_err();
+        assert_eq!(
+            CompileError::CollapsedRoom {
+                room: "102".to_owned(),
+                floor: "1".to_owned(),
+                dimension: Dimension::Width,
+            },
+            error
+        );
+        assert_eq!(
+            "The room `102` has no width in the images of floor `1`",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn warn_distant_vertices() {
+        let mut map_data = map_data("tests/json/compile.json");
+        // Negative coordinates within a map's width of the image are fine
+        map_data.vertices.get_mut("a").unwrap().location = (-50., 50.);
+        map_data.vertices.get_mut("b").unwrap().location = (1000., 50.);
+        let output = Compiler::new()
+            .base_path("tests/json")
+            .collect_warnings(true)
+            .compile(map_data)
+            .unwrap();
+        let distant: Vec<_> = output
+            .warnings
+            .iter()
+            .filter(|warning| matches!(warning, CompileWarning::DistantVertex { .. }))
+            .collect();
+        assert_eq!(
+            vec![&CompileWarning::DistantVertex {
+                vertex: "b".to_owned()
+            }],
+            distant
+        );
+        assert_eq!(
+            Some((0., 0., 100., 100.)),
+            output.map_data.floors[0].get_bounds()
+        );
+    }
+
+    #[test]
+    fn image_extent_mismatch() {
+        let mismatches = |compiler: Compiler| {
+            let output = compiler
+                .base_path("tests/json")
+                .collect_warnings(true)
+                .compile(map_data("tests/json/image_extent.json"))
+                .unwrap();
+            output
+                .warnings
+                .into_iter()
+                .filter(|warning| matches!(warning, CompileWarning::ImageExtentMismatch { .. }))
+                .collect::<Vec<_>>()
+        };
+
+        // The vertices of floor 2 only cover a speck of its image
+        assert_eq!(
+            vec![CompileWarning::ImageExtentMismatch {
+                floor: "2".to_owned(),
+                image_bounds: (0., 0., 100., 100.),
+                vertex_bounds: (20., 20., 21., 21.),
+            }],
+            mismatches(Compiler::new())
+        );
+
+        let check = ImageExtentCheck {
+            min_coverage: 0.01,
+            ..ImageExtentCheck::default()
+        };
+        assert!(mismatches(Compiler::new().image_extent_check(check)).is_empty());
+        let check = ImageExtentCheck {
+            min_vertices: 5,
+            ..ImageExtentCheck::default()
+        };
+        assert!(mismatches(Compiler::new().image_extent_check(check)).is_empty());
+
+        // Floor 1's vertices reach 10 units past the top of its images, which are 100 tall
+        let check = ImageExtentCheck {
+            max_outside: 0.05,
+            min_coverage: 0.,
+            ..ImageExtentCheck::default()
+        };
+        let mut map_data = map_data("tests/json/image_extent.json");
+        map_data.vertices.get_mut("north-1").unwrap().location = (20., 110.);
+        let output = Compiler::new()
+            .base_path("tests/json")
+            .collect_warnings(true)
+            .image_extent_check(check)
+            .compile(map_data)
+            .unwrap();
+        assert!(output.warnings.iter().any(|warning| matches!(
+            warning,
+            CompileWarning::ImageExtentMismatch { floor, .. } if floor == "1"
+        )));
+    }
+
+    #[test]
+    fn large_coordinates_keep_precision() {
+        // The image and its offsets are around 100,000, where `f32` can only represent multiples
+        // of about 0.008
+        let map_data = Compiler::new()
+            .base_path("tests/json")
+            .compile(map_data("tests/json/large_coordinates.json"))
+            .unwrap()
+            .map_data;
+        let expected = [
+            (
+                "201",
+                [
+                    (9.503, 90.243),
+                    (39.501, 90.243),
+                    (39.501, 70.241),
+                    (9.503, 70.241),
+                ],
+            ),
+            (
+                "202",
+                [
+                    (49.503, 50.243),
+                    (49.503, 30.241),
+                    (79.504, 30.241),
+                    (79.504, 50.243),
+                ],
+            ),
+        ];
+        for (room, points) in expected {
+            let outline = &map_data.rooms[room].outline;
+            assert_eq!(points.len(), outline.len(), "{}: {:?}", room, outline);
+            for (expected, actual) in points.iter().zip(outline.iter()) {
+                let error = (expected.0 - actual.0).hypot(expected.1 - actual.1);
+                assert!(error < 1e-3, "{}: {:?} became {:?}", room, expected, actual);
+            }
+        }
+    }
+
+    #[test]
+    fn check_vertices_against_elements() {
+        let mut map_data = map_data("tests/json/compile.json");
+        // Inside of the rectangle at (10, 10) to (40, 30) in the image
+        let inside = Vertex::new("1".to_owned(), (20., 80.));
+        map_data.vertices.insert("room106".to_owned(), inside);
+        let outside = Vertex::new("1".to_owned(), (20., 80.));
+        map_data.vertices.insert("room107".to_owned(), outside);
+        let map_data = map_data.verify().unwrap();
+
+        let compiler = Compiler::new()
+            .base_path("tests/json")
+            .collect_warnings(true);
+        let output = compiler.compile(map_data.clone()).unwrap();
+        assert!(without_unlinked_stairs(output.warnings).is_empty());
+
+        let output = compiler
+            .check_vertex_elements(true)
+            .compile(map_data)
+            .unwrap();
+        assert_eq!(
+            vec![CompileWarning::VertexOutsideElement {
+                vertex: "room107".to_owned(),
+                location: (20., 80.),
+                bounds: (50., 50., 90., 90.),
+            }],
+            without_unlinked_stairs(output.warnings)
+        );
+    }
+
+    #[test]
+    fn link_pois_to_nearest_vertices() {
+        let mut map_data = map_data("tests/json/compile.json");
+        map_data
+            .pois
+            .insert("fountain".to_owned(), Poi::new("1".to_owned(), (60., 75.)));
+        let mut linked = Poi::new("1".to_owned(), (60., 75.));
+        linked.vertex = Some("a".to_owned());
+        map_data.pois.insert("artwork".to_owned(), linked);
+        let map_data = map_data.verify().unwrap();
+
+        let compiled = Compiler::new()
+            .base_path("tests/json")
+            .compile(map_data.clone())
+            .unwrap()
+            .map_data;
+        assert_eq!(Some("b"), compiled.pois["fountain"].vertex.as_deref());
+        // Vertices given in the map data are kept
+        assert_eq!(Some("a"), compiled.pois["artwork"].vertex.as_deref());
+
+        let mut unknown_floor = map_data.clone();
+        unknown_floor.pois.get_mut("fountain").unwrap().floor = "2".to_owned();
+        assert!(matches!(
+            unknown_floor.verify(),
+            Err(MapDataError::UndefinedFloorNumber(floor)) if floor == "2"
+        ));
+        let mut unknown_vertex = map_data;
+        unknown_vertex.pois.get_mut("artwork").unwrap().vertex = Some("z".to_owned());
+        assert!(matches!(
+            unknown_vertex.verify(),
+            Err(MapDataError::UndefinedVertexId(vertex)) if vertex == "z"
+        ));
+    }
+
+    #[test]
+    fn warn_directed_edge_twins() {
+        let mut json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string("tests/json/compile.json").unwrap()).unwrap();
+        json["edges"] = serde_json::json!([["a", "b", true], ["b", "a", true]]);
+        let output = Compiler::new()
+            .base_path("tests/json")
+            .collect_warnings(true)
+            .compile(uncompiled::MapData::new(&json.to_string()).unwrap())
+            .unwrap();
+        assert_eq!(
+            vec![CompileWarning::DirectedEdgeTwins {
+                from: "a".to_owned(),
+                to: "b".to_owned(),
+            }],
+            without_unlinked_stairs(output.warnings)
+        );
+    }
+
+    #[test]
+    fn warn_unlinked_vertical_vertices() {
+        let mut json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string("tests/json/compile.json").unwrap()).unwrap();
+        json["vertices"]["b"]["tags"] = serde_json::json!(["elevator", "down"]);
+        let compile = |json: &serde_json::Value| {
+            Compiler::new()
+                .base_path("tests/json")
+                .collect_warnings(true)
+                .compile(uncompiled::MapData::new(&json.to_string()).unwrap())
+                .unwrap()
+                .warnings
+        };
+        let vertex = |id: &str| id.to_owned();
+        assert_eq!(
+            vec![
+                CompileWarning::UnlinkedVerticalVertex {
+                    vertex: vertex("a")
+                },
+                CompileWarning::UnlinkedVerticalVertex {
+                    vertex: vertex("b")
+                },
+                CompileWarning::UnlinkedUpDownVertex {
+                    vertex: vertex("b")
+                },
+            ],
+            compile(&json)
+        );
+
+        // Grouped stairs and elevators are linked by the edges made between their groups
+        json["vertices"]["a"]["group"] = serde_json::json!("stairwell");
+        json["vertices"]["b"]["tags"] = serde_json::json!(["elevator"]);
+        json["vertices"]["b"]["group"] = serde_json::json!("elevator");
+        assert!(compile(&json).is_empty());
+    }
+
+    #[test]
+    fn unrouted_rooms() {
+        let mut json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string("tests/json/compile.json").unwrap()).unwrap();
+        json["rooms"]["107"]["vertices"] = serde_json::json!([]);
+        let json = json.to_string();
+        assert!(matches!(
+            uncompiled::MapData::new(&json),
+            Err(MapDataDeserializeError::MapDataError(MapDataError::RoomWithoutVertices(room)))
+                if room == "107"
+        ));
+        let unverified: uncompiled::MapData = serde_json::from_str(&json).unwrap();
+        let compiler = Compiler::new().base_path("tests/json");
+        assert_eq!(
+            Some(CompileError::RoomWithoutVertices("107".to_owned())),
+            compiler.compile(unverified.clone()).err()
+        );
+
+        let options = CompileOptions {
+            allow_unrouted_rooms: true,
+            ..CompileOptions::default()
+        };
+        let map_data = unverified.verify_with_options(&options).unwrap();
+        let compiled = compiler
+            .options(options)
+            .compile(map_data)
+            .unwrap()
+            .map_data;
+        assert!(compiled.rooms["107"].tags.contains(&RoomTag::NoAccess));
+        assert!(!compiled.rooms["106"].tags.contains(&RoomTag::NoAccess));
+        // Rooms without vertices are on the floor their outline is from, so they're still drawn
+        assert_eq!(Some("1"), compiled.room_floor("107"));
+        assert!(compiled
+            .rooms_on_floor("1")
+            .any(|(number, _room)| number == "107"));
+    }
+
+    #[test]
+    fn builder_matches_compile() {
+        let compiled = map_data("tests/json/compile.json")
+            .compile(Path::new("tests/json"))
+            .unwrap();
+        let output = Compiler::new()
+            .base_path("tests/json")
+            .compile(map_data("tests/json/compile.json"))
+            .unwrap();
+        assert_eq!(compiled, output.map_data);
+        assert_eq!(
+            CompileStats {
+                floors: compiled.floors.len(),
+                vertices: compiled.vertices.len(),
+                edges: compiled.edges.len(),
+                rooms: compiled.rooms.len(),
+                rooms_without_outlines: 0,
+                simplified_points: 0,
+            },
+            output.stats
+        );
+
+        // No rooms have that prefix
+        let output = Compiler::new()
+            .base_path("tests/json")
+            .id_prefix("space")
+            .compile(map_data("tests/json/compile.json"))
+            .unwrap();
+        assert!(output.map_data.rooms.is_empty());
+        assert_eq!(compiled.rooms.len(), output.stats.rooms_without_outlines);
+    }
+
+    #[test]
+    fn room_id_prefixes_and_patterns() {
+        let compile = |compiler: Compiler, map_data| {
+            let output = compiler
+                .base_path("tests/json")
+                .collect_warnings(true)
+                .compile(map_data)
+                .unwrap();
+            let mut rooms: Vec<_> = output.map_data.rooms.into_keys().collect();
+            rooms.sort_unstable();
+            (rooms, output.warnings)
+        };
+
+        // Floor 2 recognizes its own pattern and the prefix, but not the room elements of floor 1
+        let (rooms, warnings) = compile(Compiler::new(), map_data("tests/json/id_patterns.json"));
+        assert_eq!(vec!["1", "106", "107", "210"], rooms);
+        assert!(warnings.is_empty(), "{:?}", warnings);
+
+        // Every `room` ID has the prefix `r` too, and the longest prefix wins
+        let (rooms, warnings) = compile(
+            Compiler::new().id_prefixes(vec!["r".to_owned(), "room".to_owned()]),
+            map_data("tests/json/id_patterns.json"),
+        );
+        assert_eq!(vec!["1", "106", "107", "210", "211"], rooms);
+        assert!(warnings.is_empty(), "{:?}", warnings);
+
+        // The pattern and the prefix `f2` disagree about `f2room210`
+        let (rooms, warnings) = compile(
+            Compiler::new().id_prefixes(vec!["room".to_owned(), "f2".to_owned()]),
+            map_data("tests/json/id_patterns.json"),
+        );
+        assert_eq!(vec!["1", "106", "107", "210"], rooms);
+        assert_eq!(
+            vec![CompileWarning::AmbiguousRoomId {
+                floor: "2".to_owned(),
+                id: AmbiguousId {
+                    id: "f2room210".to_owned(),
+                    matches: vec![
+                        (
+                            IdMatch::Pattern("^f2room(?P<number>.+)$".to_owned()),
+                            "210".to_owned()
+                        ),
+                        (IdMatch::Prefix("f2".to_owned()), "room210".to_owned()),
+                    ],
+                },
+            }],
+            warnings
+        );
+        assert!(warnings[0]
+            .to_string()
+            .ends_with("; using room 210 from the pattern `^f2room(?P<number>.+)$`"));
+
+        // The pattern given to the compiler is used by floors without their own
+        let mut without_floor_pattern = map_data("tests/json/id_patterns.json");
+        without_floor_pattern.floors[1].set_id_pattern(None);
+        let (rooms, _warnings) = compile(
+            Compiler::new().id_pattern(Regex::new(r"^f\d+room(\d+)$").unwrap()),
+            without_floor_pattern,
+        );
+        assert_eq!(vec!["1", "106", "107", "210"], rooms);
+
+        // Prefixes and patterns can be given for the whole map data, and for each floor
+        let mut json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string("tests/json/id_patterns.json").unwrap())
+                .unwrap();
+        json["id_prefixes"] = serde_json::json!(["r", "room"]);
+        let (rooms, warnings) = compile(
+            Compiler::new(),
+            uncompiled::MapData::new(&json.to_string()).unwrap(),
+        );
+        assert_eq!(vec!["1", "106", "107", "210", "211"], rooms);
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        json["floors"][1]["id_prefixes"] = serde_json::json!(["r"]);
+        json["floors"][1]["id_pattern"] = serde_json::Value::Null;
+        json["id_pattern"] = serde_json::json!(r"^f2room(\d+)$");
+        let (rooms, warnings) = compile(
+            Compiler::new(),
+            uncompiled::MapData::new(&json.to_string()).unwrap(),
+        );
+        // Floor 2 only has the prefix `r`, so `room1` is read as room `oom1`
+        assert_eq!(vec!["106", "107", "210", "211"], rooms);
+        assert_eq!(
+            vec![CompileWarning::UnknownRoom {
+                room: "oom1".to_owned()
+            }],
+            warnings
+        );
+
+        let mut invalid = map_data("tests/json/id_patterns.json");
+        invalid.floors[1].set_id_pattern(Some("room(".to_owned()));
+        assert!(matches!(
+            Compiler::new().base_path("tests/json").compile(invalid),
+            Err(CompileError::InvalidIdPattern { floor, .. }) if floor == "2"
+        ));
+    }
+
+    #[test]
+    fn find_orphan_rooms() {
+        let mut map_data = map_data("tests/json/compile.json");
+        let compiler = Compiler::new();
+        let base_path = Path::new("tests/json");
+        assert!(compiler
+            .orphan_svg_rooms(base_path, &map_data)
+            .unwrap()
+            .is_empty());
+
+        let mut removed: Vec<_> = map_data.rooms.keys().take(2).cloned().collect();
+        removed.sort_unstable();
+        for number in &removed {
+            map_data.rooms.remove(number);
+        }
+        let orphans = compiler.orphan_svg_rooms(base_path, &map_data).unwrap();
+        let compiled = Compiler::new()
+            .base_path(base_path)
+            .compile(self::map_data("tests/json/compile.json"))
+            .unwrap()
+            .map_data;
+        let mut found: Vec<_> = orphans
+            .values()
+            .flatten()
+            .map(|orphan| {
+                let room = &compiled.rooms[&orphan.number];
+                assert_eq!(computed_center(&room.outline, &room.holes), orphan.center);
+                orphan.number.clone()
+            })
+            .collect();
+        found.sort_unstable();
+        assert_eq!(removed, found);
+    }
+
+    #[test]
+    fn scaffold_orphan_rooms() {
+        let complete = map_data("tests/json/compile.json");
+        let mut map_data = complete.clone();
+        let number = map_data.rooms.keys().next().unwrap().clone();
+        map_data.rooms.remove(&number);
+        let base_path = Path::new("tests/json");
+
+        let scaffold = Compiler::new()
+            .scaffold_rooms(base_path, &map_data, false)
+            .unwrap();
+        assert!(scaffold.vertices.is_empty());
+        assert_eq!(
+            HashMap::from([(number.clone(), uncompiled::Room::new(HashSet::new()))]),
+            scaffold.rooms
+        );
+
+        let scaffold = Compiler::new()
+            .scaffold_rooms(base_path, &map_data, true)
+            .unwrap();
+        let vertex_id = format!("{}_auto", number);
+        let floor =
+            complete.vertices[complete.rooms[&number].vertices.iter().next().unwrap()].get_floor();
+        assert_eq!(floor, scaffold.vertices[&vertex_id].get_floor());
+        assert_eq!(HashSet::from([vertex_id]), scaffold.rooms[&number].vertices);
+
+        map_data.merge(scaffold.clone()).unwrap();
+        assert!(map_data.merge(scaffold).is_err());
+        let output = Compiler::new()
+            .base_path(base_path)
+            .compile(map_data.verify().unwrap())
+            .unwrap();
+        assert_eq!(0, output.stats.rooms_without_outlines);
+        assert!(output.map_data.rooms.contains_key(&number));
+    }
+
+    #[test]
+    fn align_floors_by_registration() {
+        let compile = |compiler: Compiler| {
+            compiler
+                .base_path("tests/json")
+                .collect_warnings(true)
+                .compile(map_data("tests/json/registration.json"))
+                .unwrap()
+        };
+
+        let output = compile(Compiler::new());
+        assert!(output.computed_offsets.is_empty());
+        assert_ne!(
+            output.map_data.rooms["101"].outline,
+            output.map_data.rooms["201"].outline
+        );
+
+        let output = compile(Compiler::new().auto_offsets(true));
+        assert_eq!(
+            vec![("1".to_owned(), (0., 100.)), ("2".to_owned(), (10., 120.))],
+            output.computed_offsets
+        );
+        assert_eq!(
+            vec![
+                CompileWarning::OffsetsMismatch {
+                    floor: "2".to_owned(),
+                    given: (0., 100.),
+                    computed: (10., 120.),
+                },
+                CompileWarning::MissingRegistration {
+                    floor: "3".to_owned()
+                },
+            ],
+            output.warnings
+        );
+        // The room was drawn in the same place on both floors before floor 2 was shifted
+        assert_eq!(
+            output.map_data.rooms["101"].outline,
+            output.map_data.rooms["201"].outline
+        );
+        let offsets: Vec<_> = output
+            .map_data
+            .floors
+            .iter()
+            .map(|floor| floor.get_offsets())
+            .collect();
+        assert_eq!(vec![(0., 100.), (10., 120.), (0., 100.)], offsets);
+    }
+
+    #[test]
+    fn simplify_outlines() {
+        let compile = |compiler: Compiler| {
+            compiler
+                .base_path("tests/json")
+                .compile(map_data("tests/json/simplify.json"))
+                .unwrap()
+        };
+
+        let output = compile(Compiler::new());
+        assert_eq!(6, output.map_data.rooms["110"].outline.len());
+        let output = compile(Compiler::new().simplify(0.1));
+        assert_eq!(4, output.map_data.rooms["110"].outline.len());
+        assert_eq!(2, output.stats.simplified_points);
+        assert_eq!(1600., output.map_data.rooms["110"].area);
+    }
+
+    #[test]
+    fn missing_image() {
+        let mut map_data = map_data("tests/json/compile.json");
+        let floor = map_data.floors[0].get_number().to_owned();
+        map_data.floors[0] =
+            crate::map_data::Floor::new(floor.clone(), PathBuf::from("missing.svg"), (0., 0.));
+        match Compiler::new().base_path("tests/json").compile(map_data) {
+            Err(CompileError::MissingFloorImage(missing)) => assert_eq!(
+                vec![MissingImage {
+                    floor,
+                    path: PathBuf::from("tests/json/missing.svg"),
+                    reason: MissingImageReason::DoesNotExist,
+                }],
+                missing
+            ),
+            other => panic!("Should fail to find the image, was {:?}", other),
+        }
+    }
+
+    #[test]
+    fn list_every_missing_image() {
+        let mut map_data = room_on_two_floors();
+        for (floor, image) in map_data
+            .floors
+            .iter_mut()
+            .zip(["missing.svg", "compile.json"])
+        {
+            *floor = crate::map_data::Floor::new(
+                floor.get_number().to_owned(),
+                PathBuf::from(image),
+                (0., 0.),
+            );
+        }
+        let reasons: Vec<_> = map_data
+            .missing_floor_images(Path::new("tests/json"))
+            .into_iter()
+            .map(|missing| missing.reason)
+            .collect();
+        assert_eq!(
+            vec![MissingImageReason::DoesNotExist, MissingImageReason::NotSvg],
+            reasons
+        );
+        assert!(map_data
+            .verify_floor_images(Path::new("tests/json"))
+            .is_err());
+    }
+
+    #[test]
+    fn allow_missing_images() {
+        let complete = Compiler::new()
+            .base_path("tests/json")
+            .allow_rooms_on_multiple_floors(true)
+            .collect_warnings(true)
+            .compile(room_on_two_floors())
+            .unwrap();
+
+        let mut map_data = room_on_two_floors();
+        let floor = map_data.floors[1].get_number().to_owned();
+        map_data.floors[1] =
+            crate::map_data::Floor::new(floor.clone(), PathBuf::from("missing.svg"), (0., 0.));
+        let output = Compiler::new()
+            .base_path("tests/json")
+            .allow_rooms_on_multiple_floors(true)
+            .allow_missing_images(true)
+            .collect_warnings(true)
+            .compile(map_data)
+            .unwrap();
+        assert_eq!(
+            vec![CompileWarning::MissingFloorImage(MissingImage {
+                floor,
+                path: PathBuf::from("tests/json/missing.svg"),
+                reason: MissingImageReason::DoesNotExist,
+            })],
+            without_unlinked_stairs(output.warnings)
+        );
+        // The rooms on both floors are still compiled from the first one
+        assert_eq!(complete.map_data.rooms, output.map_data.rooms);
+        assert_eq!(None, output.map_data.floors[1].get_bounds());
+    }
+}
@@ -1,26 +1,1370 @@
 use std::collections::{HashMap, HashSet};
+#[cfg(feature = "csv")]
+use std::io;
+use std::sync::OnceLock;
 
-use crate::map_data::{Edge, Floor, RoomTag, Vertex};
+use crate::map_data::{
+    computed_center, floor_ordinals, Edge, Floor, FloorIndex, Poi, RoomTag, UnknownFloor, Vertex,
+    VertexTag,
+};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+use nalgebra::Vector2;
+
+use crate::bounding_box::BoundingBox;
+use crate::geometry::Polygon;
+use crate::map_data::edit::MapDataEditor;
+use crate::map_data::uncompiled::MapDataError;
+use crate::routing::{CompactGraph, DistanceMatrix, RoutingGraph, RoutingOptions};
+use crate::search::SearchIndex;
+use crate::transform::CoordinateSystem;
+use crate::util::{area_with_holes, natural_cmp, point_in_polygon_with_holes, polygons_share_wall};
+
+/// Compiled map data. Values derived from it, such as the routing graph, are found the first time
+/// they're needed and kept alongside it until they're cleared, so they're ignored when comparing
+/// map data. Outside this crate, map data is created with [`Self::from_parts`] or
+/// [`Self::empty`] rather than a struct literal, which leaves room for more derived values.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[non_exhaustive]
 pub struct MapData {
     pub floors: Vec<Floor>,
     pub vertices: HashMap<String, Vertex>,
+    /// Edges as they are written in the map data. Prefer [`Self::adjacency`] and
+    /// [`Self::neighbors`] for walking the graph, since they account for directed edges and
+    /// one-way vertices.
+    #[serde(deserialize_with = "super::edge_list::deserialize")]
     pub edges: Vec<Edge>,
     pub rooms: HashMap<String, Room>,
+    /// Points of interest that aren't rooms, by ID. Each is linked to a vertex for routing
+    /// to it, unless there are no vertices on its floor.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub pois: HashMap<String, Poi>,
+    /// Convention used for every location, outline, and bound in the map data. Map data compiled
+    /// before this was recorded always used [`CoordinateSystem::YUpWithOffsets`].
+    #[serde(default)]
+    pub coordinate_system: CoordinateSystem,
+    /// Search index of the rooms, for clients that don't build it themselves
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub index: Option<SearchIndex>,
+    #[serde(skip)]
+    pub(super) graph: GraphCache,
+    #[serde(skip)]
+    pub(super) room_adjacency: RoomAdjacencyCache,
+    #[serde(skip)]
+    pub(super) tag_index: TagIndexCache,
+    #[serde(skip)]
+    pub(super) floor_index: FloorIndex,
+}
+
+/// Routing graph built the first time it's needed, which is ignored when comparing map data
+#[derive(Debug, Clone, Default)]
+pub(super) struct GraphCache(OnceLock<RoutingGraph>);
+
+impl PartialEq for GraphCache {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+/// Rooms sharing a wall with each room, sorted by number and found the first time they're needed,
+/// which is ignored when comparing map data
+#[derive(Debug, Clone, Default)]
+pub(super) struct RoomAdjacencyCache(OnceLock<HashMap<String, Vec<String>>>);
+
+impl PartialEq for RoomAdjacencyCache {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+/// Rooms and vertices with each tag, sorted by number or ID and found the first time they're
+/// needed, which is ignored when comparing map data
+#[derive(Debug, Clone, Default)]
+pub(super) struct TagIndexCache(OnceLock<TagIndex>);
+
+impl PartialEq for TagIndexCache {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct TagIndex {
+    rooms: HashMap<RoomTag, Vec<String>>,
+    vertices: HashMap<VertexTag, Vec<String>>,
+}
+
+/// Part of the map data carved out by [`MapData::subset_floors_with_report`] or
+/// [`MapData::subset_region_with_report`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Subset {
+    pub map_data: MapData,
+    /// Rooms that would have been kept, but were left out since some of their vertices are on
+    /// floors that weren't kept, sorted by number
+    pub partial_rooms: Vec<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+/// Distance in map units that the outlines of two rooms can be apart and still share a wall, which
+/// leaves room for the thickness of walls drawn between them
+const WALL_TOLERANCE: f64 = 1.;
+
+impl MapData {
+    /// Creates map data without any floors, vertices, edges, or rooms
+    pub fn empty() -> Self {
+        Self::default()
+    }
+
+    /// Creates map data from its floors, vertices, edges, and rooms, without any points of interest
+    /// or search index, in the default coordinate system
+    pub fn from_parts(
+        floors: Vec<Floor>,
+        vertices: HashMap<String, Vertex>,
+        edges: Vec<Edge>,
+        rooms: HashMap<String, Room>,
+    ) -> Self {
+        Self {
+            floors,
+            vertices,
+            edges,
+            rooms,
+            ..Self::default()
+        }
+    }
+
+    /// Finds the floor numbered `number`. Floor numbers are matched exactly, including case, so
+    /// `"g"` doesn't find a floor numbered `"G"`.
+    pub fn floor(&self, number: &str) -> Option<&Floor> {
+        self.floor_index.find(&self.floors, number)
+    }
+
+    /// Same as [`Self::floor`], but the error lists the floors there are, for reporting unknown
+    /// floors given by users
+    pub fn require_floor(&self, number: &str) -> Result<&Floor, UnknownFloor> {
+        self.floor_index.require(&self.floors, number)
+    }
+
+    pub fn has_floor(&self, number: &str) -> bool {
+        self.floor(number).is_some()
+    }
+
+    /// Returns the numbers of the floors from lowest to highest
+    pub fn floor_numbers(&self) -> Vec<&str> {
+        self.floors.iter().map(Floor::get_number).collect()
+    }
+
+    /// Recomputes the values of every room that are derived from its outline. Only the area can be
+    /// refreshed here, since the outline a center was found from is gone by the time this is
+    /// called; use [`Room::set_outline`] when changing outlines to keep computed centers in sync.
+    pub fn recompute_derived(&mut self) {
+        for room in self.rooms.values_mut() {
+            room.recompute_area();
+        }
+    }
+
+    /// Finds rooms whose stored area differs from the area of their outline by more than
+    /// `tolerance`, returning the room number, stored area, and recomputed area of each, sorted by
+    /// room number
+    pub fn stale_areas(&self, tolerance: f64) -> Vec<(&str, f64, f64)> {
+        let mut stale: Vec<_> = self
+            .rooms
+            .iter()
+            .map(|(number, room)| (number.as_str(), room.area, room.outline_area()))
+            .filter(|(_number, stored, recomputed)| (stored - recomputed).abs() > tolerance)
+            .collect();
+        stale.sort_unstable_by(|(a, ..), (b, ..)| natural_cmp(a, b));
+        stale
+    }
+
+    /// Serializes the map data as JSON with the offsets and bounds of floors, the locations of
+    /// vertices, and the centers, outlines, and areas of rooms rounded to `decimals` digits after
+    /// the decimal point. The map data itself keeps full precision, as does the JSON with 15 or more
+    /// `decimals`.
+    pub fn to_json_with_precision(&self, decimals: usize) -> serde_json::Result<String> {
+        let mut json = serde_json::to_value(self)?;
+        let round_fields = |value: &mut Value, fields: &[&str]| {
+            for field in fields {
+                if let Some(value) = value.get_mut(field) {
+                    round_json_numbers(value, decimals);
+                }
+            }
+        };
+        if let Some(floors) = json["floors"].as_array_mut() {
+            for floor in floors {
+                round_fields(floor, &["offsets", "bounds"]);
+            }
+        }
+        if let Some(vertices) = json["vertices"].as_object_mut() {
+            for vertex in vertices.values_mut() {
+                round_fields(vertex, &["location"]);
+            }
+        }
+        if let Some(rooms) = json["rooms"].as_object_mut() {
+            for room in rooms.values_mut() {
+                round_fields(room, &["center", "outline", "holes", "area"]);
+            }
+        }
+        serde_json::to_string(&json)
+    }
+
+    /// Returns the routing graph of the map data, building it the first time it's needed. The
+    /// graph isn't rebuilt if the vertices or edges change afterwards; call
+    /// [`Self::clear_graph`] after changing them.
+    pub fn graph(&self) -> &RoutingGraph {
+        self.graph.0.get_or_init(|| RoutingGraph::new(self))
+    }
+
+    /// Discards the routing graph, so that it's rebuilt from the current vertices and edges
+    pub fn clear_graph(&mut self) {
+        self.graph = GraphCache::default();
+    }
+
+    /// Discards every value derived from the map data, such as the routing graph and the rooms
+    /// sharing walls, so that they're found again from the current map data
+    pub fn clear_caches(&mut self) {
+        self.clear_graph();
+        self.clear_room_adjacency();
+        self.clear_tag_index();
+        self.floor_index = FloorIndex::default();
+    }
+
+    /// Makes the changes collected by `edit` all at once, after checking that the rooms and
+    /// vertices they refer to exist and that new edges don't conflict with the edges already
+    /// between their vertices. Only what the changes touch is checked. If any change is invalid,
+    /// none of them are made.
+    ///
+    /// ```
+    /// # use indoor_map_lib::map_data::compiled::MapData;
+    /// # use indoor_map_lib::map_data::RoomTag;
+    /// # let mut map_data = MapData::default();
+    /// let result = map_data.edit(|editor| {
+    ///     editor
+    ///         .set_room_center("101", (10., 20.))
+    ///         .retag_room("101", [RoomTag::Closed].into());
+    /// });
+    /// // There is no room 101
+    /// assert!(result.is_err());
+    /// ```
+    pub fn edit(&mut self, edit: impl FnOnce(&mut MapDataEditor)) -> Result<(), MapDataError> {
+        let mut editor = MapDataEditor::new(self);
+        edit(&mut editor);
+        editor.finish()?.apply(self);
+        Ok(())
+    }
+
+    /// Returns the routing graph along with the vertices of each room, in a form that can be
+    /// serialized and routed on without the rest of the map data
+    pub fn to_compact_graph(&self) -> CompactGraph {
+        CompactGraph::new(self, self.graph())
+    }
+
+    /// Returns the vertices that can be reached directly from each vertex, along with the
+    /// distance to each. Undirected edges appear in both directions, and edges that can't be
+    /// traveled in a direction, like directed edges or edges between floors through a one-way
+    /// vertex, don't appear in that direction.
+    pub fn adjacency(&self) -> &HashMap<String, Vec<(String, f64)>> {
+        self.graph().adjacency()
+    }
+
+    /// Returns the vertices that can be reached directly from `vertex`, along with the distance to
+    /// each
+    pub fn neighbors(&self, vertex: &str) -> &[(String, f64)] {
+        self.graph().neighbors(vertex)
+    }
+
+    /// Finds the walking distance between every pair of rooms, along the edges allowed by
+    /// `options`. The distance between two rooms is measured between whichever of their vertices
+    /// are closest.
+    pub fn distance_matrix(&self, options: &RoutingOptions) -> DistanceMatrix {
+        DistanceMatrix::new(self, &RoutingGraph::with_options(self, options))
+    }
+
+    /// Finds every room within `max_distance` of `start_vertex` along the edges allowed by
+    /// `options`, along with the distance to its closest vertex. Rooms are sorted from closest to
+    /// farthest, and then by number.
+    pub fn reachable_within(
+        &self,
+        start_vertex: &str,
+        max_distance: f64,
+        options: &RoutingOptions,
+    ) -> Vec<(String, f64)> {
+        let graph = RoutingGraph::with_options(self, options);
+        let distances = graph.distances_within([start_vertex], max_distance);
+        let mut reachable: Vec<_> = self
+            .rooms
+            .iter()
+            .filter_map(|(number, room)| {
+                room.vertices
+                    .iter()
+                    .filter_map(|vertex| distances.get(vertex.as_str()).copied())
+                    .min_by(f64::total_cmp)
+                    .map(|distance| (number.clone(), distance))
+            })
+            .collect();
+        reachable.sort_unstable_by(|(a_number, a_distance), (b_number, b_distance)| {
+            a_distance
+                .total_cmp(b_distance)
+                .then_with(|| natural_cmp(a_number, b_number))
+        });
+        reachable
+    }
+
+    /// Returns the numbers of the rooms on the same floor as `room` that share a wall with it,
+    /// sorted. Rooms share a wall if their outlines, or the outlines of their holes, run alongside
+    /// each other within a map unit. Like the routing graph, the rooms sharing walls are found the
+    /// first time they're needed; call [`Self::clear_room_adjacency`] after changing outlines.
+    pub fn adjacent_rooms(&self, room: &str) -> Vec<&str> {
+        self.room_adjacency
+            .0
+            .get_or_init(|| self.find_room_adjacency())
+            .get(room)
+            .map_or_else(Vec::new, |rooms| rooms.iter().map(String::as_str).collect())
+    }
+
+    /// Discards the rooms found sharing walls, so that they're found again from the current
+    /// outlines
+    pub fn clear_room_adjacency(&mut self) {
+        self.room_adjacency = RoomAdjacencyCache::default();
+    }
+
+    /// Returns the rooms tagged `tag` with their numbers, sorted by number. Like the routing graph,
+    /// the rooms with each tag are found the first time they're needed; call
+    /// [`Self::clear_tag_index`] after changing tags. Rooms removed since then are left out.
+    pub fn rooms_with_tag(&self, tag: &RoomTag) -> Vec<(&str, &Room)> {
+        self.tag_index()
+            .rooms
+            .get(tag)
+            .map_or_else(Vec::new, |numbers| {
+                numbers
+                    .iter()
+                    .filter_map(|number| Some((number.as_str(), self.rooms.get(number)?)))
+                    .collect()
+            })
+    }
+
+    /// Returns the rooms tagged with any of `tags` with their numbers, sorted by number
+    pub fn rooms_with_any(&self, tags: &[RoomTag]) -> Vec<(&str, &Room)> {
+        let mut rooms: Vec<_> = tags
+            .iter()
+            .flat_map(|tag| self.rooms_with_tag(tag))
+            .collect();
+        rooms.sort_unstable_by(|(a, _), (b, _)| natural_cmp(a, b));
+        rooms.dedup_by_key(|(number, _room)| *number);
+        rooms
+    }
+
+    /// Returns the vertices tagged `tag` with their IDs, sorted by ID. Vertices removed since the
+    /// tags were last found are left out.
+    pub fn vertices_with_tag(&self, tag: &VertexTag) -> Vec<(&str, &Vertex)> {
+        self.tag_index()
+            .vertices
+            .get(tag)
+            .map_or_else(Vec::new, |ids| {
+                ids.iter()
+                    .filter_map(|id| Some((id.as_str(), self.vertices.get(id)?)))
+                    .collect()
+            })
+    }
+
+    /// Discards the rooms and vertices found with each tag, so that they're found again from the
+    /// current tags
+    pub fn clear_tag_index(&mut self) {
+        self.tag_index = TagIndexCache::default();
+    }
+
+    fn tag_index(&self) -> &TagIndex {
+        self.tag_index.0.get_or_init(|| {
+            let mut index = TagIndex::default();
+            for (number, room) in &self.rooms {
+                for tag in &room.tags {
+                    index
+                        .rooms
+                        .entry(tag.clone())
+                        .or_default()
+                        .push(number.clone());
+                }
+            }
+            for (id, vertex) in &self.vertices {
+                for tag in vertex.get_tags() {
+                    index
+                        .vertices
+                        .entry(tag.clone())
+                        .or_default()
+                        .push(id.clone());
+                }
+            }
+            for numbers in index.rooms.values_mut() {
+                numbers.sort_unstable_by(|a, b| natural_cmp(a, b));
+            }
+            for ids in index.vertices.values_mut() {
+                ids.sort_unstable();
+            }
+            index
+        })
+    }
+
+    fn find_room_adjacency(&self) -> HashMap<String, Vec<String>> {
+        let mut floors: HashMap<&str, Vec<(&String, &Room)>> = HashMap::new();
+        for (number, room) in &self.rooms {
+            if let Some(floor) = self.room_floor(number) {
+                floors.entry(floor).or_default().push((number, room));
+            }
+        }
+
+        let mut adjacency: HashMap<String, Vec<String>> = HashMap::new();
+        for rooms in floors.values() {
+            for (i, (number, room)) in rooms.iter().enumerate() {
+                for (other_number, other_room) in &rooms[i + 1..] {
+                    if polygons_share_wall(
+                        &room.outline,
+                        &room.holes,
+                        &other_room.outline,
+                        &other_room.holes,
+                        WALL_TOLERANCE,
+                    ) {
+                        adjacency
+                            .entry((*number).clone())
+                            .or_default()
+                            .push((*other_number).clone());
+                        adjacency
+                            .entry((*other_number).clone())
+                            .or_default()
+                            .push((*number).clone());
+                    }
+                }
+            }
+        }
+        for rooms in adjacency.values_mut() {
+            rooms.sort_unstable_by(|a, b| natural_cmp(a, b));
+        }
+        adjacency
+    }
+
+    /// Same as [`Self::subset_floors_with_report`], without the report
+    pub fn subset_floors(&self, floors: &[&str]) -> MapData {
+        self.subset_floors_with_report(floors).map_data
+    }
+
+    /// Carves out the map data of `floors`, such as for an offline bundle of a single floor. The
+    /// subset keeps the vertices on those floors, the rooms whose vertices are all on them, the
+    /// edges between kept vertices, and the points of interest on them. Rooms with some of their
+    /// vertices on other floors, such as a stairwell, are left out and reported. Rooms without
+    /// vertices are left out too, since their floor isn't known. Unknown floor numbers are
+    /// ignored.
+    pub fn subset_floors_with_report(&self, floors: &[&str]) -> Subset {
+        self.subset(floors, |_| true, |_| true)
+    }
+
+    /// Same as [`Self::subset_region_with_report`], without the report
+    pub fn subset_region(&self, floor: &str, bounds: &BoundingBox) -> MapData {
+        self.subset_region_with_report(floor, bounds).map_data
+    }
+
+    /// Carves out the part of `floor` within `bounds`, which are in map coordinates, such as a wing
+    /// of a building. The subset keeps the rooms on the floor whose outlines overlap `bounds`
+    /// along with all of their vertices, the other vertices and points of interest within
+    /// `bounds`, and the edges between kept vertices. Rooms are left out and reported the same
+    /// way as in [`Self::subset_floors_with_report`].
+    pub fn subset_region_with_report(&self, floor: &str, bounds: &BoundingBox) -> Subset {
+        let within = |(x, y): (f64, f64)| {
+            bounds.contains(&BoundingBox::new(Vector2::new(x, y), Vector2::zeros()))
+        };
+        self.subset(&[floor], within, |room| {
+            room.outline
+                .bounding_box()
+                .is_some_and(|outline| outline.intersects(bounds))
+        })
+    }
+
+    /// Keeps the rooms on `floors` that `keep_room` accepts, along with their vertices and the
+    /// other vertices and points of interest on `floors` whose locations `keep_location` accepts
+    fn subset(
+        &self,
+        floors: &[&str],
+        keep_location: impl Fn((f64, f64)) -> bool,
+        keep_room: impl Fn(&Room) -> bool,
+    ) -> Subset {
+        let floors: HashSet<&str> = floors.iter().copied().collect();
+        let on_kept_floor = |id: &String| floors.contains(self.vertices[id].get_floor());
+
+        let mut rooms = HashMap::new();
+        let mut partial_rooms = vec![];
+        for (number, room) in &self.rooms {
+            // Rooms without vertices, such as unrouted rooms, are only on the floor of their outline
+            let on_floors = if room.vertices.is_empty() {
+                room.floor
+                    .as_deref()
+                    .is_some_and(|floor| floors.contains(floor))
+            } else {
+                room.vertices.iter().any(on_kept_floor)
+            };
+            if !keep_room(room) || !on_floors {
+                continue;
+            }
+            if room.vertices.iter().all(on_kept_floor) {
+                rooms.insert(number.clone(), room.clone());
+            } else {
+                partial_rooms.push(number.clone());
+            }
+        }
+        partial_rooms.sort_unstable_by(|a, b| natural_cmp(a, b));
+
+        let mut kept_ids: HashSet<&String> = rooms
+            .values()
+            .flat_map(|room: &Room| &room.vertices)
+            .collect();
+        kept_ids.extend(self.vertices.iter().filter_map(|(id, vertex)| {
+            (floors.contains(vertex.get_floor()) && keep_location(vertex.get_location()))
+                .then_some(id)
+        }));
+        let vertices: HashMap<_, _> = kept_ids
+            .into_iter()
+            .map(|id| (id.clone(), self.vertices[id].clone()))
+            .collect();
+
+        let edges = self
+            .edges
+            .iter()
+            .filter(|edge| {
+                vertices.contains_key(edge.get_from()) && vertices.contains_key(edge.get_to())
+            })
+            .cloned()
+            .collect();
+        let pois = self
+            .pois
+            .iter()
+            .filter(|(_id, poi)| floors.contains(poi.floor.as_str()) && keep_location(poi.location))
+            .map(|(id, poi)| {
+                let mut poi = poi.clone();
+                if !poi
+                    .vertex
+                    .as_ref()
+                    .is_some_and(|id| vertices.contains_key(id))
+                {
+                    poi.vertex = poi.nearest_vertex(&vertices).map(str::to_owned);
+                }
+                (id.clone(), poi)
+            })
+            .collect();
+
+        let mut map_data = MapData {
+            floors: self
+                .floors
+                .iter()
+                .filter(|floor| floors.contains(floor.get_number()))
+                .cloned()
+                .collect(),
+            vertices,
+            edges,
+            rooms,
+            pois,
+            coordinate_system: self.coordinate_system,
+            ..MapData::default()
+        };
+        if self.index.is_some() {
+            map_data.index = Some(SearchIndex::build(&map_data));
+        }
+        Subset {
+            map_data,
+            partial_rooms,
+        }
+    }
+
+    /// Iterates over the edges with both vertices on `floor`
+    pub fn edges_on_floor<'a>(&'a self, floor: &'a str) -> impl Iterator<Item = &'a Edge> + 'a {
+        self.edges.iter().filter(move |edge| {
+            self.vertices[edge.get_from()].get_floor() == floor
+                && self.vertices[edge.get_to()].get_floor() == floor
+        })
+    }
+
+    /// Finds the floor of a room from the floors of its vertices, choosing the lowest floor if
+    /// they are on more than one. Rooms without vertices, such as rooms tagged
+    /// [`RoomTag::NoAccess`], are on the floor their outline is from. Returns `None` if the room
+    /// doesn't exist, or has no vertices and no [`Room::floor`].
+    pub fn room_floor(&self, number: &str) -> Option<&str> {
+        let ordinals = floor_ordinals(&self.floors);
+        let room = self.rooms.get(number)?;
+        room.vertices
+            .iter()
+            .map(|id| self.vertices[id].get_floor())
+            .min_by_key(|floor| ordinals[floor])
+            .or(room.floor.as_deref())
+    }
+
+    /// Iterates over the rooms on `floor`. A room tagged [`RoomTag::MultiFloor`], such as a
+    /// stairwell, is on every floor that it has a vertex or an outline on, including the floor its
+    /// [`Room::outline`] is from, and any other room is on its [`Self::room_floor`].
+    pub fn rooms_on_floor<'a>(
+        &'a self,
+        floor: &'a str,
+    ) -> impl Iterator<Item = (&'a String, &'a Room)> + 'a {
+        self.rooms.iter().filter(move |(number, room)| {
+            if room.tags.contains(&RoomTag::MultiFloor) {
+                room.floor.as_deref() == Some(floor)
+                    || room.outlines.contains_key(floor)
+                    || room
+                        .vertices
+                        .iter()
+                        .any(|id| self.vertices[id].get_floor() == floor)
+            } else {
+                self.room_floor(number) == Some(floor)
+            }
+        })
+    }
+
+    /// Writes a directory of every room as CSV, with columns for the room number, floor, primary
+    /// name, all names, tags, area, and center. Rooms are sorted by floor and then by number,
+    /// compared by [`natural_cmp`] so that `9` comes before `10`. Columns with several values have
+    /// them separated by `;`. Points of interest follow the rooms, sorted the same way by ID, with
+    /// their location as their center and no area.
+    #[cfg(feature = "csv")]
+    pub fn to_csv<W: io::Write>(&self, writer: W) -> csv::Result<()> {
+        let ordinals = floor_ordinals(&self.floors);
+        let mut rooms: Vec<_> = self
+            .rooms
+            .iter()
+            .map(|(number, room)| (self.room_floor(number), number, room))
+            .collect();
+        let ordinal = |floor: Option<&str>| floor.map_or(usize::MAX, |floor| ordinals[floor]);
+        rooms.sort_unstable_by(|(a_floor, a_number, _), (b_floor, b_number, _)| {
+            ordinal(*a_floor)
+                .cmp(&ordinal(*b_floor))
+                .then_with(|| natural_cmp(a_number, b_number))
+        });
+
+        let mut writer = csv::Writer::from_writer(writer);
+        writer.write_record([
+            "number", "floor", "name", "names", "tags", "area", "center_x", "center_y",
+        ])?;
+        for (floor, number, room) in rooms {
+            let mut tags: Vec<_> = room.tags.iter().map(RoomTag::as_str).collect();
+            tags.sort_unstable();
+            writer.write_record([
+                number.as_str(),
+                floor.unwrap_or_default(),
+                room.names.first().map_or("", String::as_str),
+                &room.names.join(";"),
+                &tags.join(";"),
+                &room.area.to_string(),
+                &room.center.0.to_string(),
+                &room.center.1.to_string(),
+            ])?;
+        }
+
+        let mut pois: Vec<_> = self.pois.iter().collect();
+        let ordinal = |poi: &Poi| ordinals.get(poi.floor.as_str()).copied();
+        pois.sort_unstable_by(|(a_id, a_poi), (b_id, b_poi)| {
+            ordinal(a_poi)
+                .unwrap_or(usize::MAX)
+                .cmp(&ordinal(b_poi).unwrap_or(usize::MAX))
+                .then_with(|| natural_cmp(a_id, b_id))
+        });
+        for (id, poi) in pois {
+            let mut tags: Vec<_> = poi.tags.iter().map(RoomTag::as_str).collect();
+            tags.sort_unstable();
+            writer.write_record([
+                id.as_str(),
+                &poi.floor,
+                poi.names.first().map_or("", String::as_str),
+                &poi.names.join(";"),
+                &tags.join(";"),
+                "",
+                &poi.location.0.to_string(),
+                &poi.location.1.to_string(),
+            ])?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Number of digits after the decimal point from which rounding is skipped, since an `f64` doesn't
+/// have many more significant digits and scaling by larger powers of ten overflows
+const FULL_PRECISION_DECIMALS: usize = 15;
+
+/// Rounds every floating point number in `value`, including inside of arrays, to `decimals` digits
+/// after the decimal point. Numbers are left as they are with [`FULL_PRECISION_DECIMALS`] or more
+/// digits.
+fn round_json_numbers(value: &mut Value, decimals: usize) {
+    if decimals >= FULL_PRECISION_DECIMALS {
+        return;
+    }
+    match value {
+        Value::Number(number) if number.is_f64() => {
+            let number = number.as_f64().unwrap();
+            let scale = 10_f64.powi(decimals as i32);
+            let rounded = (number * scale).round() / scale;
+            // Numbers too large to scale have no digits after the decimal point to round anyway
+            *value = Value::from(if rounded.is_finite() { rounded } else { number });
+        }
+        Value::Array(values) => {
+            for value in values {
+                round_json_numbers(value, decimals);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Room {
     pub vertices: HashSet<String>,
     #[serde(default)]
     #[serde(skip_serializing_if = "Vec::is_empty")]
     pub names: Vec<String>,
-    pub center: (f32, f32),
-    pub outline: Vec<(f32, f32)>,
-    pub area: f32,
+    pub center: (f64, f64),
+    pub outline: Polygon,
+    /// Outlines of areas inside of `outline` which aren't part of the room, such as courtyards
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub holes: Vec<Polygon>,
+    pub area: f64,
+    /// Number of the floor whose images `outline` and `holes` are from. Map data compiled before
+    /// this was recorded has none.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub floor: Option<String>,
+    /// Outlines and holes of a room tagged [`RoomTag::MultiFloor`] on floors other than
+    /// [`Self::floor`], by floor number
+    #[serde(default)]
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub outlines: HashMap<String, (Polygon, Vec<Polygon>)>,
     #[serde(default)]
     #[serde(skip_serializing_if = "HashSet::is_empty")]
     pub tags: HashSet<RoomTag>,
+    /// Ranks the room above rooms that match a search equally well, before their areas are
+    /// compared. Rooms without a priority rank as if it were 0.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub priority: Option<i32>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub metadata: HashMap<String, Value>,
+}
+
+impl Room {
+    pub fn metadata(&self) -> &HashMap<String, Value> {
+        &self.metadata
+    }
+
+    /// Sets the metadata `key` to `value`, returning its old value, if any
+    pub fn set_metadata(&mut self, key: &str, value: Value) -> Option<Value> {
+        self.metadata.insert(key.to_owned(), value)
+    }
+
+    fn outline_area(&self) -> f64 {
+        area_with_holes(&self.outline, &self.holes)
+    }
+
+    /// Returns the outline and holes of the room on `floor`. Rooms on several floors are drawn with
+    /// `outline` and `holes` on floors that they have no outline of their own on.
+    pub fn outline_on_floor(&self, floor: &str) -> (&Polygon, &[Polygon]) {
+        match self.outlines.get(floor) {
+            Some((outline, holes)) => (outline, holes),
+            None => (&self.outline, &self.holes),
+        }
+    }
+
+    /// Determines if `point` is inside of the room, and not inside any of its holes
+    pub fn contains(&self, point: (f64, f64)) -> bool {
+        point_in_polygon_with_holes(point, &self.outline, &self.holes)
+    }
+
+    pub fn recompute_area(&mut self) {
+        self.area = self.outline_area();
+    }
+
+    /// Replaces the outline and holes of the room and recomputes its area. If the center was
+    /// computed from the old outline rather than given explicitly, it is computed again from the
+    /// new outline.
+    pub fn set_outline(&mut self, outline: Vec<(f64, f64)>, holes: Vec<Vec<(f64, f64)>>) {
+        let center_was_computed = self.center == computed_center(&self.outline, &self.holes);
+        self.outline = outline.into();
+        self.holes = holes.into_iter().map(Polygon::from).collect();
+        if center_was_computed {
+            self.center = computed_center(&self.outline, &self.holes);
+        }
+        self.recompute_area();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::path::Path;
+
+    use super::*;
+    use crate::map_data::uncompiled;
+    use crate::svg_room::SvgRoomConfig;
+
+    fn compiled() -> MapData {
+        let json = fs::read_to_string("tests/json/compile.json").unwrap();
+        uncompiled::MapData::new(&json)
+            .unwrap()
+            .compile(Path::new("tests/json"))
+            .unwrap()
+    }
+
+    #[test]
+    fn rooms_sharing_walls() {
+        let json = fs::read_to_string("tests/json/adjacent_rooms.json").unwrap();
+        let mut map_data: MapData = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(vec!["102"], map_data.adjacent_rooms("101"));
+        // A wall drawn half a unit thick separates 102 and 103
+        assert_eq!(vec!["101", "103"], map_data.adjacent_rooms("102"));
+        assert_eq!(vec!["102"], map_data.adjacent_rooms("103"));
+        // Across a corridor from 103
+        assert_eq!(Vec::<&str>::new(), map_data.adjacent_rooms("104"));
+        // The L-shaped room wraps around the top and right of 106, and only touches 107 at a
+        // corner
+        assert_eq!(vec!["106"], map_data.adjacent_rooms("105"));
+        assert_eq!(vec!["105"], map_data.adjacent_rooms("106"));
+        assert_eq!(Vec::<&str>::new(), map_data.adjacent_rooms("107"));
+        // On another floor, right on top of 101
+        assert_eq!(Vec::<&str>::new(), map_data.adjacent_rooms("201"));
+        assert_eq!(Vec::<&str>::new(), map_data.adjacent_rooms("999"));
+
+        // Moved underneath 101, 102, and 103
+        map_data.rooms.get_mut("104").unwrap().outline =
+            vec![(0., -10.), (30., -10.), (30., 0.), (0., 0.)].into();
+        assert_eq!(Vec::<&str>::new(), map_data.adjacent_rooms("104"));
+        map_data.clear_room_adjacency();
+        assert_eq!(vec!["101", "102", "103"], map_data.adjacent_rooms("104"));
+    }
+
+    #[test]
+    fn from_parts() {
+        let map_data = compiled();
+        let mut rebuilt = MapData::from_parts(
+            map_data.floors.clone(),
+            map_data.vertices.clone(),
+            map_data.edges.clone(),
+            map_data.rooms.clone(),
+        );
+        rebuilt.pois = map_data.pois.clone();
+        assert_eq!(map_data, rebuilt);
+        assert_eq!(map_data.adjacency(), rebuilt.adjacency());
+    }
+
+    #[test]
+    fn clear_caches() {
+        let mut map_data = compiled();
+        assert_eq!(1, map_data.rooms_with_tag(&RoomTag::Closed).len());
+        assert!(!map_data.adjacency().values().all(Vec::is_empty));
+        map_data
+            .rooms
+            .get_mut("107")
+            .unwrap()
+            .tags
+            .insert(RoomTag::Closed);
+        map_data.edges.clear();
+        assert!(!map_data.adjacency().values().all(Vec::is_empty));
+
+        map_data.clear_caches();
+        assert_eq!(2, map_data.rooms_with_tag(&RoomTag::Closed).len());
+        assert!(map_data.adjacency().values().all(Vec::is_empty));
+    }
+
+    #[test]
+    fn find_by_tag() {
+        let mut map_data = compiled();
+        let numbers = |rooms: Vec<(&str, &Room)>| -> Vec<String> {
+            rooms
+                .into_iter()
+                .map(|(number, _room)| number.to_owned())
+                .collect()
+        };
+        assert_eq!(
+            vec!["106"],
+            numbers(map_data.rooms_with_tag(&RoomTag::Closed))
+        );
+        assert!(map_data.rooms_with_tag(&RoomTag::Aed).is_empty());
+        let stairs: Vec<_> = map_data
+            .vertices_with_tag(&VertexTag::Stairs)
+            .into_iter()
+            .map(|(id, _vertex)| id)
+            .collect();
+        assert_eq!(vec!["a"], stairs);
+        assert!(map_data.vertices_with_tag(&VertexTag::Elevator).is_empty());
+
+        let room = map_data.rooms.get_mut("107").unwrap();
+        room.tags.insert(RoomTag::Aed);
+        room.tags.insert(RoomTag::Closed);
+        assert_eq!(
+            vec!["106"],
+            numbers(map_data.rooms_with_tag(&RoomTag::Closed))
+        );
+        map_data.clear_tag_index();
+        assert_eq!(
+            vec!["106", "107"],
+            numbers(map_data.rooms_with_tag(&RoomTag::Closed))
+        );
+        let safety = [RoomTag::Aed, RoomTag::BleedControl, RoomTag::Closed];
+        assert_eq!(
+            vec!["106", "107"],
+            numbers(map_data.rooms_with_any(&safety))
+        );
+        assert_eq!(
+            vec!["107"],
+            numbers(map_data.rooms_with_any(&[RoomTag::Aed]))
+        );
+
+        // Removing rooms and vertices without clearing the tags doesn't panic
+        map_data.rooms.remove("107");
+        map_data.vertices.remove("a");
+        assert_eq!(
+            vec!["106"],
+            numbers(map_data.rooms_with_tag(&RoomTag::Closed))
+        );
+        assert!(map_data.vertices_with_tag(&VertexTag::Stairs).is_empty());
+    }
+
+    #[test]
+    fn recompute_stale_areas() {
+        let mut map_data = compiled();
+        assert!(map_data.stale_areas(0.01).is_empty());
+
+        map_data.rooms.get_mut("106").unwrap().area = 500.;
+        map_data.rooms.get_mut("107").unwrap().area += 0.5;
+        assert_eq!(vec![("106", 500., 600.)], map_data.stale_areas(1.));
+        assert_eq!(2, map_data.stale_areas(0.1).len());
+
+        map_data.recompute_derived();
+        assert!(map_data.stale_areas(0.).is_empty());
+        assert_eq!(600., map_data.rooms["106"].area);
+    }
+
+    #[test]
+    fn computed_centers_follow_outline() {
+        let mut map_data = compiled();
+        let square = vec![(0., 0.), (0., 10.), (10., 10.), (10., 0.)];
+
+        let room = map_data.rooms.get_mut("106").unwrap();
+        room.set_outline(square.clone(), vec![]);
+        assert_eq!((5., 5.), room.center);
+        assert_eq!(100., room.area);
+
+        let room = map_data.rooms.get_mut("107").unwrap();
+        room.center = (1., 2.);
+        room.set_outline(square, vec![]);
+        assert_eq!((1., 2.), room.center);
+        assert_eq!(100., room.area);
+    }
+
+    #[test]
+    fn unnamed_rooms_round_trip() {
+        let mut map_data = compiled();
+        let room = map_data.rooms.get_mut("106").unwrap();
+        room.names.clear();
+        room.tags.clear();
+        room.metadata.clear();
+
+        // Empty fields are left out of the output, so they have to default when read back
+        let json = serde_json::to_string(&map_data.rooms["106"]).unwrap();
+        assert!(!json.contains("names"), "{}", json);
+        assert!(!json.contains("tags"), "{}", json);
+        assert!(!json.contains("holes"), "{}", json);
+        let json = serde_json::to_string(&map_data).unwrap();
+        let read: MapData = serde_json::from_str(&json).unwrap();
+        assert_eq!(map_data, read);
+    }
+
+    #[test]
+    fn serialize_with_precision() {
+        let mut map_data = compiled();
+        let room = map_data.rooms.get_mut("107").unwrap();
+        room.outline.points_mut()[0] = (50.123456, 90.987654);
+        room.center = (1. / 3., 2. / 3.);
+        room.metadata
+            .insert("ratio".to_owned(), Value::from(0.123456));
+
+        let json = map_data.to_json_with_precision(2).unwrap();
+        assert!(json.contains("[50.12,90.99]"), "{}", json);
+        assert!(json.contains(r#""center":[0.33,0.67]"#), "{}", json);
+        // Metadata isn't geometry, so it keeps its precision
+        assert!(json.contains("0.123456"), "{}", json);
+
+        let read: MapData = serde_json::from_str(&json).unwrap();
+        let close =
+            |a: (f64, f64), b: (f64, f64)| (a.0 - b.0).abs() <= 0.005 && (a.1 - b.1).abs() <= 0.005;
+        for (number, room) in &map_data.rooms {
+            let read_room = &read.rooms[number];
+            assert!(close(room.center, read_room.center));
+            assert_eq!(room.outline.len(), read_room.outline.len());
+            assert!(room
+                .outline
+                .iter()
+                .zip(read_room.outline.iter())
+                .all(|(a, b)| close(*a, *b)));
+            assert!((room.area - read_room.area).abs() <= 0.005);
+        }
+        // Rounding happens only when writing
+        assert_eq!(50.123456, map_data.rooms["107"].outline[0].0);
+
+        // Precisions beyond what an `f64` holds keep every number as it is
+        for decimals in [15, 400, usize::MAX] {
+            let json = map_data.to_json_with_precision(decimals).unwrap();
+            assert!(!json.contains("null"), "{}", json);
+            assert_eq!(map_data, serde_json::from_str::<MapData>(&json).unwrap());
+        }
+        map_data.rooms.get_mut("107").unwrap().area = 1e300;
+        let json = map_data.to_json_with_precision(10).unwrap();
+        assert_eq!(
+            1e300,
+            serde_json::from_str::<MapData>(&json).unwrap().rooms["107"].area
+        );
+    }
+
+    #[test]
+    fn floor_bounds() {
+        let map_data = compiled();
+        assert_eq!(Some((0., 0., 100., 100.)), map_data.floors[0].get_bounds());
+
+        let json = serde_json::to_string(&map_data).unwrap();
+        let read: MapData = serde_json::from_str(&json).unwrap();
+        assert_eq!(map_data.floors, read.floors);
+        // Compiled map data from before floors had bounds still loads
+        let json = json.replace(r#","bounds":[0.0,0.0,100.0,100.0]"#, "");
+        let read: MapData = serde_json::from_str(&json).unwrap();
+        assert_eq!(None, read.floors[0].get_bounds());
+    }
+
+    #[test]
+    fn coordinate_systems() {
+        let map_data = compiled();
+        assert_eq!(CoordinateSystem::YUpWithOffsets, map_data.coordinate_system);
+        assert!(map_data.rooms["106"].outline.points().contains(&(10., 90.)));
+
+        let json = fs::read_to_string("tests/json/compile.json").unwrap();
+        let options = uncompiled::CompileOptions {
+            svg_room: SvgRoomConfig {
+                coordinate_system: CoordinateSystem::SvgNative,
+                ..SvgRoomConfig::default()
+            },
+            ..uncompiled::CompileOptions::default()
+        };
+        // Offsets would put vertices and outlines in different places
+        let mut uncompiled = uncompiled::MapData::new(&json).unwrap();
+        let error = uncompiled
+            .clone()
+            .compile_with_options(Path::new("tests/json"), &options)
+            .unwrap_err();
+        assert_eq!(
+            Some(&uncompiled::CompileError::OffsetsWithSvgNative(
+                "1".to_owned()
+            )),
+            error.downcast_ref()
+        );
+        let floor = &uncompiled.floors[0];
+        uncompiled.floors[0] = Floor::new(
+            floor.get_number().to_owned(),
+            floor.get_image().clone(),
+            (0., 0.),
+        );
+        let svg_native = uncompiled
+            .compile_with_options(Path::new("tests/json"), &options)
+            .unwrap();
+        assert_eq!(CoordinateSystem::SvgNative, svg_native.coordinate_system);
+        assert!(svg_native.rooms["106"]
+            .outline
+            .points()
+            .contains(&(10., 10.)));
+        assert_eq!(map_data.rooms["106"].area, svg_native.rooms["106"].area);
+
+        let json = serde_json::to_string(&svg_native).unwrap();
+        assert!(
+            json.contains(r#""coordinate_system":"svg-native""#),
+            "{}",
+            json
+        );
+        // Compiled map data from before the coordinate system was recorded used a y-axis pointing up
+        let json = json.replace(r#","coordinate_system":"svg-native""#, "");
+        let read: MapData = serde_json::from_str(&json).unwrap();
+        assert_eq!(CoordinateSystem::YUpWithOffsets, read.coordinate_system);
+    }
+
+    #[test]
+    #[cfg(feature = "csv")]
+    fn room_directory_csv() {
+        let mut map_data = compiled();
+        map_data.rooms.get_mut("106").unwrap().names =
+            vec!["Café, \"Room\" 106".to_owned(), "Lounge".to_owned()];
+        let mut fountain = Poi::new("1".to_owned(), (30., 40.5));
+        fountain.names = vec!["Water Fountain".to_owned()];
+        fountain.tags.insert(RoomTag::Wf);
+        map_data.pois.insert("fountain-1".to_owned(), fountain);
+
+        let mut csv = vec![];
+        map_data.to_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(
+            vec![
+                "number,floor,name,names,tags,area,center_x,center_y",
+                r#"106,1,"Café, ""Room"" 106","Café, ""Room"" 106;Lounge",closed;quiet-room,600,25,80"#,
+                "107,1,library,library,,1600,70,70",
+                "fountain-1,1,Water Fountain,Water Fountain,wf,,30,40.5",
+            ],
+            lines
+        );
+    }
+
+    #[test]
+    fn room_distance_matrix() {
+        let mut map_data = compiled();
+        let matrix = map_data.distance_matrix(&RoutingOptions::default());
+        assert_eq!(&["106".to_owned(), "107".to_owned()], matrix.rooms());
+        assert_eq!(Some(0.), matrix.distance("106", "106"));
+        assert_eq!(Some(45f64.hypot(10.)), matrix.distance("106", "107"));
+        assert_eq!(matrix.distance("106", "107"), matrix.distance("107", "106"));
+        assert_eq!(None, matrix.distance("106", "108"));
+
+        map_data.edges.clear();
+        let matrix = map_data.distance_matrix(&RoutingOptions::default());
+        assert_eq!(Some(f64::INFINITY), matrix.distance("106", "107"));
+        assert_eq!(Some(0.), matrix.distance("107", "107"));
+
+        // Rooms are found in the order they're sorted in, which isn't lexicographic
+        let room = map_data.rooms.remove("106").unwrap();
+        map_data.rooms.clear();
+        for number in ["9", "10", "101"] {
+            map_data.rooms.insert(number.to_owned(), room.clone());
+        }
+        let matrix = map_data.distance_matrix(&RoutingOptions::default());
+        assert_eq!(
+            &["9".to_owned(), "10".to_owned(), "101".to_owned()],
+            matrix.rooms()
+        );
+        for from in ["9", "10", "101"] {
+            for to in ["9", "10", "101"] {
+                assert_eq!(Some(0.), matrix.distance(from, to), "{} to {}", from, to);
+            }
+        }
+    }
+
+    #[test]
+    fn rooms_reachable_within() {
+        let map_data = compiled();
+        let options = RoutingOptions::default();
+        assert_eq!(
+            vec![("106".to_owned(), 0.), ("107".to_owned(), 45f64.hypot(10.))],
+            map_data.reachable_within("a", 50., &options)
+        );
+        assert_eq!(
+            vec![("107".to_owned(), 0.)],
+            map_data.reachable_within("b", 40., &options)
+        );
+
+        // Vertex `a` is a stairwell
+        let options = RoutingOptions {
+            excluded_vertex_tags: [VertexTag::Stairs].into(),
+            ..RoutingOptions::default()
+        };
+        assert_eq!(
+            vec![("107".to_owned(), 0.)],
+            map_data.reachable_within("b", 50., &options)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "csv")]
+    fn distance_matrix_csv() {
+        let mut map_data = compiled();
+        map_data.edges.clear();
+        let mut csv = vec![];
+        map_data
+            .distance_matrix(&RoutingOptions::default())
+            .to_csv(&mut csv)
+            .unwrap();
+        assert_eq!(
+            "room,106,107\n106,0,inf\n107,inf,0\n",
+            String::from_utf8(csv).unwrap()
+        );
+    }
+
+    #[test]
+    fn graph_adjacency() {
+        let json = fs::read_to_string("tests/json/escalators.json").unwrap();
+        let mut map_data: MapData = serde_json::from_str(&json).unwrap();
+
+        let mut neighbors: Vec<_> = map_data.neighbors("hall-1").to_vec();
+        neighbors.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            vec![("down-1".to_owned(), 30.), ("up-1".to_owned(), 10.)],
+            neighbors
+        );
+        // The up escalator can't be taken down
+        assert_eq!(1, map_data.adjacency()["up-2"].len());
+        assert!(map_data.neighbors("nowhere").is_empty());
+        assert_eq!(2, map_data.edges_on_floor("1").count());
+        assert_eq!(0, map_data.edges_on_floor("3").count());
+
+        map_data
+            .edges
+            .push(Edge::new("up-2".to_owned(), "down-2".to_owned(), true));
+        assert_eq!(1, map_data.neighbors("up-2").len());
+        map_data.clear_graph();
+        assert_eq!(2, map_data.neighbors("up-2").len());
+        // The new edge is directed
+        assert!(map_data
+            .neighbors("down-2")
+            .iter()
+            .all(|(to, _)| to != "up-2"));
+    }
+
+    #[test]
+    fn rooms_with_holes() {
+        let json = fs::read_to_string("tests/json/courtyard.json").unwrap();
+        let map_data = uncompiled::MapData::new(&json)
+            .unwrap()
+            .compile(Path::new("tests/json"))
+            .unwrap();
+        let room = &map_data.rooms["110"];
+
+        assert_eq!(4, room.outline.len());
+        assert_eq!(
+            vec![Polygon::new(vec![
+                (40., 60.),
+                (60., 60.),
+                (60., 40.),
+                (40., 40.)
+            ])],
+            room.holes
+        );
+        assert_eq!(6400. - 400., room.area);
+        // The centroid is in the courtyard, so the center is moved into the room
+        assert!(room.contains(room.center), "{:?}", room.center);
+        assert!(!room.contains((50., 50.)));
+
+        let json = serde_json::to_string(&map_data).unwrap();
+        assert!(json.contains(r#""holes":[[[40.0,60.0]"#));
+        let read: MapData = serde_json::from_str(&json).unwrap();
+        assert_eq!(map_data, read);
+        // Rooms from before holes were supported still load
+        assert!(compiled().rooms["106"].holes.is_empty());
+    }
+
+    fn subset_fixture() -> MapData {
+        serde_json::from_str(&fs::read_to_string("tests/json/subset.json").unwrap()).unwrap()
+    }
+
+    /// Checks that everything referred to in the map data is in it, and that it reads back the
+    /// same from JSON
+    fn assert_valid(map_data: &MapData) {
+        for vertex in map_data.vertices.values() {
+            assert!(map_data.has_floor(vertex.get_floor()), "{:?}", vertex);
+        }
+        for edge in &map_data.edges {
+            assert!(
+                map_data.vertices.contains_key(edge.get_from()),
+                "{:?}",
+                edge
+            );
+            assert!(map_data.vertices.contains_key(edge.get_to()), "{:?}", edge);
+        }
+        for (number, room) in &map_data.rooms {
+            for vertex in &room.vertices {
+                assert!(
+                    map_data.vertices.contains_key(vertex),
+                    "{} {}",
+                    number,
+                    vertex
+                );
+            }
+        }
+        for (id, poi) in &map_data.pois {
+            assert!(map_data.has_floor(&poi.floor), "{}", id);
+            if let Some(vertex) = &poi.vertex {
+                assert!(map_data.vertices.contains_key(vertex), "{} {}", id, vertex);
+            }
+        }
+        let json = serde_json::to_string(map_data).unwrap();
+        let read: MapData = serde_json::from_str(&json).unwrap();
+        assert_eq!(map_data, &read);
+    }
+
+    fn sorted_keys<V>(map: &HashMap<String, V>) -> Vec<&str> {
+        let mut keys: Vec<_> = map.keys().map(String::as_str).collect();
+        keys.sort_unstable();
+        keys
+    }
+
+    #[test]
+    fn subset_floors() {
+        let map_data = subset_fixture();
+
+        let subset = map_data.subset_floors_with_report(&["1"]);
+        assert_eq!(vec!["stairwell"], subset.partial_rooms);
+        let first = subset.map_data;
+        assert_valid(&first);
+        assert_eq!(vec!["1"], first.floor_numbers());
+        assert_eq!(
+            vec!["east-1", "hall-1", "stairs-1"],
+            sorted_keys(&first.vertices)
+        );
+        assert_eq!(vec!["101", "104"], sorted_keys(&first.rooms));
+        assert_eq!(2, first.edges.len());
+        assert_eq!(Some("east-1"), first.pois["fountain-1"].vertex.as_deref());
+        assert_eq!(vec!["fountain-1"], sorted_keys(&first.pois));
+
+        // The edges to the floors above and below are dropped
+        let second = map_data.subset_floors(&["2", "unknown"]);
+        assert_valid(&second);
+        assert_eq!(vec!["2"], second.floor_numbers());
+        assert_eq!(vec!["hall-2", "stairs-2"], sorted_keys(&second.vertices));
+        assert_eq!(vec!["201"], sorted_keys(&second.rooms));
+        assert_eq!(1, second.edges.len());
+
+        let all = map_data.subset_floors_with_report(&["3", "1", "2"]);
+        assert!(all.partial_rooms.is_empty());
+        assert_eq!(map_data, all.map_data);
+        // Rooms without vertices are on the floor of their outline
+        let mut map_data = map_data;
+        let mut unrouted = map_data.rooms["101"].clone();
+        unrouted.vertices.clear();
+        unrouted.tags.insert(RoomTag::NoAccess);
+        unrouted.floor = Some("1".to_owned());
+        map_data.rooms.insert("102".to_owned(), unrouted);
+        let first = map_data.subset_floors(&["1"]);
+        assert_valid(&first);
+        assert_eq!(vec!["101", "102", "104"], sorted_keys(&first.rooms));
+        let second = map_data.subset_floors(&["2"]);
+        assert_eq!(vec!["201"], sorted_keys(&second.rooms));
+    }
+
+    #[test]
+    fn subset_region() {
+        let mut map_data = subset_fixture();
+        map_data.index = Some(SearchIndex::build(&map_data));
+        let bounds = BoundingBox::new(Vector2::new(35., 0.), Vector2::new(35., 20.));
+
+        let subset = map_data.subset_region_with_report("1", &bounds);
+        assert_eq!(vec!["stairwell"], subset.partial_rooms);
+        let wing = subset.map_data;
+        assert_valid(&wing);
+        assert_eq!(vec!["104"], sorted_keys(&wing.rooms));
+        assert_eq!(vec!["east-1", "stairs-1"], sorted_keys(&wing.vertices));
+        assert_eq!(vec!["fountain-1"], sorted_keys(&wing.pois));
+        assert_eq!(
+            vec![("east-1", "stairs-1")],
+            wing.edges
+                .iter()
+                .map(|edge| (edge.get_from(), edge.get_to()))
+                .collect::<Vec<_>>()
+        );
+        // The search index only finds rooms in the subset
+        assert_eq!(Some(SearchIndex::build(&wing)), wing.index);
+
+        // The room's vertex is kept even though it's outside of the region
+        let bounds = BoundingBox::new(Vector2::new(0., 0.), Vector2::new(5., 5.));
+        let corner = map_data.subset_region("1", &bounds);
+        assert_valid(&corner);
+        assert_eq!(vec!["101"], sorted_keys(&corner.rooms));
+        assert_eq!(vec!["hall-1"], sorted_keys(&corner.vertices));
+        assert!(corner.pois.is_empty());
+    }
 }
@@ -0,0 +1,432 @@
+//! Changes to compiled map data that are checked before any of them are made, for editors that
+//! change map data in place instead of compiling it again
+
+use std::collections::{HashMap, HashSet};
+
+use crate::map_data::compiled::MapData;
+use crate::map_data::uncompiled::MapDataError;
+use crate::map_data::{Edge, RoomTag, VertexTag};
+
+/// One change made through a [`MapDataEditor`]
+#[derive(Debug, Clone, PartialEq)]
+enum Change {
+    RoomCenter {
+        room: String,
+        center: (f64, f64),
+    },
+    RoomVertex {
+        room: String,
+        vertex: String,
+    },
+    Edge(Edge),
+    RoomTags {
+        room: String,
+        tags: HashSet<RoomTag>,
+    },
+    VertexTags {
+        vertex: String,
+        tags: HashSet<VertexTag>,
+    },
+}
+
+/// Collects changes to compiled map data for [`MapData::edit`], which checks them all before
+/// making any of them
+#[derive(Debug)]
+pub struct MapDataEditor<'m> {
+    map_data: &'m MapData,
+    changes: Vec<Change>,
+}
+
+impl<'m> MapDataEditor<'m> {
+    pub(super) fn new(map_data: &'m MapData) -> Self {
+        Self {
+            map_data,
+            changes: vec![],
+        }
+    }
+
+    /// Returns the map data as it was before the edit, since changes aren't made until the edit
+    /// is done
+    pub fn map_data(&self) -> &MapData {
+        self.map_data
+    }
+
+    /// Moves the center of `room`, such as where its label is drawn
+    pub fn set_room_center(&mut self, room: &str, center: (f64, f64)) -> &mut Self {
+        self.changes.push(Change::RoomCenter {
+            room: room.to_owned(),
+            center,
+        });
+        self
+    }
+
+    /// Adds `vertex` to the vertices that routes to `room` can end at. Unless `room` is tagged
+    /// [`RoomTag::MultiFloor`], the vertex has to be on the floor the room is on.
+    pub fn add_room_vertex(&mut self, room: &str, vertex: &str) -> &mut Self {
+        self.changes.push(Change::RoomVertex {
+            room: room.to_owned(),
+            vertex: vertex.to_owned(),
+        });
+        self
+    }
+
+    /// Adds `edge`, which can't make a pair of vertices both directed and undirected
+    pub fn add_edge(&mut self, edge: Edge) -> &mut Self {
+        self.changes.push(Change::Edge(edge));
+        self
+    }
+
+    /// Replaces the tags of `room`
+    pub fn retag_room(&mut self, room: &str, tags: HashSet<RoomTag>) -> &mut Self {
+        self.changes.push(Change::RoomTags {
+            room: room.to_owned(),
+            tags,
+        });
+        self
+    }
+
+    /// Replaces the tags of `vertex`
+    pub fn retag_vertex(&mut self, vertex: &str, tags: HashSet<VertexTag>) -> &mut Self {
+        self.changes.push(Change::VertexTags {
+            vertex: vertex.to_owned(),
+            tags,
+        });
+        self
+    }
+
+    /// Checks the changes against the map data and each other, looking only at the rooms,
+    /// vertices, and pairs of vertices they touch
+    fn verify(&self) -> Result<(), MapDataError> {
+        let map_data = self.map_data;
+        let room = |room: &str| {
+            if map_data.rooms.contains_key(room) {
+                Ok(())
+            } else {
+                Err(MapDataError::UndefinedRoomNumber(room.to_owned()))
+            }
+        };
+        let vertex = |vertex: &str| {
+            if map_data.vertices.contains_key(vertex) {
+                Ok(())
+            } else {
+                Err(MapDataError::UndefinedVertexId(vertex.to_owned()))
+            }
+        };
+
+        // Floors of the rooms given new vertices, which start out as where the rooms already are
+        let mut room_floors: HashMap<&str, Option<&str>> = HashMap::new();
+        // Whether each pair of vertices touched by a new edge has undirected and directed edges,
+        // by the pair sorted by ID
+        let mut pairs: HashMap<(&str, &str), (bool, bool)> = HashMap::new();
+        for change in &self.changes {
+            match change {
+                Change::RoomCenter {
+                    room: number,
+                    center,
+                } => {
+                    room(number)?;
+                    if !(center.0.is_finite() && center.1.is_finite()) {
+                        return Err(MapDataError::NonFiniteCenter(number.clone()));
+                    }
+                }
+                Change::RoomVertex {
+                    room: number,
+                    vertex: id,
+                } => {
+                    room(number)?;
+                    vertex(id)?;
+                    if map_data.rooms[number].tags.contains(&RoomTag::MultiFloor) {
+                        continue;
+                    }
+                    let vertex_floor = map_data.vertices[id].get_floor();
+                    let room_floor = room_floors
+                        .entry(number)
+                        .or_insert_with(|| map_data.room_floor(number))
+                        .get_or_insert(vertex_floor);
+                    if *room_floor != vertex_floor {
+                        return Err(MapDataError::RoomVertexOnOtherFloor {
+                            room: number.clone(),
+                            vertex: id.clone(),
+                        });
+                    }
+                }
+                Change::Edge(edge) => {
+                    vertex(edge.get_from())?;
+                    vertex(edge.get_to())?;
+                    pairs.insert(vertex_pair(edge), (false, false));
+                }
+                Change::RoomTags { room: number, .. } => room(number)?,
+                Change::VertexTags { vertex: id, .. } => vertex(id)?,
+            }
+        }
+
+        let new_edges = self.changes.iter().filter_map(|change| match change {
+            Change::Edge(edge) => Some(edge),
+            _ => None,
+        });
+        for edge in map_data.edges.iter().chain(new_edges) {
+            if let Some((undirected, directed)) = pairs.get_mut(&vertex_pair(edge)) {
+                *undirected |= !edge.is_directed();
+                *directed |= edge.is_directed();
+            }
+        }
+        let mixed = pairs
+            .into_iter()
+            .filter(|(_pair, (undirected, directed))| *undirected && *directed)
+            .map(|(pair, _)| pair)
+            .min();
+        match mixed {
+            Some((from, to)) => Err(MapDataError::MixedEdgeDirections {
+                from: from.to_owned(),
+                to: to.to_owned(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks the changes, returning them to be made if they're all valid
+    pub(super) fn finish(self) -> Result<Changes, MapDataError> {
+        self.verify()?;
+        Ok(Changes(self.changes))
+    }
+}
+
+/// Changes from a [`MapDataEditor`] that have been checked
+pub(super) struct Changes(Vec<Change>);
+
+impl Changes {
+    /// Makes the changes, clearing whatever was derived from what they change
+    pub(super) fn apply(self, map_data: &mut MapData) {
+        let (mut edges_changed, mut tags_changed, mut floors_changed) = (false, false, false);
+        for change in self.0 {
+            match change {
+                Change::RoomCenter { room, center } => {
+                    map_data.rooms.get_mut(&room).unwrap().center = center;
+                }
+                Change::RoomVertex { room, vertex } => {
+                    map_data
+                        .rooms
+                        .get_mut(&room)
+                        .unwrap()
+                        .vertices
+                        .insert(vertex);
+                    // Rooms are grouped by the floors of their vertices to find adjacent ones
+                    floors_changed = true;
+                }
+                Change::Edge(edge) => {
+                    map_data.edges.push(edge);
+                    edges_changed = true;
+                }
+                Change::RoomTags { room, tags } => {
+                    map_data.rooms.get_mut(&room).unwrap().tags = tags;
+                    tags_changed = true;
+                }
+                Change::VertexTags { vertex, tags } => {
+                    let old = map_data.vertices.remove(&vertex).unwrap();
+                    map_data.vertices.insert(vertex, old.with_tags(tags));
+                    // Up and down tags change which way edges between floors can be traveled
+                    edges_changed = true;
+                    tags_changed = true;
+                }
+            }
+        }
+        if edges_changed {
+            map_data.clear_graph();
+        }
+        if tags_changed {
+            map_data.clear_tag_index();
+        }
+        if floors_changed {
+            map_data.clear_room_adjacency();
+        }
+    }
+}
+
+/// Returns the IDs of the vertices of `edge`, sorted
+fn vertex_pair(edge: &Edge) -> (&str, &str) {
+    let (from, to) = (edge.get_from(), edge.get_to());
+    (from.min(to), from.max(to))
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::*;
+
+    fn closed_room() -> MapData {
+        let json = fs::read_to_string("tests/json/closed_room.json").unwrap();
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn apply_changes() {
+        let mut map_data = closed_room();
+        // Build the graph and tag index, so that the edit has to clear them
+        assert_eq!(
+            Some(3),
+            map_data
+                .graph()
+                .shortest_path("hall-west", "corner-east")
+                .map(|route| route.vertices.len())
+        );
+        assert_eq!(1, map_data.rooms_with_tag(&RoomTag::Closed).len());
+
+        map_data
+            .edit(|editor| {
+                editor
+                    .set_room_center("102", (45., -25.))
+                    .add_room_vertex("102", "hall-east")
+                    .add_edge(Edge::new(
+                        "hall-west".to_owned(),
+                        "corner-east".to_owned(),
+                        true,
+                    ))
+                    .retag_room("101", HashSet::new())
+                    .retag_room("102", [RoomTag::Closed].into());
+            })
+            .unwrap();
+
+        let room = &map_data.rooms["102"];
+        assert_eq!((45., -25.), room.center);
+        assert!(room.vertices.contains("hall-east"));
+        assert_eq!(
+            vec!["hall-west", "corner-east"],
+            map_data
+                .graph()
+                .shortest_path("hall-west", "corner-east")
+                .unwrap()
+                .vertices
+        );
+        assert_eq!(
+            vec!["102"],
+            map_data
+                .rooms_with_tag(&RoomTag::Closed)
+                .into_iter()
+                .map(|(number, _room)| number)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn reject_all_changes_if_any_is_invalid() {
+        let mut map_data = closed_room();
+        let original = closed_room();
+
+        let error = map_data
+            .edit(|editor| {
+                editor
+                    .set_room_center("102", (45., -25.))
+                    .add_room_vertex("102", "nowhere");
+            })
+            .unwrap_err();
+        assert!(matches!(error, MapDataError::UndefinedVertexId(id) if id == "nowhere"));
+        assert_eq!(original, map_data);
+
+        let error = map_data
+            .edit(|editor| {
+                editor
+                    .retag_vertex("hall-west", [VertexTag::Stairs].into())
+                    .set_room_center("103", (0., 0.));
+            })
+            .unwrap_err();
+        assert!(matches!(error, MapDataError::UndefinedRoomNumber(number) if number == "103"));
+        let error = map_data
+            .edit(|editor| {
+                editor.set_room_center("101", (f64::NAN, 0.));
+            })
+            .unwrap_err();
+        assert!(matches!(error, MapDataError::NonFiniteCenter(number) if number == "101"));
+        assert_eq!(original, map_data);
+    }
+
+    #[test]
+    fn reject_mixed_edge_directions() {
+        let mut map_data = closed_room();
+        // Against an edge already in the map data
+        let error = map_data
+            .edit(|editor| {
+                editor.add_edge(Edge::new(
+                    "door-101".to_owned(),
+                    "hall-west".to_owned(),
+                    true,
+                ));
+            })
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            MapDataError::MixedEdgeDirections { from, to } if (from.as_str(), to.as_str()) == ("door-101", "hall-west")
+        ));
+
+        // Against another new edge
+        let edge = |directed| Edge::new("corner-west".to_owned(), "hall-east".to_owned(), directed);
+        assert!(map_data
+            .edit(|editor| {
+                editor.add_edge(edge(true)).add_edge(edge(false));
+            })
+            .is_err());
+        assert_eq!(closed_room(), map_data);
+
+        map_data
+            .edit(|editor| {
+                editor.add_edge(edge(true)).add_edge(edge(true));
+            })
+            .unwrap();
+        assert_eq!(closed_room().edges.len() + 2, map_data.edges.len());
+    }
+
+    #[test]
+    fn room_vertices_on_floors() {
+        let json = fs::read_to_string("tests/json/closed_room.json").unwrap();
+        let mut json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        json["floors"].as_array_mut().unwrap().push(
+            serde_json::json!({ "number": "2", "image": "../svg/rooms.svg", "offsets": [0, 100] }),
+        );
+        json["vertices"]["upstairs"] = serde_json::json!({ "floor": "2", "location": [0, 0] });
+        json["rooms"]["103"] = serde_json::json!({
+            "outline": [[10, 10], [30, 10], [30, 30], [10, 30]],
+            "center": [20, 20],
+            "vertices": [],
+            "area": 400
+        });
+        json["rooms"]["102"]["tags"] = serde_json::json!(["multi-floor"]);
+        let mut map_data: MapData = serde_json::from_value(json).unwrap();
+        let original = map_data.clone();
+
+        let error = map_data
+            .edit(|editor| {
+                editor.add_room_vertex("101", "upstairs");
+            })
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            MapDataError::RoomVertexOnOtherFloor { room, vertex }
+                if room == "101" && vertex == "upstairs"
+        ));
+        // The first vertex of a room without any decides its floor
+        assert!(map_data
+            .edit(|editor| {
+                editor
+                    .add_room_vertex("103", "corner-west")
+                    .add_room_vertex("103", "upstairs");
+            })
+            .is_err());
+        assert_eq!(original, map_data);
+
+        // Rooms on several floors can have vertices on any of them
+        map_data
+            .edit(|editor| {
+                editor.add_room_vertex("102", "upstairs");
+            })
+            .unwrap();
+
+        // Without a floor, the room isn't next to anything until it's given a vertex
+        assert!(map_data.adjacent_rooms("103").is_empty());
+        map_data
+            .edit(|editor| {
+                editor.add_room_vertex("103", "corner-west");
+            })
+            .unwrap();
+        assert_eq!(vec!["101"], map_data.adjacent_rooms("103"));
+    }
+}
@@ -0,0 +1,276 @@
+//! Compiled map data shared between threads, which can be replaced while it's being read
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+
+use crate::map_data::compiled::MapData;
+use crate::routing::RoutingGraph;
+use crate::search::SearchIndex;
+
+type Derived = Arc<dyn Any + Send + Sync>;
+type ReplaceHook = Box<dyn Fn(&Arc<MapData>) + Send + Sync>;
+
+/// A version of the map data along with everything derived from it so far
+struct Loaded {
+    map_data: Arc<MapData>,
+    derived: Mutex<HashMap<TypeId, Derived>>,
+}
+
+impl Loaded {
+    fn new(map_data: Arc<MapData>) -> Self {
+        Self {
+            map_data,
+            derived: Mutex::default(),
+        }
+    }
+}
+
+/// Compiled map data that's read by many threads at once, such as request handlers, and
+/// occasionally replaced, such as when the map is recompiled. Readers keep the version they loaded
+/// until they're done with it, even if it's replaced in the meantime.
+///
+/// Indexes derived from the map data, such as the [`SearchIndex`], are built the first time
+/// they're asked for and cleared when the map data is replaced, so they're rebuilt lazily for the
+/// new version. Values the map data keeps itself, such as the routing graph, aren't built a second
+/// time by the handle. Caches kept outside of the handle can be cleared with [`Self::on_replace`].
+pub struct MapHandle {
+    current: RwLock<Arc<Loaded>>,
+    hooks: Mutex<Vec<ReplaceHook>>,
+}
+
+impl MapHandle {
+    pub fn new(map_data: MapData) -> Self {
+        Self {
+            current: RwLock::new(Arc::new(Loaded::new(Arc::new(map_data)))),
+            hooks: Mutex::default(),
+        }
+    }
+
+    fn current(&self) -> Arc<Loaded> {
+        // The lock is only held to clone or swap the `Arc`, which can't leave it inconsistent
+        Arc::clone(&self.current.read().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Returns the current version of the map data
+    pub fn load(&self) -> Arc<MapData> {
+        Arc::clone(&self.current().map_data)
+    }
+
+    /// Replaces the map data, clearing everything derived from the old version and then calling
+    /// the hooks added with [`Self::on_replace`]. Returns the old version.
+    pub fn replace(&self, map_data: MapData) -> Arc<MapData> {
+        let map_data = Arc::new(map_data);
+        let old = std::mem::replace(
+            &mut *self.current.write().unwrap_or_else(PoisonError::into_inner),
+            Arc::new(Loaded::new(Arc::clone(&map_data))),
+        );
+        for hook in self
+            .hooks
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+        {
+            hook(&map_data);
+        }
+        Arc::clone(&old.map_data)
+    }
+
+    /// Adds a hook called with the new map data every time it's replaced, to clear or rebuild
+    /// caches kept outside of the handle
+    pub fn on_replace(&self, hook: impl Fn(&Arc<MapData>) + Send + Sync + 'static) {
+        self.hooks
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(Box::new(hook));
+    }
+
+    /// Returns the index of type `T` derived from the current map data, building it with `build`
+    /// if it hasn't been built since the map data was last replaced. The map data is returned
+    /// along with it, so that the two are always from the same version.
+    ///
+    /// Indexes are told apart by type, so `build` should always be the same for the same `T`.
+    /// Indexes are built without holding a lock, so other indexes can be read or built meanwhile.
+    /// If several threads build the same index at once, they all get the first one finished.
+    pub fn derived<T: Any + Send + Sync>(
+        &self,
+        build: impl FnOnce(&MapData) -> T,
+    ) -> (Arc<MapData>, Arc<T>) {
+        let loaded = self.current();
+        let lock = || {
+            loaded
+                .derived
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+        };
+        let built = lock().get(&TypeId::of::<T>()).cloned();
+        let index = match built {
+            Some(index) => index,
+            None => {
+                let index: Derived = Arc::new(build(&loaded.map_data));
+                Arc::clone(lock().entry(TypeId::of::<T>()).or_insert(index))
+            }
+        };
+        let index = index
+            .downcast()
+            .expect("derived indexes are stored under their own type");
+        (Arc::clone(&loaded.map_data), index)
+    }
+
+    /// Returns the search index of the current map data
+    pub fn search_index(&self) -> Arc<SearchIndex> {
+        self.derived(SearchIndex::build).1
+    }
+
+    /// Returns the routing graph of the current map data, without any edges excluded. This is the
+    /// graph from [`MapData::graph`], which is kept with the version of the map data it came from.
+    pub fn routing_graph(&self) -> LoadedGraph {
+        LoadedGraph {
+            map_data: self.load(),
+        }
+    }
+}
+
+/// Routing graph of a version of the map data loaded from a [`MapHandle`], which keeps that version
+/// loaded until it's dropped
+#[derive(Debug, Clone)]
+pub struct LoadedGraph {
+    map_data: Arc<MapData>,
+}
+
+impl LoadedGraph {
+    /// Returns the version of the map data the graph was built from
+    pub fn map_data(&self) -> &Arc<MapData> {
+        &self.map_data
+    }
+}
+
+impl Deref for LoadedGraph {
+    type Target = RoutingGraph;
+
+    fn deref(&self) -> &RoutingGraph {
+        self.map_data.graph()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Barrier;
+    use std::thread;
+
+    use super::*;
+
+    fn map_data(rooms: &[&str]) -> MapData {
+        let rooms: serde_json::Map<_, _> = rooms
+            .iter()
+            .map(|&room| {
+                let json = serde_json::json!({
+                    "outline": [[0, 0], [1, 0], [1, 1]],
+                    "center": [0.5, 0.5],
+                    "vertices": [],
+                    "area": 0.5
+                });
+                (room.to_owned(), json)
+            })
+            .collect();
+        serde_json::from_value(serde_json::json!({
+            "floors": [],
+            "vertices": {},
+            "edges": [],
+            "rooms": rooms
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn replace_clears_derived_indexes() {
+        let handle = MapHandle::new(map_data(&["101"]));
+        let replaced = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&replaced);
+        handle.on_replace(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+
+        let builds = AtomicUsize::new(0);
+        let count_rooms = |map_data: &MapData| {
+            builds.fetch_add(1, Ordering::SeqCst);
+            map_data.rooms.len()
+        };
+        assert_eq!(1, *handle.derived(count_rooms).1);
+        assert_eq!(1, *handle.derived(count_rooms).1);
+        assert_eq!(1, builds.load(Ordering::SeqCst));
+        assert_eq!(1, handle.search_index().query("101", 5).len());
+        let graph = handle.routing_graph();
+        assert!(std::ptr::eq(handle.load().graph(), &*graph));
+
+        let old = handle.load();
+        let returned = handle.replace(map_data(&["101", "102"]));
+        assert!(Arc::ptr_eq(&old, &returned));
+        assert_eq!(1, replaced.load(Ordering::SeqCst));
+        // Readers that loaded the old version still have it
+        assert_eq!(1, old.rooms.len());
+        assert_eq!(2, handle.load().rooms.len());
+
+        let (map_data, rooms) = handle.derived(count_rooms);
+        assert_eq!(2, *rooms);
+        assert_eq!(2, map_data.rooms.len());
+        assert_eq!(2, builds.load(Ordering::SeqCst));
+        assert_eq!(1, handle.search_index().query("102", 5).len());
+        // The graph loaded before the map data was replaced is still from the old version
+        assert!(Arc::ptr_eq(&old, graph.map_data()));
+        assert!(std::ptr::eq(old.graph(), &*graph));
+        assert!(!std::ptr::eq(handle.load().graph(), &*graph));
+    }
+
+    #[test]
+    fn build_without_holding_lock() {
+        let handle = MapHandle::new(map_data(&["101"]));
+        // Building one index can read another
+        let (_map_data, results) = handle.derived(|_| handle.search_index().query("101", 5).len());
+        assert_eq!(1, *results);
+
+        // Threads building the same index at once both get the first one finished
+        let barrier = Barrier::new(2);
+        let indexes: Vec<_> = thread::scope(|scope| {
+            let threads: Vec<_> = (0..2)
+                .map(|_| {
+                    scope.spawn(|| {
+                        handle
+                            .derived(|map_data| {
+                                barrier.wait();
+                                SearchIndex::build(map_data)
+                            })
+                            .1
+                    })
+                })
+                .collect();
+            threads
+                .into_iter()
+                .map(|thread| thread.join().unwrap())
+                .collect()
+        });
+        assert!(Arc::ptr_eq(&indexes[0], &indexes[1]));
+        assert!(Arc::ptr_eq(&indexes[0], &handle.search_index()));
+    }
+
+    #[test]
+    fn read_while_replacing() {
+        let handle = MapHandle::new(map_data(&["101"]));
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..100 {
+                        let (map_data, index) = handle.derived(SearchIndex::build);
+                        assert_eq!(SearchIndex::build(&map_data), *index);
+                    }
+                });
+            }
+            for rooms in [&["101", "102"][..], &["103"], &["101", "102", "103"]] {
+                handle.replace(map_data(rooms));
+            }
+        });
+        assert_eq!(3, handle.load().rooms.len());
+    }
+}
@@ -0,0 +1,50 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+/// Type that a room metadata value must have
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum MetadataType {
+    String,
+    Number,
+    Boolean,
+    Array,
+    Object,
+}
+
+impl MetadataType {
+    pub fn matches(&self, value: &Value) -> bool {
+        match self {
+            MetadataType::String => value.is_string(),
+            MetadataType::Number => value.is_number(),
+            MetadataType::Boolean => value.is_boolean(),
+            MetadataType::Array => value.is_array(),
+            MetadataType::Object => value.is_object(),
+        }
+    }
+}
+
+/// Types of known room metadata keys, such as `{ "capacity": "number" }`. Keys not in the schema
+/// may have any value.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct MetadataSchema(pub HashMap<String, MetadataType>);
+
+impl MetadataSchema {
+    pub fn new(json_data: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json_data)
+    }
+
+    /// Returns the first key in `metadata` whose value doesn't have the type required by the
+    /// schema, along with that type.
+    pub fn find_mismatch<'m>(
+        &self,
+        metadata: &'m HashMap<String, Value>,
+    ) -> Option<(&'m str, MetadataType)> {
+        metadata.iter().find_map(|(key, value)| {
+            let expected = self.0.get(key)?;
+            (!expected.matches(value)).then_some((key.as_str(), *expected))
+        })
+    }
+}
@@ -1,163 +1,739 @@
-use std::collections::HashSet;
-use std::convert::TryFrom;
+use std::collections::{HashMap, HashSet};
+use std::convert::{Infallible, TryFrom};
+use std::fmt;
 use std::hash::Hash;
 use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::OnceLock;
 
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde_json::Value;
 
+use crate::transform::CoordinateSystem;
+use crate::util::{centroid_with_holes, point_in_polygon_with_holes, pole_of_inaccessibility};
+
+#[cfg(test)]
+mod arbitrary;
+pub mod compile;
 pub mod compiled;
+pub mod edit;
+pub mod handle;
+pub mod metadata;
+pub mod overrides;
 pub mod uncompiled;
 
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Hash)]
-pub enum VertexTag {
-    #[serde(rename = "stairs")]
-    Stairs,
-    #[serde(rename = "elevator")]
-    Elevator,
-    #[serde(rename = "up")]
-    Up,
-    #[serde(rename = "down")]
-    Down,
+/// Defines a tag enum which is written as the given strings in JSON. Strings that aren't known
+/// tags become `Other` instead of failing to deserialize, so maps using tags added after this
+/// version of the library still load, and write the tag back out unchanged.
+macro_rules! tag_enum {
+    ($(#[$meta:meta])* $name:ident { $($variant:ident => $string:literal,)* }) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Eq, PartialEq, Hash)]
+        pub enum $name {
+            $($variant,)*
+            /// Any other tag. Constructing this with the string of a known tag gives a value that
+            /// isn't equal to the known tag, but is written out the same way.
+            Other(String),
+        }
+
+        impl $name {
+            /// Returns every tag other than `Other`
+            pub fn known_tags() -> Vec<Self> {
+                vec![$(Self::$variant,)*]
+            }
+
+            pub fn as_str(&self) -> &str {
+                match self {
+                    $(Self::$variant => $string,)*
+                    Self::Other(tag) => tag,
+                }
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(self.as_str())
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = Infallible;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                Ok(match s {
+                    $($string => Self::$variant,)*
+                    other => Self::Other(other.to_owned()),
+                })
+            }
+        }
+
+        impl Serialize for $name {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_str(self.as_str())
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let tag = String::deserialize(deserializer)?;
+                Ok(match tag.parse() {
+                    Ok(tag) => tag,
+                    Err(never) => match never {},
+                })
+            }
+        }
+    };
 }
 
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Hash)]
-pub enum RoomTag {
-    #[serde(rename = "closed")]
-    Closed,
-    #[serde(rename = "women-bathroom")]
-    WomenBathroom,
-    #[serde(rename = "men-bathroom")]
-    MenBathroom,
-    #[serde(rename = "staff-women-bathroom")]
-    StaffWomenBathroom,
-    #[serde(rename = "staff-men-bathroom")]
-    StaffMenBathroom,
-    #[serde(rename = "unknown-bathroom")]
-    UnknownBathroom,
-    #[serde(rename = "bsc")]
-    Bsc,
-    #[serde(rename = "ec")]
-    Ec,
-    #[serde(rename = "wf")]
-    Wf,
-    #[serde(rename = "hs")]
-    Hs,
-    #[serde(rename = "bleed-control")]
-    BleedControl,
-    #[serde(rename = "aed")]
-    Aed,
-    #[serde(rename = "ahu")]
-    Ahu,
-    #[serde(rename = "idf")]
-    Idf,
-    #[serde(rename = "mdf")]
-    Mdf,
-    #[serde(rename = "eru")]
-    Eru,
-    #[serde(rename = "cp")]
-    Cp,
+tag_enum!(VertexTag {
+    Stairs => "stairs",
+    Elevator => "elevator",
+    Up => "up",
+    Down => "down",
+});
+
+tag_enum!(RoomTag {
+    Closed => "closed",
+    WomenBathroom => "women-bathroom",
+    MenBathroom => "men-bathroom",
+    StaffWomenBathroom => "staff-women-bathroom",
+    StaffMenBathroom => "staff-men-bathroom",
+    UnknownBathroom => "unknown-bathroom",
+    Bsc => "bsc",
+    Ec => "ec",
+    Wf => "wf",
+    Hs => "hs",
+    BleedControl => "bleed-control",
+    Aed => "aed",
+    Ahu => "ahu",
+    Idf => "idf",
+    Mdf => "mdf",
+    Eru => "eru",
+    Cp => "cp",
+    NoAccess => "no-access",
+    MultiFloor => "multi-floor",
+});
+
+tag_enum!(
+    /// What an edge passes through, for describing the step of a route along it
+    EdgeKind {
+        Door => "door",
+        DoubleDoor => "double-door",
+        Corridor => "corridor",
+        Stairs => "stairs",
+        Elevator => "elevator",
+        Ramp => "ramp",
+    }
+);
+
+tag_enum!(EdgeTag {
+    StaffOnly => "staff-only",
+    Inaccessible => "inaccessible",
+    Outdoors => "outdoors",
+});
+
+/// How close a computed room center must be to the point farthest inside of the room, in map units
+const CENTER_PRECISION: f64 = 0.5;
+
+/// Finds the center of a room that wasn't given one explicitly. The centroid is cheaper, but is
+/// outside of rooms with some concave shapes, such as L-shaped rooms, and can be inside a hole.
+fn computed_center(outline: &[(f64, f64)], holes: &[impl AsRef<[(f64, f64)]>]) -> (f64, f64) {
+    let centroid = centroid_with_holes(outline, holes);
+    if point_in_polygon_with_holes(centroid, outline, holes) {
+        centroid
+    } else {
+        pole_of_inaccessibility(outline, holes, CENTER_PRECISION)
+    }
+}
+
+/// Finds the position of each floor in `floors`, which are listed from the lowest floor to the
+/// highest, keyed by floor number
+pub(crate) fn floor_ordinals(floors: &[Floor]) -> HashMap<&str, usize> {
+    floors
+        .iter()
+        .enumerate()
+        .map(|(ordinal, floor)| (floor.number.as_str(), ordinal))
+        .collect()
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[error("There is no floor `{floor}`; the floors are {}", .floors.join(", "))]
+pub struct UnknownFloor {
+    pub floor: String,
+    /// Numbers of the floors in the map data, from lowest to highest
+    pub floors: Vec<String>,
+}
+
+/// Position of each floor by number, built the first time a floor is looked up, which is ignored
+/// when comparing map data. Floors found through it are checked against the number, so changing
+/// the floors afterwards makes lookups slower but not wrong.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct FloorIndex(OnceLock<HashMap<String, usize>>);
+
+impl PartialEq for FloorIndex {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl FloorIndex {
+    pub(crate) fn find<'a>(&self, floors: &'a [Floor], number: &str) -> Option<&'a Floor> {
+        let positions = self.0.get_or_init(|| {
+            floors
+                .iter()
+                .enumerate()
+                .map(|(position, floor)| (floor.number.clone(), position))
+                .collect()
+        });
+        positions
+            .get(number)
+            .and_then(|&position| floors.get(position))
+            .filter(|floor| floor.number == number)
+            .or_else(|| floors.iter().find(|floor| floor.number == number))
+    }
+
+    pub(crate) fn require<'a>(
+        &self,
+        floors: &'a [Floor],
+        number: &str,
+    ) -> Result<&'a Floor, UnknownFloor> {
+        self.find(floors, number).ok_or_else(|| UnknownFloor {
+            floor: number.to_owned(),
+            floors: floors.iter().map(|floor| floor.number.clone()).collect(),
+        })
+    }
+}
+
+/// A floor of the building, drawn by one or more SVG images.
+///
+/// Locations in the map data are in map coordinates, which are found from the SVG coordinates of
+/// the floor's images by the map data's [`CoordinateSystem`]. With
+/// [`CoordinateSystem::YUpWithOffsets`], the default, a point at `(x, y)` in an image is at
+/// `(x - offsets.0, offsets.1 - y)` on the map, so the y-axis points up and the offsets are where
+/// the map's origin is in the images. With [`CoordinateSystem::SvgNative`], SVG coordinates are
+/// used as they are, and compiling fails if any floor's offsets aren't 0. Convert between the two
+/// with [`Self::svg_to_map_coords`] and [`Self::map_to_svg_coords`], which undo each other.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Floor {
     number: String,
-    image: PathBuf,
-    offsets: (f32, f32),
+    #[serde(rename = "image", with = "floor_images")]
+    images: Vec<PathBuf>,
+    offsets: (f64, f64),
+    /// Extent of the floor's images in map coordinates as `(min x, min y, max x, max y)`, found
+    /// when compiling
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bounds: Option<(f64, f64, f64, f64)>,
+    /// Regular expression for the IDs of room elements in the floor's images, used in place of
+    /// [`SvgRoomConfig::id_pattern`](crate::svg_room::SvgRoomConfig::id_pattern) when compiling,
+    /// for floors whose IDs include the floor, such as `f2room210`
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id_pattern: Option<String>,
+    /// Prefixes of the IDs of room elements in the floor's images, used in place of
+    /// [`SvgRoomConfig::id_prefixes`](crate::svg_room::SvgRoomConfig::id_prefixes) when compiling
+    /// unless empty, for floors drawn with their own conventions
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    id_prefixes: Vec<String>,
+}
+
+/// A floor's images are written as a single path if there is only one, and as an array of paths
+/// otherwise, so that map data written before floors could have several images still loads.
+mod floor_images {
+    use std::path::PathBuf;
+
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum ImagesJson {
+        One(PathBuf),
+        Many(Vec<PathBuf>),
+    }
+
+    pub fn serialize<S: Serializer>(images: &[PathBuf], serializer: S) -> Result<S::Ok, S::Error> {
+        match images {
+            [image] => image.serialize(serializer),
+            images => images.serialize(serializer),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<PathBuf>, D::Error> {
+        let images = match ImagesJson::deserialize(deserializer)? {
+            ImagesJson::One(image) => vec![image],
+            ImagesJson::Many(images) => images,
+        };
+        if images.is_empty() {
+            return Err(D::Error::custom("A floor needs at least one image"));
+        }
+        Ok(images)
+    }
+}
+
+/// Sets are written in sorted order, so that writing the same map data always gives the same
+/// output
+mod sorted_set {
+    use std::collections::HashSet;
+    use std::fmt::Display;
+
+    use serde::{Serialize, Serializer};
+
+    pub fn serialize<S: Serializer, T: Serialize + Display>(
+        set: &HashSet<T>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut items: Vec<_> = set.iter().collect();
+        items.sort_by_cached_key(|item| item.to_string());
+        serializer.collect_seq(items)
+    }
+}
+
+/// Maps are written in order of their keys, for the same reason as sets
+mod sorted_map {
+    use std::collections::HashMap;
+
+    use serde::{Serialize, Serializer};
+
+    pub fn serialize<S: Serializer, V: Serialize>(
+        map: &HashMap<String, V>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut entries: Vec<_> = map.iter().collect();
+        entries.sort_unstable_by_key(|(key, _value)| *key);
+        serializer.collect_map(entries)
+    }
 }
 
 impl Floor {
+    pub fn new(number: String, image: PathBuf, offsets: (f64, f64)) -> Self {
+        Self {
+            number,
+            images: vec![image],
+            offsets,
+            bounds: None,
+            id_pattern: None,
+            id_prefixes: vec![],
+        }
+    }
+
+    /// Adds an image to be drawn above the floor's existing images
+    pub fn add_image(&mut self, image: PathBuf) {
+        self.images.push(image);
+    }
+
     pub fn get_number(&self) -> &str {
         &self.number
     }
 
+    /// Returns the base image of the floor, which is drawn beneath all of its other images
     pub fn get_image(&self) -> &PathBuf {
-        &self.image
+        &self.images[0]
+    }
+
+    /// Returns all of the floor's images, starting with the base image
+    pub fn get_images(&self) -> &[PathBuf] {
+        &self.images
     }
 
-    pub fn get_offsets(&self) -> (f32, f32) {
+    pub fn get_offsets(&self) -> (f64, f64) {
         self.offsets
     }
+
+    pub fn get_id_pattern(&self) -> Option<&str> {
+        self.id_pattern.as_deref()
+    }
+
+    pub fn set_id_pattern(&mut self, id_pattern: Option<String>) {
+        self.id_pattern = id_pattern;
+    }
+
+    pub fn get_id_prefixes(&self) -> &[String] {
+        &self.id_prefixes
+    }
+
+    pub fn set_id_prefixes(&mut self, id_prefixes: Vec<String>) {
+        self.id_prefixes = id_prefixes;
+    }
+
+    /// Converts a point in the floor's images to map coordinates
+    pub fn svg_to_map_coords(
+        &self,
+        point: (f64, f64),
+        coordinate_system: CoordinateSystem,
+    ) -> (f64, f64) {
+        coordinate_system.svg_to_map(point, self.offsets)
+    }
+
+    /// Converts a point in map coordinates to where it is in the floor's images
+    pub fn map_to_svg_coords(
+        &self,
+        point: (f64, f64),
+        coordinate_system: CoordinateSystem,
+    ) -> (f64, f64) {
+        coordinate_system.map_to_svg(point, self.offsets)
+    }
+
+    /// Returns the extent of the floor's images in map coordinates as
+    /// `(min x, min y, max x, max y)`, if the map data has been compiled. This is the viewports of
+    /// the images, widened to fit their rooms.
+    pub fn get_bounds(&self) -> Option<(f64, f64, f64, f64)> {
+        self.bounds
+    }
+
+    pub(crate) fn set_bounds(&mut self, bounds: (f64, f64, f64, f64)) {
+        self.bounds = Some(bounds);
+    }
+
+    pub(crate) fn set_offsets(&mut self, offsets: (f64, f64)) {
+        self.offsets = offsets;
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Vertex {
     floor: String,
-    location: (f32, f32),
+    location: (f64, f64),
     #[serde(default)]
     #[serde(skip_serializing_if = "HashSet::is_empty")]
+    #[serde(serialize_with = "sorted_set::serialize")]
     tags: HashSet<VertexTag>,
+    /// Name shared by vertices that are connected across floors, such as the landings of a
+    /// stairwell. Vertices in the same group on adjacent floors get edges between them.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    group: Option<String>,
 }
 
 impl Vertex {
+    pub fn new(floor: String, location: (f64, f64)) -> Self {
+        Self {
+            floor,
+            location,
+            tags: HashSet::new(),
+            group: None,
+        }
+    }
+
+    pub fn with_tags(self, tags: HashSet<VertexTag>) -> Self {
+        Self { tags, ..self }
+    }
+
+    pub fn with_group(self, group: String) -> Self {
+        Self {
+            group: Some(group),
+            ..self
+        }
+    }
+
     pub fn get_floor(&self) -> &str {
         &self.floor
     }
+
+    pub fn get_location(&self) -> (f64, f64) {
+        self.location
+    }
+
+    pub fn get_tags(&self) -> &HashSet<VertexTag> {
+        &self.tags
+    }
+
+    pub fn get_group(&self) -> Option<&str> {
+        self.group.as_deref()
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct EdgeJson(Vec<Value>);
+/// A point of interest that isn't a room, such as a water fountain, an AED on a wall, or artwork
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Poi {
+    pub floor: String,
+    /// Location in map coordinates, the same as the locations of vertices
+    pub location: (f64, f64),
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub names: Vec<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "HashSet::is_empty")]
+    #[serde(serialize_with = "sorted_set::serialize")]
+    pub tags: HashSet<RoomTag>,
+    /// Vertex that routes to the point of interest end at. If it isn't given, compiling links the
+    /// point of interest to the nearest vertex on its floor.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vertex: Option<String>,
+}
+
+impl Poi {
+    /// Creates a point of interest without any names or tags, which is linked to the nearest
+    /// vertex when compiling
+    pub fn new(floor: String, location: (f64, f64)) -> Self {
+        Self {
+            floor,
+            location,
+            names: vec![],
+            tags: HashSet::new(),
+            vertex: None,
+        }
+    }
+
+    /// Finds the vertex on the same floor nearest to the point of interest, breaking ties by ID
+    fn nearest_vertex<'a>(&self, vertices: &'a HashMap<String, Vertex>) -> Option<&'a str> {
+        let (x, y) = self.location;
+        vertices
+            .iter()
+            .filter(|(_id, vertex)| vertex.floor == self.floor)
+            .map(|(id, vertex)| {
+                let (vertex_x, vertex_y) = vertex.location;
+                ((vertex_x - x).hypot(vertex_y - y), id.as_str())
+            })
+            .min_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(b.1)))
+            .map(|(_distance, id)| id)
+    }
+}
+
+/// Edges without a kind or tags are written in the array form, so that map data written before
+/// edges had them is written back out unchanged
+#[derive(Serialize, Debug)]
+#[serde(untagged)]
+enum EdgeJson {
+    Array(Vec<Value>),
+    Object {
+        from: String,
+        to: String,
+        #[serde(skip_serializing_if = "std::ops::Not::not")]
+        directed: bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        kind: Option<EdgeKind>,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        tags: Vec<EdgeTag>,
+    },
+}
 
 impl From<Edge> for EdgeJson {
     fn from(edge: Edge) -> Self {
-        if edge.directed {
-            Self(vec![
+        if edge.kind.is_some() || !edge.tags.is_empty() {
+            let mut tags: Vec<_> = edge.tags.into_iter().collect();
+            tags.sort_unstable_by(|a, b| a.as_str().cmp(b.as_str()));
+            Self::Object {
+                from: edge.from,
+                to: edge.to,
+                directed: edge.directed,
+                kind: edge.kind,
+                tags,
+            }
+        } else if edge.directed {
+            Self::Array(vec![
                 Value::String(edge.from),
                 Value::String(edge.to),
                 Value::Bool(true),
             ])
         } else {
-            Self(vec![Value::String(edge.from), Value::String(edge.to)])
+            Self::Array(vec![Value::String(edge.from), Value::String(edge.to)])
         }
     }
 }
 
+/// Reasons an edge can't be read from map data, where edges are written as `["from", "to"]` or as
+/// `["from", "to", true]` if they are directed, or as an object with `from`, `to`, and optionally
+/// `directed`, `kind`, and `tags`
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum EdgeParseError {
+    #[error("The edge should be an array of vertex IDs or an object, found {0}")]
+    NotArrayOrObject(Value),
+    #[error("The edge is missing the vertex it goes from")]
+    MissingFrom,
+    #[error("The vertex the edge goes from should be a string, found {found}")]
+    FromNotString { found: Value },
+    #[error("The edge is missing the vertex it goes to")]
+    MissingTo,
+    #[error("The vertex the edge goes to should be a string, found {found}")]
+    ToNotString { found: Value },
+    #[error("Whether the edge is directed should be a bool, found {found}")]
+    DirectedNotBool { found: Value },
+    #[error("The edge has {0} more elements than the three it can have")]
+    ExtraElements(usize),
+    #[error("The kind of the edge should be a string, found {found}")]
+    KindNotString { found: Value },
+    #[error("The tags of the edge should be an array of strings, found {found}")]
+    TagsNotStrings { found: Value },
+    #[error("The edge has the unknown field `{0}`")]
+    UnknownField(String),
+}
+
+/// Edges are read one at a time so that the index of an edge that can't be read is reported
+pub(crate) mod edge_list {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer};
+    use serde_json::Value;
+
+    use super::Edge;
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Edge>, D::Error> {
+        Vec::<Value>::deserialize(deserializer)?
+            .into_iter()
+            .enumerate()
+            .map(|(index, value)| {
+                Edge::try_from(value)
+                    .map_err(|error| D::Error::custom(format!("Edge {}: {}", index, error)))
+            })
+            .collect()
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
-#[serde(try_from = "EdgeJson")]
+#[serde(try_from = "Value")]
 #[serde(into = "EdgeJson")]
 pub struct Edge {
     from: String,
     to: String,
     directed: bool,
+    kind: Option<EdgeKind>,
+    tags: HashSet<EdgeTag>,
+}
+
+impl Edge {
+    pub fn new(from: String, to: String, directed: bool) -> Self {
+        Self {
+            from,
+            to,
+            directed,
+            kind: None,
+            tags: HashSet::new(),
+        }
+    }
+
+    pub fn with_kind(mut self, kind: EdgeKind) -> Self {
+        self.kind = Some(kind);
+        self
+    }
+
+    pub fn with_tags(mut self, tags: HashSet<EdgeTag>) -> Self {
+        self.tags = tags;
+        self
+    }
+
+    pub fn get_from(&self) -> &str {
+        &self.from
+    }
+
+    pub fn get_to(&self) -> &str {
+        &self.to
+    }
+
+    /// Returns `true` if the edge can only be traveled from `from` to `to`
+    pub fn is_directed(&self) -> bool {
+        self.directed
+    }
+
+    pub fn get_kind(&self) -> Option<&EdgeKind> {
+        self.kind.as_ref()
+    }
+
+    pub fn get_tags(&self) -> &HashSet<EdgeTag> {
+        &self.tags
+    }
+
+    /// Reads the object form of an edge
+    fn from_object(object: serde_json::Map<String, Value>) -> Result<Self, EdgeParseError> {
+        let mut edge = Self::new(String::new(), String::new(), false);
+        let (mut has_from, mut has_to) = (false, false);
+        for (key, value) in object {
+            match key.as_str() {
+                "from" => match value {
+                    Value::String(from) => (edge.from, has_from) = (from, true),
+                    found => return Err(EdgeParseError::FromNotString { found }),
+                },
+                "to" => match value {
+                    Value::String(to) => (edge.to, has_to) = (to, true),
+                    found => return Err(EdgeParseError::ToNotString { found }),
+                },
+                "directed" => match value {
+                    Value::Bool(directed) => edge.directed = directed,
+                    found => return Err(EdgeParseError::DirectedNotBool { found }),
+                },
+                "kind" => match value {
+                    Value::String(kind) => edge.kind = Some(parse_tag(&kind)),
+                    found => return Err(EdgeParseError::KindNotString { found }),
+                },
+                "tags" => {
+                    edge.tags = match &value {
+                        Value::Array(tags) => tags
+                            .iter()
+                            .map(|tag| tag.as_str().map(parse_tag))
+                            .collect::<Option<_>>(),
+                        _ => None,
+                    }
+                    .ok_or(EdgeParseError::TagsNotStrings { found: value })?;
+                }
+                _ => return Err(EdgeParseError::UnknownField(key)),
+            }
+        }
+
+        if !has_from {
+            return Err(EdgeParseError::MissingFrom);
+        }
+        if !has_to {
+            return Err(EdgeParseError::MissingTo);
+        }
+        Ok(edge)
+    }
 }
 
-impl TryFrom<EdgeJson> for Edge {
-    type Error = &'static str;
-
-    fn try_from(value: EdgeJson) -> Result<Self, Self::Error> {
-        let mut values = value.0.into_iter();
-
-        let from = values
-            .next()
-            .ok_or("Missing from")?
-            .as_str()
-            .ok_or("From not a string")?
-            .to_owned();
-        let to = values
-            .next()
-            .ok_or("Missing to")?
-            .as_str()
-            .ok_or("To not a string")?
-            .to_owned();
+fn parse_tag<T: FromStr<Err = Infallible>>(tag: &str) -> T {
+    match tag.parse() {
+        Ok(tag) => tag,
+        Err(never) => match never {},
+    }
+}
+
+impl TryFrom<Value> for Edge {
+    type Error = EdgeParseError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        let mut values = match value {
+            Value::Array(values) => values.into_iter(),
+            Value::Object(object) => return Self::from_object(object),
+            value => return Err(EdgeParseError::NotArrayOrObject(value)),
+        };
+
+        let from = match values.next().ok_or(EdgeParseError::MissingFrom)? {
+            Value::String(from) => from,
+            found => return Err(EdgeParseError::FromNotString { found }),
+        };
+        let to = match values.next().ok_or(EdgeParseError::MissingTo)? {
+            Value::String(to) => to,
+            found => return Err(EdgeParseError::ToNotString { found }),
+        };
         let directed = match values.next() {
-            Some(value) => value.as_bool().ok_or("Directed not a bool")?,
+            Some(Value::Bool(directed)) => directed,
+            Some(found) => return Err(EdgeParseError::DirectedNotBool { found }),
             None => false,
         };
 
         if values.len() != 0 {
-            return Err("Array too long");
+            return Err(EdgeParseError::ExtraElements(values.len()));
         }
 
-        Ok(Self { from, to, directed })
+        Ok(Self::new(from, to, directed))
     }
 }
 
 #[cfg(test)]
 mod test {
+    use std::path::Path;
+
     use common_macros::{hash_map, hash_set};
+    use serde_json::json;
 
     use super::*;
-    use crate::map_data::uncompiled::{MapDataDeserializeError, MapDataError};
+    use crate::map_data::metadata::{MetadataSchema, MetadataType};
+    use crate::map_data::overrides::Overrides;
+    use crate::map_data::uncompiled::{
+        CompileError, CompileOptions, MapDataDeserializeError, MapDataError,
+    };
 
     fn file(path: &str) -> String {
         use std::fs;
@@ -171,37 +747,35 @@ mod test {
         let actual_map_data = uncompiled::MapData {
             floors: vec![Floor {
                 number: "1".to_string(),
-                image: "assets/map/1st_floor.svg".into(),
+                images: vec!["assets/map/1st_floor.svg".into()],
                 offsets: (0.0, 0.0),
+                bounds: None,
+                id_pattern: None,
+                id_prefixes: vec![],
             }],
             vertices: hash_map![
                 "a".to_string() => Vertex {
                     floor: "1".to_string(),
                     location: (434.875, 288.0),
                     tags: hash_set![VertexTag::Stairs],
+                    group: None,
                 },
                 "b".to_string() => Vertex {
                     floor: "1".to_string(),
                     location: (0.0, 0.0),
                     tags: hash_set![],
+                    group: None,
                 },
                 "c".to_string() => Vertex {
                     floor: "1".to_string(),
                     location: (0.0, 1.0),
                     tags: hash_set![],
+                    group: None,
                 },
             ],
             edges: vec![
-                Edge {
-                    from: "c".to_string(),
-                    to: "b".to_string(),
-                    directed: false,
-                },
-                Edge {
-                    from: "a".to_string(),
-                    to: "b".to_string(),
-                    directed: true,
-                },
+                Edge::new("c".to_string(), "b".to_string(), false),
+                Edge::new("a".to_string(), "b".to_string(), true),
             ],
             rooms: hash_map! {
                 "106".to_string() => uncompiled::Room {
@@ -209,6 +783,8 @@ mod test {
                     center: None,
                     names: vec![],
                     tags: hash_set![],
+                    priority: None,
+                    metadata: hash_map![],
                 },
                 "107".to_string() => uncompiled::Room {
                     vertices: hash_set!["b".to_string(), "c".to_string()],
@@ -220,12 +796,144 @@ mod test {
                         "counseling office".to_string(),
                     ],
                     tags: hash_set![],
+                    priority: None,
+                    metadata: hash_map![],
                 },
             },
+            ..uncompiled::MapData::default()
         };
         assert_eq!(actual_map_data, map_data);
     }
 
+    #[test]
+    fn write_simple_data() {
+        // Numbers are compared as floats since locations are written back out as floats
+        fn numbers_as_floats(value: Value) -> Value {
+            match value {
+                Value::Number(number) => json!(number.as_f64().unwrap()),
+                Value::Array(items) => items.into_iter().map(numbers_as_floats).collect(),
+                Value::Object(fields) => Value::Object(
+                    fields
+                        .into_iter()
+                        .map(|(key, value)| (key, numbers_as_floats(value)))
+                        .collect(),
+                ),
+                value => value,
+            }
+        }
+
+        let json = file("tests/json/simple.json");
+        let map_data = uncompiled::MapData::new(&json).unwrap();
+        let written = serde_json::to_value(&map_data).unwrap();
+        let read: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(numbers_as_floats(read), numbers_as_floats(written));
+
+        // Maps are written in order of their keys, so every save is the same
+        let json = file("tests/json/compile.json");
+        let written = serde_json::to_string(&uncompiled::MapData::new(&json).unwrap()).unwrap();
+        for _ in 0..4 {
+            let map_data = uncompiled::MapData::new(&json).unwrap();
+            assert_eq!(written, serde_json::to_string(&map_data).unwrap());
+        }
+        let map_data = uncompiled::MapData::new(&json).unwrap();
+        let mut ids: Vec<_> = map_data.vertices.keys().collect();
+        ids.sort_unstable();
+        let positions: Vec<_> = ids
+            .iter()
+            .map(|id| written.find(&format!("\"{}\":{{", id)).unwrap())
+            .collect();
+        assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn construct_with_constructors() {
+        let json = file("tests/json/simple.json");
+        let map_data = uncompiled::MapData::new(&json).unwrap();
+
+        let mut constructed = uncompiled::MapData::empty();
+        constructed.floors.push(Floor::new(
+            "1".to_owned(),
+            "assets/map/1st_floor.svg".into(),
+            (0., 0.),
+        ));
+        constructed.vertices = hash_map![
+            "a".to_owned() => Vertex::new("1".to_owned(), (434.875, 288.))
+                .with_tags(hash_set![VertexTag::Stairs]),
+            "b".to_owned() => Vertex::new("1".to_owned(), (0., 0.)),
+            "c".to_owned() => Vertex::new("1".to_owned(), (0., 1.)),
+        ];
+        constructed.edges = vec![
+            Edge::new("c".to_owned(), "b".to_owned(), false),
+            Edge::new("a".to_owned(), "b".to_owned(), true),
+        ];
+        let mut guidance = uncompiled::Room::new(hash_set!["b".to_owned(), "c".to_owned()]);
+        guidance.center = Some((489.9375, 36.9375));
+        guidance.names = [
+            "guidance",
+            "guidance office",
+            "counselors",
+            "counseling office",
+        ]
+        .iter()
+        .map(|name| name.to_string())
+        .collect();
+        constructed.rooms = hash_map![
+            "106".to_owned() => uncompiled::Room::new(hash_set!["a".to_owned()]),
+            "107".to_owned() => guidance,
+        ];
+        assert_eq!(map_data, constructed);
+
+        let mut copy = constructed.clone();
+        copy.floors[0].add_image("assets/map/1st_floor_labels.svg".into());
+        assert_eq!(1, constructed.floors[0].get_images().len());
+        assert_ne!(constructed, copy);
+
+        assert!(map_data.edges[1].is_directed());
+        assert_eq!(
+            ("a", "b"),
+            (map_data.edges[1].get_from(), map_data.edges[1].get_to())
+        );
+        assert_eq!(compiled::MapData::empty(), compiled::MapData::default());
+    }
+
+    #[test]
+    fn find_floors_by_number() {
+        let floor = |number: &str| Floor::new(number.to_owned(), "floor.svg".into(), (0., 0.));
+        let mut map_data = uncompiled::MapData::empty();
+        map_data.floors = vec![floor("G"), floor("1"), floor("2")];
+
+        assert_eq!(vec!["G", "1", "2"], map_data.floor_numbers());
+        assert_eq!("1", map_data.floor("1").unwrap().get_number());
+        assert!(map_data.has_floor("G"));
+        // Floor numbers are matched exactly
+        assert!(!map_data.has_floor("g"));
+        assert!(!map_data.has_floor(" 1"));
+        let error = map_data.require_floor("3").unwrap_err();
+        assert_eq!(
+            "There is no floor `3`; the floors are G, 1, 2",
+            error.to_string()
+        );
+
+        // Floors changed after the first lookup are still found
+        map_data.floors.remove(0);
+        map_data.floors.push(floor("3"));
+        assert_eq!(vec!["1", "2", "3"], map_data.floor_numbers());
+        assert!(!map_data.has_floor("G"));
+        assert_eq!("2", map_data.floor("2").unwrap().get_number());
+        assert_eq!("3", map_data.floor("3").unwrap().get_number());
+
+        let mut compiled = compiled::MapData::empty();
+        compiled.floors = vec![floor("1")];
+        assert!(compiled.has_floor("1"));
+        assert_eq!(
+            UnknownFloor {
+                floor: "2".to_owned(),
+                floors: vec!["1".to_owned()],
+            },
+            compiled.require_floor("2").unwrap_err()
+        );
+    }
+
     #[test]
     fn reject_repeat_floor_number() {
         let json = file("tests/json/repeat_floor_number.json");
@@ -291,4 +999,817 @@ mod test {
             Ok(_) => panic!("Should be error"),
         }
     }
+
+    #[test]
+    fn directed_edge_twins() {
+        let json = file("tests/json/directed_twins.json");
+        let map_data = uncompiled::MapData::new(&json).unwrap();
+        // The edges between `b` and `c` have different tags, so they aren't the same as an
+        // undirected edge
+        assert_eq!(vec![("a", "b")], map_data.directed_edge_twins());
+    }
+
+    #[test]
+    fn reject_mixed_edge_directions() {
+        let json = file("tests/json/mixed_edge_directions.json");
+        match uncompiled::MapData::new(&json) {
+            Err(MapDataDeserializeError::MapDataError(MapDataError::MixedEdgeDirections {
+                from,
+                to,
+            })) => assert_eq!(("a", "b"), (from.as_str(), to.as_str())),
+            other => panic!("Should be mixed edge directions, got {:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn reject_malformed_edges() {
+        let cases = [
+            (json!("a"), EdgeParseError::NotArrayOrObject(json!("a"))),
+            (json!(5), EdgeParseError::NotArrayOrObject(json!(5))),
+            (json!({"from": "a"}), EdgeParseError::MissingTo),
+            (json!({"to": "b"}), EdgeParseError::MissingFrom),
+            (
+                json!({"from": "a", "to": "b", "kind": 5}),
+                EdgeParseError::KindNotString { found: json!(5) },
+            ),
+            (
+                json!({"from": "a", "to": "b", "tags": ["staff-only", 5]}),
+                EdgeParseError::TagsNotStrings {
+                    found: json!(["staff-only", 5]),
+                },
+            ),
+            (
+                json!({"from": "a", "to": "b", "width": 2}),
+                EdgeParseError::UnknownField("width".to_owned()),
+            ),
+            (json!([]), EdgeParseError::MissingFrom),
+            (
+                json!([5, "b"]),
+                EdgeParseError::FromNotString { found: json!(5) },
+            ),
+            (json!(["a"]), EdgeParseError::MissingTo),
+            (
+                json!(["a", 5]),
+                EdgeParseError::ToNotString { found: json!(5) },
+            ),
+            (
+                json!(["a", ["b", "c"]]),
+                EdgeParseError::ToNotString {
+                    found: json!(["b", "c"]),
+                },
+            ),
+            (
+                json!(["a", "b", "yes"]),
+                EdgeParseError::DirectedNotBool {
+                    found: json!("yes"),
+                },
+            ),
+            (
+                json!(["a", "b", true, 1, 2]),
+                EdgeParseError::ExtraElements(2),
+            ),
+        ];
+        for (edge, expected) in cases {
+            assert_eq!(Err(expected), Edge::try_from(edge.clone()), "{}", edge);
+        }
+        assert_eq!(
+            Ok(Edge::new("a".to_owned(), "b".to_owned(), true)),
+            Edge::try_from(json!(["a", "b", true]))
+        );
+    }
+
+    #[test]
+    fn annotated_edges() {
+        let edge: Edge = serde_json::from_value(json!({
+            "from": "a",
+            "to": "b",
+            "directed": true,
+            "kind": "double-door",
+            "tags": ["staff-only", "outdoors"]
+        }))
+        .unwrap();
+        assert_eq!(
+            Edge::new("a".to_owned(), "b".to_owned(), true)
+                .with_kind(EdgeKind::DoubleDoor)
+                .with_tags(hash_set![EdgeTag::StaffOnly, EdgeTag::Outdoors]),
+            edge
+        );
+        assert_eq!(
+            r#"{"from":"a","to":"b","directed":true,"kind":"double-door","tags":["outdoors","staff-only"]}"#,
+            serde_json::to_string(&edge).unwrap()
+        );
+
+        let edge = Edge::new("a".to_owned(), "b".to_owned(), false).with_kind(EdgeKind::Door);
+        assert_eq!(
+            r#"{"from":"a","to":"b","kind":"door"}"#,
+            serde_json::to_string(&edge).unwrap()
+        );
+
+        // Edges without annotations are written the way they were before edges had them
+        let json = file("tests/json/simple.json");
+        let map_data = uncompiled::MapData::new(&json).unwrap();
+        assert_eq!(
+            r#"[["c","b"],["a","b",true]]"#,
+            serde_json::to_string(&map_data.edges).unwrap()
+        );
+    }
+
+    #[test]
+    fn report_index_of_malformed_edge() {
+        let json = file("tests/json/malformed_edge.json");
+        let error = uncompiled::MapData::new(&json).unwrap_err().to_string();
+        assert!(
+            error.contains(r#"Edge 1: The vertex the edge goes to should be a string, found 5"#),
+            "{}",
+            error
+        );
+
+        let error = serde_json::from_str::<compiled::MapData>(&json)
+            .unwrap_err()
+            .to_string();
+        assert!(error.starts_with("Edge 1: "), "{}", error);
+    }
+
+    #[test]
+    fn reject_non_finite_coordinates() {
+        let json = file("tests/json/compile.json");
+        // Too large for an `f64`, which JSON numbers can't be
+        let too_large = json.replace("[70, 70]", "[1e309, 70]");
+        match uncompiled::MapData::new(&too_large) {
+            Err(MapDataDeserializeError::InvalidJson(_)) => {}
+            other => panic!("Should be invalid JSON, was {:?}", other),
+        }
+
+        // Map data built in code can still have non-finite coordinates
+        let map_data = uncompiled::MapData::new(&json).unwrap();
+        let mut offsets = map_data.clone();
+        offsets.floors[0].offsets = (0., f64::INFINITY);
+        match offsets.verify() {
+            Err(MapDataError::NonFiniteOffsets(floor)) => assert_eq!("1", floor),
+            other => panic!("Should be non-finite offsets, was {:?}", other),
+        }
+
+        let mut location = map_data.clone();
+        location.vertices.get_mut("b").unwrap().location = (f64::NAN, 70.);
+        match location.verify() {
+            Err(MapDataError::NonFiniteLocation(id)) => assert_eq!("b", id),
+            other => panic!("Should be non-finite location, was {:?}", other),
+        }
+
+        let mut center = map_data;
+        center.rooms.get_mut("107").unwrap().center = Some((f64::NEG_INFINITY, 0.));
+        match center.verify() {
+            Err(MapDataError::NonFiniteCenter(room)) => assert_eq!("107", room),
+            other => panic!("Should be non-finite center, was {:?}", other),
+        }
+
+        // Negative coordinates are fine
+        let negative = json.replace("[70, 70]", "[-70, -70]");
+        assert!(uncompiled::MapData::new(&negative).is_ok());
+    }
+
+    #[test]
+    fn apply_overrides() {
+        let json = file("tests/json/simple.json");
+        let overrides = Overrides::new(&file("tests/json/overrides.json")).unwrap();
+        let map_data = uncompiled::MapData::new_with_overrides(&json, overrides).unwrap();
+
+        assert_eq!(vec!["106"], map_data.rooms.keys().collect::<Vec<_>>());
+        let room = &map_data.rooms["106"];
+        assert_eq!(hash_set![RoomTag::Closed, RoomTag::Aed], room.tags);
+        assert_eq!(vec!["construction office".to_owned()], room.names);
+
+        let overrides = Overrides::new(r#"{"rooms": {"106": {"remove-tags": ["aed"]}}}"#).unwrap();
+        let mut map_data = map_data;
+        map_data.apply_overrides(overrides).unwrap();
+        assert_eq!(hash_set![RoomTag::Closed], map_data.rooms["106"].tags);
+        assert_eq!(
+            vec!["construction office".to_owned()],
+            map_data.rooms["106"].names
+        );
+    }
+
+    #[test]
+    fn reject_overrides_for_undefined_room() {
+        let json = file("tests/json/simple.json");
+        let overrides = Overrides::new(&file("tests/json/overrides_undefined_room.json")).unwrap();
+        match uncompiled::MapData::new_with_overrides(&json, overrides) {
+            Err(MapDataDeserializeError::MapDataError(MapDataError::UndefinedOverrideRooms(
+                numbers,
+            ))) => {
+                assert_eq!(vec!["999", "1000"], numbers);
+                assert_eq!(
+                    "The room numbers `999`, `1000` in the overrides are undefined",
+                    MapDataError::UndefinedOverrideRooms(numbers).to_string()
+                );
+            }
+            other => panic!(
+                "Should be undefined room numbers 999 and 1000, was {:?}",
+                other
+            ),
+        }
+
+        assert!(Overrides::new(r#"{"rooms": {"106": {"closed": true}}}"#).is_err());
+    }
+
+    #[test]
+    fn rename_ids() {
+        let json = file("tests/json/simple.json");
+        let mut map_data = uncompiled::MapData::new(&json).unwrap();
+
+        map_data.rename_vertex("b", "hall").unwrap();
+        assert!(!map_data.vertices.contains_key("b"));
+        assert!(map_data.vertices.contains_key("hall"));
+        assert_eq!(
+            hash_set!["hall".to_owned(), "c".to_owned()],
+            map_data.rooms["107"].vertices
+        );
+        assert_eq!(
+            vec![
+                Edge::new("c".to_owned(), "hall".to_owned(), false),
+                Edge::new("a".to_owned(), "hall".to_owned(), true),
+            ],
+            map_data.edges
+        );
+
+        map_data.rename_room("107", "107a").unwrap();
+        assert!(map_data.rooms.contains_key("107a"));
+        assert!(!map_data.rooms.contains_key("107"));
+
+        map_data.rename_floor("1", "first").unwrap();
+        assert_eq!("first", map_data.floors[0].get_number());
+        assert!(map_data
+            .vertices
+            .values()
+            .all(|vertex| vertex.get_floor() == "first"));
+
+        let unchanged = map_data.clone();
+        assert!(matches!(
+            map_data.rename_vertex("a", "c"),
+            Err(MapDataError::RepeatedVertexId(id)) if id == "c"
+        ));
+        assert!(matches!(
+            map_data.rename_vertex("b", "d"),
+            Err(MapDataError::UndefinedVertexId(id)) if id == "b"
+        ));
+        assert!(matches!(
+            map_data.rename_room("106", "107a"),
+            Err(MapDataError::RepeatedRoomNumber(number)) if number == "107a"
+        ));
+        assert!(matches!(
+            map_data.rename_floor("2", "3"),
+            Err(MapDataError::UndefinedFloorNumber(number)) if number == "2"
+        ));
+        assert_eq!(unchanged, map_data);
+    }
+
+    #[test]
+    fn merge_and_split_rooms() {
+        let json = file("tests/json/simple.json");
+        let mut map_data = uncompiled::MapData::new(&json).unwrap();
+        map_data
+            .rooms
+            .get_mut("106")
+            .unwrap()
+            .names
+            .push("guidance".to_owned());
+        map_data
+            .rooms
+            .get_mut("106")
+            .unwrap()
+            .tags
+            .insert(RoomTag::Closed);
+
+        map_data.merge_rooms("107", "106").unwrap();
+        assert!(!map_data.rooms.contains_key("106"));
+        let merged = &map_data.rooms["107"];
+        assert_eq!(
+            hash_set!["a".to_owned(), "b".to_owned(), "c".to_owned()],
+            merged.vertices
+        );
+        assert_eq!(
+            vec![
+                "guidance",
+                "guidance office",
+                "counselors",
+                "counseling office"
+            ],
+            merged.names
+        );
+        assert_eq!(hash_set![RoomTag::Closed], merged.tags);
+        assert_eq!(Some((489.9375, 36.9375)), merged.center);
+
+        map_data
+            .split_room("107", "106", hash_set!["a".to_owned()])
+            .unwrap();
+        assert_eq!(hash_set!["a".to_owned()], map_data.rooms["106"].vertices);
+        assert!(map_data.rooms["106"].names.is_empty());
+        assert_eq!(
+            hash_set!["b".to_owned(), "c".to_owned()],
+            map_data.rooms["107"].vertices
+        );
+
+        let unchanged = map_data.clone();
+        assert!(matches!(
+            map_data.merge_rooms("107", "107"),
+            Err(MapDataError::MergeRoomWithItself(number)) if number == "107"
+        ));
+        assert!(matches!(
+            map_data.merge_rooms("107", "108"),
+            Err(MapDataError::UndefinedRoomNumber(number)) if number == "108"
+        ));
+        assert!(matches!(
+            map_data.split_room("107", "107a", hash_set!["a".to_owned()]),
+            Err(MapDataError::VertexNotInRoom { room, vertex }) if room == "107" && vertex == "a"
+        ));
+        assert!(matches!(
+            map_data.split_room("107", "106", hash_set!["b".to_owned()]),
+            Err(MapDataError::RepeatedRoomNumber(number)) if number == "106"
+        ));
+        assert!(matches!(
+            map_data.split_room("107", "107a", hash_set!["b".to_owned(), "c".to_owned()]),
+            Err(MapDataError::EmptySplit(number)) if number == "107"
+        ));
+        assert!(matches!(
+            map_data.split_room("107", "107a", hash_set![]),
+            Err(MapDataError::EmptySplit(number)) if number == "107"
+        ));
+        assert_eq!(unchanged, map_data);
+    }
+
+    #[test]
+    fn near_duplicate_vertices() {
+        let json = file("tests/json/simple.json");
+        let mut map_data = uncompiled::MapData::new(&json).unwrap();
+        assert_eq!(
+            vec![("b".to_owned(), "c".to_owned(), 1.)],
+            map_data.near_duplicate_vertices(1.5)
+        );
+        assert!(map_data.near_duplicate_vertices(1.).is_empty());
+        assert!(map_data.near_duplicate_vertices(0.).is_empty());
+
+        // On another floor, right on top of `b`
+        map_data.floors.push(Floor::new(
+            "2".to_owned(),
+            PathBuf::from("2nd_floor.svg"),
+            (0., 0.),
+        ));
+        map_data
+            .vertices
+            .insert("d".to_owned(), Vertex::new("2".to_owned(), (0., 0.)));
+        assert_eq!(1, map_data.near_duplicate_vertices(1.5).len());
+
+        let unchanged = map_data.clone();
+        assert!(matches!(
+            map_data.merge_vertices("b", "b"),
+            Err(MapDataError::MergeVertexWithItself(id)) if id == "b"
+        ));
+        assert!(matches!(
+            map_data.merge_vertices("b", "e"),
+            Err(MapDataError::UndefinedVertexId(id)) if id == "e"
+        ));
+        assert!(matches!(
+            map_data.merge_vertices("b", "d"),
+            Err(MapDataError::MergeVerticesAcrossFloors { keep, remove }) if keep == "b" && remove == "d"
+        ));
+        assert_eq!(unchanged, map_data);
+
+        // Becomes a second edge from `a` to `b`
+        map_data.edges.push(
+            Edge::new("a".to_owned(), "c".to_owned(), true)
+                .with_tags(hash_set![EdgeTag::StaffOnly]),
+        );
+        // Goes the other way, so it's kept
+        map_data
+            .edges
+            .push(Edge::new("c".to_owned(), "a".to_owned(), true));
+        map_data.merge_vertices("b", "c").unwrap();
+        assert!(!map_data.vertices.contains_key("c"));
+        assert_eq!(hash_set!["b".to_owned()], map_data.rooms["107"].vertices);
+        // The edge between them is gone, and the parallel edges are merged
+        assert_eq!(2, map_data.edges.len());
+        assert_eq!(
+            ("a", "b"),
+            (map_data.edges[0].get_from(), map_data.edges[0].get_to())
+        );
+        assert_eq!(&hash_set![EdgeTag::StaffOnly], map_data.edges[0].get_tags());
+        assert_eq!(
+            ("b", "a"),
+            (map_data.edges[1].get_from(), map_data.edges[1].get_to())
+        );
+        assert!(map_data.near_duplicate_vertices(1.5).is_empty());
+    }
+
+    #[test]
+    fn prefix_ids() {
+        let json = file("tests/json/simple.json");
+        let mut map_data = uncompiled::MapData::new(&json).unwrap();
+        map_data.prefix_ids("north-");
+
+        let mut vertex_ids: Vec<_> = map_data.vertices.keys().map(String::as_str).collect();
+        vertex_ids.sort_unstable();
+        assert_eq!(vec!["north-a", "north-b", "north-c"], vertex_ids);
+        assert_eq!(
+            hash_set!["north-a".to_owned()],
+            map_data.rooms["106"].vertices
+        );
+        assert!(map_data.rooms.contains_key("107"));
+        assert_eq!("north-c", map_data.edges[0].get_from());
+        assert_eq!("north-b", map_data.edges[0].get_to());
+        assert_eq!("1", map_data.floors[0].get_number());
+
+        // Rooms still match the elements of the floor images
+        let json = file("tests/json/compile.json");
+        let map_data = uncompiled::MapData::new(&json).unwrap();
+        let room_count = map_data.rooms.len();
+        let mut prefixed = map_data.clone();
+        prefixed.prefix_ids("north-");
+        let compiled = prefixed
+            .compile_with_options(Path::new("tests/json"), &CompileOptions::default())
+            .unwrap();
+        assert_eq!(room_count, compiled.rooms.len());
+        assert!(compiled
+            .rooms
+            .values()
+            .flat_map(|room| &room.vertices)
+            .all(|id| id.starts_with("north-") && compiled.vertices.contains_key(id)));
+    }
+
+    #[test]
+    fn tag_strings() {
+        for tag in RoomTag::known_tags() {
+            assert_eq!(Ok(&tag), tag.to_string().parse().as_ref());
+            assert_eq!(format!("\"{}\"", tag), serde_json::to_string(&tag).unwrap());
+        }
+        for tag in VertexTag::known_tags() {
+            assert_eq!(Ok(&tag), tag.to_string().parse().as_ref());
+        }
+        assert_eq!(Ok(RoomTag::BleedControl), "bleed-control".parse());
+        assert_eq!(
+            Ok(VertexTag::Other("ramp".to_owned())),
+            "ramp".parse::<VertexTag>()
+        );
+        assert_eq!(
+            "quiet-room",
+            RoomTag::Other("quiet-room".to_owned()).to_string()
+        );
+    }
+
+    #[test]
+    fn unknown_tags_survive_compilation() {
+        let json = file("tests/json/compile.json");
+        let map_data = uncompiled::MapData::new(&json).unwrap();
+        let compiled = map_data
+            .compile(std::path::Path::new("tests/json"))
+            .unwrap();
+        let compiled_json = serde_json::to_string(&compiled).unwrap();
+        let compiled: compiled::MapData = serde_json::from_str(&compiled_json).unwrap();
+
+        assert_eq!(
+            hash_set![RoomTag::Closed, RoomTag::Other("quiet-room".to_owned())],
+            compiled.rooms["106"].tags
+        );
+        assert_eq!(
+            hash_set![VertexTag::Stairs, VertexTag::Other("ramp".to_owned())],
+            compiled.vertices["a"].tags
+        );
+        assert!(compiled_json.contains(r#""quiet-room""#));
+    }
+
+    #[test]
+    fn metadata_survives_compilation() {
+        let json = file("tests/json/compile.json");
+        let mut map_data = uncompiled::MapData::new(&json).unwrap();
+        let schema = MetadataSchema::new(&file("tests/json/metadata_schema.json")).unwrap();
+        map_data.verify_metadata(&schema).unwrap();
+
+        let library = map_data.rooms.get_mut("107").unwrap();
+        assert_eq!(Some(&json!(60)), library.metadata().get("capacity"));
+        assert_eq!(Some(json!(60)), library.set_metadata("capacity", json!(65)));
+
+        let compiled = map_data
+            .compile(std::path::Path::new("tests/json"))
+            .unwrap();
+        let compiled_json = serde_json::to_string(&compiled).unwrap();
+        let compiled: compiled::MapData = serde_json::from_str(&compiled_json).unwrap();
+        assert_eq!(
+            hash_map![
+                "department".to_owned() => json!("media center"),
+                "capacity".to_owned() => json!(65),
+                "extension".to_owned() => json!("4321"),
+            ],
+            *compiled.rooms["107"].metadata()
+        );
+        assert!(compiled.rooms["106"].metadata().is_empty());
+    }
+
+    #[test]
+    fn reject_metadata_not_matching_schema() {
+        let json = file("tests/json/compile.json");
+        let mut map_data = uncompiled::MapData::new(&json).unwrap();
+        let schema = MetadataSchema::new(&file("tests/json/metadata_schema.json")).unwrap();
+        map_data
+            .rooms
+            .get_mut("106")
+            .unwrap()
+            .set_metadata("extension", json!(1234));
+        // Keys the schema doesn't mention can have any type
+        map_data
+            .rooms
+            .get_mut("106")
+            .unwrap()
+            .set_metadata("notes", json!(["anything"]));
+
+        match map_data.verify_metadata(&schema) {
+            Err(MapDataError::InvalidMetadata {
+                room,
+                key,
+                expected,
+            }) => {
+                assert_eq!("106", room);
+                assert_eq!("extension", key);
+                assert_eq!(MetadataType::String, expected);
+            }
+            other => panic!("Should be invalid metadata, was {:?}", other),
+        }
+    }
+
+    /// Map data from `tests/json/compile.json`, changed by `edit`
+    fn edited_compile_json(edit: impl FnOnce(&mut Value)) -> uncompiled::MapData {
+        let mut json: Value = serde_json::from_str(&file("tests/json/compile.json")).unwrap();
+        edit(&mut json);
+        uncompiled::MapData::new(&json.to_string()).unwrap()
+    }
+
+    /// Map data from `tests/json/compile.json` with the room `108` from `annex.svg`
+    fn with_annex(images: Value) -> uncompiled::MapData {
+        edited_compile_json(|json| {
+            json["floors"][0]["image"] = images;
+            json["rooms"]["108"] = serde_json::json!({ "vertices": ["a"] });
+        })
+    }
+
+    /// Map data from `tests/json/compile.json` with a second floor drawn by the same image
+    fn room_on_two_floors() -> uncompiled::MapData {
+        edited_compile_json(|json| {
+            let mut second_floor = json["floors"][0].clone();
+            second_floor["number"] = "2".into();
+            json["floors"].as_array_mut().unwrap().push(second_floor);
+        })
+    }
+
+    #[test]
+    fn rooms_from_every_floor_image() {
+        let map_data = with_annex(serde_json::json!(["../svg/rooms.svg", "../svg/annex.svg"]));
+        let floor = &map_data.floors[0];
+        assert_eq!(Path::new("../svg/rooms.svg"), floor.get_image());
+        assert_eq!(2, floor.get_images().len());
+
+        let compiled = map_data.compile(Path::new("tests/json")).unwrap();
+        let mut numbers: Vec<_> = compiled.rooms.keys().map(String::as_str).collect();
+        numbers.sort_unstable();
+        assert_eq!(vec!["106", "107", "108"], numbers);
+
+        let compiled_json = serde_json::to_string(&compiled).unwrap();
+        assert!(compiled_json.contains(r#""image":["../svg/rooms.svg","../svg/annex.svg"]"#));
+    }
+
+    #[test]
+    fn single_image_stays_a_path() {
+        let json = file("tests/json/compile.json");
+        let compiled = uncompiled::MapData::new(&json)
+            .unwrap()
+            .compile(Path::new("tests/json"))
+            .unwrap();
+        let compiled_json = serde_json::to_string(&compiled).unwrap();
+        assert!(compiled_json.contains(r#""image":"../svg/rooms.svg""#));
+
+        let no_images = json.replace(r#""../svg/rooms.svg""#, "[]");
+        match uncompiled::MapData::new(&no_images) {
+            Err(MapDataDeserializeError::InvalidJson(err)) => {
+                assert!(err.to_string().contains("at least one image"), "{}", err)
+            }
+            other => panic!("Should be invalid JSON, got {:?}", other.err()),
+        }
+    }
+
+    fn compile_error(map_data: uncompiled::MapData, options: &CompileOptions) -> CompileError {
+        map_data
+            .compile_with_options(Path::new("tests/json"), options)
+            .unwrap_err()
+            .downcast()
+            .unwrap()
+    }
+
+    #[test]
+    fn reject_room_in_two_images_of_a_floor() {
+        assert_eq!(
+            CompileError::DuplicateSvgRoom {
+                room: "106".to_owned(),
+                floor: "1".to_owned()
+            },
+            compile_error(
+                with_annex(serde_json::json!(["../svg/rooms.svg", "../svg/rooms.svg"])),
+                &CompileOptions::default()
+            )
+        );
+    }
+
+    #[test]
+    fn reject_duplicate_svg_room() {
+        let options = CompileOptions {
+            allow_rooms_on_multiple_floors: true,
+            ..CompileOptions::default()
+        };
+        assert_eq!(
+            CompileError::DuplicateSvgRoom {
+                room: "106".to_owned(),
+                floor: "1".to_owned()
+            },
+            compile_error(
+                edited_compile_json(|json| {
+                    json["floors"][0]["image"] = "../svg/duplicate_room.svg".into();
+                }),
+                &options
+            )
+        );
+    }
+
+    #[test]
+    fn rooms_on_multiple_floors() {
+        match compile_error(room_on_two_floors(), &CompileOptions::default()) {
+            CompileError::RoomOnMultipleFloors {
+                first_floor,
+                second_floor,
+                ..
+            } => assert_eq!(("1", "2"), (first_floor.as_str(), second_floor.as_str())),
+            other => panic!("Should be a room on multiple floors, was {:?}", other),
+        }
+
+        let options = CompileOptions {
+            allow_rooms_on_multiple_floors: true,
+            ..CompileOptions::default()
+        };
+        let compiled = room_on_two_floors()
+            .compile_with_options(Path::new("tests/json"), &options)
+            .unwrap();
+        assert_eq!(2, compiled.rooms.len());
+    }
+
+    #[test]
+    fn multi_floor_rooms() {
+        let json = file("tests/json/stairwell.json");
+        let mut map_data = uncompiled::MapData::new(&json).unwrap();
+        let compiled = map_data
+            .clone()
+            .compile_with_options(Path::new("tests/json"), &CompileOptions::default())
+            .unwrap();
+
+        let stairwell = &compiled.rooms["150"];
+        assert_eq!(
+            &[(60., 90.), (60., 50.), (80., 50.), (80., 90.)][..],
+            stairwell.outline.points()
+        );
+        assert_eq!(Some("1"), stairwell.floor.as_deref());
+        // The landing on the second floor is around a column
+        let (outline, holes) = stairwell.outline_on_floor("2");
+        assert_eq!(
+            &[(60., 90.), (80., 90.), (80., 60.), (60., 60.)][..],
+            outline.points()
+        );
+        assert_eq!(1, holes.len());
+        assert_eq!(
+            &[(66., 80.), (74., 80.), (74., 70.), (66., 70.)][..],
+            holes[0].points()
+        );
+        assert_eq!(&stairwell.outline, stairwell.outline_on_floor("1").0);
+        assert_eq!(Some("1"), compiled.room_floor("150"));
+        let rooms_on_floor = |compiled: &compiled::MapData, floor| {
+            let mut rooms: Vec<_> = compiled
+                .rooms_on_floor(floor)
+                .map(|(number, _room)| number.clone())
+                .collect();
+            rooms.sort_unstable();
+            rooms
+        };
+        for (floor, expected) in [("1", ["101", "150"]), ("2", ["150", "201"])] {
+            assert_eq!(expected.to_vec(), rooms_on_floor(&compiled, floor));
+        }
+
+        // A stairwell only reached from the second floor is still drawn on the first, where its
+        // outline is from
+        let mut upper_entrance = map_data.clone();
+        upper_entrance
+            .rooms
+            .get_mut("150")
+            .unwrap()
+            .vertices
+            .remove("stairs-1");
+        let compiled = upper_entrance
+            .compile_with_options(Path::new("tests/json"), &CompileOptions::default())
+            .unwrap();
+        assert_eq!(Some("2"), compiled.room_floor("150"));
+        for (floor, expected) in [("1", ["101", "150"]), ("2", ["150", "201"])] {
+            assert_eq!(expected.to_vec(), rooms_on_floor(&compiled, floor));
+        }
+
+        // Without the tag, the stairwell is like any other room
+        map_data
+            .rooms
+            .get_mut("150")
+            .unwrap()
+            .tags
+            .remove(&RoomTag::MultiFloor);
+        let compiled = map_data
+            .clone()
+            .compile_with_options(
+                Path::new("tests/json"),
+                &CompileOptions {
+                    allow_rooms_on_multiple_floors: true,
+                    ..CompileOptions::default()
+                },
+            )
+            .unwrap();
+        assert!(compiled.rooms["150"].outlines.is_empty());
+        assert_eq!(
+            0,
+            compiled
+                .rooms_on_floor("2")
+                .filter(|(number, _room)| *number == "150")
+                .count()
+        );
+        assert!(matches!(
+            map_data
+                .compile_with_options(Path::new("tests/json"), &CompileOptions::default())
+                .unwrap_err()
+                .downcast()
+                .unwrap(),
+            CompileError::RoomOnMultipleFloors { room, .. } if room == "150"
+        ));
+    }
+
+    #[test]
+    fn link_vertex_groups() {
+        let json = file("tests/json/vertex_groups.json");
+        let map_data = uncompiled::MapData::new(&json).unwrap();
+        assert_eq!(Some("stair-a"), map_data.vertices["a2"].get_group());
+
+        let mut edges: Vec<_> = map_data
+            .edges
+            .iter()
+            .map(|edge| (edge.get_from(), edge.get_to(), edge.is_directed()))
+            .collect();
+        edges.sort_unstable();
+        // The existing edge between the first two landings isn't repeated, and vertices in the
+        // same group on floors that aren't adjacent aren't connected
+        assert_eq!(
+            vec![
+                ("a2", "a1", false),
+                ("a2", "a3", false),
+                ("e1", "e2", false),
+                ("e1", "e2b", false),
+            ],
+            edges
+        );
+
+        // Verifying again doesn't add anything
+        let json = serde_json::to_string(&map_data).unwrap();
+        let read = uncompiled::MapData::new(&json).unwrap();
+        assert_eq!(map_data.edges.len(), read.edges.len());
+    }
+
+    #[test]
+    #[cfg(feature = "csv")]
+    fn import_room_names() {
+        let json = file("tests/json/compile.json");
+        let mut map_data: uncompiled::MapData = serde_json::from_str(&json).unwrap();
+        let csv = "name,room\n\
+            Library,107\n\
+            library,107\n\
+            \"Media Center, North\",107\n\
+            Library,107\n\
+            Gym,999\n";
+
+        let report = map_data.import_names_csv(csv.as_bytes()).unwrap();
+        assert_eq!(
+            uncompiled::ImportReport {
+                unknown_rooms: vec!["999".to_owned()],
+                missing_rooms: vec!["106".to_owned()],
+            },
+            report
+        );
+        assert_eq!(
+            vec!["library", "Library", "Media Center, North"],
+            map_data.rooms["107"].names
+        );
+        assert!(map_data.rooms["106"].names.is_empty());
+        assert!(map_data.clone().verify().is_ok());
+
+        assert!(matches!(
+            map_data
+                .clone()
+                .import_names_csv("room,names\n107,Library\n".as_bytes()),
+            Err(uncompiled::ImportError::MissingColumn("name"))
+        ));
+    }
 }
@@ -0,0 +1,34 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::Deserialize;
+
+use crate::map_data::RoomTag;
+
+/// Changes to rooms applied on top of the map data, such as closing rooms during construction,
+/// without editing the map data itself.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Overrides {
+    #[serde(default)]
+    pub rooms: HashMap<String, RoomOverride>,
+}
+
+impl Overrides {
+    pub fn new(json_data: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json_data)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct RoomOverride {
+    #[serde(default)]
+    pub add_tags: HashSet<RoomTag>,
+    #[serde(default)]
+    pub remove_tags: HashSet<RoomTag>,
+    /// Replaces all of the room's names
+    #[serde(default)]
+    pub names: Option<Vec<String>>,
+    /// Removes the room from the map entirely
+    #[serde(default)]
+    pub removed: bool,
+}
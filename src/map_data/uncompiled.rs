@@ -1,13 +1,24 @@
 use std::collections::{HashMap, HashSet};
-use std::convert::TryFrom;
-use std::fs;
+use std::fmt;
+use std::fs::File;
+#[cfg(feature = "csv")]
+use std::io;
+use std::path::{Path, PathBuf};
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
-use crate::map_data::{compiled, Edge, Floor, RoomTag, Vertex};
-use crate::svg_room::SvgRoom;
-use crate::util::{centroid, shoelace_area, undefined, unique};
-use std::path::Path;
+use crate::geometry::Polygon;
+use crate::map_data::compile::{Compiler, ImageExtentCheck};
+use crate::map_data::metadata::{MetadataSchema, MetadataType};
+use crate::map_data::overrides::Overrides;
+use crate::map_data::{
+    compiled, computed_center, floor_ordinals, Edge, Floor, FloorIndex, Poi, RoomTag, UnknownFloor,
+    Vertex, VertexTag,
+};
+use crate::routing::is_cross_floor;
+use crate::svg_room::SvgRoomConfig;
+use crate::util::{area_with_holes, natural_cmp, undefined, unique};
 
 #[derive(thiserror::Error, Debug)]
 pub enum MapDataDeserializeError {
@@ -27,18 +38,334 @@ pub enum MapDataError {
     UndefinedFloorNumber(String),
     #[error("The vertex ID `{0}` is undefined")]
     UndefinedVertexId(String),
+    #[error("The room number `{0}` is undefined")]
+    UndefinedRoomNumber(String),
+    /// Room numbers in overrides that aren't in the map data, sorted
+    #[error("The room numbers `{}` in the overrides are undefined", .0.join("`, `"))]
+    UndefinedOverrideRooms(Vec<String>),
+    #[error("The room number `{0}` was repeated")]
+    RepeatedRoomNumber(String),
+    #[error("The offsets of floor `{0}` are not finite")]
+    NonFiniteOffsets(String),
+    #[error("The location of vertex `{0}` is not finite")]
+    NonFiniteLocation(String),
+    #[error("The center of room `{0}` is not finite")]
+    NonFiniteCenter(String),
+    #[error("The metadata `{key}` of room `{room}` should be of type {expected:?}")]
+    InvalidMetadata {
+        room: String,
+        key: String,
+        expected: MetadataType,
+    },
+    #[error("The room `{0}` can't be merged into itself")]
+    MergeRoomWithItself(String),
+    #[error("The vertex `{0}` can't be merged into itself")]
+    MergeVertexWithItself(String),
+    #[error(
+        "The vertices `{keep}` and `{remove}` are on different floors, so they can't be merged"
+    )]
+    MergeVerticesAcrossFloors { keep: String, remove: String },
+    #[error("The vertex `{vertex}` is on a different floor from room `{room}`")]
+    RoomVertexOnOtherFloor { room: String, vertex: String },
+    #[error("The vertex `{vertex}` is not one of the vertices of room `{room}`")]
+    VertexNotInRoom { room: String, vertex: String },
+    #[error("Splitting room `{0}` would leave a room without any vertices")]
+    EmptySplit(String),
+    #[error("The room `{0}` has no vertices, so it can't be routed to")]
+    RoomWithoutVertices(String),
+    #[error("The point of interest ID `{0}` was repeated")]
+    RepeatedPoiId(String),
+    #[error("The location of point of interest `{0}` is not finite")]
+    NonFinitePoiLocation(String),
+    #[error(
+        "The vertices `{from}` and `{to}` are joined by both an undirected and a directed edge"
+    )]
+    MixedEdgeDirections { from: String, to: String },
 }
 
-#[derive(Deserialize, Debug, PartialEq)]
+impl MapDataError {
+    /// Returns the floor number, vertex ID, room number, or point of interest ID the error is about
+    pub fn id(&self) -> Option<&str> {
+        match self {
+            Self::RepeatedFloorNumber(id)
+            | Self::RepeatedVertexId(id)
+            | Self::UndefinedFloorNumber(id)
+            | Self::UndefinedVertexId(id)
+            | Self::UndefinedRoomNumber(id)
+            | Self::RepeatedRoomNumber(id)
+            | Self::NonFiniteOffsets(id)
+            | Self::NonFiniteLocation(id)
+            | Self::NonFiniteCenter(id)
+            | Self::MergeRoomWithItself(id)
+            | Self::MergeVertexWithItself(id)
+            | Self::EmptySplit(id)
+            | Self::RoomWithoutVertices(id)
+            | Self::RepeatedPoiId(id)
+            | Self::NonFinitePoiLocation(id)
+            | Self::InvalidMetadata { room: id, .. }
+            | Self::RoomVertexOnOtherFloor { room: id, .. }
+            | Self::VertexNotInRoom { vertex: id, .. }
+            | Self::MixedEdgeDirections { from: id, .. }
+            | Self::MergeVerticesAcrossFloors { keep: id, .. } => Some(id),
+            Self::UndefinedOverrideRooms(rooms) => match rooms.as_slice() {
+                [room] => Some(room),
+                _ => None,
+            },
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum CompileError {
+    #[error("The room `{room}` appears more than once in the images of floor `{floor}`")]
+    DuplicateSvgRoom { room: String, floor: String },
+    #[error("The room `{room}` appears on both floor `{first_floor}` and floor `{second_floor}`")]
+    RoomOnMultipleFloors {
+        room: String,
+        first_floor: String,
+        second_floor: String,
+    },
+    #[error("Error reading the image `{}`: {reason}", path.display())]
+    ReadImage { path: PathBuf, reason: String },
+    #[error("Error in an image of floor `{floor}`: {reason}")]
+    InvalidImage { floor: String, reason: String },
+    #[error("The room ID pattern of floor `{floor}` is invalid: {reason}")]
+    InvalidIdPattern { floor: String, reason: String },
+    #[error("Floor images can't be used: {}", describe_missing_images(.0))]
+    MissingFloorImage(Vec<MissingImage>),
+    #[error("The room `{0}` has no vertices, so it can't be routed to")]
+    RoomWithoutVertices(String),
+    #[error("The room `{room}` has no {dimension} in the images of floor `{floor}`")]
+    CollapsedRoom {
+        room: String,
+        floor: String,
+        dimension: Dimension,
+    },
+    #[error("Floor `{0}` has offsets, which svg-native coordinates don't apply")]
+    OffsetsWithSvgNative(String),
+}
+
+impl CompileError {
+    /// Returns the room or floor number the error is about, if it's about only one
+    pub fn id(&self) -> Option<&str> {
+        match self {
+            Self::DuplicateSvgRoom { room, .. }
+            | Self::RoomOnMultipleFloors { room, .. }
+            | Self::RoomWithoutVertices(room)
+            | Self::CollapsedRoom { room, .. } => Some(room),
+            Self::InvalidImage { floor, .. }
+            | Self::InvalidIdPattern { floor, .. }
+            | Self::OffsetsWithSvgNative(floor) => Some(floor),
+            Self::MissingFloorImage(images) => match images.as_slice() {
+                [image] => Some(&image.floor),
+                _ => None,
+            },
+            Self::ReadImage { .. } => None,
+        }
+    }
+}
+
+/// Width or height of a shape
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dimension {
+    Width,
+    Height,
+}
+
+impl fmt::Display for Dimension {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Width => write!(f, "width"),
+            Self::Height => write!(f, "height"),
+        }
+    }
+}
+
+/// Floor image that can't be compiled, found by [`MapData::missing_floor_images`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingImage {
+    pub floor: String,
+    pub path: PathBuf,
+    pub reason: MissingImageReason,
+}
+
+impl fmt::Display for MissingImage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "`{}` of floor `{}` {}",
+            self.path.display(),
+            self.floor,
+            self.reason
+        )
+    }
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum MissingImageReason {
+    #[error("does not exist")]
+    DoesNotExist,
+    #[error("is not an SVG")]
+    NotSvg,
+    #[error("can't be read: {0}")]
+    Unreadable(String),
+}
+
+fn describe_missing_images(images: &[MissingImage]) -> String {
+    images
+        .iter()
+        .map(MissingImage::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(feature = "csv")]
+#[derive(thiserror::Error, Debug)]
+pub enum ImportError {
+    #[error("Error reading CSV: {0}")]
+    Csv(#[from] csv::Error),
+    #[error("The CSV has no `{0}` column")]
+    MissingColumn(&'static str),
+}
+
+/// Rooms that didn't match up between an imported CSV and the map data, sorted by room number
+#[cfg(feature = "csv")]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    /// Rooms in the CSV that don't exist in the map data, which were skipped
+    pub unknown_rooms: Vec<String>,
+    /// Rooms in the map data that aren't in the CSV
+    pub missing_rooms: Vec<String>,
+}
+
+/// Options for compiling map data
+#[derive(Debug, Clone, Default)]
+pub struct CompileOptions {
+    /// Allow a room to appear in the images of more than one floor instead of failing. The room's
+    /// outline comes from the first floor it appears on, and a warning is printed with its bounds
+    /// on each floor. Rooms tagged [`RoomTag::MultiFloor`], such as stairwells, are always
+    /// allowed on more than one floor, and keep their outline on each floor.
+    ///
+    /// Compiling used to keep the outline from the first floor and print that the room doesn't
+    /// exist on the others, so map data with rooms on several floors fails to compile unless this
+    /// is set (`--allow-rooms-on-multiple-floors`) or the rooms are tagged `multi-floor`.
+    pub allow_rooms_on_multiple_floors: bool,
+    /// Compile without floor images that are missing, can't be read, or aren't SVGs, instead of
+    /// failing. Rooms in the other images are still compiled, and a warning is printed for each
+    /// image left out.
+    pub allow_missing_images: bool,
+    /// Compute the offsets of floors from an element with the ID `registration` in their images,
+    /// so that it lands at the same map coordinates on every floor. The first floor with a
+    /// registration element keeps its offsets, and floors without one keep theirs with a
+    /// warning. Offsets are only used with [`CoordinateSystem::YUpWithOffsets`], so this does
+    /// nothing with other coordinate systems.
+    ///
+    /// [`CoordinateSystem::YUpWithOffsets`]: crate::transform::CoordinateSystem::YUpWithOffsets
+    pub auto_offsets: bool,
+    /// Allow rooms without any vertices, which can't be routed to, instead of failing. They're
+    /// compiled with [`RoomTag::NoAccess`] so that clients can draw them without routing to them.
+    pub allow_unrouted_rooms: bool,
+    /// Warn about vertices whose IDs are also the IDs of elements in their floor's images, but
+    /// which lie outside of those elements, such as a vertex `stairA` outside of the element drawing
+    /// the stairs
+    pub check_vertex_elements: bool,
+    /// Fail on rooms whose outlines are narrower than
+    /// [`COLLAPSED_ROOM_EPSILON`](crate::map_data::compile::COLLAPSED_ROOM_EPSILON) in either
+    /// direction, such as a rect with `width="0"`, instead of warning about them. They can't be
+    /// hit-tested, since they have no area.
+    pub strict: bool,
+    /// When to warn that the images of a floor don't fit the floor's vertices, such as when a
+    /// floor was given the image of another floor
+    pub image_extent: ImageExtentCheck,
+    /// How room elements are recognized in floor images, and the coordinate system of the
+    /// compiled map data
+    pub svg_room: SvgRoomConfig,
+}
+
+/// Map data as it's written, before it's compiled with its floor images. Outside this crate, map
+/// data is created with [`Self::new`], [`Self::from_parts`], or [`Self::empty`] rather than a
+/// struct literal, since it keeps an index of its floors alongside its fields.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[non_exhaustive]
 pub struct MapData {
     pub floors: Vec<Floor>,
+    #[serde(serialize_with = "super::sorted_map::serialize")]
     pub vertices: HashMap<String, Vertex>,
+    #[serde(deserialize_with = "super::edge_list::deserialize")]
     pub edges: Vec<Edge>,
+    #[serde(serialize_with = "super::sorted_map::serialize")]
     pub rooms: HashMap<String, Room>,
+    /// Points of interest that aren't rooms, by ID
+    #[serde(default)]
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    #[serde(serialize_with = "super::sorted_map::serialize")]
+    pub pois: HashMap<String, Poi>,
+    /// Prefixes of the IDs of room elements in the images of floors without prefixes of their
+    /// own, used in place of [`SvgRoomConfig::id_prefixes`] when compiling unless empty
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub id_prefixes: Vec<String>,
+    /// Regular expression for the IDs of room elements in the images of floors without a pattern
+    /// of their own, used in place of [`SvgRoomConfig::id_pattern`] when compiling
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id_pattern: Option<String>,
+    #[serde(skip)]
+    pub(super) floor_index: FloorIndex,
 }
 
 impl MapData {
-    fn verify(self) -> Result<Self, MapDataError> {
+    /// Creates map data without any floors, vertices, edges, or rooms
+    pub fn empty() -> Self {
+        Self::default()
+    }
+
+    /// Creates map data from its floors, vertices, edges, and rooms, without any points of interest
+    pub fn from_parts(
+        floors: Vec<Floor>,
+        vertices: HashMap<String, Vertex>,
+        edges: Vec<Edge>,
+        rooms: HashMap<String, Room>,
+    ) -> Self {
+        Self {
+            floors,
+            vertices,
+            edges,
+            rooms,
+            ..Self::default()
+        }
+    }
+
+    /// Finds the floor numbered `number`. Floor numbers are matched exactly, including case, so
+    /// `"g"` doesn't find a floor numbered `"G"`.
+    pub fn floor(&self, number: &str) -> Option<&Floor> {
+        self.floor_index.find(&self.floors, number)
+    }
+
+    /// Same as [`Self::floor`], but the error lists the floors there are, for reporting unknown
+    /// floors given by users
+    pub fn require_floor(&self, number: &str) -> Result<&Floor, UnknownFloor> {
+        self.floor_index.require(&self.floors, number)
+    }
+
+    pub fn has_floor(&self, number: &str) -> bool {
+        self.floor(number).is_some()
+    }
+
+    /// Returns the numbers of the floors from lowest to highest
+    pub fn floor_numbers(&self) -> Vec<&str> {
+        self.floors.iter().map(Floor::get_number).collect()
+    }
+
+    /// Checks map data that was deserialized or changed without going through [`Self::new`], and
+    /// adds the edges between vertex groups
+    pub fn verify(self) -> Result<Self, MapDataError> {
+        self.verify_with_options(&CompileOptions::default())
+    }
+
+    /// Same as [`Self::verify`], but allows what `options` allows, such as rooms without vertices
+    /// with [`CompileOptions::allow_unrouted_rooms`]
+    pub fn verify_with_options(mut self, options: &CompileOptions) -> Result<Self, MapDataError> {
         // Get floor numbers and check that all are unique
         let floor_numbers = unique(self.floors.iter().map(|f| &f.number))
             .map_err(|floor_number| MapDataError::RepeatedFloorNumber(floor_number.to_owned()))?;
@@ -51,6 +378,8 @@ impl MapData {
         .map_err(|floor_number: &String| {
             MapDataError::UndefinedFloorNumber(floor_number.clone())
         })?;
+        undefined(self.pois.values().map(|poi| &poi.floor), &floor_numbers)
+            .map_err(|floor_number| MapDataError::UndefinedFloorNumber(floor_number.clone()))?;
 
         // Check that there are no undefined vertices in the rooms
         let room_vertex_ids = self.rooms.values().map(|r| &r.vertices).flatten();
@@ -62,92 +391,774 @@ impl MapData {
         undefined(edge_vertex_ids, &self.vertices.keys().collect())
             .map_err(|vertex_id| MapDataError::UndefinedVertexId(vertex_id.clone()))?;
 
+        // Check that there are no undefined vertices linked to points of interest
+        let poi_vertex_ids = self.pois.values().filter_map(|poi| poi.vertex.as_ref());
+        undefined(poi_vertex_ids, &self.vertices.keys().collect())
+            .map_err(|vertex_id| MapDataError::UndefinedVertexId(vertex_id.clone()))?;
+
+        // Check that every room can be routed to
+        if !options.allow_unrouted_rooms {
+            let unrouted = self
+                .rooms
+                .iter()
+                .filter(|(_number, room)| room.vertices.is_empty())
+                .map(|(number, _room)| number)
+                .min();
+            if let Some(number) = unrouted {
+                return Err(MapDataError::RoomWithoutVertices(number.clone()));
+            }
+        }
+
+        self.verify_finite()?;
+        self.verify_edge_directions()?;
+
+        self.link_vertex_groups();
+
         Ok(self)
     }
 
-    pub fn new(json_data: &str) -> Result<Self, MapDataDeserializeError> {
-        Ok(serde_json::from_str::<Self>(json_data)?.verify()?)
+    /// Checks that no two vertices are joined by both an undirected edge and a directed one, since
+    /// the directed edge does nothing and was probably meant to replace the undirected one
+    fn verify_edge_directions(&self) -> Result<(), MapDataError> {
+        fn pair(edge: &Edge) -> (&str, &str) {
+            let (from, to) = (edge.get_from(), edge.get_to());
+            (from.min(to), from.max(to))
+        }
+        let undirected: HashSet<_> = self
+            .edges
+            .iter()
+            .filter(|edge| !edge.is_directed())
+            .map(pair)
+            .collect();
+        let mixed = self
+            .edges
+            .iter()
+            .filter(|edge| edge.is_directed())
+            .map(pair)
+            .filter(|pair| undirected.contains(pair))
+            .min();
+        match mixed {
+            Some((from, to)) => Err(MapDataError::MixedEdgeDirections {
+                from: from.to_owned(),
+                to: to.to_owned(),
+            }),
+            None => Ok(()),
+        }
     }
 
-    fn get_floor_images(&self, base_path: &Path) -> Vec<(String, (f32, f32))> {
-        self.floors
+    /// Finds pairs of directed edges with the same kind and tags that go opposite ways between the
+    /// same two vertices, which could be replaced with one undirected edge. Each pair of vertices
+    /// is sorted by ID, and the pairs are sorted by their IDs. Compiling warns about each of them.
+    pub fn directed_edge_twins(&self) -> Vec<(&str, &str)> {
+        let mut directed: HashMap<(&str, &str), Vec<&Edge>> = HashMap::new();
+        for edge in self.edges.iter().filter(|edge| edge.is_directed()) {
+            directed
+                .entry((edge.get_from(), edge.get_to()))
+                .or_default()
+                .push(edge);
+        }
+        let mut twins: Vec<_> = self
+            .edges
             .iter()
-            .map(|floor| (floor.get_image(), floor.get_offsets()))
-            .map(|(image_rel_path, o)| (base_path.join(image_rel_path), o))
-            .map(|(image_path, o)| {
-                (
-                    fs::read_to_string(image_path).expect("Image file doesn't exist"),
-                    o,
-                )
+            .filter(|edge| edge.is_directed() && edge.get_from() < edge.get_to())
+            .filter(|edge| {
+                directed
+                    .get(&(edge.get_to(), edge.get_from()))
+                    .is_some_and(|others| {
+                        others.iter().any(|other| {
+                            other.get_kind() == edge.get_kind()
+                                && other.get_tags() == edge.get_tags()
+                        })
+                    })
             })
-            .collect()
+            .map(|edge| (edge.get_from(), edge.get_to()))
+            .collect();
+        twins.sort_unstable();
+        twins.dedup();
+        twins
     }
 
-    fn svg_rooms(image_content: &str) -> impl Iterator<Item = anyhow::Result<SvgRoom>> + '_ {
-        let svg_reader = svg::read(image_content).expect("SVG must be valid");
-        svg_reader.filter_map(|event| {
-            event
-                .map(|event| SvgRoom::try_from(event).ok())
-                .map_err(|err| err.into())
-                .transpose()
+    /// Adds undirected edges between the vertices of each group on adjacent floors, such as the
+    /// landings of a stairwell, unless there is already an edge between them
+    fn link_vertex_groups(&mut self) {
+        let ordinals = floor_ordinals(&self.floors);
+        let mut groups: HashMap<&str, Vec<(usize, &str)>> = HashMap::new();
+        for (id, vertex) in &self.vertices {
+            if let Some(group) = vertex.get_group() {
+                let ordinal = ordinals[vertex.get_floor()];
+                groups.entry(group).or_default().push((ordinal, id));
+            }
+        }
+        let connected: HashSet<(&str, &str)> = self
+            .edges
+            .iter()
+            .flat_map(|e| [(e.get_from(), e.get_to()), (e.get_to(), e.get_from())])
+            .collect();
+
+        let mut groups: Vec<_> = groups.into_iter().collect();
+        groups.sort_unstable();
+        let mut new_edges = vec![];
+        for (_group, mut members) in groups {
+            members.sort_unstable();
+            for (i, (ordinal, from)) in members.iter().enumerate() {
+                for (other_ordinal, to) in &members[i + 1..] {
+                    if *other_ordinal == ordinal + 1 && !connected.contains(&(from, to)) {
+                        new_edges.push(Edge::new((*from).to_owned(), (*to).to_owned(), false));
+                    }
+                }
+            }
+        }
+        self.edges.extend(new_edges);
+    }
+
+    /// Finds the stairs and elevators that neither have an edge to another floor nor belong to a
+    /// group, since they were probably meant to connect to another floor, sorted by ID. Compiling
+    /// warns about each of them.
+    pub fn unlinked_vertical_vertices(&self) -> Vec<&str> {
+        self.vertices_off_cross_floor_edges(|tags| {
+            tags.contains(&VertexTag::Stairs) || tags.contains(&VertexTag::Elevator)
+        })
+        .filter(|(_id, vertex)| vertex.get_group().is_none())
+        .map(|(id, _vertex)| id)
+        .collect()
+    }
+
+    /// Finds the vertices tagged up or down without an edge to another floor, since the tags only
+    /// affect those edges, sorted by ID. Compiling warns about each of them.
+    pub fn unlinked_up_down_vertices(&self) -> Vec<&str> {
+        self.vertices_off_cross_floor_edges(|tags| {
+            tags.contains(&VertexTag::Up) || tags.contains(&VertexTag::Down)
         })
+        .map(|(id, _vertex)| id)
+        .collect()
     }
 
-    pub fn compile(mut self, base_path: &Path) -> anyhow::Result<compiled::MapData> {
-        let mut compiled_rooms = HashMap::with_capacity(self.rooms.len());
+    /// Iterates over the vertices sorted by ID that `has_tags` accepts the tags of and that aren't
+    /// on any edge to another floor
+    fn vertices_off_cross_floor_edges(
+        &self,
+        has_tags: impl Fn(&HashSet<VertexTag>) -> bool,
+    ) -> impl Iterator<Item = (&str, &Vertex)> {
+        let cross_floor: HashSet<&str> = self
+            .edges
+            .iter()
+            .filter(|edge| is_cross_floor(edge, &self.vertices))
+            .flat_map(|edge| [edge.get_from(), edge.get_to()])
+            .collect();
+        let mut vertices: Vec<_> = self
+            .vertices
+            .iter()
+            .map(|(id, vertex)| (id.as_str(), vertex))
+            .filter(|(id, vertex)| !cross_floor.contains(id) && has_tags(vertex.get_tags()))
+            .collect();
+        vertices.sort_unstable_by_key(|(id, _vertex)| *id);
+        vertices.into_iter()
+    }
+
+    /// Checks that all coordinates are finite, since a `NaN` or infinity would otherwise make its
+    /// way silently into room outlines and routing distances. Negative coordinates are allowed;
+    /// vertices far outside of the floor images are only warned about during compilation.
+    fn verify_finite(&self) -> Result<(), MapDataError> {
+        let is_finite = |(x, y): (f64, f64)| x.is_finite() && y.is_finite();
+
+        if let Some(floor) = self.floors.iter().find(|f| !is_finite(f.get_offsets())) {
+            return Err(MapDataError::NonFiniteOffsets(floor.number.clone()));
+        }
+        if let Some((id, _)) = self
+            .vertices
+            .iter()
+            .find(|(_id, v)| !is_finite(v.get_location()))
+        {
+            return Err(MapDataError::NonFiniteLocation(id.clone()));
+        }
+        if let Some((id, _)) = self.pois.iter().find(|(_id, poi)| !is_finite(poi.location)) {
+            return Err(MapDataError::NonFinitePoiLocation(id.clone()));
+        }
+        if let Some((number, _)) = self
+            .rooms
+            .iter()
+            .find(|(_number, r)| matches!(r.center, Some(center) if !is_finite(center)))
+        {
+            return Err(MapDataError::NonFiniteCenter(number.clone()));
+        }
+        Ok(())
+    }
+
+    pub fn new(json_data: &str) -> Result<Self, MapDataDeserializeError> {
+        Ok(serde_json::from_str::<Self>(json_data)?.verify()?)
+    }
+
+    /// Same as [`Self::new`], but applies `overrides` before verifying the map data.
+    pub fn new_with_overrides(
+        json_data: &str,
+        overrides: Overrides,
+    ) -> Result<Self, MapDataDeserializeError> {
+        let mut map_data = serde_json::from_str::<Self>(json_data)?;
+        map_data.apply_overrides(overrides)?;
+        Ok(map_data.verify()?)
+    }
+
+    /// Applies each room override to the room with its number. Fails without changing anything
+    /// if any overrides are for rooms that don't exist, listing all of them, so that overrides for
+    /// rooms which have since been renumbered or removed are noticed.
+    pub fn apply_overrides(&mut self, overrides: Overrides) -> Result<(), MapDataError> {
+        let mut undefined: Vec<_> = overrides
+            .rooms
+            .keys()
+            .filter(|number| !self.rooms.contains_key(*number))
+            .cloned()
+            .collect();
+        if !undefined.is_empty() {
+            undefined.sort_unstable_by(|a, b| natural_cmp(a, b));
+            return Err(MapDataError::UndefinedOverrideRooms(undefined));
+        }
+
+        for (number, room_override) in overrides.rooms {
+            if room_override.removed {
+                self.rooms.remove(&number);
+                continue;
+            }
+
+            let room = self
+                .rooms
+                .get_mut(&number)
+                .expect("Room numbers were checked above");
+            room.tags.extend(room_override.add_tags);
+            room.tags
+                .retain(|tag| !room_override.remove_tags.contains(tag));
+            if let Some(names) = room_override.names {
+                room.names = names;
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds room names from a CSV with `room` and `name` columns. A room can have several rows to
+    /// give it several names, which are added in order after its existing names; names the room
+    /// already has are skipped. Rooms in the CSV that don't exist are skipped and reported, along
+    /// with rooms that aren't in the CSV.
+    #[cfg(feature = "csv")]
+    pub fn import_names_csv(&mut self, reader: impl io::Read) -> Result<ImportReport, ImportError> {
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(reader);
+        let headers = reader.headers()?;
+        let column = |name| {
+            headers
+                .iter()
+                .position(|header| header == name)
+                .ok_or(ImportError::MissingColumn(name))
+        };
+        let (room_column, name_column) = (column("room")?, column("name")?);
 
-        for (image_content, offsets) in self.get_floor_images(base_path) {
-            for svg_room in Self::svg_rooms(&image_content) {
-                let svg_room = svg_room?;
-                let outline = svg_room.outline(offsets);
-                let uncompiled_room = match self.rooms.remove(svg_room.get_number()) {
-                    Some(old_room) => old_room,
-                    None => {
-                        println!("Room does not exist: {}", svg_room.get_number());
+        let mut unknown_rooms = HashSet::new();
+        let mut imported_rooms = HashSet::new();
+        for record in reader.records() {
+            let record = record?;
+            let number = record.get(room_column).unwrap_or_default();
+            let name = record.get(name_column).unwrap_or_default();
+            let room = match self.rooms.get_mut(number) {
+                Some(room) => room,
+                None => {
+                    unknown_rooms.insert(number.to_owned());
+                    continue;
+                }
+            };
+            imported_rooms.insert(number.to_owned());
+            if !name.is_empty() && !room.names.iter().any(|existing| existing == name) {
+                room.names.push(name.to_owned());
+            }
+        }
+
+        let mut unknown_rooms: Vec<_> = unknown_rooms.into_iter().collect();
+        unknown_rooms.sort_unstable_by(|a, b| natural_cmp(a, b));
+        let mut missing_rooms: Vec<_> = self
+            .rooms
+            .keys()
+            .filter(|number| !imported_rooms.contains(*number))
+            .cloned()
+            .collect();
+        missing_rooms.sort_unstable_by(|a, b| natural_cmp(a, b));
+        Ok(ImportReport {
+            unknown_rooms,
+            missing_rooms,
+        })
+    }
+
+    /// Changes the ID of the vertex `old` to `new`, along with every reference to it from rooms
+    /// and edges. Nothing is changed if `old` is undefined or `new` is already used.
+    pub fn rename_vertex(&mut self, old: &str, new: &str) -> Result<(), MapDataError> {
+        if self.vertices.contains_key(new) {
+            return Err(MapDataError::RepeatedVertexId(new.to_owned()));
+        }
+        let vertex = self
+            .vertices
+            .remove(old)
+            .ok_or_else(|| MapDataError::UndefinedVertexId(old.to_owned()))?;
+        self.vertices.insert(new.to_owned(), vertex);
+
+        for room in self.rooms.values_mut() {
+            if room.vertices.remove(old) {
+                room.vertices.insert(new.to_owned());
+            }
+        }
+        for poi in self.pois.values_mut() {
+            if poi.vertex.as_deref() == Some(old) {
+                poi.vertex = Some(new.to_owned());
+            }
+        }
+        for edge in &mut self.edges {
+            for id in [&mut edge.from, &mut edge.to] {
+                if id == old {
+                    *id = new.to_owned();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Finds pairs of vertices on the same floor that are closer than `epsilon`, which were
+    /// probably meant to be the same vertex, along with the distance between them. Each pair is
+    /// sorted by ID, and the pairs are sorted by their IDs.
+    pub fn near_duplicate_vertices(&self, epsilon: f64) -> Vec<(String, String, f64)> {
+        if epsilon.is_nan() || epsilon <= 0. {
+            return vec![];
+        }
+        // Vertices closer than epsilon are always in the same cell or neighboring cells
+        let cell =
+            |(x, y): (f64, f64)| ((x / epsilon).floor() as i64, (y / epsilon).floor() as i64);
+        let mut grid: HashMap<(&str, (i64, i64)), Vec<&str>> = HashMap::new();
+        for (id, vertex) in &self.vertices {
+            grid.entry((&vertex.floor, cell(vertex.location)))
+                .or_default()
+                .push(id);
+        }
+
+        let mut pairs = vec![];
+        for (id, vertex) in &self.vertices {
+            let (cell_x, cell_y) = cell(vertex.location);
+            for neighbor_cell in
+                (-1..=1).flat_map(|dx| (-1..=1).map(move |dy| (cell_x + dx, cell_y + dy)))
+            {
+                let Some(neighbors) = grid.get(&(vertex.floor.as_str(), neighbor_cell)) else {
+                    continue;
+                };
+                for &other_id in neighbors {
+                    if id.as_str() >= other_id {
                         continue;
                     }
-                };
+                    let (x, y) = vertex.location;
+                    let (other_x, other_y) = self.vertices[other_id].location;
+                    let distance = (other_x - x).hypot(other_y - y);
+                    if distance < epsilon {
+                        pairs.push((id.clone(), other_id.to_owned(), distance));
+                    }
+                }
+            }
+        }
+        pairs.sort_unstable_by(|(a, b, _), (other_a, other_b, _)| (a, b).cmp(&(other_a, other_b)));
+        pairs
+    }
 
-                let compiled_room = uncompiled_room.compile(outline);
-                compiled_rooms.insert(svg_room.get_number().to_owned(), compiled_room);
+    /// Merges the vertex `remove` into the vertex `keep`, such as a near duplicate found by
+    /// [`Self::near_duplicate_vertices`]. Edges and rooms referring to `remove` refer to `keep`
+    /// instead, and edges that would then connect `keep` to itself are removed. Edges of `keep`
+    /// that would then join the same vertices in the same direction are merged into the first
+    /// of them, which gains their tags, along with their kind if it doesn't have one. The kept
+    /// vertex keeps its location and gains the tags of the removed vertex, along with its group
+    /// if it doesn't have one. Nothing is changed if either vertex is undefined, they are the same
+    /// vertex, or they are on different floors.
+    pub fn merge_vertices(&mut self, keep: &str, remove: &str) -> Result<(), MapDataError> {
+        if keep == remove {
+            return Err(MapDataError::MergeVertexWithItself(keep.to_owned()));
+        }
+        let keep_floor = match self.vertices.get(keep) {
+            Some(kept) => &kept.floor,
+            None => return Err(MapDataError::UndefinedVertexId(keep.to_owned())),
+        };
+        match self.vertices.get(remove) {
+            Some(removed) if removed.floor != *keep_floor => {
+                return Err(MapDataError::MergeVerticesAcrossFloors {
+                    keep: keep.to_owned(),
+                    remove: remove.to_owned(),
+                })
             }
+            Some(_removed) => {}
+            None => return Err(MapDataError::UndefinedVertexId(remove.to_owned())),
         }
+        let removed = self.vertices.remove(remove).unwrap();
 
-        Ok(compiled::MapData {
-            floors: self.floors,
-            vertices: self.vertices,
-            rooms: compiled_rooms,
-            edges: self.edges,
-        })
+        let kept = self.vertices.get_mut(keep).unwrap();
+        kept.tags.extend(removed.tags);
+        if kept.group.is_none() {
+            kept.group = removed.group;
+        }
+        for room in self.rooms.values_mut() {
+            if room.vertices.remove(remove) {
+                room.vertices.insert(keep.to_owned());
+            }
+        }
+        for poi in self.pois.values_mut() {
+            if poi.vertex.as_deref() == Some(remove) {
+                poi.vertex = Some(keep.to_owned());
+            }
+        }
+        for edge in &mut self.edges {
+            for id in [&mut edge.from, &mut edge.to] {
+                if id == remove {
+                    *id = keep.to_owned();
+                }
+            }
+        }
+        self.edges.retain(|edge| edge.from != edge.to);
+
+        // Index in the merged edges of the first edge of `keep` to each neighbor, by its other
+        // end and the direction it goes, if any
+        let mut first_edges: HashMap<(String, Option<bool>), usize> = HashMap::new();
+        let mut edges: Vec<Edge> = Vec::with_capacity(self.edges.len());
+        for edge in self.edges.drain(..) {
+            let key = if edge.from == keep {
+                (edge.to.clone(), edge.directed.then_some(true))
+            } else if edge.to == keep {
+                (edge.from.clone(), edge.directed.then_some(false))
+            } else {
+                edges.push(edge);
+                continue;
+            };
+            match first_edges.get(&key) {
+                Some(&index) => {
+                    let first = &mut edges[index];
+                    first.tags.extend(edge.tags);
+                    if first.kind.is_none() {
+                        first.kind = edge.kind;
+                    }
+                }
+                None => {
+                    first_edges.insert(key, edges.len());
+                    edges.push(edge);
+                }
+            }
+        }
+        self.edges = edges;
+        Ok(())
+    }
+
+    /// Changes the number of the room `old` to `new`. The room's element in the floor images must
+    /// be renamed to match. Nothing is changed if `old` is undefined or `new` is already used.
+    pub fn rename_room(&mut self, old: &str, new: &str) -> Result<(), MapDataError> {
+        if self.rooms.contains_key(new) {
+            return Err(MapDataError::RepeatedRoomNumber(new.to_owned()));
+        }
+        let room = self
+            .rooms
+            .remove(old)
+            .ok_or_else(|| MapDataError::UndefinedRoomNumber(old.to_owned()))?;
+        self.rooms.insert(new.to_owned(), room);
+        Ok(())
+    }
+
+    /// Merges the room `absorb` into the room `keep` and removes `absorb`. The kept room gains
+    /// the vertices, tags, and any names it doesn't already have from the absorbed room, along with
+    /// any metadata keys it doesn't already have. Its center is left unchanged. Nothing is changed
+    /// if either room is undefined or they are the same room.
+    pub fn merge_rooms(&mut self, keep: &str, absorb: &str) -> Result<(), MapDataError> {
+        if keep == absorb {
+            return Err(MapDataError::MergeRoomWithItself(keep.to_owned()));
+        }
+        if !self.rooms.contains_key(keep) {
+            return Err(MapDataError::UndefinedRoomNumber(keep.to_owned()));
+        }
+        let absorbed = self
+            .rooms
+            .remove(absorb)
+            .ok_or_else(|| MapDataError::UndefinedRoomNumber(absorb.to_owned()))?;
+
+        let kept = self.rooms.get_mut(keep).unwrap();
+        kept.vertices.extend(absorbed.vertices);
+        for name in absorbed.names {
+            if !kept.names.contains(&name) {
+                kept.names.push(name);
+            }
+        }
+        kept.tags.extend(absorbed.tags);
+        for (key, value) in absorbed.metadata {
+            kept.metadata.entry(key).or_insert(value);
+        }
+        Ok(())
+    }
+
+    /// Adds the floors, vertices, edges, rooms, and points of interest of `fragment`, such as rooms
+    /// generated by [`Compiler::scaffold_rooms`]. Nothing is changed if a floor number, vertex ID,
+    /// room number, or point of interest ID of the fragment is already used.
+    pub fn merge(&mut self, fragment: MapData) -> Result<(), MapDataError> {
+        if let Some(floor) = fragment
+            .floors
+            .iter()
+            .find(|floor| self.floors.iter().any(|f| f.number == floor.number))
+        {
+            return Err(MapDataError::RepeatedFloorNumber(floor.number.clone()));
+        }
+        if let Some(id) = fragment
+            .vertices
+            .keys()
+            .find(|id| self.vertices.contains_key(*id))
+        {
+            return Err(MapDataError::RepeatedVertexId(id.clone()));
+        }
+        if let Some(number) = fragment
+            .rooms
+            .keys()
+            .find(|number| self.rooms.contains_key(*number))
+        {
+            return Err(MapDataError::RepeatedRoomNumber(number.clone()));
+        }
+        if let Some(id) = fragment.pois.keys().find(|id| self.pois.contains_key(*id)) {
+            return Err(MapDataError::RepeatedPoiId(id.clone()));
+        }
+
+        self.floors.extend(fragment.floors);
+        self.vertices.extend(fragment.vertices);
+        self.edges.extend(fragment.edges);
+        self.rooms.extend(fragment.rooms);
+        self.pois.extend(fragment.pois);
+        Ok(())
+    }
+
+    /// Splits the vertices in `vertex_partition` off of the room `room` into a new room numbered
+    /// `new_number`, without any names, tags, or metadata. Both rooms must be left with at least
+    /// one vertex. The new room's element must be added to the floor images. Nothing is changed if
+    /// `room` is undefined, `new_number` is already used, or the partition isn't a subset of the
+    /// room's vertices.
+    pub fn split_room(
+        &mut self,
+        room: &str,
+        new_number: &str,
+        vertex_partition: HashSet<String>,
+    ) -> Result<(), MapDataError> {
+        if self.rooms.contains_key(new_number) {
+            return Err(MapDataError::RepeatedRoomNumber(new_number.to_owned()));
+        }
+        let original = self
+            .rooms
+            .get_mut(room)
+            .ok_or_else(|| MapDataError::UndefinedRoomNumber(room.to_owned()))?;
+
+        let mut outside: Vec<_> = vertex_partition
+            .difference(&original.vertices)
+            .cloned()
+            .collect();
+        outside.sort_unstable();
+        if let Some(vertex) = outside.into_iter().next() {
+            return Err(MapDataError::VertexNotInRoom {
+                room: room.to_owned(),
+                vertex,
+            });
+        }
+        if vertex_partition.is_empty() || vertex_partition.len() == original.vertices.len() {
+            return Err(MapDataError::EmptySplit(room.to_owned()));
+        }
+
+        original
+            .vertices
+            .retain(|vertex| !vertex_partition.contains(vertex));
+        self.rooms
+            .insert(new_number.to_owned(), Room::new(vertex_partition));
+        Ok(())
+    }
+
+    /// Changes the number of the floor `old` to `new`, along with the floor of every vertex and
+    /// point of interest on it.
+    /// Nothing is changed if `old` is undefined or `new` is already used.
+    pub fn rename_floor(&mut self, old: &str, new: &str) -> Result<(), MapDataError> {
+        if self.floors.iter().any(|floor| floor.number == new) {
+            return Err(MapDataError::RepeatedFloorNumber(new.to_owned()));
+        }
+        let floor = self
+            .floors
+            .iter_mut()
+            .find(|floor| floor.number == old)
+            .ok_or_else(|| MapDataError::UndefinedFloorNumber(old.to_owned()))?;
+        floor.number = new.to_owned();
+
+        for vertex in self.vertices.values_mut() {
+            if vertex.floor == old {
+                vertex.floor = new.to_owned();
+            }
+        }
+        for poi in self.pois.values_mut() {
+            if poi.floor == old {
+                poi.floor = new.to_owned();
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds `prefix` to the start of every vertex ID and point of interest ID, so that map data for
+    /// several buildings can be merged without their IDs colliding. Room numbers are left
+    /// unchanged, since they are matched to the IDs of elements in the floor images, and so are
+    /// floor numbers, since buildings share floors.
+    pub fn prefix_ids(&mut self, prefix: &str) {
+        let prefixed = |id: &str| format!("{}{}", prefix, id);
+
+        self.vertices = self
+            .vertices
+            .drain()
+            .map(|(id, vertex)| (prefixed(&id), vertex))
+            .collect();
+        for room in self.rooms.values_mut() {
+            room.vertices = room.vertices.iter().map(|id| prefixed(id)).collect();
+        }
+        self.pois = self
+            .pois
+            .drain()
+            .map(|(id, mut poi)| {
+                poi.vertex = poi.vertex.as_deref().map(prefixed);
+                (prefixed(&id), poi)
+            })
+            .collect();
+        for edge in &mut self.edges {
+            edge.from = prefixed(&edge.from);
+            edge.to = prefixed(&edge.to);
+        }
+    }
+
+    /// Finds every floor image that doesn't exist, can't be read, or doesn't have an `.svg`
+    /// extension, with paths relative to `base_path`
+    pub fn missing_floor_images(&self, base_path: &Path) -> Vec<MissingImage> {
+        self.floors
+            .iter()
+            .flat_map(|floor| {
+                floor.images.iter().filter_map(move |image_rel_path| {
+                    let path = base_path.join(image_rel_path);
+                    let is_svg = path
+                        .extension()
+                        .is_some_and(|extension| extension.eq_ignore_ascii_case("svg"));
+                    let reason = if !path.is_file() {
+                        MissingImageReason::DoesNotExist
+                    } else if !is_svg {
+                        MissingImageReason::NotSvg
+                    } else if let Err(err) = File::open(&path) {
+                        MissingImageReason::Unreadable(err.to_string())
+                    } else {
+                        return None;
+                    };
+                    Some(MissingImage {
+                        floor: floor.number.clone(),
+                        path,
+                        reason,
+                    })
+                })
+            })
+            .collect()
+    }
+
+    /// Checks that every floor image exists, can be read, and is an SVG, listing every one that
+    /// isn't at once
+    pub fn verify_floor_images(&self, base_path: &Path) -> Result<(), CompileError> {
+        let missing = self.missing_floor_images(base_path);
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(CompileError::MissingFloorImage(missing))
+        }
+    }
+
+    /// Checks that the metadata of every room has the types required by `schema`.
+    pub fn verify_metadata(&self, schema: &MetadataSchema) -> Result<(), MapDataError> {
+        for (number, room) in &self.rooms {
+            if let Some((key, expected)) = schema.find_mismatch(&room.metadata) {
+                return Err(MapDataError::InvalidMetadata {
+                    room: number.clone(),
+                    key: key.to_owned(),
+                    expected,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn compile(self, base_path: &Path) -> anyhow::Result<compiled::MapData> {
+        self.compile_with_options(base_path, &CompileOptions::default())
+    }
+
+    /// Compiles with `options`, printing warnings as they are found. See [`Compiler`] for more
+    /// options and for the warnings and statistics of compilation.
+    pub fn compile_with_options(
+        self,
+        base_path: &Path,
+        options: &CompileOptions,
+    ) -> anyhow::Result<compiled::MapData> {
+        let output = Compiler::new()
+            .base_path(base_path)
+            .options(options.clone())
+            .compile(self)?;
+        Ok(output.map_data)
     }
 }
 
-#[derive(Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
 pub struct Room {
+    #[serde(serialize_with = "super::sorted_set::serialize")]
     pub vertices: HashSet<String>,
     #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
     pub names: Vec<String>,
     #[serde(default)]
-    pub center: Option<(f32, f32)>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub center: Option<(f64, f64)>,
     #[serde(default)]
+    #[serde(skip_serializing_if = "HashSet::is_empty")]
+    #[serde(serialize_with = "super::sorted_set::serialize")]
     pub tags: HashSet<RoomTag>,
+    /// Ranks the room above rooms that match a search equally well, before their areas are
+    /// compared. Rooms without a priority rank as if it were 0.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub priority: Option<i32>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    #[serde(serialize_with = "super::sorted_map::serialize")]
+    pub metadata: HashMap<String, Value>,
 }
 
 impl Room {
-    pub fn compile(self, outline: Vec<(f32, f32)>) -> compiled::Room {
+    /// Creates a room reached from `vertices`, without any names, tags, or metadata, and with its
+    /// center left to be found from its outline
+    pub fn new(vertices: HashSet<String>) -> Self {
+        Self {
+            vertices,
+            ..Self::default()
+        }
+    }
+
+    pub fn metadata(&self) -> &HashMap<String, Value> {
+        &self.metadata
+    }
+
+    /// Sets the metadata `key` to `value`, returning its old value, if any
+    pub fn set_metadata(&mut self, key: &str, value: Value) -> Option<Value> {
+        self.metadata.insert(key.to_owned(), value)
+    }
+
+    pub fn compile(self, outline: Vec<(f64, f64)>, holes: Vec<Vec<(f64, f64)>>) -> compiled::Room {
         let center = match self.center {
             Some(center) => center,
-            None => centroid(&outline),
+            None => computed_center(&outline, &holes),
         };
-        let area = shoelace_area(&outline).abs();
+        let area = area_with_holes(&outline, &holes);
 
         compiled::Room {
             vertices: self.vertices,
             names: self.names,
             center,
-            outline,
+            outline: outline.into(),
+            holes: holes.into_iter().map(Polygon::from).collect(),
             area,
+            floor: None,
+            outlines: HashMap::new(),
             tags: self.tags,
+            priority: self.priority,
+            metadata: self.metadata,
         }
     }
 }
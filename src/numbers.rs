@@ -0,0 +1,265 @@
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum ParseNumberError {
+    #[error("Expected a number in {0:?}")]
+    ExpectedNumber(String),
+    #[error("Unsupported unit `{unit}` in {input:?}")]
+    UnsupportedUnit { input: String, unit: String },
+}
+
+/// Parses the number at the start of `input` using the SVG number grammar (optional sign, digits
+/// with an optional fractional part, optional exponent), returning it and the unparsed rest of the
+/// input. An `e` not followed by exponent digits is left in the rest, so `2em` parses as `2` with
+/// the rest `em`.
+pub fn parse_number(input: &str) -> Option<(f64, &str)> {
+    let bytes = input.as_bytes();
+    let digits_from = |start: usize| {
+        start
+            + bytes[start.min(bytes.len())..]
+                .iter()
+                .take_while(|byte| byte.is_ascii_digit())
+                .count()
+    };
+
+    let mut end = 0;
+    if matches!(bytes.first(), Some(b'+' | b'-')) {
+        end += 1;
+    }
+    let integer_end = digits_from(end);
+    let mut has_digits = integer_end > end;
+    end = integer_end;
+
+    if bytes.get(end) == Some(&b'.') {
+        let fraction_end = digits_from(end + 1);
+        if fraction_end > end + 1 || has_digits {
+            has_digits |= fraction_end > end + 1;
+            end = fraction_end;
+        }
+    }
+    if !has_digits {
+        return None;
+    }
+
+    if matches!(bytes.get(end), Some(b'e' | b'E')) {
+        let mut exponent_start = end + 1;
+        if matches!(bytes.get(exponent_start), Some(b'+' | b'-')) {
+            exponent_start += 1;
+        }
+        let exponent_end = digits_from(exponent_start);
+        if exponent_end > exponent_start {
+            end = exponent_end;
+        }
+    }
+
+    let number = input[..end].parse().ok()?;
+    Some((number, &input[end..]))
+}
+
+fn is_separator(c: char) -> bool {
+    c.is_whitespace() || c == ','
+}
+
+/// Parses a list of numbers separated by whitespace and/or commas, such as the arguments to a
+/// transform function. Separators may be left out where unambiguous, so `1-2.5.5` is
+/// `[1, -2.5, 0.5]`.
+pub fn parse_number_list(input: &str) -> Result<Vec<f64>, ParseNumberError> {
+    let mut numbers = Vec::new();
+    let mut rest = input.trim_start_matches(is_separator);
+    while !rest.is_empty() {
+        let (number, after) =
+            parse_number(rest).ok_or_else(|| ParseNumberError::ExpectedNumber(input.to_owned()))?;
+        numbers.push(number);
+        rest = after.trim_start_matches(is_separator);
+    }
+    Ok(numbers)
+}
+
+/// Parses a length such as `12.5`, `1e3mm`, or `100%`, returning the number and its (possibly
+/// empty) unit.
+pub fn parse_length(input: &str) -> Result<(f64, &str), ParseNumberError> {
+    let trimmed = input.trim();
+    let (number, unit) =
+        parse_number(trimmed).ok_or_else(|| ParseNumberError::ExpectedNumber(input.to_owned()))?;
+    let unit = unit.trim();
+    if unit == "%" || unit.chars().all(|c| c.is_ascii_alphabetic()) {
+        Ok((number, unit))
+    } else {
+        Err(ParseNumberError::ExpectedNumber(input.to_owned()))
+    }
+}
+
+/// Writes `number` with at most `decimals` digits after the decimal point, leaving out trailing
+/// zeros
+pub fn format_rounded(number: f64, decimals: usize) -> String {
+    let mut text = format!("{:.*}", decimals, number);
+    if text.contains('.') {
+        text.truncate(text.trim_end_matches('0').trim_end_matches('.').len());
+    }
+    if text == "-0" {
+        text.remove(0);
+    }
+    text
+}
+
+/// Rounds every number in `input` with a fractional part or an exponent, such as the numbers in
+/// path data or a transform, to at most `decimals` digits after the decimal point. Everything else
+/// is left unchanged, including the flags of arc commands in path data, which may be packed
+/// together with the number after them as in `a5 5 0 011.5 10`.
+pub fn round_numbers(input: &str, decimals: usize) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+    // The path command the numbers are arguments of, and how many arguments have been seen
+    let mut command = None;
+    let mut argument = 0;
+    while let Some(c) = rest.chars().next() {
+        if matches!(command, Some('a' | 'A'))
+            && matches!(argument % ARC_ARGUMENTS, 3 | 4)
+            && (c == '0' || c == '1')
+        {
+            output.push(c);
+            rest = &rest[1..];
+            argument += 1;
+            continue;
+        }
+        if c.is_ascii_alphabetic() {
+            // Only a lone letter is a path command, not a word like `translate`
+            let word = rest
+                .find(|c: char| !c.is_ascii_alphabetic())
+                .unwrap_or(rest.len());
+            command = (word == 1).then_some(c);
+            argument = 0;
+            output.push_str(&rest[..word]);
+            rest = &rest[word..];
+            continue;
+        }
+        match parse_number(rest) {
+            Some((number, after)) => {
+                let text = &rest[..rest.len() - after.len()];
+                if text.contains(['.', 'e', 'E']) {
+                    let rounded = format_rounded(number, decimals);
+                    // Numbers like the second in `1.5.5` rely on the decimal point to separate
+                    // them from the one before
+                    if rounded.starts_with(|c: char| c.is_ascii_digit())
+                        && output.ends_with(|c: char| c.is_ascii_digit() || c == '.')
+                    {
+                        output.push(' ');
+                    }
+                    output.push_str(&rounded);
+                } else {
+                    output.push_str(text);
+                }
+                rest = after;
+                argument += 1;
+            }
+            None => {
+                output.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    output
+}
+
+/// Number of arguments of each arc in an arc command, the fourth and fifth of which are flags
+const ARC_ARGUMENTS: usize = 7;
+
+/// Multiplies the number of a length such as `2px` or `1.5` by `factor`, keeping its unit
+pub fn scale_length(input: &str, factor: f64) -> Result<String, ParseNumberError> {
+    let (number, unit) = parse_length(input)?;
+    Ok(format!("{}{}", number * factor, unit))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn number_lists() {
+        let cases: &[(&str, &[f64])] = &[
+            ("", &[]),
+            ("1", &[1.]),
+            ("1 0 0 1 -2.5e-3 1.2e2", &[1., 0., 0., 1., -2.5e-3, 1.2e2]),
+            ("200, 200", &[200., 200.]),
+            ("200 ,200", &[200., 200.]),
+            ("  12,\t-3.5\n", &[12., -3.5]),
+            ("1-2", &[1., -2.]),
+            ("1.5.5", &[1.5, 0.5]),
+            ("-.5e+2,+3E1", &[-50., 30.]),
+            ("5. 6", &[5., 6.]),
+            (
+                "0.866025,0.5,-0.5,0.866025,-92.5,61",
+                &[0.866025, 0.5, -0.5, 0.866025, -92.5, 61.],
+            ),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                Ok(expected.to_vec()),
+                parse_number_list(input),
+                "{:?}",
+                input
+            );
+        }
+
+        for input in ["a", "1 b", "1,,x", "--1", "."] {
+            assert!(parse_number_list(input).is_err(), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn rounded_numbers() {
+        assert_eq!("434.875", format_rounded(434.87500381469727, 3));
+        assert_eq!("434.88", format_rounded(434.87500381469727, 2));
+        assert_eq!("435", format_rounded(434.875, 0));
+        assert_eq!("0", format_rounded(-0.0001, 2));
+        assert_eq!("10", format_rounded(10., 2));
+
+        let cases = [
+            (
+                "M 10.123456,20.987654 L 30 40.5 Z",
+                "M 10.12,20.99 L 30 40.5 Z",
+            ),
+            ("translate(5.00001, -3.14159)", "translate(5, -3.14)"),
+            ("m1.23456-7.891011", "m1.23-7.89"),
+            ("1.5.5", "1.5 0.5"),
+            ("1.0001.5", "1 0.5"),
+            ("2.5e-3 1.2e2", "0 120"),
+            ("a5 5 0 0110 10", "a5 5 0 0110 10"),
+            ("a5 5 0 011 10", "a5 5 0 011 10"),
+            ("a5.001 5 0 011.2345 10", "a5 5 0 01 1.23 10"),
+            (
+                "A5 5 0 1,0.5.5 5 5 0 01.5-1",
+                "A5 5 0 1,0 0.5 0.5 5 5 0 01 0.5-1",
+            ),
+            ("M0 0a1 1 0 00.5.5l1.2345 0", "M0 0a1 1 0 00 0.5 0.5l1.23 0"),
+            ("rotate(1.2345) scale(0.5)", "rotate(1.23) scale(0.5)"),
+            ("scale(2)", "scale(2)"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(expected, round_numbers(input, 2), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn lengths() {
+        let cases: &[(&str, f64, &str)] = &[
+            ("12", 12., ""),
+            (" 12.5 ", 12.5, ""),
+            ("210mm", 210., "mm"),
+            ("1e3mm", 1e3, "mm"),
+            ("2em", 2., "em"),
+            ("1.5e1em", 15., "em"),
+            ("100%", 100., "%"),
+            ("-3px", -3., "px"),
+        ];
+        for (input, number, unit) in cases {
+            assert_eq!(Ok((*number, *unit)), parse_length(input), "{:?}", input);
+        }
+
+        for input in ["", "mm", "12 34", "1e3mm5"] {
+            assert!(parse_length(input).is_err(), "{:?}", input);
+        }
+
+        assert_eq!(Ok("1.25".to_owned()), scale_length("2.5", 0.5));
+        assert_eq!(Ok("0.5px".to_owned()), scale_length(" 2px ", 0.25));
+        assert!(scale_length("inherit", 0.5).is_err());
+    }
+}
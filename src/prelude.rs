@@ -0,0 +1,25 @@
+//! The types most programs using the library need, for importing all at once
+//!
+//! ```
+//! use indoor_map_lib::prelude::*;
+//!
+//! let map_data: MapData = serde_json::from_str(
+//!     r#"{"floors": [], "vertices": {}, "edges": [], "rooms": {}}"#,
+//! )
+//! .unwrap();
+//! assert!(map_data.rooms.is_empty());
+//! ```
+//!
+//! Compiled map data is what clients load, so its types are exported under their own names. The
+//! uncompiled types are exported with an `Uncompiled` prefix.
+
+pub use crate::bounding_box::{BoundingBox, BoundingSquare};
+pub use crate::geometry::Polygon;
+pub use crate::map_data::compile::{CompileOutput, Compiler};
+pub use crate::map_data::compiled::{MapData, Room};
+pub use crate::map_data::handle::{LoadedGraph, MapHandle};
+pub use crate::map_data::uncompiled::{MapData as UncompiledMapData, Room as UncompiledRoom};
+pub use crate::map_data::{Edge, EdgeKind, EdgeTag, Floor, Poi, RoomTag, Vertex, VertexTag};
+pub use crate::routing::{DistanceMatrix, Route, RoutingGraph, RoutingOptions};
+pub use crate::search::SearchIndex;
+pub use crate::svg_parser::{SvgElement, SvgParseError};
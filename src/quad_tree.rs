@@ -0,0 +1,151 @@
+use nalgebra::Vector2;
+
+use crate::bounding_box::BoundingBox;
+
+const MAX_NODE_ITEMS: usize = 16;
+const MAX_DEPTH: u32 = 12;
+
+/// Region quadtree over bounding boxes. Each item is stored in the deepest node whose region fully
+/// contains it, so items straddling a split stay in the parent node.
+#[derive(Debug)]
+pub struct QuadTree<T> {
+    root: QuadNode<T>,
+}
+
+#[derive(Debug)]
+struct QuadNode<T> {
+    region: BoundingBox,
+    depth: u32,
+    items: Vec<(BoundingBox, T)>,
+    children: Option<Box<[QuadNode<T>; 4]>>,
+}
+
+impl<T> QuadTree<T> {
+    /// Builds a quadtree covering the union of the bounding boxes of `items`.
+    pub fn new(items: Vec<(BoundingBox, T)>) -> Self {
+        let region = items
+            .iter()
+            .map(|(bounding_box, _)| bounding_box.clone())
+            .reduce(|a, b| a.union(&b))
+            .unwrap_or_else(|| BoundingBox::new(Vector2::zeros(), Vector2::zeros()));
+
+        let mut root = QuadNode::new(region, 0);
+        for (bounding_box, item) in items {
+            root.insert(bounding_box, item);
+        }
+        Self { root }
+    }
+
+    /// Returns every item whose bounding box intersects `bounds`, in no particular order.
+    pub fn query(&self, bounds: &BoundingBox) -> Vec<&T> {
+        let mut found = Vec::new();
+        self.root.query(bounds, &mut found);
+        found
+    }
+}
+
+impl<T> QuadNode<T> {
+    fn new(region: BoundingBox, depth: u32) -> Self {
+        Self {
+            region,
+            depth,
+            items: vec![],
+            children: None,
+        }
+    }
+
+    fn quadrant_for(&mut self, bounding_box: &BoundingBox) -> Option<&mut QuadNode<T>> {
+        self.children
+            .as_mut()?
+            .iter_mut()
+            .find(|child| child.region.contains(bounding_box))
+    }
+
+    fn insert(&mut self, bounding_box: BoundingBox, item: T) {
+        if let Some(child) = self.quadrant_for(&bounding_box) {
+            child.insert(bounding_box, item);
+            return;
+        }
+
+        self.items.push((bounding_box, item));
+        if self.children.is_none() && self.items.len() > MAX_NODE_ITEMS && self.depth < MAX_DEPTH {
+            self.split();
+        }
+    }
+
+    fn split(&mut self) {
+        let half = self.region.get_size() / 2.;
+        let top_left = self.region.get_top_left();
+        let quadrant = |offset: Vector2<f64>| {
+            QuadNode::new(BoundingBox::new(top_left + offset, half), self.depth + 1)
+        };
+        self.children = Some(Box::new([
+            quadrant(Vector2::new(0., 0.)),
+            quadrant(Vector2::new(half[0], 0.)),
+            quadrant(Vector2::new(0., half[1])),
+            quadrant(half),
+        ]));
+
+        for (bounding_box, item) in std::mem::take(&mut self.items) {
+            self.insert(bounding_box, item);
+        }
+    }
+
+    fn query<'a>(&'a self, bounds: &BoundingBox, found: &mut Vec<&'a T>) {
+        found.extend(
+            self.items
+                .iter()
+                .filter(|(bounding_box, _)| bounding_box.intersects(bounds))
+                .map(|(_, item)| item),
+        );
+        if let Some(children) = &self.children {
+            for child in children.iter() {
+                if child.region.intersects(bounds) {
+                    child.query(bounds, found);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Small deterministic generator so the test doesn't need a random number crate
+    fn pseudo_random(seed: &mut u64) -> f64 {
+        *seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (*seed >> 33) as f64 / (1u64 << 31) as f64
+    }
+
+    fn random_box(seed: &mut u64, max_size: f64) -> BoundingBox {
+        let top_left = Vector2::new(pseudo_random(seed) * 1000., pseudo_random(seed) * 1000.);
+        let size = Vector2::new(
+            pseudo_random(seed) * max_size,
+            pseudo_random(seed) * max_size,
+        );
+        BoundingBox::new(top_left, size)
+    }
+
+    #[test]
+    fn query_matches_brute_force() {
+        let mut seed = 42;
+        let boxes: Vec<_> = (0..2000).map(|_| random_box(&mut seed, 20.)).collect();
+        let tree = QuadTree::new(boxes.iter().cloned().zip(0..).collect());
+
+        for _ in 0..100 {
+            let query = random_box(&mut seed, 150.);
+            let mut actual: Vec<usize> = tree.query(&query).into_iter().copied().collect();
+            actual.sort_unstable();
+            let expected: Vec<usize> = boxes
+                .iter()
+                .enumerate()
+                .filter(|(_, bounding_box)| bounding_box.intersects(&query))
+                .map(|(id, _)| id)
+                .collect();
+            assert_eq!(expected, actual);
+        }
+    }
+}
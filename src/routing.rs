@@ -0,0 +1,902 @@
+//! Finding routes between vertices of compiled map data
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet};
+use std::convert::TryFrom;
+#[cfg(feature = "csv")]
+use std::io;
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::map_data::compiled::MapData;
+use crate::map_data::{floor_ordinals, Edge, EdgeTag, RoomTag, Vertex, VertexTag};
+use crate::util::natural_cmp;
+
+/// Route between two vertices, as the IDs of every vertex along it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Route {
+    pub vertices: Vec<String>,
+    /// Total length of the route's edges, in map units
+    pub distance: f64,
+}
+
+/// Which edges of map data routes may travel along
+#[derive(Debug, Clone, Default)]
+pub struct RoutingOptions {
+    /// Edges with any of these tags are left out of the routing graph, such as
+    /// [`EdgeTag::StaffOnly`] edges when routing visitors
+    pub excluded_edge_tags: HashSet<EdgeTag>,
+    /// Edges to or from a vertex with any of these tags are left out of the routing graph, such
+    /// as [`VertexTag::Elevator`] vertices when planning evacuation routes
+    pub excluded_vertex_tags: HashSet<VertexTag>,
+    /// How routes treat vertices inside of rooms tagged [`RoomTag::Closed`], such as a classroom
+    /// that's usually cut through
+    pub closed_rooms: ClosedRooms,
+}
+
+/// How routes treat vertices whose rooms are all tagged [`RoomTag::Closed`]. Vertices that aren't
+/// in any room, such as those along corridors, and vertices shared with a room that isn't closed
+/// are never affected.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ClosedRooms {
+    /// Routes go through closed rooms like any other
+    #[default]
+    Allowed,
+    /// Every edge into a vertex of a closed room is this many map units longer, so routes only go
+    /// through closed rooms when going around them is longer by more than that. The penalty is
+    /// included in the distances of routes. A negative or `NaN` penalty is taken as 0, since
+    /// shortest routes can't be found with edges shorter than nothing, and an infinite penalty
+    /// is the same as [`Self::Excluded`].
+    Penalized(f64),
+    /// Edges to or from vertices of closed rooms are left out of the routing graph, so routes
+    /// never go through or to closed rooms
+    Excluded,
+}
+
+impl ClosedRooms {
+    /// Replaces a penalty that can't be added to the lengths of edges with what it stands for
+    fn normalized(self) -> Self {
+        match self {
+            Self::Penalized(penalty) if penalty == f64::INFINITY => Self::Excluded,
+            // `max` also turns `NaN` into 0
+            Self::Penalized(penalty) => Self::Penalized(penalty.max(0.)),
+            other => other,
+        }
+    }
+}
+
+/// The edges of map data that can be traveled, in each direction they can be traveled in
+#[derive(Debug, Clone, Default)]
+pub struct RoutingGraph {
+    /// Vertices that can be reached directly from each vertex, along with the length of the edge
+    adjacency: HashMap<String, Vec<(String, f64)>>,
+}
+
+fn distance(from: &Vertex, to: &Vertex) -> f64 {
+    let (from, to) = (from.get_location(), to.get_location());
+    (to.0 - from.0).hypot(to.1 - from.1)
+}
+
+impl RoutingGraph {
+    /// Builds the routing graph of compiled map data. An edge between floors that goes through a
+    /// vertex tagged [`VertexTag::Up`], such as an escalator, can only be traveled from the lower
+    /// floor to the higher one, and an edge through a vertex tagged [`VertexTag::Down`] can only
+    /// be traveled from the higher floor to the lower one. Floors are ordered as they are listed
+    /// in the map data, from lowest to highest.
+    pub fn new(map_data: &MapData) -> Self {
+        Self::with_options(map_data, &RoutingOptions::default())
+    }
+
+    /// Same as [`Self::new`], but leaves out the edges excluded by `options`
+    pub fn with_options(map_data: &MapData, options: &RoutingOptions) -> Self {
+        let ordinals = floor_ordinals(&map_data.floors);
+        let mut adjacency: HashMap<String, Vec<(String, f64)>> = HashMap::new();
+        let closed_rooms = options.closed_rooms.normalized();
+        let closed = match closed_rooms {
+            ClosedRooms::Allowed => HashSet::new(),
+            ClosedRooms::Penalized(_) | ClosedRooms::Excluded => closed_room_vertices(map_data),
+        };
+
+        let mut add = |from: &str, to: &str| {
+            let (from_vertex, to_vertex) = (&map_data.vertices[from], &map_data.vertices[to]);
+            let excluded =
+                |vertex: &Vertex| !vertex.get_tags().is_disjoint(&options.excluded_vertex_tags);
+            if excluded(from_vertex) || excluded(to_vertex) {
+                return;
+            }
+            let penalty = match closed_rooms {
+                ClosedRooms::Excluded if closed.contains(from) || closed.contains(to) => return,
+                ClosedRooms::Penalized(penalty) if closed.contains(to) => penalty,
+                _ => 0.,
+            };
+            let from_ordinal = ordinals[from_vertex.get_floor()];
+            let to_ordinal = ordinals[to_vertex.get_floor()];
+            let has_tag =
+                |tag| from_vertex.get_tags().contains(&tag) || to_vertex.get_tags().contains(&tag);
+            let allowed = match from_ordinal.cmp(&to_ordinal) {
+                Ordering::Less => !has_tag(VertexTag::Down),
+                Ordering::Greater => !has_tag(VertexTag::Up),
+                Ordering::Equal => true,
+            };
+            if allowed {
+                adjacency
+                    .entry(from.to_owned())
+                    .or_default()
+                    .push((to.to_owned(), distance(from_vertex, to_vertex) + penalty));
+            }
+        };
+
+        for edge in &map_data.edges {
+            if !edge.get_tags().is_disjoint(&options.excluded_edge_tags) {
+                continue;
+            }
+            add(edge.get_from(), edge.get_to());
+            if !edge.is_directed() {
+                add(edge.get_to(), edge.get_from());
+            }
+        }
+        Self { adjacency }
+    }
+
+    /// Returns the vertices that can be reached directly from each vertex, along with the length
+    /// of the edge to each
+    pub fn adjacency(&self) -> &HashMap<String, Vec<(String, f64)>> {
+        &self.adjacency
+    }
+
+    /// Returns the vertices that can be reached directly from `vertex`, along with the length of
+    /// the edge to each
+    pub fn neighbors(&self, vertex: &str) -> &[(String, f64)] {
+        self.adjacency.get(vertex).map_or(&[], Vec::as_slice)
+    }
+
+    /// Finds the length of the shortest route to every vertex that can be reached from any of
+    /// `sources`, including the sources themselves
+    pub fn distances_from<'a>(
+        &'a self,
+        sources: impl IntoIterator<Item = &'a str>,
+    ) -> HashMap<&'a str, f64> {
+        self.distances_within(sources, f64::INFINITY)
+    }
+
+    /// Same as [`Self::distances_from`], but only finds the vertices within `max_distance` of the
+    /// sources, without searching any farther
+    pub fn distances_within<'a>(
+        &'a self,
+        sources: impl IntoIterator<Item = &'a str>,
+        max_distance: f64,
+    ) -> HashMap<&'a str, f64> {
+        let mut distances: HashMap<&str, f64> = HashMap::new();
+        let mut queue = BinaryHeap::new();
+        for source in sources {
+            distances.insert(source, 0.);
+            queue.push(QueueEntry {
+                distance: 0.,
+                vertex: source,
+            });
+        }
+
+        while let Some(QueueEntry { distance, vertex }) = queue.pop() {
+            if distance > distances[vertex] {
+                continue;
+            }
+            for (neighbor, length) in self.neighbors(vertex) {
+                let neighbor_distance = distance + length;
+                if neighbor_distance <= max_distance
+                    && distances
+                        .get(neighbor.as_str())
+                        .is_none_or(|&known| neighbor_distance < known)
+                {
+                    distances.insert(neighbor, neighbor_distance);
+                    queue.push(QueueEntry {
+                        distance: neighbor_distance,
+                        vertex: neighbor,
+                    });
+                }
+            }
+        }
+        distances
+    }
+
+    /// Finds the shortest route from `from` to `to`, or `None` if `to` can't be reached
+    pub fn shortest_path(&self, from: &str, to: &str) -> Option<Route> {
+        let mut distances: HashMap<&str, f64> = HashMap::new();
+        let mut previous: HashMap<&str, &str> = HashMap::new();
+        let mut queue = BinaryHeap::new();
+        distances.insert(from, 0.);
+        queue.push(QueueEntry {
+            distance: 0.,
+            vertex: from,
+        });
+
+        while let Some(QueueEntry { distance, vertex }) = queue.pop() {
+            if vertex == to {
+                let mut vertices = vec![to.to_owned()];
+                let mut current = to;
+                while let Some(&prev) = previous.get(current) {
+                    vertices.push(prev.to_owned());
+                    current = prev;
+                }
+                vertices.reverse();
+                return Some(Route { vertices, distance });
+            }
+            if distance > distances[vertex] {
+                continue;
+            }
+
+            for (neighbor, length) in self.neighbors(vertex) {
+                let neighbor_distance = distance + length;
+                if distances
+                    .get(neighbor.as_str())
+                    .is_none_or(|&known| neighbor_distance < known)
+                {
+                    distances.insert(neighbor, neighbor_distance);
+                    previous.insert(neighbor, vertex);
+                    queue.push(QueueEntry {
+                        distance: neighbor_distance,
+                        vertex: neighbor,
+                    });
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Routing graph of map data on its own, without room outlines or anything else routes don't need,
+/// which can be serialized for clients that only route. Vertices are numbered, and the edges from
+/// each are stored together in one array, so it stays small and quick to load. Reading one checks
+/// that the arrays fit together and that edge lengths aren't negative, failing with an
+/// [`InvalidCompactGraph`] if they aren't.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "CompactGraphJson")]
+pub struct CompactGraph {
+    /// ID of each vertex, sorted, so that a vertex's index is its position here
+    vertices: Vec<String>,
+    /// Location of each vertex
+    locations: Vec<(f64, f64)>,
+    /// Floor numbers, from lowest to highest
+    floors: Vec<String>,
+    /// Index into `floors` of the floor of each vertex
+    vertex_floors: Vec<u32>,
+    /// Where the edges from each vertex start in `targets` and `lengths`, with an extra entry at
+    /// the end, so the edges from vertex `i` are at `edge_starts[i]..edge_starts[i + 1]`
+    edge_starts: Vec<u32>,
+    /// Index of the vertex each edge goes to
+    targets: Vec<u32>,
+    /// Length of each edge
+    lengths: Vec<f64>,
+    /// Indices of the vertices of each room, sorted, by room number
+    rooms: BTreeMap<String, Vec<u32>>,
+}
+
+/// Reason that a [`CompactGraph`] being read can't be used, since routing along it would panic or
+/// give wrong routes
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum InvalidCompactGraph {
+    #[error("`{field}` has {found} entries, but should have {expected}")]
+    WrongLength {
+        field: &'static str,
+        expected: usize,
+        found: usize,
+    },
+    #[error("The vertices should be sorted without repeats")]
+    UnsortedVertices,
+    #[error("The edge starts should begin at 0, never decrease, and end at the number of edges")]
+    InvalidEdgeStarts,
+    #[error("The length of edge {index} should be a finite number of at least 0")]
+    InvalidLength { index: usize },
+    #[error("`{field}` has the index {index}, but there are only {len} entries to index")]
+    IndexOutOfBounds {
+        field: &'static str,
+        index: u32,
+        len: usize,
+    },
+}
+
+/// Fields of a [`CompactGraph`] as they are read, before they are checked
+#[derive(Deserialize)]
+struct CompactGraphJson {
+    vertices: Vec<String>,
+    locations: Vec<(f64, f64)>,
+    floors: Vec<String>,
+    vertex_floors: Vec<u32>,
+    edge_starts: Vec<u32>,
+    targets: Vec<u32>,
+    lengths: Vec<f64>,
+    rooms: BTreeMap<String, Vec<u32>>,
+}
+
+impl TryFrom<CompactGraphJson> for CompactGraph {
+    type Error = InvalidCompactGraph;
+
+    fn try_from(json: CompactGraphJson) -> Result<Self, Self::Error> {
+        let vertex_count = json.vertices.len();
+        // The default graph has no edge starts at all
+        let edge_starts_len = if json.edge_starts.is_empty() && vertex_count == 0 {
+            0
+        } else {
+            vertex_count + 1
+        };
+        for (field, expected, found) in [
+            ("locations", vertex_count, json.locations.len()),
+            ("vertex_floors", vertex_count, json.vertex_floors.len()),
+            ("edge_starts", edge_starts_len, json.edge_starts.len()),
+            ("lengths", json.targets.len(), json.lengths.len()),
+        ] {
+            if expected != found {
+                return Err(InvalidCompactGraph::WrongLength {
+                    field,
+                    expected,
+                    found,
+                });
+            }
+        }
+        if json.vertices.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err(InvalidCompactGraph::UnsortedVertices);
+        }
+        let edge_starts_valid = json.edge_starts.is_empty()
+            || (json.edge_starts[0] == 0
+                && json.edge_starts.windows(2).all(|pair| pair[0] <= pair[1])
+                && json.edge_starts[vertex_count] as usize == json.targets.len());
+        if !edge_starts_valid {
+            return Err(InvalidCompactGraph::InvalidEdgeStarts);
+        }
+        // Shortest paths can't be found along edges shorter than nothing
+        if let Some(index) = json
+            .lengths
+            .iter()
+            .position(|length| !(length.is_finite() && *length >= 0.))
+        {
+            return Err(InvalidCompactGraph::InvalidLength { index });
+        }
+
+        let indices = [
+            ("vertex_floors", &json.vertex_floors, json.floors.len()),
+            ("targets", &json.targets, vertex_count),
+        ]
+        .into_iter()
+        .chain(
+            json.rooms
+                .values()
+                .map(|vertices| ("rooms", vertices, vertex_count)),
+        );
+        for (field, indices, len) in indices {
+            if let Some(&index) = indices.iter().find(|&&index| index as usize >= len) {
+                return Err(InvalidCompactGraph::IndexOutOfBounds { field, index, len });
+            }
+        }
+
+        Ok(Self {
+            vertices: json.vertices,
+            locations: json.locations,
+            floors: json.floors,
+            vertex_floors: json.vertex_floors,
+            edge_starts: json.edge_starts,
+            targets: json.targets,
+            lengths: json.lengths,
+            rooms: json.rooms,
+        })
+    }
+}
+
+impl CompactGraph {
+    /// Builds the compact form of `graph`, the routing graph of `map_data`
+    pub(crate) fn new(map_data: &MapData, graph: &RoutingGraph) -> Self {
+        let mut vertices: Vec<_> = map_data.vertices.keys().cloned().collect();
+        vertices.sort_unstable();
+        let index = |id: &str| {
+            vertices
+                .binary_search_by(|vertex| vertex.as_str().cmp(id))
+                .expect("edges and rooms only have vertices of the map data") as u32
+        };
+
+        let floors: Vec<_> = map_data
+            .floors
+            .iter()
+            .map(|floor| floor.get_number().to_owned())
+            .collect();
+        let ordinals = floor_ordinals(&map_data.floors);
+        let mut locations = Vec::with_capacity(vertices.len());
+        let mut vertex_floors = Vec::with_capacity(vertices.len());
+        let mut edge_starts = Vec::with_capacity(vertices.len() + 1);
+        let mut targets = Vec::new();
+        let mut lengths = Vec::new();
+        for id in &vertices {
+            let vertex = &map_data.vertices[id];
+            locations.push(vertex.get_location());
+            vertex_floors.push(ordinals[vertex.get_floor()] as u32);
+            edge_starts.push(targets.len() as u32);
+            for (to, length) in graph.neighbors(id) {
+                targets.push(index(to));
+                lengths.push(*length);
+            }
+        }
+        edge_starts.push(targets.len() as u32);
+
+        let rooms = map_data
+            .rooms
+            .iter()
+            .map(|(number, room)| {
+                let mut vertices: Vec<_> =
+                    room.vertices.iter().map(|vertex| index(vertex)).collect();
+                vertices.sort_unstable();
+                (number.clone(), vertices)
+            })
+            .collect();
+        Self {
+            vertices,
+            locations,
+            floors,
+            vertex_floors,
+            edge_starts,
+            targets,
+            lengths,
+            rooms,
+        }
+    }
+
+    /// Returns the IDs of the vertices, where a vertex's index is its position
+    pub fn vertices(&self) -> &[String] {
+        &self.vertices
+    }
+
+    /// Returns the index of the vertex with the ID `id`, if there is one
+    pub fn vertex_index(&self, id: &str) -> Option<usize> {
+        self.vertices
+            .binary_search_by(|vertex| vertex.as_str().cmp(id))
+            .ok()
+    }
+
+    /// Returns the location of the vertex at `index`
+    pub fn location(&self, index: usize) -> (f64, f64) {
+        self.locations[index]
+    }
+
+    /// Returns the number of the floor of the vertex at `index`
+    pub fn floor(&self, index: usize) -> &str {
+        &self.floors[self.vertex_floors[index] as usize]
+    }
+
+    /// Returns the indices of the vertices that can be reached directly from the vertex at
+    /// `index`, along with the length of the edge to each
+    pub fn neighbors(&self, index: usize) -> impl Iterator<Item = (usize, f64)> + '_ {
+        let edges = self.edge_starts[index] as usize..self.edge_starts[index + 1] as usize;
+        self.targets[edges.clone()]
+            .iter()
+            .zip(&self.lengths[edges])
+            .map(|(&to, &length)| (to as usize, length))
+    }
+
+    /// Returns the indices of the vertices of the room numbered `number`, which is empty if there
+    /// is no such room
+    pub fn room_vertices(&self, number: &str) -> &[u32] {
+        self.rooms.get(number).map_or(&[], Vec::as_slice)
+    }
+
+    /// Finds the shortest route from `from` to `to`, or `None` if `to` can't be reached, like
+    /// [`RoutingGraph::shortest_path`]
+    pub fn shortest_path(&self, from: &str, to: &str) -> Option<Route> {
+        let (from, to) = (self.vertex_index(from)?, self.vertex_index(to)?);
+        let mut distances = vec![f64::INFINITY; self.vertices.len()];
+        let mut previous: Vec<Option<usize>> = vec![None; self.vertices.len()];
+        let mut queue = BinaryHeap::new();
+        distances[from] = 0.;
+        queue.push(QueueEntry {
+            distance: 0.,
+            vertex: from,
+        });
+
+        while let Some(QueueEntry { distance, vertex }) = queue.pop() {
+            if vertex == to {
+                let mut vertices = vec![self.vertices[to].clone()];
+                let mut current = to;
+                while let Some(prev) = previous[current] {
+                    vertices.push(self.vertices[prev].clone());
+                    current = prev;
+                }
+                vertices.reverse();
+                return Some(Route { vertices, distance });
+            }
+            if distance > distances[vertex] {
+                continue;
+            }
+
+            for (neighbor, length) in self.neighbors(vertex) {
+                let neighbor_distance = distance + length;
+                if neighbor_distance < distances[neighbor] {
+                    distances[neighbor] = neighbor_distance;
+                    previous[neighbor] = Some(vertex);
+                    queue.push(QueueEntry {
+                        distance: neighbor_distance,
+                        vertex: neighbor,
+                    });
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Walking distances between every pair of rooms, measured between whichever vertices of the two
+/// rooms are closest along the routing graph
+#[derive(Debug, Clone, PartialEq)]
+pub struct DistanceMatrix {
+    /// Room numbers in the order of the rows and columns, sorted by [`natural_cmp`]
+    rooms: Vec<String>,
+    /// Distances with a row for each room the distance is from, and `f64::INFINITY` for rooms
+    /// that can't be reached
+    distances: Vec<f64>,
+}
+
+impl DistanceMatrix {
+    /// Finds the distances between the rooms of `map_data` along `graph`, with a search from each
+    /// room run in parallel
+    pub(crate) fn new(map_data: &MapData, graph: &RoutingGraph) -> Self {
+        let mut rooms: Vec<_> = map_data.rooms.keys().cloned().collect();
+        rooms.sort_unstable_by(|a, b| natural_cmp(a, b));
+
+        let distances = rooms
+            .par_iter()
+            .flat_map_iter(|from| {
+                let reached =
+                    graph.distances_from(map_data.rooms[from].vertices.iter().map(String::as_str));
+                rooms.iter().map(move |to| {
+                    map_data.rooms[to]
+                        .vertices
+                        .iter()
+                        .filter_map(|vertex| reached.get(vertex.as_str()))
+                        .copied()
+                        .fold(f64::INFINITY, f64::min)
+                })
+            })
+            .collect();
+        Self { rooms, distances }
+    }
+
+    /// Returns the numbers of the rooms in the matrix, sorted
+    pub fn rooms(&self) -> &[String] {
+        &self.rooms
+    }
+
+    /// Returns the distance from room `from` to room `to`, which is `f64::INFINITY` if `to` can't
+    /// be reached from `from`, or `None` if either isn't a room in the matrix
+    pub fn distance(&self, from: &str, to: &str) -> Option<f64> {
+        let index = |number: &str| {
+            self.rooms
+                .binary_search_by(|room| natural_cmp(room, number))
+                .ok()
+        };
+        Some(self.distances[index(from)? * self.rooms.len() + index(to)?])
+    }
+
+    /// Writes the matrix as CSV, with a header row and column of room numbers. Rooms that can't be
+    /// reached have a distance of `inf`.
+    #[cfg(feature = "csv")]
+    pub fn to_csv<W: io::Write>(&self, writer: W) -> csv::Result<()> {
+        let mut writer = csv::Writer::from_writer(writer);
+        writer
+            .write_record(std::iter::once("room").chain(self.rooms.iter().map(String::as_str)))?;
+        if !self.rooms.is_empty() {
+            for (from, row) in self
+                .rooms
+                .iter()
+                .zip(self.distances.chunks(self.rooms.len()))
+            {
+                writer.write_record(
+                    std::iter::once(from.clone())
+                        .chain(row.iter().map(|distance| distance.to_string())),
+                )?;
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Vertex waiting to be visited, by ID or index, ordered so that the closest vertex is at the top
+/// of the heap
+#[derive(Debug, PartialEq)]
+struct QueueEntry<V> {
+    distance: f64,
+    vertex: V,
+}
+
+impl<V: Ord> Eq for QueueEntry<V> {}
+
+impl<V: Ord> Ord for QueueEntry<V> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .distance
+            .total_cmp(&self.distance)
+            .then_with(|| self.vertex.cmp(&other.vertex))
+    }
+}
+
+impl<V: Ord> PartialOrd for QueueEntry<V> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Finds the vertices that are in at least one room, where every room they're in is tagged
+/// [`RoomTag::Closed`]
+fn closed_room_vertices(map_data: &MapData) -> HashSet<&str> {
+    let mut all_closed: HashMap<&str, bool> = HashMap::new();
+    for room in map_data.rooms.values() {
+        let closed = room.tags.contains(&RoomTag::Closed);
+        for vertex in &room.vertices {
+            *all_closed.entry(vertex).or_insert(true) &= closed;
+        }
+    }
+    all_closed
+        .into_iter()
+        .filter(|(_vertex, closed)| *closed)
+        .map(|(vertex, _closed)| vertex)
+        .collect()
+}
+
+/// Determines if `edge` connects vertices on different floors
+pub(crate) fn is_cross_floor(edge: &Edge, vertices: &HashMap<String, Vertex>) -> bool {
+    vertices[edge.get_from()].get_floor() != vertices[edge.get_to()].get_floor()
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::*;
+
+    fn escalators() -> MapData {
+        let json = fs::read_to_string("tests/json/escalators.json").unwrap();
+        serde_json::from_str(&json).unwrap()
+    }
+
+    fn route(graph: &RoutingGraph, from: &str, to: &str) -> Vec<String> {
+        graph.shortest_path(from, to).unwrap().vertices
+    }
+
+    #[test]
+    fn one_way_escalators() {
+        let graph = RoutingGraph::new(&escalators());
+
+        assert_eq!(
+            vec!["hall-1", "up-1", "up-2", "hall-2"],
+            route(&graph, "hall-1", "hall-2")
+        );
+        // The up escalator is closer, but can't be taken down
+        assert_eq!(
+            vec!["hall-2", "down-2", "down-1", "hall-1"],
+            route(&graph, "hall-2", "hall-1")
+        );
+        assert!(graph.neighbors("up-2").iter().all(|(to, _)| to != "up-1"));
+        assert!(graph
+            .neighbors("down-1")
+            .iter()
+            .all(|(to, _)| to != "down-2"));
+    }
+
+    #[test]
+    fn exclude_edge_tags() {
+        let mut map_data = escalators();
+        let edge = &mut map_data.edges[1];
+        *edge = edge.clone().with_tags([EdgeTag::StaffOnly].into());
+
+        let graph = RoutingGraph::new(&map_data);
+        assert_eq!(
+            vec!["hall-1", "up-1", "up-2", "hall-2"],
+            route(&graph, "hall-1", "hall-2")
+        );
+
+        let options = RoutingOptions {
+            excluded_edge_tags: [EdgeTag::StaffOnly].into(),
+            ..RoutingOptions::default()
+        };
+        let graph = RoutingGraph::with_options(&map_data, &options);
+        // The down escalator can't be taken up
+        assert_eq!(None, graph.shortest_path("hall-1", "hall-2"));
+        assert!(graph.neighbors("up-1").iter().all(|(to, _)| to != "up-2"));
+    }
+
+    #[test]
+    fn exclude_vertex_tags() {
+        let options = RoutingOptions {
+            excluded_vertex_tags: [VertexTag::Up].into(),
+            ..RoutingOptions::default()
+        };
+        let graph = RoutingGraph::with_options(&escalators(), &options);
+        assert!(graph.neighbors("hall-1").iter().all(|(to, _)| to != "up-1"));
+        assert_eq!(None, graph.shortest_path("hall-1", "hall-2"));
+        assert_eq!(
+            vec!["hall-2", "down-2", "down-1", "hall-1"],
+            route(&graph, "hall-2", "hall-1")
+        );
+    }
+
+    #[test]
+    fn avoid_closed_rooms() {
+        let json = fs::read_to_string("tests/json/closed_room.json").unwrap();
+        let map_data: MapData = serde_json::from_str(&json).unwrap();
+        let through_room = vec!["hall-west", "door-101", "door-101-102", "hall-east"];
+        let around_room = vec!["hall-west", "corner-west", "corner-east", "hall-east"];
+
+        let graph = RoutingGraph::new(&map_data);
+        assert_eq!(through_room, route(&graph, "hall-west", "hall-east"));
+
+        let graph_with = |closed_rooms| {
+            let options = RoutingOptions {
+                closed_rooms,
+                ..RoutingOptions::default()
+            };
+            RoutingGraph::with_options(&map_data, &options)
+        };
+        let graph = graph_with(ClosedRooms::Excluded);
+        assert_eq!(around_room, route(&graph, "hall-west", "hall-east"));
+        assert_eq!(None, graph.shortest_path("hall-west", "door-101"));
+        // Also in room 102, which isn't closed
+        assert_eq!(
+            vec![
+                "hall-west",
+                "corner-west",
+                "corner-east",
+                "hall-east",
+                "door-101-102"
+            ],
+            route(&graph, "hall-west", "door-101-102")
+        );
+
+        // Going around is 40 units longer
+        let graph = graph_with(ClosedRooms::Penalized(25.));
+        let route_through = graph.shortest_path("hall-west", "hall-east").unwrap();
+        assert_eq!(through_room, route_through.vertices);
+        assert_eq!(65., route_through.distance);
+        let graph = graph_with(ClosedRooms::Penalized(50.));
+        assert_eq!(around_room, route(&graph, "hall-west", "hall-east"));
+
+        // Penalties that would make edges shorter or unusable are taken as none
+        for penalty in [-100., f64::NAN, f64::NEG_INFINITY] {
+            let graph = graph_with(ClosedRooms::Penalized(penalty));
+            let route_through = graph.shortest_path("hall-west", "hall-east").unwrap();
+            assert_eq!(through_room, route_through.vertices);
+            assert_eq!(40., route_through.distance);
+        }
+        let graph = graph_with(ClosedRooms::Penalized(f64::INFINITY));
+        assert_eq!(around_room, route(&graph, "hall-west", "hall-east"));
+        assert_eq!(None, graph.shortest_path("hall-west", "door-101"));
+    }
+
+    #[test]
+    fn distances_from_several_sources() {
+        let graph = RoutingGraph::new(&escalators());
+        let distances = graph.distances_from(["hall-2", "down-1"]);
+        assert_eq!(0., distances["hall-2"]);
+        assert_eq!(0., distances["down-1"]);
+        assert_eq!(10., distances["up-2"]);
+        assert_eq!(30., distances["hall-1"]);
+        assert_eq!(6, distances.len());
+
+        // The escalator's landings line up, so riding it adds no distance
+        let distances = graph.distances_from(["up-1"]);
+        assert_eq!(10., distances["hall-2"]);
+
+        let distances = graph.distances_within(["hall-1"], 20.);
+        let mut reached: Vec<_> = distances.keys().copied().collect();
+        reached.sort_unstable();
+        assert_eq!(vec!["hall-1", "hall-2", "up-1", "up-2"], reached);
+    }
+
+    #[test]
+    fn compact_graph_routes() {
+        for fixture in ["escalators", "closed_room"] {
+            let json = fs::read_to_string(format!("tests/json/{}.json", fixture)).unwrap();
+            let map_data: MapData = serde_json::from_str(&json).unwrap();
+            let compact = map_data.to_compact_graph();
+            let compact: CompactGraph =
+                serde_json::from_str(&serde_json::to_string(&compact).unwrap()).unwrap();
+
+            for from in map_data.vertices.keys() {
+                for to in map_data.vertices.keys() {
+                    assert_eq!(
+                        map_data.graph().shortest_path(from, to),
+                        compact.shortest_path(from, to)
+                    );
+                }
+            }
+            for (number, room) in &map_data.rooms {
+                let vertices: HashSet<_> = compact
+                    .room_vertices(number)
+                    .iter()
+                    .map(|&index| compact.vertices()[index as usize].clone())
+                    .collect();
+                assert_eq!(room.vertices, vertices);
+            }
+        }
+
+        let compact = escalators().to_compact_graph();
+        let up_2 = compact.vertex_index("up-2").unwrap();
+        assert_eq!("2", compact.floor(up_2));
+        assert_eq!(None, compact.shortest_path("hall-1", "nowhere"));
+        assert!(compact.room_vertices("nowhere").is_empty());
+    }
+
+    #[test]
+    fn reject_invalid_compact_graphs() {
+        let json = serde_json::to_value(escalators().to_compact_graph()).unwrap();
+        let read = |edit: &dyn Fn(&mut serde_json::Value)| {
+            let mut json = json.clone();
+            edit(&mut json);
+            serde_json::from_value::<CompactGraph>(json).map_err(|err| err.to_string())
+        };
+        assert!(read(&|_json| {}).is_ok());
+        let default = serde_json::to_value(CompactGraph::default()).unwrap();
+        assert_eq!(
+            CompactGraph::default(),
+            serde_json::from_value(default).unwrap()
+        );
+
+        let errors = [
+            read(&|json| {
+                json["locations"].as_array_mut().unwrap().pop();
+            }),
+            read(&|json| json["vertices"].as_array_mut().unwrap().swap(0, 1)),
+            read(&|json| {
+                let edge_starts = json["edge_starts"].as_array_mut().unwrap();
+                let last = edge_starts.len() - 1;
+                edge_starts[last] = 1000.into();
+            }),
+            read(&|json| json["lengths"][1] = (-1.).into()),
+            read(&|json| json["targets"][0] = 1000.into()),
+            read(&|json| json["vertex_floors"][0] = 2.into()),
+            read(&|json| json["rooms"]["nowhere"] = serde_json::json!([1000])),
+        ];
+        let expected = [
+            InvalidCompactGraph::WrongLength {
+                field: "locations",
+                expected: 6,
+                found: 5,
+            },
+            InvalidCompactGraph::UnsortedVertices,
+            InvalidCompactGraph::InvalidEdgeStarts,
+            InvalidCompactGraph::InvalidLength { index: 1 },
+            InvalidCompactGraph::IndexOutOfBounds {
+                field: "targets",
+                index: 1000,
+                len: 6,
+            },
+            InvalidCompactGraph::IndexOutOfBounds {
+                field: "vertex_floors",
+                index: 2,
+                len: 2,
+            },
+            InvalidCompactGraph::IndexOutOfBounds {
+                field: "rooms",
+                index: 1000,
+                len: 6,
+            },
+        ];
+        for (error, expected) in errors.into_iter().zip(expected) {
+            assert_eq!(Err(expected.to_string()), error);
+        }
+    }
+
+    #[test]
+    fn shortest_paths() {
+        let mut map_data = escalators();
+        let graph = RoutingGraph::new(&map_data);
+        let route = graph.shortest_path("hall-1", "hall-2").unwrap();
+        assert_eq!(20., route.distance);
+        assert_eq!(
+            Some(Route {
+                vertices: vec!["hall-1".to_owned()],
+                distance: 0.
+            }),
+            graph.shortest_path("hall-1", "hall-1")
+        );
+
+        // Without the down escalator, there is no way back down
+        map_data.edges.retain(|edge| edge.get_from() != "down-2");
+        let graph = RoutingGraph::new(&map_data);
+        assert_eq!(None, graph.shortest_path("hall-2", "hall-1"));
+    }
+}
@@ -0,0 +1,320 @@
+//! Searching rooms and points of interest by number and name, ranked the same way for every
+//! client
+
+use std::cmp::Reverse;
+
+use serde::{Deserialize, Serialize};
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::map_data::compiled::MapData;
+
+/// Score of a query that is exactly a room's number
+const EXACT_NUMBER_SCORE: u32 = 100;
+/// Score of a query that is the start of a room's number
+const NUMBER_PREFIX_SCORE: u32 = 70;
+/// Score of a query that is exactly one of a room's names
+const EXACT_NAME_SCORE: u32 = 60;
+/// Score of a query that is the start of one of a room's names
+const NAME_PREFIX_SCORE: u32 = 50;
+/// Score of each query token that starts a token of the room, if all of them do
+const TOKEN_SCORE: u32 = 10;
+/// Steps of prominence per doubling of a room's area, so that rooms of about the same size tie
+const AREA_PROMINENCE_PER_DOUBLING: f64 = 2.;
+
+/// Lowercases `text` and removes its diacritics, so that `Café` matches `cafe`
+fn normalize(text: &str) -> String {
+    text.nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Splits normalized text into tokens at whitespace and punctuation
+fn tokenize(normalized: &str) -> impl Iterator<Item = &str> {
+    normalized
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+}
+
+/// Normalizes `text` and rejoins its tokens with single spaces, so that punctuation and spacing
+/// don't affect prefix matches
+fn normalize_tokens(text: &str) -> String {
+    tokenize(&normalize(text)).collect::<Vec<_>>().join(" ")
+}
+
+/// Room or point of interest matching a query, with higher scores for better matches
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SearchHit {
+    /// Room number, or point of interest ID if `poi` is set
+    pub room: String,
+    pub score: u32,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_false")]
+    pub poi: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct IndexedRoom {
+    room: String,
+    number: String,
+    names: Vec<String>,
+    tokens: Vec<String>,
+    /// Points of interest are indexed the same way as rooms, with their ID as their number
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_false")]
+    poi: bool,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_zero")]
+    priority: i32,
+    /// Log-scaled area of the room, from [`area_prominence`]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_zero")]
+    area: u32,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+fn is_zero<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+/// Ranks rooms by area on a log scale, rounded so that it's the same on every platform and rooms
+/// only a little larger than others don't outrank them
+fn area_prominence(area: f64) -> u32 {
+    if area.is_finite() && area > 0. {
+        ((area + 1.).log2() * AREA_PROMINENCE_PER_DOUBLING).round() as u32
+    } else {
+        0
+    }
+}
+
+impl IndexedRoom {
+    fn score(&self, query: &str, query_tokens: &[&str]) -> Option<u32> {
+        if self.number == query {
+            return Some(EXACT_NUMBER_SCORE);
+        }
+        if self.number.starts_with(query) {
+            return Some(NUMBER_PREFIX_SCORE);
+        }
+        if self.names.iter().any(|name| name == query) {
+            return Some(EXACT_NAME_SCORE);
+        }
+        if self.names.iter().any(|name| name.starts_with(query)) {
+            return Some(NAME_PREFIX_SCORE);
+        }
+        let all_tokens_match = query_tokens.iter().all(|query_token| {
+            self.tokens
+                .iter()
+                .any(|token| token.starts_with(query_token))
+        });
+        if all_tokens_match {
+            Some(TOKEN_SCORE * query_tokens.len() as u32)
+        } else {
+            None
+        }
+    }
+}
+
+/// Normalized room numbers, names, and their tokens, which can be written into compiled map data
+/// for clients that can't build the index themselves
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchIndex {
+    /// Sorted by room number, with points of interest after rooms of the same number
+    rooms: Vec<IndexedRoom>,
+}
+
+impl SearchIndex {
+    pub fn build(map_data: &MapData) -> Self {
+        let rooms = map_data.rooms.iter().map(|(number, room)| {
+            let priority = room.priority.unwrap_or_default();
+            (
+                number,
+                &room.names,
+                false,
+                priority,
+                area_prominence(room.area),
+            )
+        });
+        let pois = map_data
+            .pois
+            .iter()
+            .map(|(id, poi)| (id, &poi.names, true, 0, 0));
+        let mut rooms: Vec<_> = rooms
+            .chain(pois)
+            .map(|(room, names, poi, priority, area)| {
+                let number = normalize_tokens(room);
+                let names: Vec<_> = names.iter().map(|name| normalize_tokens(name)).collect();
+                let mut tokens: Vec<String> = tokenize(&number)
+                    .chain(names.iter().flat_map(|name| tokenize(name)))
+                    .map(str::to_owned)
+                    .collect();
+                tokens.sort_unstable();
+                tokens.dedup();
+                IndexedRoom {
+                    room: room.clone(),
+                    number,
+                    names,
+                    tokens,
+                    poi,
+                    priority,
+                    area,
+                }
+            })
+            .collect();
+        rooms.sort_unstable_by(|a, b| (&a.room, a.poi).cmp(&(&b.room, b.poi)));
+        Self { rooms }
+    }
+
+    /// Finds the rooms matching `query`, best matches first. An exact room number ranks above
+    /// the start of a room number, which ranks above an exact name and then the start of a name.
+    /// Otherwise, every word of the query must start a word of the room's number or names. Rooms
+    /// with the same score are sorted by priority, then by area, so that the main gym comes
+    /// before a small room that is also named gym, and then by number. At most `limit` are
+    /// returned.
+    pub fn query(&self, query: &str, limit: usize) -> Vec<SearchHit> {
+        let query = normalize_tokens(query);
+        let query_tokens: Vec<_> = tokenize(&query).collect();
+        if query_tokens.is_empty() {
+            return vec![];
+        }
+
+        let mut hits: Vec<_> = self
+            .rooms
+            .iter()
+            .filter_map(|room| {
+                room.score(&query, &query_tokens)
+                    .map(|score| (Reverse((score, room.priority, room.area)), room))
+            })
+            .collect();
+        // The rooms are already sorted by number, and the sort is stable
+        hits.sort_by_key(|(key, _room)| *key);
+        hits.truncate(limit);
+        hits.into_iter()
+            .map(|(Reverse((score, _, _)), room)| SearchHit {
+                room: room.room.clone(),
+                score,
+                poi: room.poi,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::*;
+
+    fn index() -> SearchIndex {
+        let json = fs::read_to_string("tests/json/search.json").unwrap();
+        let map_data: MapData = serde_json::from_str(&json).unwrap();
+        SearchIndex::build(&map_data)
+    }
+
+    fn rooms(hits: Vec<SearchHit>) -> Vec<String> {
+        hits.into_iter().map(|hit| hit.room).collect()
+    }
+
+    #[test]
+    fn normalize_text() {
+        assert_eq!("cafe", normalize("Café"));
+        assert_eq!("ecole", normalize("ÉCOLE"));
+        assert_eq!("media center", normalize_tokens("  Media-Center! "));
+    }
+
+    #[test]
+    fn rank_matches() {
+        let index = index();
+
+        // An exact number beats the start of other numbers
+        assert_eq!(vec!["106", "1065"], rooms(index.query("106", 10)));
+        assert_eq!(
+            vec![
+                SearchHit {
+                    room: "204".to_owned(),
+                    score: EXACT_NAME_SCORE,
+                    poi: false,
+                },
+                SearchHit {
+                    room: "106".to_owned(),
+                    score: NAME_PREFIX_SCORE,
+                    poi: false,
+                },
+                SearchHit {
+                    room: "1065".to_owned(),
+                    score: 2 * TOKEN_SCORE,
+                    poi: false,
+                },
+            ],
+            index.query("science lab", 10)
+        );
+        assert_eq!(vec!["204"], rooms(index.query("science lab", 1)));
+        // Every token has to match
+        assert_eq!(vec!["1065"], rooms(index.query("lab prep", 10)));
+        assert_eq!(vec!["300"], rooms(index.query("cafe", 10)));
+        assert_eq!(vec!["300"], rooms(index.query("CAFÉTÉRIA", 10)));
+        assert!(index.query("gym", 10).is_empty());
+        assert!(index.query(" - ", 10).is_empty());
+
+        // Points of interest are found by name and ID
+        assert_eq!(
+            vec![SearchHit {
+                room: "fountain-1".to_owned(),
+                score: NAME_PREFIX_SCORE,
+                poi: true,
+            }],
+            index.query("water", 10)
+        );
+        assert_eq!(vec!["fountain-1"], rooms(index.query("fountain 1", 10)));
+    }
+
+    #[test]
+    fn rank_prominent_rooms_first() {
+        let json = fs::read_to_string("tests/json/search_prominence.json").unwrap();
+        let mut map_data: MapData = serde_json::from_str(&json).unwrap();
+        let index = SearchIndex::build(&map_data);
+
+        // Equal names rank by priority, then area, then number. The gym office is larger than
+        // every gym, but only starts with the query.
+        assert_eq!(
+            vec!["190", "150", "105", "110", "120"],
+            rooms(index.query("gym", 10))
+        );
+        assert_eq!(vec!["190", "150"], rooms(index.query("gym", 2)));
+        // Scores are still those of the matches
+        assert!(index
+            .query("gym", 10)
+            .iter()
+            .take(4)
+            .all(|hit| hit.score == EXACT_NAME_SCORE));
+
+        // Rooms within a few percent of each other's area tie
+        assert_eq!(area_prominence(140.), area_prominence(145.));
+        assert!(area_prominence(300.) > area_prominence(145.));
+        assert_eq!(0, area_prominence(f64::NAN));
+
+        map_data.rooms.get_mut("190").unwrap().priority = Some(-1);
+        assert_eq!(
+            vec!["150", "105", "110", "190", "120"],
+            rooms(SearchIndex::build(&map_data).query("gym", 10))
+        );
+    }
+
+    #[test]
+    fn index_round_trips() {
+        let index = index();
+        let json = serde_json::to_string(&index).unwrap();
+        assert_eq!(index, serde_json::from_str(&json).unwrap());
+
+        let mut map_data: MapData =
+            serde_json::from_str(&fs::read_to_string("tests/json/search.json").unwrap()).unwrap();
+        assert!(!serde_json::to_string(&map_data).unwrap().contains("index"));
+        map_data.index = Some(index);
+        let json = serde_json::to_string(&map_data).unwrap();
+        assert!(json.contains(r#""index":{"rooms":[{"room":"106""#));
+        assert_eq!(map_data, serde_json::from_str(&json).unwrap());
+    }
+}
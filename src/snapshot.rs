@@ -0,0 +1,23 @@
+//! Comparing output with snapshots of it kept in `tests/snapshots`
+
+use std::path::Path;
+use std::{env, fs};
+
+/// Compares `actual` with the snapshot at `tests/snapshots/{name}`. Run the tests with
+/// `UPDATE_SNAPSHOTS` set to write the snapshot instead, after checking that the change in output
+/// is intended.
+pub fn assert_snapshot(name: &str, actual: &str) {
+    let path = Path::new("tests/snapshots").join(name);
+    if env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        fs::write(&path, actual).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&path)
+        .unwrap_or_else(|_| panic!("Missing snapshot {}:\n{}", path.display(), actual));
+    assert!(
+        expected == actual,
+        "Output differs from the snapshot {}:\n{}",
+        path.display(),
+        actual
+    );
+}
@@ -0,0 +1,263 @@
+use std::collections::{HashMap, HashSet};
+
+/// Splits the contents of a `style` attribute (or a CSS rule body) into `(property, value)`
+/// pairs. Properties are trimmed and lowercased, values are trimmed and have `!important`
+/// removed. Malformed declarations without a `:` are ignored.
+pub fn declarations(style: &str) -> impl Iterator<Item = (String, &str)> {
+    style.split(';').filter_map(|declaration| {
+        let (property, value) = declaration.split_once(':')?;
+        let value = value.trim();
+        let value = value.strip_suffix("!important").unwrap_or(value).trim();
+        Some((property.trim().to_ascii_lowercase(), value))
+    })
+}
+
+/// Rewrites the value of every declaration of `property` in `style` with `rewrite`, keeping the
+/// rest of the style as it was written. Values are passed to `rewrite` trimmed and without
+/// `!important`, which is added back afterwards.
+pub fn replace_property(style: &str, property: &str, rewrite: impl Fn(&str) -> String) -> String {
+    style
+        .split(';')
+        .map(|declaration| match declaration.split_once(':') {
+            Some((name, value)) if name.trim().eq_ignore_ascii_case(property) => {
+                let value = value.trim();
+                match value.strip_suffix("!important") {
+                    Some(value) => format!("{}:{} !important", name, rewrite(value.trim())),
+                    None => format!("{}:{}", name, rewrite(value)),
+                }
+            }
+            _ => declaration.to_owned(),
+        })
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+/// Returns `true` if the CSS declaration `property: value` makes an element and its children
+/// invisible.
+pub fn hides_element(property: &str, value: &str) -> bool {
+    match property {
+        "display" => value.eq_ignore_ascii_case("none"),
+        "visibility" => {
+            value.eq_ignore_ascii_case("hidden") || value.eq_ignore_ascii_case("collapse")
+        }
+        _ => false,
+    }
+}
+
+/// Returns `true` if any declaration in `style` hides the element.
+pub fn style_hides_element(style: &str) -> bool {
+    declarations(style).any(|(property, value)| hides_element(&property, value))
+}
+
+/// Returns `true` if `style` has `display: none`, which hides an element along with all of its
+/// children, whatever their own styles are.
+pub fn style_undisplays_element(style: &str) -> bool {
+    declarations(style)
+        .any(|(property, value)| property == "display" && hides_element(&property, value))
+}
+
+/// Reads a value of the `visibility` property as whether it shows an element, or `None` if it
+/// leaves that to the element's parent, like `inherit`. Unlike `display: none`, a child can be
+/// shown with `visibility: visible` even if its parent is hidden.
+pub fn visibility(value: &str) -> Option<bool> {
+    if value.eq_ignore_ascii_case("visible") {
+        Some(true)
+    } else if hides_element("visibility", value) {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/// Finds whether the last `visibility` declaration in `style` shows the element, or `None` if
+/// there isn't one. See [`visibility`].
+pub fn style_visibility(style: &str) -> Option<bool> {
+    declarations(style)
+        .filter(|(property, _value)| property == "visibility")
+        .filter_map(|(_property, value)| visibility(value))
+        .last()
+}
+
+/// How the rules of a stylesheet show or hide classes, found by [`class_visibility`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClassVisibility {
+    /// Classes with `display: none`
+    pub undisplayed: HashSet<String>,
+    /// Whether each class with a `visibility` declaration shows its elements
+    pub visible: HashMap<String, bool>,
+}
+
+impl ClassVisibility {
+    /// Adds the classes of a later stylesheet, whose rules win like they do in CSS
+    pub fn extend(&mut self, later: ClassVisibility) {
+        self.undisplayed.extend(later.undisplayed);
+        self.visible.extend(later.visible);
+    }
+}
+
+/// Finds the classes hidden by a stylesheet. Only rules made up entirely of simple class
+/// selectors (`.a, .b { display: none }`) are considered.
+pub fn hidden_classes(stylesheet: &str) -> HashSet<String> {
+    class_rules(stylesheet)
+        .into_iter()
+        .filter(|(_class, body)| style_hides_element(body))
+        .map(|(class, _body)| class)
+        .collect()
+}
+
+/// Same as [`hidden_classes`], but tells classes with `display: none` apart from those with
+/// `visibility`, which their elements' children can override
+pub fn class_visibility(stylesheet: &str) -> ClassVisibility {
+    let mut classes = ClassVisibility::default();
+    for (class, body) in class_rules(stylesheet) {
+        if style_undisplays_element(&body) {
+            classes.undisplayed.insert(class.clone());
+        }
+        if let Some(visible) = style_visibility(&body) {
+            classes.visible.insert(class, visible);
+        }
+    }
+    classes
+}
+
+/// Finds the class and body of every rule in a stylesheet made up entirely of simple class
+/// selectors, with one entry for each class of a rule
+fn class_rules(stylesheet: &str) -> Vec<(String, String)> {
+    let stylesheet = strip_comments(stylesheet);
+    stylesheet
+        .split('}')
+        .filter_map(|rule| rule.split_once('{'))
+        .flat_map(|(selectors, body)| {
+            selectors
+                .split(',')
+                .map(move |selector| (selector.trim(), body))
+        })
+        .filter_map(|(selector, body)| Some((selector.strip_prefix('.')?, body)))
+        .filter(|(class, _body)| {
+            !class.is_empty()
+                && class
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        })
+        .map(|(class, body)| (class.to_owned(), body.to_owned()))
+        .collect()
+}
+
+fn strip_comments(stylesheet: &str) -> String {
+    let mut stripped = String::with_capacity(stylesheet.len());
+    let mut rest = stylesheet;
+    while let Some(start) = rest.find("/*") {
+        stripped.push_str(&rest[..start]);
+        rest = match rest[start + 2..].find("*/") {
+            Some(end) => &rest[start + 2 + end + 2..],
+            None => "",
+        };
+    }
+    stripped.push_str(rest);
+    stripped
+}
+
+#[cfg(test)]
+mod test {
+    use common_macros::{hash_map, hash_set};
+
+    use super::*;
+
+    #[test]
+    fn hidden_styles() {
+        let hidden = [
+            "display:none",
+            "display: none;",
+            "fill:blue;display:none",
+            "  DISPLAY :  None ; fill: red",
+            "display:none !important",
+            "visibility:hidden",
+            "stroke:pink; visibility : collapse",
+        ];
+        for style in hidden {
+            assert!(style_hides_element(style), "{:?} should be hidden", style);
+        }
+
+        let visible = [
+            "",
+            "display:inline",
+            "fill:none",
+            "visibility:visible",
+            "display",
+            "font-family: 'display:none'",
+        ];
+        for style in visible {
+            assert!(!style_hides_element(style), "{:?} should be visible", style);
+        }
+    }
+
+    #[test]
+    fn visibility_of_styles() {
+        assert_eq!(None, style_visibility("fill:red;display:none"));
+        assert_eq!(Some(false), style_visibility("visibility: hidden"));
+        assert_eq!(
+            Some(true),
+            style_visibility("visibility:hidden; VISIBILITY: Visible")
+        );
+        assert_eq!(
+            Some(false),
+            style_visibility("visibility:collapse;visibility:inherit")
+        );
+        assert!(style_undisplays_element("fill:red; display : none"));
+        assert!(!style_undisplays_element("visibility:hidden"));
+
+        let stylesheet = "
+            .st1 { display: none }
+            .st2, .st3 { visibility: hidden }
+            .st3 { visibility: visible }
+        ";
+        assert_eq!(
+            ClassVisibility {
+                undisplayed: hash_set!["st1".to_owned()],
+                visible: hash_map!["st2".to_owned() => false, "st3".to_owned() => true],
+            },
+            class_visibility(stylesheet)
+        );
+    }
+
+    #[test]
+    fn replace_properties() {
+        let double = |value: &str| format!("{}{}", value, value);
+        assert_eq!(
+            "fill:red; stroke-width:22;stroke:blue",
+            replace_property(
+                "fill:red; stroke-width: 2;stroke:blue",
+                "stroke-width",
+                double
+            )
+        );
+        assert_eq!(
+            "STROKE-WIDTH :33 !important;",
+            replace_property("STROKE-WIDTH : 3 !important;", "stroke-width", double)
+        );
+        assert_eq!(
+            "fill:red;stroke",
+            replace_property("fill:red;stroke", "stroke-width", double)
+        );
+    }
+
+    #[test]
+    fn hidden_classes_from_stylesheet() {
+        let stylesheet = "
+            .st0 { fill: #FFFFFF; }
+            .st1,.st2 {display:none}
+            /* .commented { display: none } */
+            .st3
+            {
+                stroke: black;
+                visibility: hidden;
+            }
+            g.st4 { display: none }
+            #id { display: none }
+        ";
+        assert_eq!(
+            hash_set!["st1".to_owned(), "st2".to_owned(), "st3".to_owned()],
+            hidden_classes(stylesheet)
+        );
+    }
+}
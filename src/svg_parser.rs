@@ -1,9 +1,9 @@
-use std::borrow::{Borrow, Cow};
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::iter::Peekable;
-use std::num::ParseFloatError;
+use std::sync::Arc;
 
-use anyhow::{anyhow, Context};
 use nalgebra::{Matrix3, Vector2, Vector3};
 use svg::events::Event;
 use svg::node::element::tag::Type;
@@ -12,16 +12,651 @@ use svg::node::{Attributes, Value};
 use svg::{Element, Parser};
 
 use crate::bounding_box::BoundingBox;
+use crate::numbers::{self, ParseNumberError};
+use crate::quad_tree::QuadTree;
+use crate::style;
+use crate::svg_path_parser::PathData;
+use crate::trace::span;
 use crate::transform;
-use crate::util::max_f64;
-use svg::node::element::path::Data;
 
+/// Attributes of a `use` element that can hold the reference to the element it draws
+const USE_REFERENCE_ATTRIBUTES: [&str; 2] = ["href", "xlink:href"];
+/// Limit on `use` elements nested inside inlined `use` elements, to stop reference cycles
+const MAX_USE_DEPTH: usize = 32;
+
+/// Attributes that change how an element is drawn, which are never stripped
+const REQUIRED_ATTRIBUTES: [&str; 5] = ["d", "transform", "viewBox", "style", "fill"];
+
+/// Elements that draw a shape, whose strokes can be made non-scaling
+const SHAPE_ELEMENTS: [&str; 7] = [
+    "path", "rect", "circle", "ellipse", "line", "polyline", "polygon",
+];
+
+/// Attributes holding only numbers and path or transform commands, whose numbers can be rounded
+/// when writing elements
+const NUMERIC_ATTRIBUTES: [&str; 18] = [
+    "d",
+    "transform",
+    "viewBox",
+    "points",
+    "x",
+    "y",
+    "width",
+    "height",
+    "x1",
+    "y1",
+    "x2",
+    "y2",
+    "cx",
+    "cy",
+    "r",
+    "rx",
+    "ry",
+    "stroke-width",
+];
+
+/// Size of one CSS point in user units (CSS pixels)
+const PX_PER_PT: f64 = 96. / 72.;
+
+/// Reason that a `transform` attribute couldn't be parsed
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum TransformError {
+    #[error("malformed {function} transform {transform:?}")]
+    Malformed {
+        function: &'static str,
+        transform: String,
+    },
+    #[error("wrong number of arguments to {function} transform {transform:?}")]
+    ArgumentCount {
+        function: &'static str,
+        transform: String,
+    },
+    #[error("unterminated transform {transform:?}")]
+    Unterminated { transform: String },
+    #[error(transparent)]
+    InvalidNumber(#[from] ParseNumberError),
+}
+
+/// Where an element is in a document, to help find what caused an error or warning
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Location {
+    /// Line of the element's tag, counting from 1, if it is known
+    pub line: Option<usize>,
+    /// ID of the element, if it has one
+    pub id: Option<String>,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.line, &self.id) {
+            (Some(line), Some(id)) => write!(f, "line {} (id={:?})", line, id),
+            (Some(line), None) => write!(f, "line {}", line),
+            (None, Some(id)) => write!(f, "id={:?}", id),
+            (None, None) => write!(f, "unknown location"),
+        }
+    }
+}
+
+/// Error that stopped SVG data from being parsed into an `SvgElement` tree. It converts into an
+/// `anyhow::Error` like any other error, for callers that don't need to match on it.
+#[derive(thiserror::Error, Debug)]
+pub enum SvgParseError {
+    #[error("Error reading SVG: {0}")]
+    Io(#[from] std::io::Error),
+    /// Malformed XML, along with its line and column
+    #[error(transparent)]
+    Xml(#[from] svg::parser::Error),
+    #[error("Expected SVG data but did not find any")]
+    NoSvgData,
+    #[error("Unexpected end of SVG")]
+    UnexpectedEnd,
+    #[error("{location}: unexpected end of SVG, missing end tag for <{name}>")]
+    MissingEndTag { name: String, location: Location },
+    #[error("{location}: mismatched end tag, expected </{expected}> but found </{found}>")]
+    MismatchedEndTag {
+        expected: String,
+        found: String,
+        location: Location,
+    },
+    #[error("{location}: unexpected end tag </{name}>")]
+    UnexpectedEndTag { name: String, location: Location },
+    #[error("{location}: the `{attribute}` attribute of <{tag}> is missing")]
+    MissingAttribute {
+        tag: String,
+        attribute: &'static str,
+        location: Location,
+    },
+    #[error("{location}: invalid path data in <{tag}>: {source}")]
+    InvalidPathData {
+        tag: String,
+        location: Location,
+        source: svg::parser::Error,
+    },
+    #[error("{location}: {source}")]
+    InvalidTransform {
+        tag: String,
+        location: Location,
+        source: TransformError,
+    },
+    #[error(
+        "{location}: too many nested use elements referencing {reference:?}, which may form a cycle"
+    )]
+    UseCycle {
+        reference: String,
+        location: Location,
+    },
+    #[error("{location}: elements are nested more than {max_depth} deep")]
+    TooDeep {
+        max_depth: usize,
+        location: Location,
+    },
+    #[error("{location}: there are more than {max_elements} elements")]
+    TooManyElements {
+        max_elements: usize,
+        location: Location,
+    },
+}
+
+/// Options controlling how SVG data is turned into an `SvgElement` tree.
+#[derive(Debug, Clone)]
+pub struct ParseOptions {
+    /// Skip elements with namespaced tag names (such as Inkscape's `sodipodi:namedview`) along
+    /// with all of their children
+    pub skip_namespaced: bool,
+    /// Keep elements hidden with `display: none` or `visibility: hidden`, whether set through an
+    /// attribute, the `style` attribute, or a class hidden by a `<style>` element. Hidden elements
+    /// are skipped by default, which is a breaking change from when every element was kept, so
+    /// set this to keep parsing the same elements as before. An element hidden with `visibility`
+    /// is kept if any of its descendants is shown again with `visibility: visible`.
+    pub include_hidden: bool,
+    /// Font size in user units that `em` lengths are relative to
+    pub base_font_size: f64,
+    /// Deepest that elements can be nested, counting the root element, before parsing fails with
+    /// [`SvgParseError::TooDeep`]. Parsed elements are walked recursively, so this keeps
+    /// pathological SVGs from overflowing the stack.
+    pub max_depth: usize,
+    /// Most elements that can be parsed, counting the copies made when inlining `use` elements,
+    /// before parsing fails with [`SvgParseError::TooManyElements`]
+    pub max_elements: usize,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            skip_namespaced: false,
+            include_hidden: false,
+            base_font_size: 16.,
+            max_depth: 1024,
+            max_elements: 1_000_000,
+        }
+    }
+}
+
+/// Determines if an attribute only holds data for an editor, such as Inkscape's `inkscape:label`
+/// and `sodipodi:nodetypes` or `data-*` attributes, none of which change how the SVG is drawn
+pub fn is_editor_metadata(name: &str) -> bool {
+    [
+        "inkscape:",
+        "sodipodi:",
+        "xmlns:inkscape",
+        "xmlns:sodipodi",
+        "data-",
+    ]
+    .iter()
+    .any(|prefix| name.starts_with(prefix))
+}
+
+/// Same as [`SvgElement::strip_attributes`], but for a node of a `svg` document and its
+/// descendants
+pub fn strip_node_attributes(node: &mut dyn svg::Node, strip: &dyn Fn(&str) -> bool) {
+    if let Some(attributes) = node.get_attributes_mut() {
+        attributes.retain(|name, _| !should_strip(name, strip));
+    }
+    if let Some(children) = node.get_children_mut() {
+        for child in children {
+            strip_node_attributes(child.as_mut(), strip);
+        }
+    }
+}
+
+fn should_strip(name: &str, strip: &dyn Fn(&str) -> bool) -> bool {
+    !REQUIRED_ATTRIBUTES.contains(&name) && strip(name)
+}
+
+/// Something in an SVG document that was ignored or guessed at while parsing, without stopping the
+/// rest of the document from being parsed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseWarning {
+    /// A transform function that isn't supported, such as `skewX`, was treated as the identity
+    UnknownTransform { transform: String },
+    /// An element with a namespaced tag name was skipped along with its children
+    SkippedNamespacedElement { name: String },
+    /// A hidden element was skipped along with its children
+    SkippedHiddenElement { name: String, id: Option<String> },
+    /// A length attribute that couldn't be resolved was treated as 0
+    InvalidLength {
+        attribute: String,
+        value: String,
+        reason: String,
+    },
+    /// A `viewBox` that isn't four numbers was ignored
+    MalformedViewBox { value: String },
+    /// Text content outside of a stylesheet was dropped
+    DroppedText { parent: String, text: String },
+    /// A `use` element references an element that doesn't exist, so it was left empty
+    MissingUseReference { reference: String },
+}
+
+impl fmt::Display for ParseWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownTransform { transform } => {
+                write!(f, "Ignored unsupported transform {:?}", transform)
+            }
+            Self::SkippedNamespacedElement { name } => {
+                write!(f, "Skipped namespaced element <{}>", name)
+            }
+            Self::SkippedHiddenElement { name, id: Some(id) } => {
+                write!(f, "Skipped hidden element <{}> with ID {:?}", name, id)
+            }
+            Self::SkippedHiddenElement { name, id: None } => {
+                write!(f, "Skipped hidden element <{}>", name)
+            }
+            Self::InvalidLength {
+                attribute,
+                value,
+                reason,
+            } => write!(f, "Treated {}={:?} as 0: {}", attribute, value, reason),
+            Self::MalformedViewBox { value } => write!(f, "Ignored malformed viewBox {:?}", value),
+            Self::DroppedText { parent, text } => {
+                write!(f, "Dropped text {:?} in <{}>", text.trim(), parent)
+            }
+            Self::MissingUseReference { reference } => {
+                write!(
+                    f,
+                    "Could not find element {:?} referenced by <use>",
+                    reference
+                )
+            }
+        }
+    }
+}
+
+/// Warning about the element at `location`
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocatedWarning {
+    pub location: Location,
+    pub warning: ParseWarning,
+}
+
+impl fmt::Display for LocatedWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.warning)
+    }
+}
+
+/// Everything noteworthy that happened while parsing a document, returned by
+/// [`SvgElement::from_svg_data_with_report`].
+#[derive(Debug, Clone, Default)]
+pub struct ParseReport {
+    pub warnings: Vec<LocatedWarning>,
+}
+
+/// Element being parsed, which errors and warnings are reported at
+#[derive(Debug, Clone, Default)]
+struct CurrentElement {
+    /// Byte offset of the element's tag name in the SVG data
+    offset: Option<usize>,
+    id: Option<String>,
+}
+
+/// Part of a document to keep while parsing it, so that the elements outside of it are dropped as
+/// soon as they are parsed
+struct Selection<'s> {
+    bounding_box: &'s BoundingBox,
+    keep: &'s dyn Fn(&SvgElement) -> bool,
+    /// IDs of the elements referenced by `use` elements, which are kept wherever they are, along
+    /// with the `use` elements, so that the `use` elements can be inlined
+    references: &'s HashSet<String>,
+}
+
+impl Selection<'_> {
+    /// Determines if `element` is or contains a `use` element or an element one references
+    fn draws_reference(&self, element: &SvgElement) -> bool {
+        element.use_reference().is_some()
+            || element
+                .attributes
+                .get("id")
+                .is_some_and(|id| self.references.contains(&**id))
+            || element
+                .children
+                .iter()
+                .any(|child| self.draws_reference(child))
+    }
+}
+
+/// Element whose start tag has been parsed, but whose end tag hasn't been reached yet
+struct OpenElement<'a, 's> {
+    name: &'a str,
+    attributes: Attributes,
+    global_top_left: Vector2<f64>,
+    size: Vector2<f64>,
+    transformation: Matrix3<f64>,
+    /// Selection to keep the children in, which is `None` inside of `defs`
+    selection: Option<&'s Selection<'s>>,
+    children: Vec<SvgElement<'a>>,
+    /// Bottom right corner of every child that was parsed, including those left out
+    children_bottom_right: Option<Vector2<f64>>,
+    /// Whether the element is shown, which is inherited by its children
+    visible: bool,
+    /// Viewport, element, and visibility to go back to once this element is finished
+    outer_viewport: Option<Vector2<f64>>,
+    outer_element: CurrentElement,
+    outer_visible: bool,
+}
+
+impl<'a> OpenElement<'a, '_> {
+    /// Adds a parsed child, unless it's outside of the selection
+    fn add_child(&mut self, child: SvgElement<'a>) {
+        let child_bottom_right = child.get_bottom_right();
+        self.children_bottom_right = Some(match self.children_bottom_right {
+            Some(bottom_right) => bottom_right.sup(&child_bottom_right),
+            None => child_bottom_right,
+        });
+        let selected = self.selection.is_none_or(|selection| {
+            child.tag_name == "defs"
+                || (child.bounding_box.intersects(selection.bounding_box)
+                    && (selection.keep)(&child))
+                || selection.draws_reference(&child)
+        });
+        if selected {
+            self.children.push(child);
+        }
+    }
+
+    /// Finishes the element once its end tag is reached. Returns `None` if the element is hidden
+    /// and none of its children are shown.
+    fn finish(self, context: &mut ParseContext) -> Option<SvgElement<'a>> {
+        if !self.visible && self.children.is_empty() {
+            context.warn(ParseWarning::SkippedHiddenElement {
+                name: self.name.to_owned(),
+                id: self.attributes.get("id").map(|id| id.to_string()),
+            });
+        }
+        // The root's viewport is kept for inlining `use` elements once parsing is done
+        if self.outer_viewport.is_some() {
+            context.viewport = self.outer_viewport;
+        }
+        context.current = self.outer_element;
+        context.visible = self.outer_visible;
+        if !self.visible && self.children.is_empty() {
+            return None;
+        }
+        Some(SvgElement {
+            bounding_box: SvgElement::fit_bottom_right(
+                self.global_top_left,
+                self.size,
+                self.children_bottom_right,
+            ),
+            children: self.children,
+            tag_name: Cow::Borrowed(self.name),
+            attributes: Arc::new(self.attributes),
+        })
+    }
+}
+
+/// State shared by every element while parsing one document
+struct ParseContext<'o> {
+    svg_data: &'o str,
+    options: &'o ParseOptions,
+    hidden_classes: style::ClassVisibility,
+    /// Whether the parent of the element being parsed is shown
+    visible: bool,
+    /// Size of the nearest enclosing viewport, which percentage lengths are relative to. `None`
+    /// until the root element is reached.
+    viewport: Option<Vector2<f64>>,
+    current: CurrentElement,
+    warnings: Vec<LocatedWarning>,
+    /// Number of elements parsed so far
+    elements: usize,
+}
+
+impl<'o> ParseContext<'o> {
+    fn new(svg_data: &'o str, options: &'o ParseOptions) -> Result<Self, SvgParseError> {
+        let hidden_classes = if options.include_hidden {
+            style::ClassVisibility::default()
+        } else {
+            Self::hidden_classes(svg_data)?
+        };
+        Ok(Self {
+            svg_data,
+            options,
+            hidden_classes,
+            visible: true,
+            viewport: None,
+            current: CurrentElement::default(),
+            warnings: Vec::new(),
+            elements: 0,
+        })
+    }
+
+    /// Finds the element whose tag name is `name`, which is only located if it was borrowed from
+    /// the SVG data
+    fn element(&self, name: &str, attributes: Option<&Attributes>) -> CurrentElement {
+        let start = self.svg_data.as_ptr() as usize;
+        let name_start = name.as_ptr() as usize;
+        let in_svg_data = (start..start + self.svg_data.len()).contains(&name_start);
+        CurrentElement {
+            offset: in_svg_data.then(|| name_start - start),
+            id: attributes
+                .and_then(|attributes| attributes.get("id"))
+                .map(|id| id.to_string()),
+        }
+    }
+
+    /// Finds the line of an element, which is only counted when an error or warning needs it
+    fn locate(&self, element: &CurrentElement) -> Location {
+        Location {
+            line: element
+                .offset
+                .map(|offset| self.svg_data[..offset].matches('\n').count() + 1),
+            id: element.id.clone(),
+        }
+    }
+
+    /// Location of the element being parsed
+    fn location(&self) -> Location {
+        self.locate(&self.current)
+    }
+
+    /// Fails if the element being parsed is nested deeper than allowed, where the root element has
+    /// a depth of 1
+    fn check_depth(&self, depth: usize) -> Result<(), SvgParseError> {
+        if depth > self.options.max_depth {
+            Err(SvgParseError::TooDeep {
+                max_depth: self.options.max_depth,
+                location: self.location(),
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Counts `count` more elements, failing if there are more than allowed
+    fn count_elements(&mut self, count: usize) -> Result<(), SvgParseError> {
+        self.elements += count;
+        if self.elements > self.options.max_elements {
+            Err(SvgParseError::TooManyElements {
+                max_elements: self.options.max_elements,
+                location: self.location(),
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Warns about the element being parsed
+    fn warn(&mut self, warning: ParseWarning) {
+        let location = self.location();
+        self.warnings.push(LocatedWarning { location, warning });
+    }
+
+    fn warn_at(&mut self, element: &CurrentElement, warning: ParseWarning) {
+        let location = self.locate(element);
+        self.warnings.push(LocatedWarning { location, warning });
+    }
+
+    /// Converts the length in the attribute `key` to user units. Percentages are resolved against
+    /// the width (`axis` 0) or height (`axis` 1) of the current viewport. Lengths that can't be
+    /// resolved are treated as 0 with a warning.
+    fn length(&mut self, attributes: &Attributes, key: &str, axis: usize) -> Option<f64> {
+        let value = attributes.get(key)?;
+        let length: Result<f64, String> = match numbers::parse_length(value) {
+            // Our exports are drawn with millimetres as user units, so those aren't converted
+            Ok((number, "" | "px" | "mm")) => Ok(number),
+            Ok((number, "pt")) => Ok(number * PX_PER_PT),
+            Ok((number, "em")) => Ok(number * self.options.base_font_size),
+            Ok((number, "%")) => self
+                .viewport
+                .map(|viewport| number / 100. * viewport[axis])
+                .ok_or_else(|| format!("no viewport to resolve {:?} against", &**value)),
+            Ok((_, unit)) => Err(ParseNumberError::UnsupportedUnit {
+                input: value.to_string(),
+                unit: unit.to_owned(),
+            }
+            .to_string()),
+            Err(err) => Err(err.to_string()),
+        };
+        Some(length.unwrap_or_else(|reason| {
+            self.warn(ParseWarning::InvalidLength {
+                attribute: key.to_owned(),
+                value: value.to_string(),
+                reason,
+            });
+            0.
+        }))
+    }
+
+    /// Finds the size of the viewport established by an `svg` element: its `viewBox` if it has
+    /// one, and otherwise its width and height.
+    fn svg_viewport(&mut self, attributes: &Attributes) -> Vector2<f64> {
+        if let Some(view_box) = attributes.get("viewBox") {
+            match numbers::parse_number_list(view_box).as_deref() {
+                Ok(&[_, _, width, height]) => return Vector2::new(width, height),
+                _ => self.warn(ParseWarning::MalformedViewBox {
+                    value: view_box.to_string(),
+                }),
+            }
+        }
+        let outer_viewport = self.viewport.unwrap_or_else(Vector2::zeros);
+        Vector2::new(
+            self.length(attributes, "width", 0)
+                .unwrap_or(outer_viewport[0]),
+            self.length(attributes, "height", 1)
+                .unwrap_or(outer_viewport[1]),
+        )
+    }
+
+    /// Collects the classes shown or hidden by all `<style>` elements in the document, so that
+    /// elements appearing before the stylesheet are also hidden
+    fn hidden_classes(svg_data: &str) -> Result<style::ClassVisibility, SvgParseError> {
+        let mut hidden_classes = style::ClassVisibility::default();
+        let mut in_style = false;
+        for event in svg::read(svg_data)? {
+            match event? {
+                Event::Tag("style", Type::Start, _attributes) => in_style = true,
+                Event::Tag("style", Type::End, _attributes) => in_style = false,
+                Event::Text(stylesheet) if in_style => {
+                    hidden_classes.extend(style::class_visibility(stylesheet))
+                }
+                Event::Declaration(declaration) if in_style => {
+                    let stylesheet = declaration
+                        .trim_start_matches("<![CDATA[")
+                        .trim_end_matches("]]>");
+                    hidden_classes.extend(style::class_visibility(stylesheet))
+                }
+                _ => {}
+            }
+        }
+        Ok(hidden_classes)
+    }
+
+    /// Returns the reason for skipping an element, if it should be skipped
+    fn skip_reason(&self, name: &str, attributes: &Attributes) -> Option<ParseWarning> {
+        if self.options.skip_namespaced && name.contains(':') {
+            Some(ParseWarning::SkippedNamespacedElement {
+                name: name.to_owned(),
+            })
+        } else if !self.options.include_hidden && self.is_undisplayed(attributes) {
+            Some(ParseWarning::SkippedHiddenElement {
+                name: name.to_owned(),
+                id: attributes.get("id").map(|id| id.to_string()),
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Determines if an element has `display: none`, which hides it along with all of its children
+    fn is_undisplayed(&self, attributes: &Attributes) -> bool {
+        let hidden_by_attribute = attributes
+            .get("display")
+            .is_some_and(|value| style::hides_element("display", value.trim()));
+        let hidden_by_style = attributes
+            .get("style")
+            .is_some_and(|style| style::style_undisplays_element(style));
+        let hidden_by_class = attributes.get("class").is_some_and(|classes| {
+            classes
+                .split_whitespace()
+                .any(|class| self.hidden_classes.undisplayed.contains(class))
+        });
+        hidden_by_attribute || hidden_by_style || hidden_by_class
+    }
+
+    /// Determines if an element is shown by its `visibility`, set through its `style` attribute, a
+    /// class, or an attribute, in that order, or inherited from its parent. An element with
+    /// classes that both show and hide it is hidden.
+    fn is_visible(&self, attributes: &Attributes) -> bool {
+        if self.options.include_hidden {
+            return true;
+        }
+        let from_style = attributes
+            .get("style")
+            .and_then(|style| style::style_visibility(style));
+        let from_class = attributes.get("class").and_then(|classes| {
+            classes
+                .split_whitespace()
+                .filter_map(|class| self.hidden_classes.visible.get(class).copied())
+                .reduce(|a, b| a && b)
+        });
+        let from_attribute = attributes
+            .get("visibility")
+            .and_then(|value| style::visibility(value.trim()));
+        from_style
+            .or(from_class)
+            .or(from_attribute)
+            .unwrap_or(self.visible)
+    }
+}
+
+/// Spatial index over every element of an `SvgElement` tree, built by [`SvgElement::build_index`].
+/// Elements are identified by their position in a pre-order traversal of the tree.
 #[derive(Debug)]
+pub struct SvgElementIndex {
+    tree: QuadTree<usize>,
+    parents: Vec<Option<usize>>,
+    /// Number of elements in the subtree rooted at each element, including the element itself
+    subtree_sizes: Vec<usize>,
+}
+
+#[derive(Debug, Clone)]
 pub struct SvgElement<'a> {
     bounding_box: BoundingBox,
     children: Vec<SvgElement<'a>>,
-    tag_name: &'a str,
-    attributes: Attributes,
+    tag_name: Cow<'a, str>,
+    /// Shared between an element and the copies made of it by selection, and only cloned when
+    /// one of them modifies its attributes
+    attributes: Arc<Attributes>,
 }
 
 impl<'a> SvgElement<'a> {
@@ -29,22 +664,129 @@ impl<'a> SvgElement<'a> {
         Self {
             bounding_box,
             children: vec![],
-            tag_name: "svg",
-            attributes: HashMap::with_capacity(0),
+            tag_name: Cow::Borrowed("svg"),
+            attributes: Arc::new(HashMap::with_capacity(0)),
+        }
+    }
+
+    pub fn from_svg_data(svg_data: &'a str) -> Result<Self, SvgParseError> {
+        Self::from_svg_data_with_options(svg_data, &ParseOptions::default())
+    }
+
+    pub fn from_svg_data_with_options(
+        svg_data: &'a str,
+        options: &ParseOptions,
+    ) -> Result<Self, SvgParseError> {
+        Self::from_svg_data_with_report(svg_data, options).map(|(element, _report)| element)
+    }
+
+    /// Same as [`Self::from_svg_data_with_options`], but also reports what was ignored or guessed
+    /// at while parsing.
+    pub fn from_svg_data_with_report(
+        svg_data: &'a str,
+        options: &ParseOptions,
+    ) -> Result<(Self, ParseReport), SvgParseError> {
+        let mut context = ParseContext::new(svg_data, options)?;
+        let mut element = Self::parse_root(svg_data, None, &mut context)?;
+        element.inline_uses(&mut context)?;
+        let report = ParseReport {
+            warnings: context.warnings,
+        };
+        Ok((element, report))
+    }
+
+    /// Parses the elements of `svg_data` that [`Self::select_with_filter`] would select from the
+    /// element parsed by [`Self::from_svg_data_with_report`], without keeping the rest, so that
+    /// the memory used grows with the size of the selection rather than the whole document. This
+    /// is slower when selecting many parts of the same document, since it is parsed again each
+    /// time. Returns `None` if the root element doesn't overlap `bounding_box`.
+    ///
+    /// `keep` is called after the children of an element are selected, so an element whose
+    /// children were all left out has no children. `defs` elements are always kept whole. `use`
+    /// elements are inlined like they are by [`Self::from_svg_data_with_report`], so documents
+    /// with them keep the `use` elements and the elements they reference while parsing, wherever
+    /// they are, and are selected again once the `use` elements are inlined.
+    pub fn select_from_svg_data(
+        svg_data: &'a str,
+        options: &ParseOptions,
+        bounding_box: &BoundingBox,
+        keep: &dyn Fn(&SvgElement) -> bool,
+    ) -> Result<(Option<Self>, ParseReport), SvgParseError> {
+        let mut context = ParseContext::new(svg_data, options)?;
+        let references = Self::use_references(svg_data)?;
+        let selection = Selection {
+            bounding_box,
+            keep,
+            references: &references,
+        };
+        let mut element = Self::parse_root(svg_data, Some(&selection), &mut context)?;
+        let element = if references.is_empty() {
+            element
+                .bounding_box
+                .intersects(bounding_box)
+                .then_some(element)
+        } else {
+            element.inline_uses(&mut context)?;
+            element.select_with_filter(bounding_box, keep)
+        };
+        let report = ParseReport {
+            warnings: context.warnings,
+        };
+        Ok((element, report))
+    }
+
+    /// Collects the IDs of the elements referenced by `use` elements in the document
+    fn use_references(svg_data: &str) -> Result<HashSet<String>, SvgParseError> {
+        let mut references = HashSet::new();
+        for event in svg::read(svg_data)? {
+            if let Event::Tag("use", Type::Start | Type::Empty, attributes) = event? {
+                let reference = USE_REFERENCE_ATTRIBUTES
+                    .iter()
+                    .find_map(|name| attributes.get(*name))
+                    .and_then(|reference| reference.strip_prefix('#'));
+                references.extend(reference.map(str::to_owned));
+            }
         }
+        Ok(references)
     }
 
-    pub fn from_svg_data(svg_data: &'a str) -> anyhow::Result<Self> {
+    fn parse_root(
+        svg_data: &'a str,
+        selection: Option<&Selection>,
+        context: &mut ParseContext,
+    ) -> Result<Self, SvgParseError> {
+        span!("parse_svg", bytes = svg_data.len());
         let mut parser = svg::read(svg_data)?.peekable();
         let initial_transformation_matrix =
             Matrix3::new(1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0);
 
-        // Allow skipping over `<?xml version="1.0" encoding="UTF-8" standalone="no"?>`, which is
-        // ignored
-        match Self::parse_event(&initial_transformation_matrix, &mut parser)? {
-            Some(element) => Ok(element),
-            None => Self::parse_event(&initial_transformation_matrix, &mut parser)?
-                .ok_or_else(|| anyhow!("Expected SVG data but did not find any")),
+        // Allow skipping over `<?xml version="1.0" encoding="UTF-8" standalone="no"?>`, comments,
+        // and anything else before the root element, which are ignored
+        while parser.peek().is_some() {
+            if let Some(element) = Self::parse_event(
+                &initial_transformation_matrix,
+                &mut parser,
+                selection,
+                context,
+            )? {
+                return Ok(element);
+            }
+        }
+        Err(SvgParseError::NoSvgData)
+    }
+
+    /// Converts this element into one that no longer borrows from the SVG data it was parsed from,
+    /// so that it can outlive that data.
+    pub fn into_owned(self) -> SvgElement<'static> {
+        SvgElement {
+            bounding_box: self.bounding_box,
+            children: self
+                .children
+                .into_iter()
+                .map(SvgElement::into_owned)
+                .collect(),
+            tag_name: Cow::Owned(self.tag_name.into_owned()),
+            attributes: self.attributes,
         }
     }
 
@@ -56,53 +798,288 @@ impl<'a> SvgElement<'a> {
         self.bounding_box.clone()
     }
 
+    pub fn has_children(&self) -> bool {
+        !self.children.is_empty()
+    }
+
+    /// Returns the child elements, in document order. Text, comments, and elements left out while
+    /// parsing, such as hidden ones, aren't included. This is part of the stable API, for tools
+    /// that walk the tree, along with the other accessors.
+    pub fn get_children(&self) -> &[SvgElement<'a>] {
+        &self.children
+    }
+
+    /// Returns the tag name, including its namespace prefix if it has one, such as `rect` or
+    /// `sodipodi:namedview`
+    pub fn get_tag_name(&self) -> &str {
+        &self.tag_name
+    }
+
+    pub fn get_attr(&self, name: &str) -> Option<&Value> {
+        self.attributes.get(name)
+    }
+
+    /// Returns every attribute by name
+    pub fn get_attributes(&self) -> &Attributes {
+        &self.attributes
+    }
+
+    pub fn get_id(&self) -> Option<&str> {
+        self.get_attr("id").map(|id| &**id)
+    }
+
+    /// Iterates over this element and its descendants in document order, with each element
+    /// before its children
+    pub fn iter_descendants(&self) -> impl Iterator<Item = &SvgElement<'a>> {
+        let mut stack = vec![self];
+        std::iter::from_fn(move || {
+            let element = stack.pop()?;
+            stack.extend(element.children.iter().rev());
+            Some(element)
+        })
+    }
+
+    /// Finds every element matching `predicate` among this element and its descendants, in
+    /// document order, such as every `image` element:
+    ///
+    /// ```
+    /// # use indoor_map_lib::svg_parser::SvgElement;
+    /// let svg_data = r#"<svg viewBox="0 0 10 10"><g><image width="5" height="5" /></g></svg>"#;
+    /// let root = SvgElement::from_svg_data(svg_data)?;
+    /// let images = root.find_all(|element| element.get_tag_name() == "image");
+    /// assert_eq!(1, images.len());
+    /// # Ok::<(), indoor_map_lib::svg_parser::SvgParseError>(())
+    /// ```
+    pub fn find_all(&self, predicate: impl Fn(&SvgElement) -> bool) -> Vec<&SvgElement<'a>> {
+        self.iter_descendants()
+            .filter(|element| predicate(element))
+            .collect()
+    }
+
+    /// Finds the first element with the ID `id` among this element and its descendants, in
+    /// document order
+    pub fn find_by_id(&self, id: &str) -> Option<&SvgElement<'a>> {
+        self.iter_descendants()
+            .find(|element| element.get_id() == Some(id))
+    }
+
     pub fn set_attr(&mut self, name: &str, value: Value) {
-        self.attributes.insert(name.to_owned(), value);
+        Arc::make_mut(&mut self.attributes).insert(name.to_owned(), value);
     }
 
     pub fn delete_attr(&mut self, name: &str) {
-        self.attributes.remove(name);
+        if self.attributes.contains_key(name) {
+            Arc::make_mut(&mut self.attributes).remove(name);
+        }
+    }
+
+    /// Removes the attributes of this element and its descendants whose names `strip` returns
+    /// `true` for, such as those matched by [`is_editor_metadata`]. Attributes that change how an
+    /// element is drawn, like `d` and `transform`, are always kept.
+    pub fn strip_attributes(&mut self, strip: &dyn Fn(&str) -> bool) {
+        if self.attributes.keys().any(|name| should_strip(name, strip)) {
+            Arc::make_mut(&mut self.attributes).retain(|name, _| !should_strip(name, strip));
+        }
+        for child in &mut self.children {
+            child.strip_attributes(strip);
+        }
+    }
+
+    /// Multiplies the stroke widths of this element and its descendants by `factor`, whether
+    /// they're set in a `stroke-width` attribute or in a `style` attribute. Stroke widths that
+    /// aren't lengths, such as `inherit`, are left as they are.
+    pub fn scale_stroke_widths(&mut self, factor: f64) {
+        let scale = |width: &str| numbers::scale_length(width, factor).ok();
+        let stroke_width = self
+            .attributes
+            .get("stroke-width")
+            .and_then(|width| scale(width));
+        let style = self
+            .attributes
+            .get("style")
+            .filter(|style| {
+                style::declarations(style).any(|(property, _value)| property == "stroke-width")
+            })
+            .map(|style| {
+                style::replace_property(style, "stroke-width", |width| {
+                    scale(width).unwrap_or_else(|| width.to_owned())
+                })
+            });
+        if stroke_width.is_some() || style.is_some() {
+            let attributes = Arc::make_mut(&mut self.attributes);
+            if let Some(stroke_width) = stroke_width {
+                attributes.insert("stroke-width".to_owned(), stroke_width.into());
+            }
+            if let Some(style) = style {
+                attributes.insert("style".to_owned(), style.into());
+            }
+        }
+        for child in &mut self.children {
+            child.scale_stroke_widths(factor);
+        }
+    }
+
+    /// Sets `vector-effect="non-scaling-stroke"` on the shapes (`path`, `rect`, `circle`,
+    /// `ellipse`, `line`, `polyline`, and `polygon` elements) among this element and its
+    /// descendants, so that their strokes are drawn the same width however much they're scaled
+    pub fn set_non_scaling_strokes(&mut self) {
+        if SHAPE_ELEMENTS.contains(&self.tag_name.as_ref()) {
+            self.set_attr("vector-effect", "non-scaling-stroke".into());
+        }
+        for child in &mut self.children {
+            child.set_non_scaling_strokes();
+        }
     }
 
     /// Returns `Some` if this element overlaps the given bounding box. The returned element only
     /// has the children of this element which overlap the bounding box, the children only keep
     /// their children which overlap, and so on.
     pub fn select_with(&self, bounding_box: &BoundingBox) -> Option<Self> {
+        self.select_with_filter(bounding_box, &|_element| true)
+    }
+
+    /// Same as [`Self::select_with`], but descendants are also left out, along with their
+    /// children, if `keep` returns `false` for them. `keep` isn't called for this element.
+    pub fn select_with_filter(
+        &self,
+        bounding_box: &BoundingBox,
+        keep: &dyn Fn(&SvgElement) -> bool,
+    ) -> Option<Self> {
         if self.bounding_box.intersects(bounding_box) {
             let selected_children = self
                 .children
                 .iter()
-                .filter_map(|child| child.select_with(bounding_box))
+                .filter(|child| keep(child))
+                .filter_map(|child| child.select_with_filter(bounding_box, keep))
                 .collect::<Vec<_>>();
             Some(Self {
                 bounding_box: self.bounding_box.clone(),
                 children: selected_children,
-                tag_name: self.tag_name,
-                attributes: self.attributes.clone(),
+                tag_name: self.tag_name.clone(),
+                attributes: Arc::clone(&self.attributes),
             })
         } else {
             None
         }
     }
 
-    fn num_from_attr(attributes: &Attributes, key: &str) -> Result<Option<f64>, ParseFloatError> {
-        attributes
-            .get(key)
-            .map(|value| value.trim_end_matches("mm").parse())
-            .transpose()
+    /// Builds a spatial index over this element and all of its descendants, so that repeated
+    /// selections with [`Self::select_with_index`] only visit elements near the selected area.
+    pub fn build_index(&self) -> SvgElementIndex {
+        let mut items = Vec::new();
+        let mut parents = Vec::new();
+        let mut subtree_sizes = Vec::new();
+        self.add_to_index(None, &mut items, &mut parents, &mut subtree_sizes);
+        SvgElementIndex {
+            tree: QuadTree::new(items),
+            parents,
+            subtree_sizes,
+        }
+    }
+
+    fn add_to_index(
+        &self,
+        parent: Option<usize>,
+        items: &mut Vec<(BoundingBox, usize)>,
+        parents: &mut Vec<Option<usize>>,
+        subtree_sizes: &mut Vec<usize>,
+    ) {
+        let id = parents.len();
+        items.push((self.bounding_box.clone(), id));
+        parents.push(parent);
+        subtree_sizes.push(1);
+        for child in &self.children {
+            child.add_to_index(Some(id), items, parents, subtree_sizes);
+        }
+        subtree_sizes[id] = parents.len() - id;
+    }
+
+    /// Same as [`Self::select_with`], but uses `index` to find the overlapping elements. `index`
+    /// must have been built from this element with [`Self::build_index`].
+    pub fn select_with_index(
+        &self,
+        index: &SvgElementIndex,
+        bounding_box: &BoundingBox,
+    ) -> Option<Self> {
+        self.select_with_index_and_filter(index, bounding_box, &|_element| true)
+    }
+
+    /// Same as [`Self::select_with_filter`], but uses `index` to find the overlapping elements
+    pub fn select_with_index_and_filter(
+        &self,
+        index: &SvgElementIndex,
+        bounding_box: &BoundingBox,
+        keep: &dyn Fn(&SvgElement) -> bool,
+    ) -> Option<Self> {
+        let mut overlapping = index.tree.query(bounding_box);
+        // Pre-order IDs put every parent before its children, so parents are decided first
+        overlapping.sort_unstable();
+
+        // An element is selected if it overlaps and all of its ancestors were selected
+        let mut selected = vec![false; index.parents.len()];
+        for &id in overlapping {
+            selected[id] = match index.parents[id] {
+                Some(parent) => selected[parent],
+                None => true,
+            };
+        }
+
+        if selected.first().copied().unwrap_or(false) {
+            Some(self.select_indexed(0, index, &selected, keep))
+        } else {
+            None
+        }
     }
 
-    fn parse_matrix_transform(matrix: &str) -> anyhow::Result<Matrix3<f64>> {
-        let data_str = matrix.trim_start_matches("matrix(").trim_end_matches(')');
-        let data = data_str
-            .split_whitespace()
-            .map(|num| num.parse())
-            .collect::<Result<Vec<f64>, _>>()?;
+    fn select_indexed(
+        &self,
+        id: usize,
+        index: &SvgElementIndex,
+        selected: &[bool],
+        keep: &dyn Fn(&SvgElement) -> bool,
+    ) -> Self {
+        let mut selected_children = Vec::new();
+        let mut child_id = id + 1;
+        for child in &self.children {
+            if selected[child_id] && keep(child) {
+                selected_children.push(child.select_indexed(child_id, index, selected, keep));
+            }
+            child_id += index.subtree_sizes[child_id];
+        }
+        Self {
+            bounding_box: self.bounding_box.clone(),
+            children: selected_children,
+            tag_name: self.tag_name.clone(),
+            attributes: Arc::clone(&self.attributes),
+        }
+    }
+
+    /// Parses the arguments of a single transform function such as `translate(10, 20)`
+    fn transform_arguments(function: &str, name: &'static str) -> Result<Vec<f64>, TransformError> {
+        let arguments = function
+            .trim()
+            .strip_prefix(name)
+            .map(str::trim_start)
+            .and_then(|rest| rest.strip_prefix('('))
+            .and_then(|rest| rest.strip_suffix(')'))
+            .ok_or_else(|| TransformError::Malformed {
+                function: name,
+                transform: function.to_owned(),
+            })?;
+        Ok(numbers::parse_number_list(arguments)?)
+    }
+
+    fn argument_count(function: &'static str, transform: &str) -> TransformError {
+        TransformError::ArgumentCount {
+            function,
+            transform: transform.to_owned(),
+        }
+    }
+
+    fn parse_matrix_transform(matrix: &str) -> Result<Matrix3<f64>, TransformError> {
+        let data = Self::transform_arguments(matrix, "matrix")?;
         if data.len() != 6 {
-            Err(anyhow!(
-                "Wrong number of arguments to matrix transform: {:?}",
-                matrix
-            ))
+            Err(Self::argument_count("matrix", matrix))
         } else {
             Ok(Matrix3::new(
                 data[0], data[2], data[4], data[1], data[3], data[5], 0.0, 0.0, 1.0,
@@ -110,19 +1087,10 @@ impl<'a> SvgElement<'a> {
         }
     }
 
-    fn parse_translate_transform(translate: &str) -> anyhow::Result<Matrix3<f64>> {
-        let data_str = translate
-            .trim_start_matches("translate(")
-            .trim_end_matches(')');
-        let data = data_str
-            .split(&[' ', ','][..])
-            .map(|num| num.parse())
-            .collect::<Result<Vec<f64>, _>>()?;
+    fn parse_translate_transform(translate: &str) -> Result<Matrix3<f64>, TransformError> {
+        let data = Self::transform_arguments(translate, "translate")?;
         if data.is_empty() || data.len() > 2 {
-            Err(anyhow!(
-                "Wrong number of arguments to translate transform: {:?}",
-                translate
-            ))
+            Err(Self::argument_count("translate", translate))
         } else {
             let x = data[0];
             let y = data.get(1).copied().unwrap_or(0.0);
@@ -130,12 +1098,8 @@ impl<'a> SvgElement<'a> {
         }
     }
 
-    fn parse_rotate_transform(rotate: &str) -> anyhow::Result<Matrix3<f64>> {
-        let data_str = rotate.trim_start_matches("rotate(").trim_end_matches(')');
-        let data = data_str
-            .split(&[' ', ','][..])
-            .map(|num| num.parse())
-            .collect::<Result<Vec<f64>, _>>()?;
+    fn parse_rotate_transform(rotate: &str) -> Result<Matrix3<f64>, TransformError> {
+        let data = Self::transform_arguments(rotate, "rotate")?;
 
         match data.len() {
             1 => Ok(transform::rotate_deg(data[0])),
@@ -143,162 +1107,596 @@ impl<'a> SvgElement<'a> {
                 data[0],
                 Vector2::new(data[1], data[2]),
             )),
-            0 => Err(anyhow!(
-                "Not enough arguments to rotate transform: {:?}",
-                rotate
-            )),
-            2 => Err(anyhow!("Expected a y-coordinate to rotate about")),
-            _ => Err(anyhow!(
-                "Too many arguments to rotate transform: {:?}",
-                rotate
-            )),
+            // Rotating about a point needs both of its coordinates
+            _ => Err(Self::argument_count("rotate", rotate)),
         }
     }
 
-    fn parse_scale_transform(scale: &str) -> anyhow::Result<Matrix3<f64>> {
-        let data_str = scale.trim_start_matches("scale(").trim_end_matches(')');
-        let data = data_str
-            .split(&[' ', ','][..])
-            .map(|num| num.parse())
-            .collect::<Result<Vec<f64>, _>>()?;
+    fn parse_scale_transform(scale: &str) -> Result<Matrix3<f64>, TransformError> {
+        let data = Self::transform_arguments(scale, "scale")?;
 
         match data.len() {
             1 => Ok(transform::scale(Vector2::new(data[0], data[0]))),
             2 => Ok(transform::scale(Vector2::new(data[0], data[1]))),
-            0 => Err(anyhow!(
-                "Not enough arguments to scale transform: {:?}",
-                scale
-            )),
-            _ => Err(anyhow!(
-                "Too many arguments to scale transform: {:?}",
-                scale
-            )),
+            _ => Err(Self::argument_count("scale", scale)),
+        }
+    }
+
+    /// Parses a transform list such as `translate(10 20) rotate(45)`, composing the transforms
+    /// from left to right. Unsupported transform functions are skipped with a warning.
+    fn parse_transform(
+        transformation: &str,
+        warnings: &mut Vec<ParseWarning>,
+    ) -> Result<Matrix3<f64>, TransformError> {
+        let is_separator = |c: char| c.is_whitespace() || c == ',';
+        let mut matrix = Matrix3::identity();
+        let mut rest = transformation.trim_start_matches(is_separator);
+        while !rest.is_empty() {
+            let end = rest.find(')').ok_or_else(|| TransformError::Unterminated {
+                transform: transformation.to_owned(),
+            })? + 1;
+            match Self::parse_transform_function(&rest[..end])? {
+                Some(function_matrix) => matrix *= function_matrix,
+                None => warnings.push(ParseWarning::UnknownTransform {
+                    transform: rest[..end].trim().to_owned(),
+                }),
+            }
+            rest = rest[end..].trim_start_matches(is_separator);
         }
+        Ok(matrix)
     }
 
-    fn parse_transform(transformation: &str) -> anyhow::Result<Matrix3<f64>> {
-        match transformation {
+    /// Returns `None` for transform functions that aren't supported
+    fn parse_transform_function(
+        transformation: &str,
+    ) -> Result<Option<Matrix3<f64>>, TransformError> {
+        match transformation.trim_start() {
             matrix if matrix.starts_with("matrix") => Self::parse_matrix_transform(matrix),
             translate if translate.starts_with("translate") => {
                 Self::parse_translate_transform(translate)
             }
             rotate if rotate.starts_with("rotate") => Self::parse_rotate_transform(rotate),
             scale if scale.starts_with("scale") => Self::parse_scale_transform(scale),
-            other => panic!("Unimplemented transformation {}", other),
+            _other => return Ok(None),
         }
+        .map(Some)
     }
 
-    fn parse_children<'b>(
+    /// Consumes events up to and including the end tag matching the start tag `name`, which must
+    /// already have been consumed.
+    fn skip_element<'b>(
         parser: &'b mut Peekable<Parser<'a>>,
-        current_transformation_matrix: &Matrix3<f64>,
-    ) -> anyhow::Result<Vec<Self>> {
-        let mut children = Vec::new();
-        while let Some(Ok(event)) = parser.peek() {
-            if let Event::Tag(_name, Type::End, _attributes) = event {
-                break;
-            }
-            if let Some(element) = Self::parse_event(current_transformation_matrix, parser)? {
-                children.push(element);
+        name: &'a str,
+        context: &ParseContext,
+    ) -> Result<(), SvgParseError> {
+        let mut open_tags = vec![name];
+        while let Some(&open_tag) = open_tags.last() {
+            match parser.next() {
+                None => {
+                    let location = context.locate(&context.element(open_tag, None));
+                    return Err(Self::missing_end_tag(open_tag, location));
+                }
+                Some(event) => match event? {
+                    Event::Tag(name, Type::Start, _attributes) => open_tags.push(name),
+                    Event::Tag(name, Type::End, _attributes) => {
+                        if name != open_tag {
+                            let location = context.locate(&context.element(name, None));
+                            return Err(Self::mismatched_end_tag(open_tag, name, location));
+                        }
+                        open_tags.pop();
+                    }
+                    _ => {}
+                },
             }
         }
-        // Consume ending tag
-        parser.next().unwrap()?;
-        Ok(children)
+        Ok(())
     }
 
+    fn missing_end_tag(name: &str, location: Location) -> SvgParseError {
+        SvgParseError::MissingEndTag {
+            name: name.to_owned(),
+            location,
+        }
+    }
+
+    fn mismatched_end_tag(expected: &str, found: &str, location: Location) -> SvgParseError {
+        SvgParseError::MismatchedEndTag {
+            expected: expected.to_owned(),
+            found: found.to_owned(),
+            location,
+        }
+    }
+
+    /// Parses the element with the start tag `name` along with its descendants. Descendants are
+    /// parsed in a loop rather than recursively, so that an SVG with deeply nested elements fails
+    /// with [`SvgParseError::TooDeep`] instead of overflowing the stack.
     fn parse_tag<'b>(
         current_transformation_matrix: &Matrix3<f64>,
         name: &'a str,
         children_type: Type,
         attributes: Attributes,
         parser: &'b mut Peekable<Parser<'a>>,
-    ) -> anyhow::Result<Self> {
-        let (size, local_top_left_homogenous) = match name {
+        selection: Option<&Selection>,
+        context: &mut ParseContext,
+    ) -> Result<Option<Self>, SvgParseError> {
+        let mut open_elements = Vec::new();
+        if let Some(element) = Self::start_element(
+            current_transformation_matrix,
+            name,
+            children_type,
+            attributes,
+            selection,
+            &mut open_elements,
+            context,
+        )? {
+            return Ok(Some(element));
+        }
+
+        loop {
+            // The element was hidden
+            let Some(parent) = open_elements.last() else {
+                return Ok(None);
+            };
+            let mut finished = None;
+            match parser.peek() {
+                None => return Err(Self::missing_end_tag(parent.name, context.location())),
+                Some(Err(_)) => {
+                    if let Some(Err(err)) = parser.next() {
+                        return Err(err.into());
+                    }
+                }
+                Some(Ok(Event::Tag(name, Type::End, _attributes))) => {
+                    if *name != parent.name {
+                        let location = context.locate(&context.element(name, None));
+                        return Err(Self::mismatched_end_tag(parent.name, name, location));
+                    }
+                    // Consume ending tag
+                    parser.next();
+                    finished = open_elements
+                        .pop()
+                        .and_then(|element| element.finish(context));
+                }
+                Some(Ok(Event::Text(text))) => {
+                    if parent.name != "style" && !text.trim().is_empty() {
+                        context.warn(ParseWarning::DroppedText {
+                            parent: parent.name.to_owned(),
+                            text: text.to_string(),
+                        });
+                    }
+                    parser.next();
+                }
+                Some(Ok(_)) => {
+                    let transformation = parent.transformation;
+                    let selection = parent.selection;
+                    if let Some((name, children_type, attributes)) =
+                        Self::next_tag(parser, context)?
+                    {
+                        finished = Self::start_element(
+                            &transformation,
+                            name,
+                            children_type,
+                            attributes,
+                            selection,
+                            &mut open_elements,
+                            context,
+                        )?;
+                    }
+                }
+            }
+
+            if let Some(element) = finished {
+                match open_elements.last_mut() {
+                    Some(parent) => parent.add_child(element),
+                    None => return Ok(Some(element)),
+                }
+            }
+        }
+    }
+
+    /// Parses the start tag of an element, returning the element if it has no children, or adding
+    /// it to `open_elements` until its end tag is reached if it does
+    fn start_element<'s>(
+        parent_transformation_matrix: &Matrix3<f64>,
+        name: &'a str,
+        children_type: Type,
+        attributes: Attributes,
+        selection: Option<&'s Selection<'s>>,
+        open_elements: &mut Vec<OpenElement<'a, 's>>,
+        context: &mut ParseContext,
+    ) -> Result<Option<Self>, SvgParseError> {
+        let outer_viewport = context.viewport;
+        let element = context.element(name, Some(&attributes));
+        let outer_element = std::mem::replace(&mut context.current, element);
+        let visible = context.is_visible(&attributes);
+        let outer_visible = std::mem::replace(&mut context.visible, visible);
+        context.check_depth(open_elements.len() + 1)?;
+        context.count_elements(1)?;
+        if name == "svg" {
+            // Percentages on an svg element are resolved against its own viewBox, like those of
+            // its children
+            context.viewport = Some(context.svg_viewport(&attributes));
+        }
+
+        let (size, local_top_left_homogenous) = Self::local_geometry(name, &attributes, context)?;
+        let transformation =
+            Self::element_transformation(parent_transformation_matrix, name, &attributes, context)?
+                .into_owned();
+        let global_top_left = Self::global_top_left(&transformation, local_top_left_homogenous);
+
+        let element = OpenElement {
+            name,
+            attributes,
+            global_top_left,
+            size,
+            transformation,
+            // Definitions are kept whole, since they can be referenced from anywhere
+            selection: selection.filter(|_selection| name != "defs"),
+            children: Vec::new(),
+            children_bottom_right: None,
+            visible,
+            outer_viewport,
+            outer_element,
+            outer_visible,
+        };
+        match children_type {
+            Type::End => Err(SvgParseError::UnexpectedEndTag {
+                name: name.to_owned(),
+                location: context.location(),
+            }),
+            Type::Empty => Ok(element.finish(context)),
+            Type::Start => {
+                open_elements.push(element);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Finds the size and homogenous top left corner of an element in its own coordinate system,
+    /// ignoring its children.
+    fn local_geometry(
+        name: &str,
+        attributes: &Attributes,
+        context: &mut ParseContext,
+    ) -> Result<(Vector2<f64>, Vector3<f64>), SvgParseError> {
+        match name {
             "path" => {
-                let d = attributes.get("d").context("Missing path data")?;
-                let data = Data::parse(d)?;
+                let d = attributes
+                    .get("d")
+                    .ok_or_else(|| SvgParseError::MissingAttribute {
+                        tag: name.to_owned(),
+                        attribute: "d",
+                        location: context.location(),
+                    })?;
+                let data = PathData::parse(d).map_err(|source| SvgParseError::InvalidPathData {
+                    tag: name.to_owned(),
+                    location: context.location(),
+                    source,
+                })?;
                 let bounds = BoundingBox::from(&data);
 
                 let top_left = bounds.get_top_left();
                 let homogenous_top_left = Vector3::new(top_left[0], top_left[1], 1.);
 
-                (bounds.get_size(), homogenous_top_left)
+                Ok((bounds.get_size(), homogenous_top_left))
             }
-            "rect" | _ => {
-                let min_width: f64 = Self::num_from_attr(&attributes, "width")?.unwrap_or(0.0);
-                let min_height: f64 = Self::num_from_attr(&attributes, "height")?.unwrap_or(0.0);
+            _ => {
+                let min_width = context.length(attributes, "width", 0).unwrap_or(0.0);
+                let min_height = context.length(attributes, "height", 1).unwrap_or(0.0);
                 let size = Vector2::new(min_width, min_height);
 
-                let x: f64 = Self::num_from_attr(&attributes, "x")?.unwrap_or(0.0);
-                let y: f64 = Self::num_from_attr(&attributes, "y")?.unwrap_or(0.0);
+                let x = context.length(attributes, "x", 0).unwrap_or(0.0);
+                let y = context.length(attributes, "y", 1).unwrap_or(0.0);
                 let top_left = Vector3::new(x, y, 1.);
 
-                (size, top_left)
+                Ok((size, top_left))
             }
-        };
+        }
+    }
 
-        let current_transformation_matrix = match attributes.get("transform") {
+    /// Applies an element's `transform` attribute, if any, to the transformation of its parent.
+    fn element_transformation<'m>(
+        current_transformation_matrix: &'m Matrix3<f64>,
+        name: &str,
+        attributes: &Attributes,
+        context: &mut ParseContext,
+    ) -> Result<Cow<'m, Matrix3<f64>>, SvgParseError> {
+        Ok(match attributes.get("transform") {
             Some(transformation) => {
-                Cow::Owned(current_transformation_matrix * Self::parse_transform(transformation)?)
+                let mut warnings = vec![];
+                let matrix =
+                    Self::parse_transform(transformation, &mut warnings).map_err(|source| {
+                        SvgParseError::InvalidTransform {
+                            tag: name.to_owned(),
+                            location: context.location(),
+                            source,
+                        }
+                    })?;
+                for warning in warnings {
+                    context.warn(warning);
+                }
+                Cow::Owned(current_transformation_matrix * matrix)
             }
             None => Cow::Borrowed(current_transformation_matrix),
+        })
+    }
+
+    fn global_top_left(
+        current_transformation_matrix: &Matrix3<f64>,
+        local_top_left_homogenous: Vector3<f64>,
+    ) -> Vector2<f64> {
+        let global_top_left_homogenous = current_transformation_matrix * local_top_left_homogenous;
+        Vector2::new(global_top_left_homogenous[0], global_top_left_homogenous[1])
+    }
+
+    /// Extends the bounding box with the given top left corner and size to the right and bottom so
+    /// that it also contains all of `children`.
+    fn fit_children(
+        global_top_left: Vector2<f64>,
+        size: Vector2<f64>,
+        children: &[Self],
+    ) -> BoundingBox {
+        let children_bottom_right = children
+            .iter()
+            .map(|child| child.get_bottom_right())
+            .reduce(|a, b| a.sup(&b));
+        Self::fit_bottom_right(global_top_left, size, children_bottom_right)
+    }
+
+    /// Same as [`Self::fit_children`], but with the bottom right corner of all of the children,
+    /// or `None` if there are none
+    fn fit_bottom_right(
+        global_top_left: Vector2<f64>,
+        size: Vector2<f64>,
+        children_bottom_right: Option<Vector2<f64>>,
+    ) -> BoundingBox {
+        let bottom_right = global_top_left + size;
+        let actual_bottom_right = match children_bottom_right {
+            Some(children_bottom_right) => children_bottom_right.sup(&bottom_right),
+            None => bottom_right,
         };
+        let actual_size = actual_bottom_right - global_top_left;
 
-        let global_top_left_homogenous: Vector3<f64> =
-            <Cow<Matrix3<f64>> as Borrow<Matrix3<f64>>>::borrow(&current_transformation_matrix)
-                * local_top_left_homogenous;
+        BoundingBox::new(global_top_left, actual_size)
+    }
+
+    /// Recomputes the bounding boxes of this element and its descendants as if its parent had the
+    /// transformation `current_transformation_matrix`.
+    fn relayout(
+        &self,
+        current_transformation_matrix: &Matrix3<f64>,
+        context: &mut ParseContext,
+    ) -> Result<Self, SvgParseError> {
+        let element = context.element(&self.tag_name, Some(&self.attributes));
+        let outer_element = std::mem::replace(&mut context.current, element);
+        let element = self.relayout_current(current_transformation_matrix, context);
+        context.current = outer_element;
+        element
+    }
+
+    fn relayout_current(
+        &self,
+        current_transformation_matrix: &Matrix3<f64>,
+        context: &mut ParseContext,
+    ) -> Result<Self, SvgParseError> {
+        let (size, local_top_left_homogenous) =
+            Self::local_geometry(&self.tag_name, &self.attributes, context)?;
+        let current_transformation_matrix = Self::element_transformation(
+            current_transformation_matrix,
+            &self.tag_name,
+            &self.attributes,
+            context,
+        )?;
         let global_top_left =
-            Vector2::new(global_top_left_homogenous[0], global_top_left_homogenous[1]);
+            Self::global_top_left(&current_transformation_matrix, local_top_left_homogenous);
 
-        match children_type {
-            Type::End => Err(anyhow!(
-                "Unexpected end tag: {}, {:?}, {:?}",
-                name,
-                children_type,
-                attributes
-            )),
-            Type::Empty => Ok(Self {
-                bounding_box: BoundingBox::new(global_top_left, size),
-                children: vec![],
-                tag_name: name,
-                attributes,
-            }),
-            Type::Start => {
-                let bottom_right = global_top_left + size;
-                let right = bottom_right[0];
-                let bottom = bottom_right[1];
+        let children = self
+            .children
+            .iter()
+            .map(|child| child.relayout(&current_transformation_matrix, context))
+            .collect::<Result<Vec<_>, _>>()?;
 
-                let children = Self::parse_children(parser, &current_transformation_matrix)?;
-                let (rights, bottoms): (Vec<f64>, Vec<f64>) = children
-                    .iter()
-                    .map(|child| child.get_bottom_right())
-                    .map(|bottom_right| (bottom_right[0], bottom_right[1]))
-                    .unzip();
-
-                let max_right = max_f64(rights.into_iter())
-                    .map_or(right, |child_max_right| child_max_right.max(right));
-                let max_bottom = max_f64(bottoms.into_iter())
-                    .map_or(bottom, |child_max_bottom| child_max_bottom.max(bottom));
-
-                let actual_bottom_right = Vector2::new(max_right, max_bottom);
-                let actual_size = actual_bottom_right - global_top_left;
-
-                Ok(Self {
-                    bounding_box: BoundingBox::new(global_top_left, actual_size),
-                    children,
-                    tag_name: name,
-                    attributes,
-                })
+        Ok(Self {
+            bounding_box: Self::fit_children(global_top_left, size, &children),
+            children,
+            tag_name: self.tag_name.clone(),
+            attributes: Arc::clone(&self.attributes),
+        })
+    }
+
+    /// Counts this element and its descendants
+    fn element_count(&self) -> usize {
+        1 + self
+            .children
+            .iter()
+            .map(SvgElement::element_count)
+            .sum::<usize>()
+    }
+
+    /// Returns the ID of the element referenced by this element, if it is a `use` element.
+    fn use_reference(&self) -> Option<&str> {
+        if self.tag_name != "use" {
+            return None;
+        }
+        USE_REFERENCE_ATTRIBUTES
+            .iter()
+            .find_map(|name| self.attributes.get(*name))
+            .and_then(|reference| reference.strip_prefix('#'))
+    }
+
+    fn collect_use_references(&self, references: &mut HashSet<String>) {
+        if let Some(reference) = self.use_reference() {
+            references.insert(reference.to_owned());
+        }
+        for child in &self.children {
+            child.collect_use_references(references);
+        }
+    }
+
+    fn collect_definitions(
+        &self,
+        references: &HashSet<String>,
+        definitions: &mut HashMap<String, Self>,
+    ) {
+        if let Some(id) = self.attributes.get("id") {
+            if references.contains(&**id) {
+                definitions.insert(id.to_string(), self.clone());
             }
         }
+        for child in &self.children {
+            child.collect_definitions(references, definitions);
+        }
+    }
+
+    /// Replaces every `use` element with a group containing a copy of the element it references,
+    /// so that tiles don't depend on `<defs>` they might not include, and so that `use` elements
+    /// get the bounding box of what they draw. References to missing elements are left alone.
+    fn inline_uses(&mut self, context: &mut ParseContext) -> Result<(), SvgParseError> {
+        let mut references = HashSet::new();
+        self.collect_use_references(&mut references);
+        if references.is_empty() {
+            return Ok(());
+        }
+
+        let mut definitions = HashMap::new();
+        self.collect_definitions(&references, &mut definitions);
+        self.inline_uses_with(&Matrix3::identity(), &definitions, 0, 1, context)
+    }
+
+    /// Inlines the `use` elements in this element, which is nested `use_depth` inlined `use`
+    /// elements deep and at a depth of `depth` in the document
+    fn inline_uses_with(
+        &mut self,
+        parent_transformation_matrix: &Matrix3<f64>,
+        definitions: &HashMap<String, Self>,
+        use_depth: usize,
+        depth: usize,
+        context: &mut ParseContext,
+    ) -> Result<(), SvgParseError> {
+        if depth > context.options.max_depth {
+            context.current = context.element(&self.tag_name, Some(&self.attributes));
+            context.check_depth(depth)?;
+        }
+
+        // Problems with the transform were already reported when this element was parsed
+        let reported = context.warnings.len();
+        let current_transformation_matrix = Self::element_transformation(
+            parent_transformation_matrix,
+            &self.tag_name,
+            &self.attributes,
+            context,
+        )?
+        .into_owned();
+        context.warnings.truncate(reported);
+
+        let reference = self.use_reference();
+        let definition = reference.and_then(|reference| definitions.get(reference));
+        if let (Some(reference), None) = (reference, definition) {
+            let element = context.element(&self.tag_name, Some(&self.attributes));
+            context.warn_at(
+                &element,
+                ParseWarning::MissingUseReference {
+                    reference: reference.to_owned(),
+                },
+            );
+        }
+        if let Some(definition) = definition {
+            if use_depth >= MAX_USE_DEPTH {
+                let element = context.element(&self.tag_name, Some(&self.attributes));
+                return Err(SvgParseError::UseCycle {
+                    reference: self.use_reference().unwrap_or_default().to_owned(),
+                    location: context.locate(&element),
+                });
+            }
+
+            // Like the transform, the position and the referenced element were already checked
+            // for problems when they were parsed
+            let reported = context.warnings.len();
+            let x = context.length(&self.attributes, "x", 0).unwrap_or(0.0);
+            let y = context.length(&self.attributes, "y", 1).unwrap_or(0.0);
+            let current_transformation_matrix =
+                current_transformation_matrix * transform::translate(Vector2::new(x, y));
+
+            context.current = context.element(&self.tag_name, Some(&self.attributes));
+            context.count_elements(definition.element_count())?;
+            let mut inlined = definition.relayout(&current_transformation_matrix, context)?;
+            context.warnings.truncate(reported);
+            if inlined.tag_name == "symbol" {
+                inlined.tag_name = Cow::Borrowed("g");
+            }
+            // The copy would otherwise duplicate the referenced element's ID
+            inlined.delete_attr("id");
+            inlined.inline_uses_with(
+                &current_transformation_matrix,
+                definitions,
+                use_depth + 1,
+                depth + 1,
+                context,
+            )?;
+
+            let translation = format!("translate({} {})", x, y);
+            let attributes = Arc::make_mut(&mut self.attributes);
+            for name in USE_REFERENCE_ATTRIBUTES
+                .iter()
+                .chain(&["x", "y", "width", "height"])
+            {
+                attributes.remove(*name);
+            }
+            let transformation = match attributes.get("transform") {
+                Some(transformation) => format!("{} {}", &**transformation, translation),
+                None => translation,
+            };
+            attributes.insert("transform".to_owned(), transformation.into());
+            self.tag_name = Cow::Borrowed("g");
+            self.children = vec![inlined];
+        } else {
+            for child in &mut self.children {
+                child.inline_uses_with(
+                    &current_transformation_matrix,
+                    definitions,
+                    use_depth,
+                    depth + 1,
+                    context,
+                )?;
+            }
+        }
+
+        self.bounding_box = Self::fit_children(
+            self.bounding_box.get_top_left(),
+            self.bounding_box.get_size(),
+            &self.children,
+        );
+        Ok(())
     }
 
     fn parse_event<'b>(
         current_transformation_matrix: &Matrix3<f64>,
         parser: &'b mut Peekable<Parser<'a>>,
-    ) -> anyhow::Result<Option<Self>> {
+        selection: Option<&Selection>,
+        context: &mut ParseContext,
+    ) -> Result<Option<Self>, SvgParseError> {
+        Self::next_tag(parser, context)?
+            .map(|(name, children_type, attributes)| {
+                Self::parse_tag(
+                    current_transformation_matrix,
+                    name,
+                    children_type,
+                    attributes,
+                    parser,
+                    selection,
+                    context,
+                )
+            })
+            .transpose()
+            .map(Option::flatten)
+    }
+
+    /// Consumes the next event, returning it if it's a tag of an element that isn't skipped
+    fn next_tag<'b>(
+        parser: &'b mut Peekable<Parser<'a>>,
+        context: &mut ParseContext,
+    ) -> Result<Option<(&'a str, Type, Attributes)>, SvgParseError> {
         match parser.next() {
-            None => Err(anyhow!("Unexpected end of SVG")),
+            None => Err(SvgParseError::UnexpectedEnd),
             Some(Err(err)) => Err(err.into()),
             Some(Ok(event)) => match event {
                 // Nothing we need to do with these, so skip them
@@ -307,26 +1705,858 @@ impl<'a> SvgElement<'a> {
                 | Event::UnpaddedComment(_)
                 | Event::Declaration(_)
                 | Event::Instruction(_) => Ok(None),
-                Event::Tag(name, children_type, attributes) => Some(Self::parse_tag(
-                    current_transformation_matrix,
-                    name,
-                    children_type,
-                    attributes,
-                    parser,
-                ))
-                .transpose(),
+                Event::Tag(name, children_type, attributes) => {
+                    if let Some(reason) = context.skip_reason(name, &attributes) {
+                        let element = context.element(name, Some(&attributes));
+                        context.warn_at(&element, reason);
+                        if let Type::Start = children_type {
+                            Self::skip_element(parser, name, context)?;
+                        }
+                        return Ok(None);
+                    }
+                    Ok(Some((name, children_type, attributes)))
+                }
             },
         }
     }
 
+    /// Converts the element and its descendants to an element of the `svg` crate, with children in
+    /// their order in the source. The `svg` crate writes attributes in order of their names, so
+    /// writing the same element always gives the same output.
     pub fn as_element(&self) -> GenericElement {
-        let mut element = GenericElement::new(self.tag_name);
-        for (name, value) in &self.attributes {
-            element.assign(name, value.clone());
+        self.as_element_with_precision(None)
+    }
+
+    /// Same as [`Self::as_element`], but if `decimals` is given, rounds the numbers in geometric
+    /// attributes like `d`, `transform`, and `viewBox` to that many digits after the decimal
+    /// point. The element itself keeps full precision.
+    pub fn as_element_with_precision(&self, decimals: Option<usize>) -> GenericElement {
+        let mut element = GenericElement::new(self.tag_name.as_ref());
+        for (name, value) in self.attributes.iter() {
+            match decimals {
+                Some(decimals) if NUMERIC_ATTRIBUTES.contains(&name.as_str()) => {
+                    element.assign(name, numbers::round_numbers(value, decimals));
+                }
+                _ => element.assign(name, value.clone()),
+            }
         }
         for child in &self.children {
-            element.append(child.as_element());
+            element.append(child.as_element_with_precision(decimals));
         }
         element
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use nalgebra::Vector2;
+
+    use super::*;
+
+    fn file(path: &str) -> String {
+        use std::fs;
+        fs::read_to_string(path).unwrap()
+    }
+
+    fn assert_same_tree(expected: &SvgElement, actual: &SvgElement) {
+        assert_eq!(expected.tag_name, actual.tag_name);
+        assert_eq!(
+            expected.bounding_box.get_top_left(),
+            actual.bounding_box.get_top_left()
+        );
+        assert_eq!(
+            expected.bounding_box.get_size(),
+            actual.bounding_box.get_size()
+        );
+        let expected_attributes: BTreeMap<_, _> = expected
+            .attributes
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.to_string()))
+            .collect();
+        let actual_attributes: BTreeMap<_, _> = actual
+            .attributes
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.to_string()))
+            .collect();
+        assert_eq!(expected_attributes, actual_attributes);
+        assert_eq!(expected.children.len(), actual.children.len());
+        for (expected_child, actual_child) in expected.children.iter().zip(&actual.children) {
+            assert_same_tree(expected_child, actual_child);
+        }
+    }
+
+    #[test]
+    fn indexed_selection_matches_select_with() {
+        let svg_data = file("tests/svg/nested_groups.svg");
+        let root = SvgElement::from_svg_data(&svg_data).unwrap();
+        let index = root.build_index();
+
+        for zoom in 0..4 {
+            let tiles = 2_u32.pow(zoom);
+            let edge_length = 400. / tiles as f64;
+            for x in 0..tiles {
+                for y in 0..tiles {
+                    let top_left = Vector2::new(x as f64, y as f64) * edge_length;
+                    let bounds = BoundingBox::new(top_left, Vector2::new(edge_length, edge_length));
+
+                    let expected = root.select_with(&bounds);
+                    let actual = root.select_with_index(&index, &bounds);
+                    match (expected, actual) {
+                        (Some(expected), Some(actual)) => assert_same_tree(&expected, &actual),
+                        (None, None) => {}
+                        (expected, actual) => panic!(
+                            "Selections differ for {:?}: {:?} vs {:?}",
+                            bounds, expected, actual
+                        ),
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn select_while_parsing() {
+        let svg_data = file("tests/svg/nested_groups.svg");
+        let options = ParseOptions::default();
+        let root = SvgElement::from_svg_data(&svg_data).unwrap();
+        let keep = |_element: &SvgElement| true;
+        let select = |bounding_box: &BoundingBox| {
+            SvgElement::select_from_svg_data(&svg_data, &options, bounding_box, &keep)
+                .unwrap()
+                .0
+        };
+
+        let size = root.get_bounding_box().get_size();
+        for (x, y) in [(0., 0.), (0.5, 0.), (0., 0.5), (0.25, 0.25)] {
+            let bounding_box = BoundingBox::new(
+                Vector2::new(x * size[0], y * size[1]),
+                Vector2::new(size[0] / 4., size[1] / 4.),
+            );
+            let expected = root.select_with(&bounding_box).unwrap();
+            assert_same_tree(&expected, &select(&bounding_box).unwrap());
+        }
+        let outside = BoundingBox::new(-size * 2., size);
+        assert!(select(&outside).is_none());
+    }
+
+    #[test]
+    fn filtered_selection() {
+        let svg_data = r#"<svg width="100" height="100">
+            <g id="group">
+                <rect id="small" x="0" y="0" width="2" height="2" />
+                <rect id="large" x="10" y="10" width="50" height="5" />
+            </g>
+            <rect id="tiny" x="90" y="90" width="1" height="1" />
+        </svg>"#;
+        let root = SvgElement::from_svg_data(svg_data).unwrap();
+        let index = root.build_index();
+        let everything = BoundingBox::new(Vector2::new(0., 0.), Vector2::new(100., 100.));
+        let keep = |element: &SvgElement| {
+            let size = element.get_bounding_box().get_size();
+            element.has_children() || size[0].max(size[1]) >= 10.
+        };
+
+        let expected = root.select_with_filter(&everything, &keep).unwrap();
+        let ids: Vec<_> = expected.children[0]
+            .children
+            .iter()
+            .map(|child| child.attributes["id"].to_string())
+            .collect();
+        assert_eq!(vec!["large"], ids);
+        assert_eq!(1, expected.children.len());
+
+        let actual = root
+            .select_with_index_and_filter(&index, &everything, &keep)
+            .unwrap();
+        assert_same_tree(&expected, &actual);
+    }
+
+    #[test]
+    fn owned_element_outlives_svg_data() {
+        let owned = {
+            let svg_data = file("tests/svg/nested_groups.svg");
+            SvgElement::from_svg_data(&svg_data).unwrap().into_owned()
+        };
+
+        let svg_data = file("tests/svg/nested_groups.svg");
+        let borrowed = SvgElement::from_svg_data(&svg_data).unwrap();
+        assert_same_tree(&borrowed, &owned);
+    }
+
+    fn tag_names(element: &SvgElement) -> Vec<String> {
+        element
+            .iter_descendants()
+            .map(|element| element.get_tag_name().to_owned())
+            .collect()
+    }
+
+    fn ids(element: &SvgElement) -> Vec<String> {
+        element
+            .iter_descendants()
+            .filter_map(|element| element.get_id())
+            .map(str::to_owned)
+            .collect()
+    }
+
+    #[test]
+    fn parse_inkscape_svg() {
+        let svg_data = file("tests/svg/inkscape.svg");
+        let root = SvgElement::from_svg_data(&svg_data).unwrap();
+        assert_eq!(
+            vec![
+                "svg",
+                "sodipodi:namedview",
+                "metadata",
+                "rdf:RDF",
+                "cc:Work",
+                "dc:format",
+                "dc:type",
+                "dc:title",
+                "g",
+                "g",
+                "g",
+                "rect",
+                "rect",
+                "g",
+            ],
+            tag_names(&root)
+        );
+
+        let layer = &root.children[2];
+        assert_eq!(2, layer.children.len());
+        assert_eq!(2, layer.children[0].children.len());
+        assert_eq!(1, layer.children[0].children[0].children.len());
+    }
+
+    #[test]
+    fn skip_namespaced_elements() {
+        let svg_data = file("tests/svg/inkscape.svg");
+        let options = ParseOptions {
+            skip_namespaced: true,
+            ..ParseOptions::default()
+        };
+        let root = SvgElement::from_svg_data_with_options(&svg_data, &options).unwrap();
+        assert_eq!(
+            vec!["svg", "metadata", "g", "g", "g", "rect", "rect", "g"],
+            tag_names(&root)
+        );
+    }
+
+    #[test]
+    fn write_with_precision() {
+        let svg_data = r##"<svg viewBox="0 0 100.123456 100">
+            <path id="room1.5" d="M 10.123456,20.987654 L 30 40.5 Z" fill="#1e5ab4" />
+            <g transform="translate(0.333333, 0)"><rect x="1.23456" y="2" width="3" height="4" /></g>
+        </svg>"##;
+        let element = SvgElement::from_svg_data(svg_data).unwrap();
+
+        let written = element.as_element_with_precision(Some(1)).to_string();
+        assert!(
+            written.contains(r#"viewBox="0 0 100.1 100""#),
+            "{}",
+            written
+        );
+        assert!(
+            written.contains(r#"d="M 10.1,21 L 30 40.5 Z""#),
+            "{}",
+            written
+        );
+        assert!(
+            written.contains(r#"transform="translate(0.3, 0)""#),
+            "{}",
+            written
+        );
+        assert!(written.contains(r#"x="1.2""#), "{}", written);
+        // Attributes that aren't geometry are unchanged
+        assert!(written.contains(r#"id="room1.5""#), "{}", written);
+        assert!(written.contains(r##"fill="#1e5ab4""##), "{}", written);
+
+        assert!(element
+            .as_element()
+            .to_string()
+            .contains(r#"d="M 10.123456,20.987654 L 30 40.5 Z""#));
+    }
+
+    #[test]
+    fn strip_editor_metadata() {
+        let svg_data = file("tests/svg/metadata.svg");
+        let mut root = SvgElement::from_svg_data(&svg_data).unwrap();
+        let original = root.as_element().to_string();
+        root.strip_attributes(&is_editor_metadata);
+        let stripped = root.as_element().to_string();
+
+        assert!(
+            stripped.len() * 2 < original.len(),
+            "{} bytes stripped down to {}",
+            original.len(),
+            stripped.len()
+        );
+        assert!(!stripped.contains("inkscape:"));
+        assert!(!stripped.contains("sodipodi:"));
+        assert!(!stripped.contains("data-"));
+        assert_eq!(
+            vec!["svg8", "layer1", "room101", "room102", "stairs"],
+            ids(&root)
+        );
+
+        // Attributes needed to draw the SVG are kept even if asked to strip them
+        root.strip_attributes(&|_name| true);
+        let layer = &root.children[0];
+        let attribute_names = |element: &SvgElement| {
+            let mut names: Vec<_> = element.attributes.keys().cloned().collect();
+            names.sort_unstable();
+            names
+        };
+        assert_eq!(vec!["viewBox"], attribute_names(&root));
+        assert_eq!(vec!["transform"], attribute_names(layer));
+        assert_eq!(vec!["d", "style"], attribute_names(&layer.children[0]));
+        assert_eq!(vec!["d", "fill"], attribute_names(&layer.children[1]));
+    }
+
+    #[test]
+    fn strip_document_node_metadata() {
+        let svg_data = file("tests/svg/metadata.svg");
+        let mut document = svg::Document::from_event_parser(svg::read(&svg_data).unwrap()).unwrap();
+        let original = document.to_string();
+        strip_node_attributes(document.get_mut_svg(), &is_editor_metadata);
+        let stripped = document.to_string();
+
+        assert!(stripped.len() * 2 < original.len());
+        assert!(!stripped.contains("inkscape:"));
+        assert!(stripped.contains(r#"d="M 10,10 H 40 V 30 H 10 Z""#));
+    }
+
+    #[test]
+    fn skip_hidden_elements() {
+        let svg_data = file("tests/svg/hidden.svg");
+        let root = SvgElement::from_svg_data(&svg_data).unwrap();
+        // Children can be shown again with `visibility`, but not `display`
+        assert_eq!(
+            vec![
+                "visible",
+                "styles",
+                "shown-class",
+                "invisible-group",
+                "shown-child"
+            ],
+            ids(&root)
+        );
+
+        let options = ParseOptions {
+            include_hidden: true,
+            ..ParseOptions::default()
+        };
+        let root = SvgElement::from_svg_data_with_options(&svg_data, &options).unwrap();
+        assert_eq!(
+            vec![
+                "visible",
+                "hidden-attribute",
+                "hidden-child",
+                "hidden-style",
+                "invisible-style",
+                "hidden-class",
+                "styles",
+                "shown-class",
+                "late-hidden-class",
+                "invisible-group",
+                "invisible-child",
+                "shown-child",
+                "invisible-class",
+                "inherits-invisible",
+            ],
+            ids(&root)
+        );
+    }
+
+    #[test]
+    fn inline_use_elements() {
+        let svg_data = file("tests/svg/use.svg");
+        let root = SvgElement::from_svg_data(&svg_data).unwrap();
+
+        let door1 = root.find_by_id("door1").unwrap();
+        assert_eq!("g", door1.tag_name);
+        assert_eq!(
+            Some("translate(300 300)"),
+            door1.attributes.get("transform").map(|value| &**value)
+        );
+        assert!(!door1.attributes.contains_key("xlink:href"));
+        assert_eq!(1, door1.children.len());
+        assert_eq!("g", door1.children[0].tag_name);
+        assert!(!door1.children[0].attributes.contains_key("id"));
+        assert_eq!(Vector2::new(300., 300.), door1.bounding_box.get_top_left());
+        assert_eq!(Vector2::new(310., 312.), door1.get_bottom_right());
+
+        let door2 = root.find_by_id("door2").unwrap();
+        assert_eq!(
+            Some("translate(100,0) translate(50 200)"),
+            door2.attributes.get("transform").map(|value| &**value)
+        );
+        assert_eq!(Vector2::new(150., 200.), door2.bounding_box.get_top_left());
+        assert_eq!(Vector2::new(160., 212.), door2.get_bottom_right());
+
+        // Uses nested in the referenced element are inlined too
+        let door3 = root.find_by_id("door3").unwrap();
+        let double_door = &door3.children[0];
+        assert_eq!(2, double_door.children.len());
+        assert!(double_door.children.iter().all(|door| door.tag_name == "g"));
+        assert_eq!(Vector2::new(200., 50.), door3.bounding_box.get_top_left());
+        assert_eq!(Vector2::new(220., 62.), door3.get_bottom_right());
+
+        let missing = root.find_by_id("missing").unwrap();
+        assert_eq!("use", missing.tag_name);
+        assert!(missing.children.is_empty());
+
+        // A tile away from the <defs> still gets the door's geometry
+        let tile = BoundingBox::new(Vector2::new(290., 290.), Vector2::new(50., 50.));
+        let selected = root.select_with(&tile).unwrap();
+        assert_eq!(vec!["svg", "g", "g", "rect", "path"], tag_names(&selected));
+    }
+
+    #[test]
+    fn reject_use_cycle() {
+        let svg_data = r##"<svg><g id="a"><use href="#a" x="1" /></g></svg>"##;
+        let error = SvgElement::from_svg_data(svg_data).unwrap_err();
+        assert_eq!(
+            r#"line 1: too many nested use elements referencing "a", which may form a cycle"#,
+            error.to_string()
+        );
+        assert!(matches!(error, SvgParseError::UseCycle { reference, .. } if reference == "a"));
+    }
+
+    #[test]
+    fn walk_elements() {
+        let svg_data = file("tests/svg/nested_groups.svg");
+        let root = SvgElement::from_svg_data(&svg_data).unwrap();
+
+        let rects = root.find_all(|element| element.get_tag_name() == "rect");
+        let ids: Vec<_> = rects.iter().filter_map(|rect| rect.get_id()).collect();
+        assert_eq!(
+            vec!["rect1", "rect2", "rect3", "rect4", "rect5", "rect6"],
+            ids
+        );
+        assert_eq!(
+            Some("40"),
+            rects[1].get_attributes().get("width").map(|width| &**width)
+        );
+
+        let layers: Vec<_> = root
+            .get_children()
+            .iter()
+            .map(|child| child.get_tag_name())
+            .collect();
+        assert_eq!(vec!["g", "g", "rect"], layers);
+        let group1 = root.find_by_id("group1").unwrap();
+        assert_eq!(
+            1,
+            group1
+                .find_all(|element| element.get_tag_name() == "rect"
+                    && element
+                        .get_attr("width")
+                        .is_some_and(|width| &**width == "100"))
+                .len()
+        );
+    }
+
+    fn nested_groups(depth: usize) -> String {
+        format!(
+            r#"<svg width="10" height="10">{}<rect width="1" height="1" />{}</svg>"#,
+            "<g>".repeat(depth - 2),
+            "</g>".repeat(depth - 2)
+        )
+    }
+
+    #[test]
+    fn reject_deep_nesting() {
+        let options = ParseOptions::default();
+        let svg_data = nested_groups(options.max_depth);
+        let root = SvgElement::from_svg_data(&svg_data).unwrap();
+        assert_eq!(options.max_depth, root.element_count());
+        // Elements nested as deeply as allowed can still be walked recursively to make tiles
+        let bounding_box = BoundingBox::new(Vector2::zeros(), Vector2::new(10., 10.));
+        let index = root.build_index();
+        let selected = root.select_with_index(&index, &bounding_box).unwrap();
+        assert_eq!(options.max_depth, selected.element_count());
+        selected.as_element().to_string();
+
+        // Far too deep to parse recursively without overflowing the stack
+        let svg_data = nested_groups(50_000);
+        let error = SvgElement::from_svg_data(&svg_data).unwrap_err();
+        assert!(matches!(
+            error,
+            SvgParseError::TooDeep {
+                max_depth: 1024,
+                ..
+            }
+        ));
+        assert_eq!(
+            "line 1: elements are nested more than 1024 deep",
+            error.to_string()
+        );
+        let error =
+            SvgElement::select_from_svg_data(&svg_data, &options, &bounding_box, &|_element| true)
+                .unwrap_err();
+        assert!(matches!(error, SvgParseError::TooDeep { .. }));
+
+        let options = ParseOptions {
+            max_depth: 3,
+            ..ParseOptions::default()
+        };
+        assert!(SvgElement::from_svg_data_with_options(&nested_groups(3), &options).is_ok());
+        let error =
+            SvgElement::from_svg_data_with_options(&nested_groups(4), &options).unwrap_err();
+        assert!(matches!(error, SvgParseError::TooDeep { max_depth: 3, .. }));
+
+        // Inlined `use` elements can't nest the elements they reference any deeper either
+        let svg_data = r##"<svg>
+            <g id="a"><g><rect width="1" height="1" /></g></g>
+            <g><use href="#a" /></g>
+        </svg>"##;
+        let error = SvgElement::from_svg_data_with_options(svg_data, &options).unwrap_err();
+        assert!(matches!(error, SvgParseError::TooDeep { max_depth: 3, .. }));
+    }
+
+    #[test]
+    fn reject_too_many_elements() {
+        let options = ParseOptions {
+            max_elements: 4,
+            ..ParseOptions::default()
+        };
+        let svg_data = r#"<svg><rect id="a" /><rect /><rect /></svg>"#;
+        assert!(SvgElement::from_svg_data_with_options(svg_data, &options).is_ok());
+        let svg_data = r#"<svg><rect /><rect /><rect /><rect id="last" /></svg>"#;
+        let error = SvgElement::from_svg_data_with_options(svg_data, &options).unwrap_err();
+        assert_eq!(
+            r#"line 1 (id="last"): there are more than 4 elements"#,
+            error.to_string()
+        );
+
+        // Each level of `use` elements doubles the number of elements once they're inlined
+        let mut svg_data = String::from(r#"<svg><defs><rect id="a0" width="1" height="1" />"#);
+        for level in 1..30 {
+            svg_data += &format!(
+                r##"<g id="a{level}"><use href="#a{0}" /><use href="#a{0}" /></g>"##,
+                level - 1
+            );
+        }
+        svg_data += r##"</defs><use href="#a29" /></svg>"##;
+        let options = ParseOptions {
+            max_elements: 10_000,
+            ..ParseOptions::default()
+        };
+        let error = SvgElement::from_svg_data_with_options(&svg_data, &options).unwrap_err();
+        assert!(matches!(
+            error,
+            SvgParseError::TooManyElements {
+                max_elements: 10_000,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn reject_mismatched_end_tag() {
+        let error = SvgElement::from_svg_data("<svg><g><rect /></svg></g>").unwrap_err();
+        assert_eq!(
+            "line 1: mismatched end tag, expected </g> but found </svg>",
+            error.to_string()
+        );
+
+        let options = ParseOptions {
+            skip_namespaced: true,
+            ..ParseOptions::default()
+        };
+        let error =
+            SvgElement::from_svg_data_with_options("<svg>\n<a:b>\n<c></a:b></c></svg>", &options)
+                .unwrap_err();
+        assert_eq!(
+            "line 3: mismatched end tag, expected </c> but found </a:b>",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn reject_truncated_svg() {
+        let error = SvgElement::from_svg_data("<svg><g><rect />").unwrap_err();
+        assert_eq!(
+            "line 1: unexpected end of SVG, missing end tag for <g>",
+            error.to_string()
+        );
+        assert!(matches!(error, SvgParseError::MissingEndTag { name, .. } if name == "g"));
+
+        let svg_data = file("tests/svg/inkscape.svg");
+        let truncated = &svg_data[..svg_data.find("<rect").unwrap()];
+        match SvgElement::from_svg_data(truncated).unwrap_err() {
+            SvgParseError::MissingEndTag { name, location } => {
+                assert_eq!("g", name);
+                assert_eq!(
+                    Location {
+                        line: Some(39),
+                        id: Some("g12".to_owned())
+                    },
+                    location
+                );
+            }
+            other => panic!("Should be a missing end tag, was {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reject_bad_matrix() {
+        let svg_data = "<svg>\n<g>\n\n<rect id=\"g8842\" transform=\"matrix(1 0)\" /></g></svg>";
+        let error = SvgElement::from_svg_data(svg_data).unwrap_err();
+        assert_eq!(
+            r#"line 4 (id="g8842"): wrong number of arguments to matrix transform "matrix(1 0)""#,
+            error.to_string()
+        );
+        match error {
+            SvgParseError::InvalidTransform { tag, source, .. } => {
+                assert_eq!("rect", tag);
+                assert_eq!(
+                    TransformError::ArgumentCount {
+                        function: "matrix",
+                        transform: "matrix(1 0)".to_owned()
+                    },
+                    source
+                );
+            }
+            other => panic!("Should be an invalid transform, was {:?}", other),
+        }
+
+        let error =
+            SvgElement::from_svg_data(r#"<svg><path d="M 0 0 L 10 x" /></svg>"#).unwrap_err();
+        assert!(matches!(error, SvgParseError::InvalidPathData { tag, .. } if tag == "path"));
+        let error = SvgElement::from_svg_data("<svg><path /></svg>").unwrap_err();
+        assert!(matches!(
+            error,
+            SvgParseError::MissingAttribute { attribute: "d", .. }
+        ));
+    }
+
+    #[test]
+    fn parse_real_world_transforms() {
+        let cases = [
+            (
+                "matrix(1 0 0 1 -2.5e-3 1.2e2)",
+                transform::translate(Vector2::new(-2.5e-3, 1.2e2)),
+            ),
+            (
+                "matrix(1,0,0,1,10,20)",
+                transform::translate(Vector2::new(10., 20.)),
+            ),
+            (
+                "translate(200, 200)",
+                transform::translate(Vector2::new(200., 200.)),
+            ),
+            (
+                "translate(1e2)",
+                transform::translate(Vector2::new(100., 0.)),
+            ),
+            (
+                "translate(-5-5)",
+                transform::translate(Vector2::new(-5., -5.)),
+            ),
+            (
+                " translate ( 3 ,\n 4 ) ",
+                transform::translate(Vector2::new(3., 4.)),
+            ),
+            (
+                "rotate(90 , 10,10)",
+                transform::rotate_deg_about(90., Vector2::new(10., 10.)),
+            ),
+            ("scale(.5)", transform::scale(Vector2::new(0.5, 0.5))),
+            ("scale(2E0,-1)", transform::scale(Vector2::new(2., -1.))),
+            (
+                "translate(10,0) scale(2)",
+                transform::translate(Vector2::new(10., 0.))
+                    * transform::scale(Vector2::new(2., 2.)),
+            ),
+            (
+                "scale(2),translate(10)",
+                transform::scale(Vector2::new(2., 2.))
+                    * transform::translate(Vector2::new(10., 0.)),
+            ),
+        ];
+        for (transformation, expected) in cases {
+            let mut warnings = vec![];
+            let actual = SvgElement::parse_transform(transformation, &mut warnings).unwrap();
+            assert_eq!(Vec::<ParseWarning>::new(), warnings);
+            assert!(
+                (actual - expected).abs().max() < 1e-9,
+                "{:?} parsed as {}",
+                transformation,
+                actual
+            );
+        }
+
+        for transformation in ["translate(1 2", "scale()", "rotate(1 2)", "matrix(1 0)"] {
+            assert!(
+                SvgElement::parse_transform(transformation, &mut vec![]).is_err(),
+                "{:?}",
+                transformation
+            );
+        }
+    }
+
+    #[test]
+    fn parse_lengths_with_units() {
+        let svg_data = r#"<svg width="1e3mm" height="2.1E2"><rect x="1e1" y="-5" width="20mm" height="1e1" /></svg>"#;
+        let root = SvgElement::from_svg_data(svg_data).unwrap();
+        assert_eq!(Vector2::new(1000., 210.), root.bounding_box.get_size());
+        let rect = &root.children[0];
+        assert_eq!(Vector2::new(10., -5.), rect.bounding_box.get_top_left());
+        assert_eq!(Vector2::new(20., 10.), rect.bounding_box.get_size());
+
+        // Unresolvable lengths don't stop the document from loading
+        let root = SvgElement::from_svg_data(r#"<svg width="10furlongs" height="5" />"#).unwrap();
+        assert_eq!(Vector2::new(0., 5.), root.bounding_box.get_size());
+    }
+
+    #[test]
+    fn resolve_length_units() {
+        let svg_data = file("tests/svg/percentages.svg");
+        let root = SvgElement::from_svg_data(&svg_data).unwrap();
+        assert_eq!(Vector2::new(800., 600.), root.bounding_box.get_size());
+
+        let geometry = |id| {
+            let element = root.find_by_id(id).unwrap();
+            (
+                element.bounding_box.get_top_left(),
+                element.bounding_box.get_size(),
+            )
+        };
+        assert_eq!(
+            (Vector2::new(0., 0.), Vector2::new(800., 600.)),
+            geometry("background")
+        );
+        assert_eq!(
+            (Vector2::new(180., 50.), Vector2::new(400., 32.)),
+            geometry("hallway")
+        );
+        assert_eq!(
+            (Vector2::new(116., 82.), Vector2::new(300., 150.)),
+            geometry("room")
+        );
+        // Nested svg elements establish a new viewport from their viewBox
+        assert_eq!(Vector2::new(20., 5.), geometry("inset-background").1);
+        // Unknown units are treated as 0 instead of failing the whole document
+        assert_eq!(Vector2::new(0., 150.), geometry("label-box").1);
+
+        let options = ParseOptions {
+            base_font_size: 10.,
+            ..ParseOptions::default()
+        };
+        let root = SvgElement::from_svg_data_with_options(&svg_data, &options).unwrap();
+        let hallway = root.find_by_id("hallway").unwrap();
+        assert_eq!(20., hallway.bounding_box.get_size()[1]);
+    }
+
+    #[test]
+    fn report_parse_warnings() {
+        let svg_data = r##"<svg viewBox="0 0 bogus" width="10" height="10">
+            <sodipodi:namedview><inkscape:grid /></sodipodi:namedview>
+            <g id="hidden" style="display:none"><rect width="5" /></g>
+            <rect width="3furlongs" transform="skewX(10) translate(1)" />
+            <text x="1" y="1">Room 101</text>
+            <use href="#nowhere" x="1em" />
+            <style>.a { fill: red }</style>
+        </svg>"##;
+        let options = ParseOptions {
+            skip_namespaced: true,
+            ..ParseOptions::default()
+        };
+        let (root, report) = SvgElement::from_svg_data_with_report(svg_data, &options).unwrap();
+        assert_eq!(
+            vec!["svg", "rect", "text", "use", "style"],
+            tag_names(&root)
+        );
+        assert_eq!(
+            vec![
+                ParseWarning::MalformedViewBox {
+                    value: "0 0 bogus".to_owned()
+                },
+                ParseWarning::SkippedNamespacedElement {
+                    name: "sodipodi:namedview".to_owned()
+                },
+                ParseWarning::SkippedHiddenElement {
+                    name: "g".to_owned(),
+                    id: Some("hidden".to_owned())
+                },
+                ParseWarning::InvalidLength {
+                    attribute: "width".to_owned(),
+                    value: "3furlongs".to_owned(),
+                    reason: "Unsupported unit `furlongs` in \"3furlongs\"".to_owned()
+                },
+                ParseWarning::UnknownTransform {
+                    transform: "skewX(10)".to_owned()
+                },
+                ParseWarning::DroppedText {
+                    parent: "text".to_owned(),
+                    text: "Room 101".to_owned()
+                },
+                ParseWarning::MissingUseReference {
+                    reference: "nowhere".to_owned()
+                },
+            ],
+            report
+                .warnings
+                .iter()
+                .map(|located| located.warning.clone())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![1, 2, 3, 4, 4, 5, 6],
+            report
+                .warnings
+                .iter()
+                .map(|located| located.location.line.unwrap())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            r#"line 4: Ignored unsupported transform "skewX(10)""#,
+            report.warnings[4].to_string()
+        );
+
+        let (_root, report) =
+            SvgElement::from_svg_data_with_report(&file("tests/svg/nested_groups.svg"), &options)
+                .unwrap();
+        assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+    }
+
+    fn assert_shares_attributes(original: &SvgElement, selected: &SvgElement) {
+        assert!(Arc::ptr_eq(&original.attributes, &selected.attributes));
+        for (original_child, selected_child) in original.children.iter().zip(&selected.children) {
+            assert_shares_attributes(original_child, selected_child);
+        }
+    }
+
+    #[test]
+    fn selection_shares_attributes() {
+        let rects: String = (0..5000)
+            .map(|i| {
+                format!(
+                    r#"<rect id="rect{}" x="{}" y="{}" width="5" height="5" fill="red" />"#,
+                    i,
+                    (i % 100) * 10,
+                    (i / 100) * 10
+                )
+            })
+            .collect();
+        let svg_data = format!(r#"<svg width="1000" height="500"><g>{}</g></svg>"#, rects);
+        let root = SvgElement::from_svg_data(&svg_data).unwrap();
+
+        let everything = BoundingBox::new(Vector2::new(0., 0.), Vector2::new(1000., 500.));
+        let mut selected = root.select_with(&everything).unwrap();
+        assert_eq!(5000, selected.children[0].children.len());
+        assert_shares_attributes(&root, &selected);
+
+        // Modifying the selection copies its attributes instead of changing the original's
+        selected.set_attr("viewBox", "0 0 10 10".into());
+        selected.delete_attr("width");
+        assert!(!Arc::ptr_eq(&root.attributes, &selected.attributes));
+        assert!(root.attributes.contains_key("width"));
+        assert!(!root.attributes.contains_key("viewBox"));
+        assert_shares_attributes(&root.children[0], &selected.children[0]);
+    }
+}
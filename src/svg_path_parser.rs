@@ -1,68 +1,360 @@
-use svg::node::element::path;
-use svg::node::element::path::{Command as RawCommand, Position};
+use std::ops::Deref;
+use std::slice::{ChunksExact, Iter};
+
+use svg::node::element::path::Position;
+
+/// A command of path data as it's written, like [`svg::node::element::path::Command`], but with
+/// its parameters parsed as `f64`
+#[derive(Clone, Debug, PartialEq)]
+pub enum RawCommand {
+    Move(Position, Vec<f64>),
+    Line(Position, Vec<f64>),
+    HorizontalLine(Position, Vec<f64>),
+    VerticalLine(Position, Vec<f64>),
+    QuadraticCurve(Position, Vec<f64>),
+    SmoothQuadraticCurve(Position, Vec<f64>),
+    CubicCurve(Position, Vec<f64>),
+    SmoothCubicCurve(Position, Vec<f64>),
+    EllipticalArc(Position, Vec<f64>),
+    Close,
+}
+
+/// Commands of the `d` attribute of a path. [`svg::node::element::path::Data`] parses parameters
+/// as `f32`, which can't tell apart coordinates less than about 0.01 apart once they're around
+/// 100,000, so outlines of large floors are parsed with this instead.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PathData(Vec<RawCommand>);
+
+impl PathData {
+    /// Parses path data with the same syntax accepted by [`svg::node::element::path::Data::parse`]
+    pub fn parse(content: &str) -> Result<Self, svg::parser::Error> {
+        let mut parser = PathDataParser {
+            content,
+            position: 0,
+        };
+        let mut commands = vec![];
+        while let Some(command) = parser.read_command()? {
+            commands.push(command);
+        }
+        Ok(Self(commands))
+    }
+}
+
+impl Deref for PathData {
+    type Target = [RawCommand];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+struct PathDataParser<'a> {
+    content: &'a str,
+    /// Byte offset of the next character to read
+    position: usize,
+}
+
+impl PathDataParser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.content.as_bytes().get(self.position).copied()
+    }
+
+    fn error(&self, message: String) -> svg::parser::Error {
+        // Path data is a single attribute value, so positions are given as a column of line 1
+        svg::parser::Error::new((1, self.position + 1), message)
+    }
+
+    fn consume_whitespace(&mut self) {
+        while self.peek().is_some_and(|byte| byte.is_ascii_whitespace()) {
+            self.position += 1;
+        }
+    }
+
+    /// Consumes a run of digits, returning how many there were
+    fn consume_digits(&mut self) -> usize {
+        let start = self.position;
+        while self.peek().is_some_and(|byte| byte.is_ascii_digit()) {
+            self.position += 1;
+        }
+        self.position - start
+    }
+
+    fn read_command(&mut self) -> Result<Option<RawCommand>, svg::parser::Error> {
+        self.consume_whitespace();
+        let name = match self.content[self.position..].chars().next() {
+            Some(name) if name.is_ascii_alphabetic() => name,
+            Some(_) => return Err(self.error("expected a path command".to_owned())),
+            None => return Ok(None),
+        };
+        self.position += 1;
+        let position = if name.is_ascii_uppercase() {
+            Position::Absolute
+        } else {
+            Position::Relative
+        };
+        let command: fn(Position, Vec<f64>) -> RawCommand = match name.to_ascii_uppercase() {
+            'M' => RawCommand::Move,
+            'L' => RawCommand::Line,
+            'H' => RawCommand::HorizontalLine,
+            'V' => RawCommand::VerticalLine,
+            'Q' => RawCommand::QuadraticCurve,
+            'T' => RawCommand::SmoothQuadraticCurve,
+            'C' => RawCommand::CubicCurve,
+            'S' => RawCommand::SmoothCubicCurve,
+            'A' => return Ok(Some(RawCommand::EllipticalArc(position, self.read_arc()?))),
+            'Z' => return Ok(Some(RawCommand::Close)),
+            _ => {
+                self.position -= 1;
+                return Err(self.error(format!("found an unknown path command '{name}'")));
+            }
+        };
+        Ok(Some(command(position, self.read_parameters()?)))
+    }
+
+    fn consume_separator(&mut self) {
+        self.consume_whitespace();
+        if self.peek() == Some(b',') {
+            self.position += 1;
+        }
+    }
+
+    fn read_parameters(&mut self) -> Result<Vec<f64>, svg::parser::Error> {
+        let mut parameters = vec![];
+        while let Some(number) = self.read_number()? {
+            parameters.push(number);
+            self.consume_separator();
+        }
+        Ok(parameters)
+    }
+
+    /// Reads the parameters of an elliptical arc, whose flags can be written without anything
+    /// separating them from the next parameter, as in `a 5 5 0 017 7`
+    fn read_arc(&mut self) -> Result<Vec<f64>, svg::parser::Error> {
+        let mut parameters = vec![];
+        loop {
+            let parameter = match parameters.len() % 7 {
+                3 | 4 => self.read_flag()?,
+                _ => self.read_number()?,
+            };
+            match parameter {
+                Some(parameter) => parameters.push(parameter),
+                None => return Ok(parameters),
+            }
+            self.consume_separator();
+        }
+    }
+
+    fn read_flag(&mut self) -> Result<Option<f64>, svg::parser::Error> {
+        self.consume_whitespace();
+        let flag = match self.peek() {
+            Some(b'0') => 0.,
+            Some(b'1') => 1.,
+            _ => return Err(self.error("failed to parse a flag in an elliptical arc".to_owned())),
+        };
+        self.position += 1;
+        Ok(Some(flag))
+    }
+
+    /// Reads a number if the next character can start one. Numbers can follow each other without
+    /// separators when there's no ambiguity, as in `1-2.5.5`, which is `1`, `-2.5`, and `.5`.
+    fn read_number(&mut self) -> Result<Option<f64>, svg::parser::Error> {
+        self.consume_whitespace();
+        let start = self.position;
+        if matches!(self.peek(), Some(b'+' | b'-')) {
+            self.position += 1;
+        }
+        let mut digits = self.consume_digits();
+        if self.peek() == Some(b'.') {
+            self.position += 1;
+            digits += self.consume_digits();
+        }
+        if digits == 0 {
+            if self.position == start {
+                return Ok(None);
+            }
+            let number = &self.content[start..self.position];
+            self.position = start;
+            return Err(self.error(format!("failed to parse a number '{number}'")));
+        }
+        if matches!(self.peek(), Some(b'e' | b'E')) {
+            let mantissa_end = self.position;
+            self.position += 1;
+            if matches!(self.peek(), Some(b'+' | b'-')) {
+                self.position += 1;
+            }
+            if self.consume_digits() == 0 {
+                self.position = mantissa_end;
+            }
+        }
+        let number = &self.content[start..self.position];
+        number
+            .parse()
+            .map(Some)
+            .map_err(|_| self.error(format!("failed to parse a number '{number}'")))
+    }
+}
 
 /// Represents a command. First component is the absolute x destination, second is the absolute y
 /// destination. Does not store any information besides the destination.
 #[derive(Copy, Clone, Debug)]
-pub struct Command(pub f32, pub f32);
+pub struct Command(pub f64, pub f64);
 
 impl Command {
-    pub fn from_raw_command(raw_command: &RawCommand, last_command: Command) -> Vec<Self> {
-        match raw_command {
-            RawCommand::Close => vec![], // Ignored
+    /// Iterates over the destinations of a raw command, computing each as it's reached rather
+    /// than collecting them. `last_command` is the destination before the raw command, which
+    /// relative commands build on.
+    pub fn from_raw_command(raw_command: &RawCommand, last_command: Command) -> RawCommandIter<'_> {
+        let (position, parameters, destination) = match raw_command {
+            RawCommand::Close => {
+                return RawCommandIter {
+                    chunks: [].chunks_exact(1),
+                    destination: Destination::Pair(0),
+                    position: Position::Absolute,
+                    last_command,
+                }
+            }
             RawCommand::HorizontalLine(position, parameters) => {
-                let chunks = parameters.chunks_exact(1);
-                let coords: Vec<_> = match position {
-                    Position::Absolute => chunks.map(|chunk| (chunk[0], last_command.1)).collect(),
-                    Position::Relative => chunks.map(|chunk| (chunk[0], 0.0)).collect(),
-                };
-                Self::from_coords_position(coords.into_iter(), *position, last_command)
+                (position, parameters, Destination::Horizontal)
             }
             RawCommand::VerticalLine(position, parameters) => {
-                let chunks = parameters.chunks_exact(1);
-                let coords: Vec<_> = match position {
-                    Position::Absolute => chunks.map(|chunk| (last_command.0, chunk[0])).collect(),
-                    Position::Relative => chunks.map(|chunk| (0.0, chunk[0])).collect(),
-                };
-                Self::from_coords_position(coords.into_iter(), *position, last_command)
+                (position, parameters, Destination::Vertical)
             }
             RawCommand::SmoothQuadraticCurve(position, parameters)
             | RawCommand::Move(position, parameters)
             | RawCommand::Line(position, parameters) => {
-                let coords = parameters.chunks_exact(2).map(|chunk| (chunk[0], chunk[1]));
-                Self::from_coords_position(coords, *position, last_command)
+                (position, parameters, Destination::Pair(0))
             }
             RawCommand::SmoothCubicCurve(position, parameters)
             | RawCommand::QuadraticCurve(position, parameters) => {
-                let coords = parameters.chunks_exact(4).map(|chunk| (chunk[2], chunk[3]));
-                Self::from_coords_position(coords, *position, last_command)
+                (position, parameters, Destination::Pair(2))
             }
             RawCommand::CubicCurve(position, parameters) => {
-                let coords = parameters.chunks_exact(6).map(|chunk| (chunk[4], chunk[5]));
-                Self::from_coords_position(coords, *position, last_command)
+                (position, parameters, Destination::Pair(4))
             }
             RawCommand::EllipticalArc(position, parameters) => {
-                let coords = parameters.chunks_exact(7).map(|chunk| (chunk[5], chunk[6]));
-                Self::from_coords_position(coords, *position, last_command)
+                (position, parameters, Destination::Pair(5))
             }
+        };
+        RawCommandIter {
+            chunks: parameters.chunks_exact(destination.chunk_size()),
+            destination,
+            position: *position,
+            last_command,
         }
     }
+}
 
-    fn from_coords_position<T>(coords: T, position: Position, last_command: Command) -> Vec<Command>
-    where
-        T: Iterator<Item = (f32, f32)>,
-    {
-        match position {
-            Position::Absolute => coords.map(|(x, y)| Command(x, y)).collect(),
-            Position::Relative => {
-                coords
-                    .fold((vec![], last_command), |(mut acc, last_command), (x, y)| {
-                        let command = Command(last_command.0 + x, last_command.1 + y);
-                        acc.push(command);
-                        (acc, command)
-                    })
-                    .0
+/// Where the destination of a command is in each group of its parameters
+#[derive(Copy, Clone, Debug)]
+enum Destination {
+    /// The only parameter is the x-coordinate
+    Horizontal,
+    /// The only parameter is the y-coordinate
+    Vertical,
+    /// The x- and y-coordinates are the last two parameters, starting at the given index
+    Pair(usize),
+}
+
+impl Destination {
+    fn chunk_size(&self) -> usize {
+        match self {
+            Destination::Horizontal | Destination::Vertical => 1,
+            Destination::Pair(index) => index + 2,
+        }
+    }
+}
+
+/// Iterator over the destinations of a single raw command. See [`Command::from_raw_command`].
+#[derive(Clone, Debug)]
+pub struct RawCommandIter<'a> {
+    chunks: ChunksExact<'a, f64>,
+    destination: Destination,
+    position: Position,
+    last_command: Command,
+}
+
+impl Iterator for RawCommandIter<'_> {
+    type Item = Command;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let chunk = self.chunks.next()?;
+        let last = self.last_command;
+        let (x, y) = match (self.destination, self.position) {
+            (Destination::Horizontal, Position::Absolute) => (chunk[0], last.1),
+            (Destination::Horizontal, Position::Relative) => (chunk[0], 0.0),
+            (Destination::Vertical, Position::Absolute) => (last.0, chunk[0]),
+            (Destination::Vertical, Position::Relative) => (0.0, chunk[0]),
+            (Destination::Pair(index), _) => (chunk[index], chunk[index + 1]),
+        };
+        let command = match self.position {
+            Position::Absolute => Command(x, y),
+            Position::Relative => Command(last.0 + x, last.1 + y),
+        };
+        self.last_command = command;
+        Some(command)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.chunks.size_hint()
+    }
+}
+
+/// Iterator over the destinations of every command in path data, without collecting them like
+/// [`SimpleSvgPath`] does
+#[derive(Clone, Debug)]
+pub struct PathIter<'a> {
+    raw_commands: Iter<'a, RawCommand>,
+    current: Option<RawCommandIter<'a>>,
+    last_command: Command,
+    subpath_start: Command,
+    /// Set while the first destination of a move command hasn't been reached yet
+    starting_subpath: bool,
+    /// Set if the last destination returned was the first of a move command
+    started_subpath: bool,
+}
+
+impl<'a> PathIter<'a> {
+    pub fn new(raw_commands: &'a PathData) -> Self {
+        Self {
+            raw_commands: raw_commands.iter(),
+            current: None,
+            last_command: Command(0.0, 0.0),
+            subpath_start: Command(0.0, 0.0),
+            starting_subpath: false,
+            started_subpath: false,
+        }
+    }
+
+    /// Returns `true` if the last destination returned starts a new subpath, as the first
+    /// destination of a move command
+    pub fn started_subpath(&self) -> bool {
+        self.started_subpath
+    }
+}
+
+impl Iterator for PathIter<'_> {
+    type Item = Command;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(command) = self.current.as_mut().and_then(Iterator::next) {
+                self.started_subpath = self.starting_subpath;
+                if self.starting_subpath {
+                    self.subpath_start = command;
+                    self.starting_subpath = false;
+                }
+                self.last_command = command;
+                return Some(command);
             }
+
+            let raw_command = self.raw_commands.next()?;
+            match raw_command {
+                // Closing a subpath returns to its start, which later relative commands build on
+                RawCommand::Close => self.last_command = self.subpath_start,
+                RawCommand::Move(..) => self.starting_subpath = true,
+                _ => {}
+            }
+            self.current = Some(Command::from_raw_command(raw_command, self.last_command));
         }
     }
 }
@@ -70,29 +362,47 @@ impl Command {
 #[derive(Clone, Debug)]
 pub struct SimpleSvgPath {
     path: Vec<Command>,
+    /// Index in `path` of the first command of each subpath after the first
+    subpath_starts: Vec<usize>,
 }
 
-impl From<&path::Data> for SimpleSvgPath {
-    fn from(raw_commands: &path::Data) -> Self {
-        let path = raw_commands
+impl SimpleSvgPath {
+    /// Splits the path into the subpaths started by each move command, leaving out empty ones
+    pub fn subpaths(&self) -> Vec<&[Command]> {
+        let ends = self
+            .subpath_starts
             .iter()
-            .fold(
-                (vec![], Command(0.0, 0.0)),
-                |(mut acc, last_command), raw_command| {
-                    let command = Command::from_raw_command(raw_command, last_command);
-                    let last = command.last().copied();
-                    match last {
-                        Some(new_last_command) => {
-                            acc.extend(command.into_iter());
-                            (acc, new_last_command)
-                        }
-                        None => (acc, last_command),
-                    }
-                },
-            )
-            .0;
+            .copied()
+            .chain(std::iter::once(self.path.len()));
+        let mut start = 0;
+        let mut subpaths = Vec::new();
+        for end in ends {
+            if end > start {
+                subpaths.push(&self.path[start..end]);
+            }
+            start = end;
+        }
+        subpaths
+    }
+}
+
+impl From<&PathData> for SimpleSvgPath {
+    fn from(raw_commands: &PathData) -> Self {
+        let mut path = vec![];
+        let mut subpath_starts = vec![];
+
+        let mut commands = PathIter::new(raw_commands);
+        while let Some(command) = commands.next() {
+            if commands.started_subpath() && !path.is_empty() {
+                subpath_starts.push(path.len());
+            }
+            path.push(command);
+        }
 
-        Self { path }
+        Self {
+            path,
+            subpath_starts,
+        }
     }
 }
 
@@ -104,3 +414,294 @@ impl IntoIterator for SimpleSvgPath {
         self.path.into_iter()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use svg::events::Event;
+    use svg::node::element::path;
+
+    use super::*;
+
+    fn coords(commands: &[Command]) -> Vec<(f64, f64)> {
+        commands
+            .iter()
+            .map(|command| (command.0, command.1))
+            .collect()
+    }
+
+    #[test]
+    fn split_subpaths() {
+        let data =
+            PathData::parse("M 0 0 L 10 0 L 10 10 Z m 2 2 h 2 v 2 z M 20 20 L 30 20").unwrap();
+        let path = SimpleSvgPath::from(&data);
+        let subpaths: Vec<_> = path.subpaths().into_iter().map(coords).collect();
+        assert_eq!(
+            vec![
+                vec![(0., 0.), (10., 0.), (10., 10.)],
+                // Relative to the start of the closed subpath before it
+                vec![(2., 2.), (4., 2.), (4., 4.)],
+                vec![(20., 20.), (30., 20.)],
+            ],
+            subpaths
+        );
+        assert_eq!(8, path.into_iter().count());
+
+        let data = PathData::parse("M 1 1 L 2 2").unwrap();
+        assert_eq!(1, SimpleSvgPath::from(&data).subpaths().len());
+    }
+
+    /// Converts a command parsed by the `svg` crate for comparison, with its parameters as `f32`
+    fn from_svg_command(command: &path::Command) -> (String, Vec<f32>) {
+        let (name, parameters) = match command {
+            path::Command::Move(position, parameters) => (format!("M{position:?}"), parameters),
+            path::Command::Line(position, parameters) => (format!("L{position:?}"), parameters),
+            path::Command::HorizontalLine(position, parameters) => {
+                (format!("H{position:?}"), parameters)
+            }
+            path::Command::VerticalLine(position, parameters) => {
+                (format!("V{position:?}"), parameters)
+            }
+            path::Command::QuadraticCurve(position, parameters) => {
+                (format!("Q{position:?}"), parameters)
+            }
+            path::Command::SmoothQuadraticCurve(position, parameters) => {
+                (format!("T{position:?}"), parameters)
+            }
+            path::Command::CubicCurve(position, parameters) => {
+                (format!("C{position:?}"), parameters)
+            }
+            path::Command::SmoothCubicCurve(position, parameters) => {
+                (format!("S{position:?}"), parameters)
+            }
+            path::Command::EllipticalArc(position, parameters) => {
+                (format!("A{position:?}"), parameters)
+            }
+            path::Command::Close => return ("Z".to_owned(), vec![]),
+        };
+        (name, parameters.to_vec())
+    }
+
+    fn to_svg_command(command: &RawCommand) -> (String, Vec<f32>) {
+        let (name, parameters) = match command {
+            RawCommand::Move(position, parameters) => (format!("M{position:?}"), parameters),
+            RawCommand::Line(position, parameters) => (format!("L{position:?}"), parameters),
+            RawCommand::HorizontalLine(position, parameters) => {
+                (format!("H{position:?}"), parameters)
+            }
+            RawCommand::VerticalLine(position, parameters) => {
+                (format!("V{position:?}"), parameters)
+            }
+            RawCommand::QuadraticCurve(position, parameters) => {
+                (format!("Q{position:?}"), parameters)
+            }
+            RawCommand::SmoothQuadraticCurve(position, parameters) => {
+                (format!("T{position:?}"), parameters)
+            }
+            RawCommand::CubicCurve(position, parameters) => (format!("C{position:?}"), parameters),
+            RawCommand::SmoothCubicCurve(position, parameters) => {
+                (format!("S{position:?}"), parameters)
+            }
+            RawCommand::EllipticalArc(position, parameters) => {
+                (format!("A{position:?}"), parameters)
+            }
+            RawCommand::Close => return ("Z".to_owned(), vec![]),
+        };
+        (
+            name,
+            parameters
+                .iter()
+                .map(|&parameter| parameter as f32)
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn parse_like_svg_crate() {
+        let mut paths = vec![
+            "M 0 0 L 10 0 10 10 Z m 2 2 h 2 3 v 2 -1 z M 20 20 H 25 V 30".to_owned(),
+            "c 1 1 2 2 3 3 S 5 5 6 6 q 1 1 2 2 T 4 4 a 5 5 0 0 1 7 7 Q 1 2 3 4 A 1 1 0 1 0 8 9"
+                .to_owned(),
+            "M1-2.5.5e1,+3E-1L.5 1 l-1e2-0 a5 5 0 017 7".to_owned(),
+            "  \n M 1 2 z  ".to_owned(),
+            String::new(),
+        ];
+        for entry in fs::read_dir("tests/svg").unwrap() {
+            let svg_data = fs::read_to_string(entry.unwrap().path()).unwrap();
+            for event in svg::read(&svg_data).unwrap() {
+                if let Ok(Event::Tag("path", _, attributes)) = event {
+                    paths.push(attributes["d"].to_string());
+                }
+            }
+        }
+
+        for d in &paths {
+            let expected: Vec<_> = path::Data::parse(d)
+                .unwrap()
+                .iter()
+                .map(from_svg_command)
+                .collect();
+            let parsed: Vec<_> = PathData::parse(d)
+                .unwrap()
+                .iter()
+                .map(to_svg_command)
+                .collect();
+            assert_eq!(expected, parsed, "{}", d);
+        }
+    }
+
+    #[test]
+    fn parse_full_precision() {
+        let data = PathData::parse("M 100010.003 100010.007 l 0.001 -1e-3").unwrap();
+        assert_eq!(
+            vec![(100010.003, 100010.007), (100010.004, 100010.006)],
+            coords(&PathIter::new(&data).collect::<Vec<_>>())
+                .into_iter()
+                .map(|(x, y)| ((x * 1e3).round() / 1e3, (y * 1e3).round() / 1e3))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            &[RawCommand::Move(
+                Position::Absolute,
+                vec![100010.003, 100010.007]
+            )],
+            &data[..1]
+        );
+    }
+
+    #[test]
+    fn reject_invalid_path_data() {
+        for d in [
+            "1 2",
+            "M 1 2 #",
+            "M 1 -",
+            "M 1 .",
+            "X 1 2",
+            "A 1 1 0 2 0 8 9",
+        ] {
+            assert!(PathData::parse(d).is_err(), "{}", d);
+        }
+        // A number can end with its decimal point
+        assert_eq!(
+            &[RawCommand::Line(Position::Absolute, vec![1., 2.])],
+            &PathData::parse("L 1. 2").unwrap()[..]
+        );
+    }
+
+    /// Destinations of a raw command, collected as they were before commands were iterated over
+    fn collected_raw_command(raw_command: &RawCommand, last_command: Command) -> Vec<Command> {
+        let from_coords_position = |coords: Vec<(f64, f64)>, position: &Position| match position {
+            Position::Absolute => coords.into_iter().map(|(x, y)| Command(x, y)).collect(),
+            Position::Relative => {
+                coords
+                    .into_iter()
+                    .fold((vec![], last_command), |(mut acc, last_command), (x, y)| {
+                        let command = Command(last_command.0 + x, last_command.1 + y);
+                        acc.push(command);
+                        (acc, command)
+                    })
+                    .0
+            }
+        };
+        let pairs = |parameters: &[f64], size: usize| {
+            parameters
+                .chunks_exact(size)
+                .map(|chunk| (chunk[size - 2], chunk[size - 1]))
+                .collect()
+        };
+        match raw_command {
+            RawCommand::Close => vec![],
+            RawCommand::HorizontalLine(position, parameters) => {
+                let y = match position {
+                    Position::Absolute => last_command.1,
+                    Position::Relative => 0.0,
+                };
+                let coords = parameters.iter().map(|x| (*x, y)).collect();
+                from_coords_position(coords, position)
+            }
+            RawCommand::VerticalLine(position, parameters) => {
+                let x = match position {
+                    Position::Absolute => last_command.0,
+                    Position::Relative => 0.0,
+                };
+                let coords = parameters.iter().map(|y| (x, *y)).collect();
+                from_coords_position(coords, position)
+            }
+            RawCommand::SmoothQuadraticCurve(position, parameters)
+            | RawCommand::Move(position, parameters)
+            | RawCommand::Line(position, parameters) => {
+                from_coords_position(pairs(parameters, 2), position)
+            }
+            RawCommand::SmoothCubicCurve(position, parameters)
+            | RawCommand::QuadraticCurve(position, parameters) => {
+                from_coords_position(pairs(parameters, 4), position)
+            }
+            RawCommand::CubicCurve(position, parameters) => {
+                from_coords_position(pairs(parameters, 6), position)
+            }
+            RawCommand::EllipticalArc(position, parameters) => {
+                from_coords_position(pairs(parameters, 7), position)
+            }
+        }
+    }
+
+    /// Destinations of path data, collected as they were before commands were iterated over
+    fn collected_path(data: &PathData) -> Vec<Command> {
+        let mut path = vec![];
+        let mut last_command = Command(0.0, 0.0);
+        let mut subpath_start = last_command;
+        for raw_command in data.iter() {
+            let commands = collected_raw_command(raw_command, last_command);
+            match raw_command {
+                RawCommand::Close => last_command = subpath_start,
+                RawCommand::Move(..) => {
+                    if let Some(first) = commands.first() {
+                        subpath_start = *first;
+                    }
+                }
+                _ => {}
+            }
+            if let Some(last) = commands.last() {
+                last_command = *last;
+            }
+            path.extend(commands);
+        }
+        path
+    }
+
+    #[test]
+    fn iterated_commands_match_collected() {
+        let mut paths = vec![PathData::parse(
+            "M 0 0 L 10 0 10 10 Z m 2 2 h 2 3 v 2 -1 z M 20 20 H 25 V 30 \
+             c 1 1 2 2 3 3 S 5 5 6 6 q 1 1 2 2 T 4 4 a 5 5 0 0 1 7 7 Q 1 2 3 4 A 1 1 0 1 0 8 9",
+        )
+        .unwrap()];
+        for entry in fs::read_dir("tests/svg").unwrap() {
+            let svg_data = fs::read_to_string(entry.unwrap().path()).unwrap();
+            for event in svg::read(&svg_data).unwrap() {
+                if let Ok(Event::Tag("path", _, attributes)) = event {
+                    paths.push(PathData::parse(&attributes["d"]).unwrap());
+                }
+            }
+        }
+
+        for data in &paths {
+            let expected = coords(&collected_path(data));
+            assert_eq!(expected, coords(&PathIter::new(data).collect::<Vec<_>>()));
+            assert_eq!(
+                expected,
+                coords(&SimpleSvgPath::from(data).into_iter().collect::<Vec<_>>())
+            );
+            for raw_command in data.iter() {
+                let last_command = Command(2., 3.);
+                assert_eq!(
+                    coords(&collected_raw_command(raw_command, last_command)),
+                    coords(
+                        &Command::from_raw_command(raw_command, last_command).collect::<Vec<_>>()
+                    )
+                );
+            }
+        }
+    }
+}
@@ -1,22 +1,54 @@
-use crate::svg_path_parser::SimpleSvgPath;
+use crate::geometry::{offset_polyline, ring_contains, ring_signed_area};
+use crate::numbers::parse_length;
+use crate::style;
+use crate::svg_path_parser::{PathData, PathIter, RawCommand};
+use crate::transform::CoordinateSystem;
+use regex::Regex;
+use std::collections::HashMap;
 use std::convert::TryFrom;
+use std::fmt;
 use svg::events::Event;
-use svg::node::element::path;
+use svg::node::Attributes;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ExtractError {
+    #[error("Error reading SVG: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Error parsing SVG: {0}")]
+    Svg(#[from] svg::parser::Error),
+    #[error("The path data of room `{room}` is invalid: {source}")]
+    InvalidPathData {
+        room: String,
+        source: svg::parser::Error,
+    },
+    #[error("The `{attribute}` attribute of room `{room}` is missing or invalid")]
+    InvalidAttribute {
+        room: String,
+        attribute: &'static str,
+    },
+    #[error("The room `{0}` appears more than once")]
+    DuplicateRoom(String),
+}
 
 #[derive(Debug)]
 pub enum SvgRoomShape {
     Rect {
-        width: f32,
-        height: f32,
-        x: f32,
-        y: f32,
+        width: f64,
+        height: f64,
+        x: f64,
+        y: f64,
+    },
+    Path(PathData),
+    /// Open path drawn along the middle of a room with a stroke as wide as the room, such as a
+    /// corridor
+    Centerline {
+        path: PathData,
+        width: f64,
     },
-    Path(path::Data),
 }
 
-fn transform_svg_coords(coords: (f32, f32), offsets: (f32, f32)) -> (f32, f32) {
-    (coords.0 - offsets.0, -coords.1 + offsets.1)
-}
+/// Outer ring of a room's outline, along with the rings of any holes in it
+pub type OutlineWithHoles = (Vec<(f64, f64)>, Vec<Vec<(f64, f64)>>);
 
 #[derive(Debug)]
 pub struct SvgRoom {
@@ -24,28 +56,83 @@ pub struct SvgRoom {
     shape: SvgRoomShape,
 }
 
+/// Finds the points of each subpath of `path_data` in map coordinates
+fn subpaths(
+    path_data: &PathData,
+    offsets: (f64, f64),
+    coordinate_system: CoordinateSystem,
+) -> Vec<Vec<(f64, f64)>> {
+    let mut subpaths: Vec<Vec<(f64, f64)>> = vec![];
+    let mut commands = PathIter::new(path_data);
+    while let Some(command) = commands.next() {
+        let coords = coordinate_system.svg_to_map((command.0, command.1), offsets);
+        match subpaths.last_mut() {
+            Some(subpath) if !commands.started_subpath() => subpath.push(coords),
+            _ => subpaths.push(vec![coords]),
+        }
+    }
+    subpaths
+}
+
+/// Splits the rings of a room's outline into its outer ring and any holes in it. The ring with the
+/// largest area is the outer ring, and rings inside of it are holes. Any other rings are appended
+/// to the outer ring, as they were before holes were supported.
+fn split_holes(mut rings: Vec<Vec<(f64, f64)>>) -> OutlineWithHoles {
+    let outer_index = rings
+        .iter()
+        .map(|ring| ring_signed_area(ring).abs())
+        .enumerate()
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(index, _)| index);
+    let mut outline = match outer_index {
+        Some(index) => rings.remove(index),
+        None => return (vec![], vec![]),
+    };
+
+    let (holes, others): (Vec<_>, Vec<_>) = rings
+        .into_iter()
+        .partition(|ring| ring.iter().all(|point| ring_contains(&outline, *point)));
+    outline.extend(others.into_iter().flatten());
+    (outline, holes)
+}
+
 impl SvgRoom {
-    pub fn outline(&self, offsets: (f32, f32)) -> Vec<(f32, f32)> {
+    /// Returns the outline of the room in map coordinates, along with the outlines of any holes
+    /// in it, such as courtyards or columns drawn as extra subpaths
+    pub fn outline(
+        &self,
+        offsets: (f64, f64),
+        coordinate_system: CoordinateSystem,
+    ) -> OutlineWithHoles {
         match &self.shape {
             SvgRoomShape::Rect {
                 x,
                 y,
                 width,
                 height,
-            } => vec![
-                (*x, *y),
-                (*x, y + height),
-                (x + width, y + height),
-                (x + width, *y),
-            ]
-            .into_iter()
-            .map(|coords| transform_svg_coords(coords, offsets))
-            .collect(),
-            SvgRoomShape::Path(path_data) => SimpleSvgPath::from(path_data)
+            } => {
+                let outline = vec![
+                    (*x, *y),
+                    (*x, y + height),
+                    (x + width, y + height),
+                    (x + width, *y),
+                ]
                 .into_iter()
-                // TODO: Integrate interfaces to avoid destructuring:   \/
-                .map(|coords| transform_svg_coords((coords.0, coords.1), offsets))
-                .collect(),
+                .map(|coords| coordinate_system.svg_to_map(coords, offsets))
+                .collect();
+                (outline, vec![])
+            }
+            SvgRoomShape::Path(path_data) => {
+                split_holes(subpaths(path_data, offsets, coordinate_system))
+            }
+            SvgRoomShape::Centerline { path, width } => {
+                let rings = subpaths(path, offsets, coordinate_system)
+                    .iter()
+                    .map(|subpath| offset_polyline(subpath, width / 2.))
+                    .filter(|ring| !ring.is_empty())
+                    .collect();
+                split_holes(rings)
+            }
         }
     }
 
@@ -54,51 +141,700 @@ impl SvgRoom {
     }
 }
 
-impl<'a> TryFrom<Event<'a>> for SvgRoom {
-    type Error = ();
+/// How room elements are recognized in floor images, and how their outlines are placed on the map
+#[derive(Debug, Clone)]
+pub struct SvgRoomConfig {
+    /// Prefixes of the IDs of room elements, which are followed by the room number. An ID that
+    /// starts with more than one of them uses the longest.
+    pub id_prefixes: Vec<String>,
+    /// Pattern of the IDs of room elements, tried before the prefixes, whose capture group named
+    /// `number` or else whose first capture group is the room number, such as `^f\d+room(.+)$`
+    /// for IDs like `f2room210`
+    pub id_pattern: Option<Regex>,
+    /// Also check the `inkscape:label` attribute of elements whose ID isn't a room's, since
+    /// Inkscape keeps generated IDs like `rect1234` when an element is given a label
+    pub use_inkscape_label: bool,
+    /// Convention used for the map coordinates of outlines
+    pub coordinate_system: CoordinateSystem,
+    /// Determines if the rest of an ID after a prefix, or the number captured by the pattern, is a
+    /// room number. Elements whose IDs start with a prefix or match the pattern without giving a
+    /// room number aren't rooms, and are reported by [`extract_rooms_with_report`].
+    pub valid_number: fn(&str) -> bool,
+}
 
-    fn try_from(event: Event<'a>) -> Result<Self, Self::Error> {
-        match event {
-            Event::Tag("rect", _, attr) => {
-                let number = attr
-                    .get("id")
-                    .ok_or(())?
-                    .strip_prefix("room")
-                    .ok_or(())?
-                    .to_owned();
-
-                let width = attr.get("width").ok_or(())?.parse().map_err(|_| ())?;
-                let height = attr.get("height").ok_or(())?.parse().map_err(|_| ())?;
-                let x = attr.get("x").ok_or(())?.parse().map_err(|_| ())?;
-                let y = attr.get("y").ok_or(())?.parse().map_err(|_| ())?;
-
-                Ok(Self {
-                    number,
-                    shape: SvgRoomShape::Rect {
-                        width,
-                        height,
-                        x,
-                        y,
-                    },
+impl Default for SvgRoomConfig {
+    fn default() -> Self {
+        Self {
+            id_prefixes: vec!["room".to_owned()],
+            id_pattern: None,
+            use_inkscape_label: true,
+            coordinate_system: CoordinateSystem::default(),
+            valid_number: is_room_number,
+        }
+    }
+}
+
+/// Determines if `number` is made up of letters, digits, dots, and dashes, with at least one
+/// letter or digit, so that IDs like `room1_backup` aren't mistaken for rooms while rooms named
+/// with letters, like `roomgym`, are still found
+pub fn is_room_number(number: &str) -> bool {
+    number
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == '.' || c == '-')
+        && number.chars().any(char::is_alphanumeric)
+}
+
+/// Stricter version of [`is_room_number`] that also requires a digit, so that IDs like `roomcopy`
+/// aren't rooms either
+pub fn is_numbered_room(number: &str) -> bool {
+    is_room_number(number) && number.chars().any(|c| c.is_ascii_digit())
+}
+
+/// Prefix or pattern of [`SvgRoomConfig`] that an ID matched
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IdMatch {
+    Prefix(String),
+    Pattern(String),
+}
+
+impl fmt::Display for IdMatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Prefix(prefix) => write!(f, "prefix `{}`", prefix),
+            Self::Pattern(pattern) => write!(f, "pattern `{}`", pattern),
+        }
+    }
+}
+
+/// ID or label of an element that gives different room numbers for the pattern and the longest
+/// prefix it starts with, such as `f2room210` with the pattern `^f2room(.+)$` and the prefix `f2`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AmbiguousId {
+    pub id: String,
+    /// What matched and the room number it gives, in the order they were tried. The room number
+    /// of the first is used.
+    pub matches: Vec<(IdMatch, String)>,
+}
+
+impl fmt::Display for AmbiguousId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let matches: Vec<_> = self
+            .matches
+            .iter()
+            .map(|(id_match, number)| format!("{} as room {}", id_match, number))
+            .collect();
+        write!(f, "{:?} matches {}", self.id, matches.join(" and "))?;
+        if let Some((id_match, number)) = self.matches.first() {
+            write!(f, "; using room {} from the {}", number, id_match)?;
+        }
+        Ok(())
+    }
+}
+
+/// Element whose ID and `inkscape:label` give different room numbers, where the ID's is used
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LabelConflict {
+    pub id: String,
+    pub label: String,
+    /// Room numbers given by the ID and the label
+    pub numbers: (String, String),
+}
+
+impl fmt::Display for LabelConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "ID {:?} is room {} but label {:?} is room {}; using the ID",
+            self.id, self.numbers.0, self.label, self.numbers.1
+        )
+    }
+}
+
+/// Room number of an element, along with the ID or label it came from if it was ambiguous, and
+/// its ID and label if they disagree
+struct RoomNumber {
+    number: String,
+    ambiguous: Option<AmbiguousId>,
+    label_conflict: Option<LabelConflict>,
+}
+
+impl SvgRoomConfig {
+    /// Finds the room numbers that `value` gives for the pattern and for the longest prefix it
+    /// starts with, in that order, leaving out the prefix if it gives the same number as the
+    /// pattern. Shorter prefixes are less specific, so `room101` is room `101` with the prefixes
+    /// `r` and `room`. Also returns whether the pattern or prefix matched without giving a valid
+    /// room number.
+    fn id_matches(&self, value: &str) -> (Vec<(IdMatch, String)>, bool) {
+        let from_pattern = self.id_pattern.iter().filter_map(|pattern| {
+            let captures = pattern.captures(value)?;
+            let number = captures.name("number").or_else(|| captures.get(1))?;
+            Some((IdMatch::Pattern(pattern.to_string()), number.as_str()))
+        });
+        let from_prefixes = self
+            .id_prefixes
+            .iter()
+            .filter(|prefix| value.starts_with(prefix.as_str()))
+            .max_by_key(|prefix| prefix.len())
+            .map(|prefix| (IdMatch::Prefix(prefix.clone()), &value[prefix.len()..]));
+
+        let mut matches: Vec<(IdMatch, String)> = vec![];
+        let mut invalid = false;
+        for (id_match, number) in from_pattern.chain(from_prefixes) {
+            if !(self.valid_number)(number) {
+                invalid = true;
+            } else if matches.iter().all(|(_, seen)| seen != number) {
+                matches.push((id_match, number.to_owned()));
+            }
+        }
+        (matches, invalid)
+    }
+
+    /// Finds the room number of an element from its attributes, or `Ok(None)` if it isn't a room.
+    /// If its ID or label matches a prefix or the pattern without giving a valid room number, that
+    /// ID or label is the error.
+    fn room_number(&self, attributes: &Attributes) -> Result<Option<RoomNumber>, String> {
+        let mut invalid = None;
+        let mut number = |key| {
+            let value = attributes.get(key)?;
+            let (matches, has_invalid) = self.id_matches(value);
+            if matches.is_empty() {
+                if has_invalid {
+                    invalid.get_or_insert_with(|| value.to_string());
+                }
+                return None;
+            }
+            let number = matches[0].1.clone();
+            let ambiguous = (matches.len() > 1).then(|| AmbiguousId {
+                id: value.to_string(),
+                matches,
+            });
+            Some(RoomNumber {
+                number,
+                ambiguous,
+                label_conflict: None,
+            })
+        };
+        let from_id = number("id");
+        let from_label = if self.use_inkscape_label {
+            number("inkscape:label")
+        } else {
+            None
+        };
+
+        let number = match (from_id, from_label) {
+            (Some(from_id), Some(from_label)) if from_id.number != from_label.number => {
+                let label_conflict = LabelConflict {
+                    id: attributes["id"].to_string(),
+                    label: attributes["inkscape:label"].to_string(),
+                    numbers: (from_id.number.clone(), from_label.number),
+                };
+                Some(RoomNumber {
+                    label_conflict: Some(label_conflict),
+                    ..from_id
                 })
             }
-            Event::Tag("path", _, attr) => {
-                let number = attr
-                    .get("id")
-                    .ok_or(())?
-                    .strip_prefix("room")
-                    .ok_or(())?
-                    .to_owned();
-
-                let d = attr.get("d").ok_or(())?;
-                let path_data = path::Data::parse(d).map_err(|_| ())?;
-
-                Ok(Self {
-                    number,
-                    shape: SvgRoomShape::Path(path_data),
+            (Some(number), _) | (None, Some(number)) => Some(number),
+            (None, None) => None,
+        };
+        match (number, invalid) {
+            (None, Some(invalid)) => Err(invalid),
+            (number, _) => Ok(number),
+        }
+    }
+}
+
+/// What an SVG event is when looking for rooms
+enum RoomEvent {
+    /// A room, along with its ID or label if that was ambiguous, and its ID and label if they
+    /// disagree
+    Room(SvgRoom, Option<AmbiguousId>, Option<LabelConflict>),
+    /// An element with an ID or label that starts with a room prefix or matches the room
+    /// pattern, but doesn't give a valid room number
+    InvalidId(String),
+    Other,
+}
+
+impl SvgRoom {
+    /// Reads a room from an SVG event, recognizing rooms as described by `config`. Returns
+    /// `Ok(None)` if the event isn't for a room element.
+    pub fn from_event(event: Event, config: &SvgRoomConfig) -> Result<Option<Self>, ExtractError> {
+        Ok(match Self::read_event(event, config)? {
+            RoomEvent::Room(room, _ambiguous, _label_conflict) => Some(room),
+            RoomEvent::InvalidId(_) | RoomEvent::Other => None,
+        })
+    }
+
+    fn read_event(event: Event, config: &SvgRoomConfig) -> Result<RoomEvent, ExtractError> {
+        let (name, attr) = match event {
+            Event::Tag(name @ ("rect" | "path"), _, attr) => (name, attr),
+            _ => return Ok(RoomEvent::Other),
+        };
+        let RoomNumber {
+            number,
+            ambiguous,
+            label_conflict,
+        } = match config.room_number(&attr) {
+            Ok(Some(number)) => number,
+            Ok(None) => return Ok(RoomEvent::Other),
+            Err(id) => return Ok(RoomEvent::InvalidId(id)),
+        };
+
+        let shape = if name == "rect" {
+            // A missing attribute is `default`, like `x` and `y`, which are 0 in SVG
+            let number_attr = |attribute, default| {
+                match attr.get(attribute) {
+                    Some(value) => value.parse().ok(),
+                    None => default,
+                }
+                .ok_or_else(|| ExtractError::InvalidAttribute {
+                    room: number.clone(),
+                    attribute,
                 })
+            };
+            SvgRoomShape::Rect {
+                width: number_attr("width", None)?,
+                height: number_attr("height", None)?,
+                x: number_attr("x", Some(0.))?,
+                y: number_attr("y", Some(0.))?,
+            }
+        } else {
+            let d = attr
+                .get("d")
+                .ok_or_else(|| ExtractError::InvalidAttribute {
+                    room: number.clone(),
+                    attribute: "d",
+                })?;
+            let path_data = PathData::parse(d).map_err(|source| ExtractError::InvalidPathData {
+                room: number.clone(),
+                source,
+            })?;
+            // A path that is never closed, with a `stroke-width`, is the centerline of a
+            // corridor rather than an outline
+            let closed = path_data
+                .iter()
+                .any(|command| matches!(command, RawCommand::Close));
+            let width = if closed {
+                None
+            } else {
+                stroke_width(&attr).map_err(|()| ExtractError::InvalidAttribute {
+                    room: number.clone(),
+                    attribute: "stroke-width",
+                })?
+            };
+            match width.filter(|width| *width > 0.) {
+                Some(width) => SvgRoomShape::Centerline {
+                    path: path_data,
+                    width,
+                },
+                None => SvgRoomShape::Path(path_data),
             }
+        };
+
+        Ok(RoomEvent::Room(
+            Self { number, shape },
+            ambiguous,
+            label_conflict,
+        ))
+    }
+}
+
+/// Reads the stroke width of an element in user units from its `style` attribute, or from its
+/// `stroke-width` attribute if its style doesn't set one, like
+/// [`SvgElement::scale_stroke_widths`](crate::svg_parser::SvgElement::scale_stroke_widths).
+/// Widths in units other than `px` can't be read.
+fn stroke_width(attr: &Attributes) -> Result<Option<f64>, ()> {
+    let from_style = attr.get("style").and_then(|style| {
+        style::declarations(style)
+            .filter(|(property, _value)| property == "stroke-width")
+            .map(|(_property, value)| value.to_owned())
+            .last()
+    });
+    let width = match from_style {
+        Some(width) => width,
+        None => match attr.get("stroke-width") {
+            Some(width) => width.to_string(),
+            None => return Ok(None),
+        },
+    };
+    match parse_length(&width) {
+        Ok((width, "" | "px")) => Ok(Some(width)),
+        _ => Err(()),
+    }
+}
+
+impl<'a> TryFrom<Event<'a>> for SvgRoom {
+    type Error = ();
+
+    fn try_from(event: Event<'a>) -> Result<Self, Self::Error> {
+        match Self::from_event(event, &SvgRoomConfig::default()) {
+            Ok(Some(room)) => Ok(room),
             _ => Err(()),
         }
     }
 }
+
+/// Rooms found in an SVG by [`extract_rooms_with_report`]
+#[derive(Debug, Default)]
+pub struct ExtractedRooms {
+    /// Outline of every room along with the outlines of any holes in it, keyed by room number
+    pub rooms: HashMap<String, OutlineWithHoles>,
+    /// IDs or labels of elements that start with a room prefix or match the room pattern, but
+    /// don't give a valid room number, in the order they appear
+    pub invalid_ids: Vec<String>,
+    /// IDs or labels of rooms that give different room numbers for more than one of the prefixes
+    /// and pattern, in the order they appear
+    pub ambiguous_ids: Vec<AmbiguousId>,
+    /// Rooms whose IDs and labels give different room numbers, in the order they appear
+    pub label_conflicts: Vec<LabelConflict>,
+    /// Bounds of the image in map coordinates as `(min x, min y, max x, max y)`, from the viewport
+    /// of its root `svg` element widened to fit every room, or `None` if it has neither. Other
+    /// elements and transforms aren't read, unlike the bounding box of a parsed
+    /// [`SvgElement`](crate::svg_parser::SvgElement).
+    pub bounds: Option<(f64, f64, f64, f64)>,
+}
+
+/// Reads the corners of the viewport of an `svg` element in SVG coordinates. Lengths that aren't in
+/// user units are taken to be 0.
+fn viewport(attr: &Attributes) -> [(f64, f64); 2] {
+    let length = |name| match attr.get(name).map(|value| parse_length(value)) {
+        Some(Ok((length, "" | "px"))) => length,
+        _ => 0.,
+    };
+    let (x, y) = (length("x"), length("y"));
+    [(x, y), (x + length("width"), y + length("height"))]
+}
+
+/// Widens `bounds` to fit `points`
+fn fit_bounds(
+    bounds: Option<(f64, f64, f64, f64)>,
+    points: &[(f64, f64)],
+) -> Option<(f64, f64, f64, f64)> {
+    points.iter().fold(bounds, |bounds, &(x, y)| {
+        Some(match bounds {
+            Some((min_x, min_y, max_x, max_y)) => {
+                (min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y))
+            }
+            None => (x, y, x, y),
+        })
+    })
+}
+
+/// Same as [`extract_rooms_with_holes`], but also reports the elements that look like rooms
+/// without having valid room numbers, such as `room1_backup`, and the rooms whose IDs or labels
+/// are ambiguous or disagree
+pub fn extract_rooms_with_report(
+    svg_content: &str,
+    offsets: (f64, f64),
+    config: &SvgRoomConfig,
+) -> Result<ExtractedRooms, ExtractError> {
+    let mut extracted = ExtractedRooms::default();
+    let mut found_root = false;
+    for event in svg::read(svg_content)? {
+        let event = event?;
+        if let Event::Tag("svg", _, attr) = &event {
+            if !found_root {
+                found_root = true;
+                let corners = viewport(attr)
+                    .map(|corner| config.coordinate_system.svg_to_map(corner, offsets));
+                extracted.bounds = fit_bounds(extracted.bounds, &corners);
+            }
+        }
+        match SvgRoom::read_event(event, config)? {
+            RoomEvent::Room(room, ambiguous, label_conflict) => {
+                extracted.ambiguous_ids.extend(ambiguous);
+                extracted.label_conflicts.extend(label_conflict);
+                let outline = room.outline(offsets, config.coordinate_system);
+                extracted.bounds = fit_bounds(extracted.bounds, &outline.0);
+                if extracted
+                    .rooms
+                    .insert(room.number.clone(), outline)
+                    .is_some()
+                {
+                    return Err(ExtractError::DuplicateRoom(room.number));
+                }
+            }
+            RoomEvent::InvalidId(id) => extracted.invalid_ids.push(id),
+            RoomEvent::Other => {}
+        }
+    }
+    Ok(extracted)
+}
+
+/// Finds the outline of every room in an SVG, along with the outlines of any holes in them, in map
+/// coordinates. See [`extract_rooms`].
+pub fn extract_rooms_with_holes(
+    svg_content: &str,
+    offsets: (f64, f64),
+    config: &SvgRoomConfig,
+) -> Result<HashMap<String, OutlineWithHoles>, ExtractError> {
+    extract_rooms_with_report(svg_content, offsets, config).map(|extracted| extracted.rooms)
+}
+
+/// Finds the outline of every room in an SVG, keyed by room number. Outlines are in map
+/// coordinates found from `offsets` using the coordinate system in `config`. Rooms are recognized
+/// as described by `config`, and a room element without a valid shape is an error rather than
+/// being skipped.
+pub fn extract_rooms(
+    svg_content: &str,
+    offsets: (f64, f64),
+    config: &SvgRoomConfig,
+) -> Result<HashMap<String, Vec<(f64, f64)>>, ExtractError> {
+    let rooms = extract_rooms_with_holes(svg_content, offsets, config)?;
+    Ok(rooms
+        .into_iter()
+        .map(|(number, (outline, _holes))| (number, outline))
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::*;
+    use crate::svg_path_parser::SimpleSvgPath;
+
+    fn room_numbers(config: &SvgRoomConfig) -> Vec<String> {
+        let svg_data = fs::read_to_string("tests/svg/labels.svg").unwrap();
+        svg::read(&svg_data)
+            .unwrap()
+            .filter_map(|event| SvgRoom::from_event(event.unwrap(), config).unwrap())
+            .map(|room| room.number)
+            .collect()
+    }
+
+    #[test]
+    fn room_numbers_from_labels() {
+        let config = SvgRoomConfig::default();
+        // The ID wins over a conflicting label
+        assert_eq!(vec!["201", "213", "214", "215"], room_numbers(&config));
+        let svg_data = fs::read_to_string("tests/svg/labels.svg").unwrap();
+        let extracted = extract_rooms_with_report(&svg_data, (0., 0.), &config).unwrap();
+        assert_eq!(
+            vec![LabelConflict {
+                id: "room215".to_owned(),
+                label: "room216".to_owned(),
+                numbers: ("215".to_owned(), "216".to_owned()),
+            }],
+            extracted.label_conflicts
+        );
+        assert_eq!(
+            r#"ID "room215" is room 215 but label "room216" is room 216; using the ID"#,
+            extracted.label_conflicts[0].to_string()
+        );
+
+        let config = SvgRoomConfig {
+            use_inkscape_label: false,
+            ..SvgRoomConfig::default()
+        };
+        assert_eq!(vec!["201", "215"], room_numbers(&config));
+        let extracted = extract_rooms_with_report(&svg_data, (0., 0.), &config).unwrap();
+        assert!(extracted.label_conflicts.is_empty());
+
+        let config = SvgRoomConfig {
+            id_prefixes: vec!["rm_".to_owned()],
+            ..SvgRoomConfig::default()
+        };
+        assert_eq!(vec!["301", "302"], room_numbers(&config));
+    }
+
+    #[test]
+    fn image_bounds() {
+        let svg_data = r#"<svg width="100" height="50px">
+            <rect id="room1" x="90" y="10" width="20" height="10" />
+        </svg>"#;
+        let config = SvgRoomConfig {
+            coordinate_system: CoordinateSystem::SvgNative,
+            ..SvgRoomConfig::default()
+        };
+        let extracted = extract_rooms_with_report(svg_data, (0., 0.), &config).unwrap();
+        assert_eq!(Some((0., 0., 110., 50.)), extracted.bounds);
+
+        let extracted =
+            extract_rooms_with_report("<svg></svg>", (0., 0.), &SvgRoomConfig::default()).unwrap();
+        assert_eq!(Some((0., 0., 0., 0.)), extracted.bounds);
+    }
+
+    #[test]
+    fn reject_invalid_room_numbers() {
+        let svg_data = r#"<svg>
+            <rect id="room1" x="0" y="0" width="10" height="10" />
+            <rect id="room10" x="10" y="0" width="10" height="10" />
+            <rect id="room1_backup" x="0" y="0" width="10" height="10" />
+            <rect id="roomcopy" x="0" y="0" width="10" height="10" />
+            <path id="room 12" d="M 0 0 L 10 0 L 10 10 Z" />
+            <rect id="rect5" inkscape:label="room1.5-A" x="0" y="20" width="10" height="10" />
+        </svg>"#;
+        let extracted =
+            extract_rooms_with_report(svg_data, (0., 0.), &SvgRoomConfig::default()).unwrap();
+        let mut numbers: Vec<_> = extracted.rooms.keys().map(String::as_str).collect();
+        numbers.sort_unstable();
+        assert_eq!(vec!["1", "1.5-A", "10", "copy"], numbers);
+        assert_eq!(vec!["room1_backup", "room 12"], extracted.invalid_ids);
+
+        let config = SvgRoomConfig {
+            valid_number: is_numbered_room,
+            ..SvgRoomConfig::default()
+        };
+        let extracted = extract_rooms_with_report(svg_data, (0., 0.), &config).unwrap();
+        let mut numbers: Vec<_> = extracted.rooms.keys().map(String::as_str).collect();
+        numbers.sort_unstable();
+        assert_eq!(vec!["1", "1.5-A", "10"], numbers);
+        assert_eq!(
+            vec!["room1_backup", "roomcopy", "room 12"],
+            extracted.invalid_ids
+        );
+
+        let config = SvgRoomConfig {
+            valid_number: |number| !number.is_empty() && !number.contains(' '),
+            ..SvgRoomConfig::default()
+        };
+        let extracted = extract_rooms_with_report(svg_data, (0., 0.), &config).unwrap();
+        assert_eq!(5, extracted.rooms.len());
+        assert!(extracted.rooms.contains_key("1_backup"));
+        assert_eq!(vec!["room 12"], extracted.invalid_ids);
+    }
+
+    #[test]
+    fn extract_room_outlines() {
+        let svg_data = fs::read_to_string("tests/svg/rooms.svg").unwrap();
+        let rooms = extract_rooms(&svg_data, (0., 100.), &SvgRoomConfig::default()).unwrap();
+        assert_eq!(2, rooms.len());
+        assert_eq!(
+            vec![(10., 90.), (10., 70.), (40., 70.), (40., 90.)],
+            rooms["106"]
+        );
+        assert_eq!(4, rooms["107"].len());
+    }
+
+    #[test]
+    fn streamed_outlines_match_subpaths() {
+        let config = SvgRoomConfig {
+            coordinate_system: CoordinateSystem::SvgNative,
+            ..SvgRoomConfig::default()
+        };
+        for file in ["tests/svg/courtyard.svg", "tests/svg/rooms.svg"] {
+            let svg_data = fs::read_to_string(file).unwrap();
+            for event in svg::read(&svg_data).unwrap() {
+                let room = match SvgRoom::from_event(event.unwrap(), &config).unwrap() {
+                    Some(room) => room,
+                    None => continue,
+                };
+                if let SvgRoomShape::Path(data) = &room.shape {
+                    let rings = SimpleSvgPath::from(data)
+                        .subpaths()
+                        .into_iter()
+                        .map(|subpath| subpath.iter().map(|c| (c.0, c.1)).collect())
+                        .collect();
+                    assert_eq!(
+                        split_holes(rings),
+                        room.outline((0., 0.), CoordinateSystem::SvgNative)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn corridor_outlines_from_centerlines() {
+        let svg_data = fs::read_to_string("tests/svg/corridors.svg").unwrap();
+        let rooms =
+            extract_rooms_with_holes(&svg_data, (0., 100.), &SvgRoomConfig::default()).unwrap();
+        let area = |number: &str| ring_signed_area(&rooms[number].0).abs();
+
+        // Straight, bent, and zigzag corridors have the area of their centerlines times their
+        // widths
+        for (number, length, width) in [
+            ("c1", 80., 8.),
+            ("c2", 50. + 30., 6.),
+            ("c3", 20. + 20_f64.hypot(20.) + 20., 4.),
+            ("c4", 40., 2.),
+        ] {
+            let expected = length * width;
+            assert!(
+                (area(number) - expected).abs() < expected * 0.01,
+                "Room {} has an area of {}, not {}",
+                number,
+                area(number),
+                expected
+            );
+        }
+        assert_eq!(
+            vec![(10., 54.), (90., 54.), (90., 46.), (10., 46.)],
+            rooms["c1"].0
+        );
+
+        // Closed paths and paths without a stroke width are still outlines
+        assert_eq!(200., area("106"));
+        assert_eq!(4, rooms["107"].0.len());
+        assert_eq!(100., area("107"));
+    }
+
+    #[test]
+    fn svg_native_outlines() {
+        let svg_data = fs::read_to_string("tests/svg/rooms.svg").unwrap();
+        let config = SvgRoomConfig {
+            coordinate_system: CoordinateSystem::SvgNative,
+            ..SvgRoomConfig::default()
+        };
+        // Offsets are ignored, and the y-axis still points down
+        let rooms = extract_rooms(&svg_data, (0., 100.), &config).unwrap();
+        assert_eq!(
+            vec![(10., 10.), (10., 30.), (40., 30.), (40., 10.)],
+            rooms["106"]
+        );
+    }
+
+    #[test]
+    fn rect_position_defaults_to_origin() {
+        let svg_data = r#"<svg><rect id="room1" width="10" height="20" /><rect id="room2" x="5" width="10" height="20" /></svg>"#;
+        let config = SvgRoomConfig {
+            coordinate_system: CoordinateSystem::SvgNative,
+            ..SvgRoomConfig::default()
+        };
+        let rooms = extract_rooms(svg_data, (0., 0.), &config).unwrap();
+        assert_eq!(vec![(0., 0.), (0., 20.), (10., 20.), (10., 0.)], rooms["1"]);
+        assert_eq!(vec![(5., 0.), (5., 20.), (15., 20.), (15., 0.)], rooms["2"]);
+    }
+
+    #[test]
+    fn reject_invalid_rooms() {
+        let config = SvgRoomConfig::default();
+        let extract = |element: &str| {
+            let svg_data = format!("<svg>{}</svg>", element);
+            extract_rooms(&svg_data, (0., 0.), &config)
+        };
+
+        assert!(matches!(
+            extract(r#"<path id="room1" d="M 0 0 L 10 x" />"#),
+            Err(ExtractError::InvalidPathData { room, .. }) if room == "1"
+        ));
+        assert!(matches!(
+            extract(r#"<rect id="room2" x="0" y="0" width="wide" height="10" />"#),
+            Err(ExtractError::InvalidAttribute { room, attribute: "width" }) if room == "2"
+        ));
+        assert!(matches!(
+            extract(r#"<path id="room4" d="M 0 0 L 10 0" style="stroke-width:thick" />"#),
+            Err(ExtractError::InvalidAttribute { room, attribute: "stroke-width" }) if room == "4"
+        ));
+        assert!(matches!(
+            extract(r#"<rect id="room3" x="0" y="0" width="10" />"#),
+            Err(ExtractError::InvalidAttribute {
+                attribute: "height",
+                ..
+            })
+        ));
+        let svg_data = fs::read_to_string("tests/svg/duplicate_room.svg").unwrap();
+        assert!(matches!(
+            extract_rooms(&svg_data, (0., 0.), &config),
+            Err(ExtractError::DuplicateRoom(room)) if room == "106"
+        ));
+        assert!(matches!(
+            extract(r#"<rect id="room5" x="left" width="10" height="10" />"#),
+            Err(ExtractError::InvalidAttribute { room, attribute: "x" }) if room == "5"
+        ));
+        // Elements that aren't rooms are never errors
+        assert!(extract(r#"<rect id="wall" width="wide" />"#)
+            .unwrap()
+            .is_empty());
+    }
+}
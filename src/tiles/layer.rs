@@ -0,0 +1,533 @@
+use std::borrow::Cow;
+use std::str::FromStr;
+
+use anyhow::anyhow;
+
+use crate::bounding_box::{BoundingBox, BoundingSquare};
+use crate::svg_parser::{
+    is_editor_metadata, ParseOptions, ParseReport, SvgElement, SvgElementIndex, SvgParseError,
+};
+use crate::tiles::{Tile, TileCoords};
+use crate::trace::span;
+
+/// Margin added around a tile when selecting the elements it includes, so that elements just
+/// outside the tile whose strokes reach into it aren't cut off. The tile's viewBox is unaffected.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Overlap {
+    /// Margin in SVG units
+    Units(f64),
+    /// Margin as a fraction of the tile's edge length
+    Fraction(f64),
+}
+
+impl Overlap {
+    fn margin(&self, edge_length: f64) -> f64 {
+        match self {
+            Overlap::Units(units) => *units,
+            Overlap::Fraction(fraction) => fraction * edge_length,
+        }
+    }
+}
+
+impl Default for Overlap {
+    fn default() -> Self {
+        Overlap::Units(0.)
+    }
+}
+
+impl FromStr for Overlap {
+    type Err = anyhow::Error;
+
+    /// Parses SVG units such as `2.5`, or a percentage of the tile size such as `5%`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let overlap = match s.trim().strip_suffix('%') {
+            Some(percentage) => Overlap::Fraction(percentage.trim().parse::<f64>()? / 100.),
+            None => Overlap::Units(s.trim().parse()?),
+        };
+        match overlap {
+            Overlap::Units(margin) | Overlap::Fraction(margin) if margin >= 0. => Ok(overlap),
+            _ => Err(anyhow!("Overlap must not be negative: {}", s)),
+        }
+    }
+}
+
+/// How the strokes of elements are rewritten in tiles, since stroke widths set in absolute units
+/// look wrong once tiles are scaled by different amounts at different zoom levels
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct StrokeOptions {
+    /// Stroke widths in tiles at zoom level `z` are multiplied by `scale` to the power of `z`, so
+    /// a scale of 0.5 keeps strokes the same width on screen when each zoom level is shown at
+    /// twice the size of the one before
+    pub scale: Option<f64>,
+    /// Sets `vector-effect="non-scaling-stroke"` on every shape, so strokes are drawn the same
+    /// width however much tiles are scaled
+    pub non_scaling: bool,
+}
+
+/// Where the elements of each tile come from
+#[derive(Debug)]
+enum Source<'a> {
+    /// The whole image, parsed once and indexed
+    Parsed {
+        root_element: SvgElement<'a>,
+        index: SvgElementIndex,
+    },
+    /// The SVG data, parsed again for each tile, keeping only the elements in that tile
+    Streamed {
+        svg_data: Cow<'a, str>,
+        options: ParseOptions,
+        strip_metadata: bool,
+    },
+}
+
+/// An SVG image split into square tiles, where the zoom level 0 tile covers `bounds` and each tile
+/// is split into four at the next zoom level
+#[derive(Debug)]
+pub struct Layer<'a> {
+    source: Source<'a>,
+    bounds: BoundingSquare,
+    overlap: Overlap,
+    /// Elements without children are left out of a tile if their width and height are both
+    /// smaller than this fraction of the tile's edge length
+    min_feature_size: Option<f64>,
+    /// Digits after the decimal point that numbers in geometric attributes of tiles are rounded
+    /// to when written
+    precision: Option<usize>,
+    strokes: StrokeOptions,
+    report: ParseReport,
+}
+
+impl<'a> Layer<'a> {
+    pub fn new(
+        svg_data: &'a str,
+        bounds: BoundingSquare,
+        options: &ParseOptions,
+    ) -> Result<Self, SvgParseError> {
+        let (root_element, report) = SvgElement::from_svg_data_with_report(svg_data, options)?;
+        let index = root_element.build_index();
+        Ok(Self {
+            source: Source::Parsed {
+                root_element,
+                index,
+            },
+            bounds,
+            overlap: Overlap::default(),
+            min_feature_size: None,
+            precision: None,
+            strokes: StrokeOptions::default(),
+            report,
+        })
+    }
+
+    /// Same as [`Self::new`], but only the SVG data is kept rather than the parsed image, which
+    /// takes several times as much memory. The SVG data is checked for errors up front, and then
+    /// parsed again for each tile, keeping only the elements in that tile and the ones drawn by
+    /// `use` elements, so memory use while parsing grows with the size of the tiles rather than
+    /// the image. This is much slower for many tiles; see [`SvgElement::select_from_svg_data`].
+    pub fn new_low_memory(
+        svg_data: &'a str,
+        bounds: BoundingSquare,
+        options: &ParseOptions,
+    ) -> Result<Self, SvgParseError> {
+        let (_root_element, report) = SvgElement::select_from_svg_data(
+            svg_data,
+            options,
+            &bounds.as_bounding_box(),
+            &|_element| false,
+        )?;
+        Ok(Self {
+            source: Source::Streamed {
+                svg_data: Cow::Borrowed(svg_data),
+                options: options.clone(),
+                strip_metadata: false,
+            },
+            bounds,
+            overlap: Overlap::default(),
+            min_feature_size: None,
+            precision: None,
+            strokes: StrokeOptions::default(),
+            report,
+        })
+    }
+
+    /// Converts this layer into one that no longer borrows from the SVG data it was created from,
+    /// so that it can outlive that data, such as to be shared with a [`LayerService`]
+    ///
+    /// [`LayerService`]: crate::tiles::LayerService
+    pub fn into_owned(self) -> Layer<'static> {
+        let source = match self.source {
+            Source::Parsed {
+                root_element,
+                index,
+            } => Source::Parsed {
+                root_element: root_element.into_owned(),
+                index,
+            },
+            Source::Streamed {
+                svg_data,
+                options,
+                strip_metadata,
+            } => Source::Streamed {
+                svg_data: Cow::Owned(svg_data.into_owned()),
+                options,
+                strip_metadata,
+            },
+        };
+        Layer {
+            source,
+            bounds: self.bounds,
+            overlap: self.overlap,
+            min_feature_size: self.min_feature_size,
+            precision: self.precision,
+            strokes: self.strokes,
+            report: self.report,
+        }
+    }
+
+    pub fn with_overlap(self, overlap: Overlap) -> Self {
+        Self { overlap, ..self }
+    }
+
+    /// Leaves elements without children out of tiles if their width and height are both smaller
+    /// than `fraction` of the tile's edge length, so that details too small to see, like door
+    /// swings, don't bloat low zoom level tiles
+    pub fn with_min_feature_size(self, fraction: Option<f64>) -> Self {
+        Self {
+            min_feature_size: fraction,
+            ..self
+        }
+    }
+
+    /// Rounds numbers in geometric attributes of tiles, such as path data and viewBoxes, to
+    /// `decimals` digits after the decimal point when they are written
+    pub fn with_precision(self, decimals: Option<usize>) -> Self {
+        Self {
+            precision: decimals,
+            ..self
+        }
+    }
+
+    /// Rewrites the strokes of the elements in every tile as given by `strokes`
+    pub fn with_strokes(self, strokes: StrokeOptions) -> Self {
+        Self { strokes, ..self }
+    }
+
+    /// Strips attributes only used by editors, such as `inkscape:*`, `sodipodi:*`, and `data-*`,
+    /// from every tile if `strip` is `true`
+    pub fn with_stripped_metadata(mut self, strip: bool) -> Self {
+        match &mut self.source {
+            Source::Parsed { root_element, .. } => {
+                if strip {
+                    root_element.strip_attributes(&is_editor_metadata);
+                }
+            }
+            Source::Streamed { strip_metadata, .. } => *strip_metadata = strip,
+        }
+        self
+    }
+
+    pub fn report(&self) -> &ParseReport {
+        &self.report
+    }
+
+    /// Returns the area of the SVG covered by the zoom level 0 tile
+    pub fn bounds(&self) -> &BoundingSquare {
+        &self.bounds
+    }
+
+    /// Returns the area of the SVG covered by the tile at `coords`
+    pub fn tile_bounds(&self, coords: &TileCoords) -> BoundingBox {
+        self.bounds.subdivide(coords)
+    }
+
+    /// Writes the tile at each of `coords` as a standalone SVG document, such as the tiles found
+    /// by [`tiles_covering`](crate::tiles::tiles_covering)
+    pub fn render_tiles(&self, coords: &[TileCoords]) -> Vec<(TileCoords, String)> {
+        coords
+            .iter()
+            .map(|coords| (coords.clone(), self.tile(coords).to_document()))
+            .collect()
+    }
+
+    pub fn tile(&self, coords: &TileCoords) -> Tile<'_> {
+        span!(
+            "select_tile",
+            zoom = coords.zoom,
+            x = coords.location[0],
+            y = coords.location[1]
+        );
+        let bounds = self.bounds.subdivide(coords);
+        let edge_length = self.bounds.tile_edge_length(coords.zoom);
+        let view_box = bounds.as_view_box();
+        let selection_bounds = bounds.expand(self.overlap.margin(edge_length));
+        let min_size = self
+            .min_feature_size
+            .map_or(0., |fraction| fraction * edge_length);
+        let keep = |element: &SvgElement| {
+            let size = element.get_bounding_box().get_size();
+            element.has_children() || size[0].max(size[1]) >= min_size
+        };
+        let selected = match &self.source {
+            Source::Parsed {
+                root_element,
+                index,
+            } => root_element.select_with_index_and_filter(index, &selection_bounds, &keep),
+            Source::Streamed {
+                svg_data,
+                options,
+                strip_metadata,
+            } => {
+                let (selected, _report) =
+                    SvgElement::select_from_svg_data(svg_data, options, &selection_bounds, &keep)
+                        .expect("The SVG data was checked when the layer was created");
+                selected.map(|mut selected| {
+                    if *strip_metadata {
+                        selected.strip_attributes(&is_editor_metadata);
+                    }
+                    selected
+                })
+            }
+        };
+        let mut svg = selected.unwrap_or_else(|| SvgElement::empty_root(bounds));
+        if let Some(scale) = self.strokes.scale {
+            svg.scale_stroke_widths(scale.powi(coords.zoom as i32));
+        }
+        if self.strokes.non_scaling {
+            svg.set_non_scaling_strokes();
+        }
+        svg.set_attr("viewBox", view_box.into());
+        svg.delete_attr("height");
+        svg.delete_attr("width");
+        Tile::new(svg).with_precision(self.precision)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use nalgebra::Vector2;
+
+    use super::*;
+    use crate::tiles::MAX_ZOOM;
+
+    fn tile_ids(layer: &Layer, coords: &TileCoords) -> Vec<&'static str> {
+        let tile = layer.tile(coords).as_element().to_string();
+        ["left", "right"]
+            .into_iter()
+            .filter(|id| tile.contains(&format!(r#"id="{}""#, id)))
+            .collect()
+    }
+
+    #[test]
+    fn overlap_includes_elements_near_tile_edges() {
+        // The first rect ends 1 unit before the boundary between the two tiles at zoom 1, and
+        // the second starts 2 units after it
+        let svg_data = r#"<svg width="100" height="100">
+            <rect id="left" x="10" y="10" width="39" height="10" />
+            <rect id="right" x="52" y="10" width="10" height="10" />
+        </svg>"#;
+        let bounds = BoundingSquare::new(Vector2::new(0., 0.), 100.);
+        let left = TileCoords::new(Vector2::new(0, 0), 1);
+        let right = TileCoords::new(Vector2::new(1, 0), 1);
+
+        let layer = Layer::new(svg_data, bounds.clone(), &ParseOptions::default()).unwrap();
+        assert_eq!(vec!["left"], tile_ids(&layer, &left));
+        assert_eq!(vec!["right"], tile_ids(&layer, &right));
+
+        let layer = layer.with_overlap(Overlap::Units(1.5));
+        assert_eq!(vec!["left"], tile_ids(&layer, &left));
+        assert_eq!(vec!["left", "right"], tile_ids(&layer, &right));
+
+        let layer = layer.with_overlap("5%".parse().unwrap());
+        assert_eq!(vec!["left", "right"], tile_ids(&layer, &left));
+        assert_eq!(vec!["left", "right"], tile_ids(&layer, &right));
+        // The viewBox still covers exactly the tile
+        let tile = layer.tile(&right).as_element().to_string();
+        assert!(tile.contains(r#"viewBox="50 0 50 50""#), "{}", tile);
+    }
+
+    #[test]
+    fn small_features_left_out_of_low_zoom_tiles() {
+        let svg_data = r#"<svg width="100" height="100">
+            <rect id="left" x="10" y="10" width="30" height="1" />
+            <rect id="right" x="60" y="10" width="4" height="4" />
+        </svg>"#;
+        let bounds = BoundingSquare::new(Vector2::new(0., 0.), 100.);
+        let whole = TileCoords::new(Vector2::new(0, 0), 0);
+        let right = TileCoords::new(Vector2::new(1, 0), 1);
+
+        let layer = Layer::new(svg_data, bounds, &ParseOptions::default()).unwrap();
+        assert_eq!(vec!["left", "right"], tile_ids(&layer, &whole));
+
+        // The small rect is 4% of the zoom level 0 tile, but 8% of the zoom level 1 tile
+        let layer = layer.with_min_feature_size(Some(0.05));
+        assert_eq!(vec!["left"], tile_ids(&layer, &whole));
+        assert_eq!(vec!["right"], tile_ids(&layer, &right));
+    }
+
+    #[test]
+    fn normalized_strokes() {
+        let svg_data = r#"<svg width="100" height="100">
+            <g stroke-width="4">
+                <rect x="10" y="10" width="30" height="30" style="fill:red; stroke-width: 2px" />
+                <path d="M 60 60 L 70 70" stroke-width="inherit" />
+            </g>
+        </svg>"#;
+        let bounds = BoundingSquare::new(Vector2::new(0., 0.), 100.);
+        let whole = TileCoords::new(Vector2::new(0, 0), 0);
+        let top_left = TileCoords::new(Vector2::new(0, 0), 2);
+
+        let layer = Layer::new(svg_data, bounds, &ParseOptions::default())
+            .unwrap()
+            .with_strokes(StrokeOptions {
+                scale: Some(0.5),
+                non_scaling: false,
+            });
+        let tile = layer.tile(&whole).as_element().to_string();
+        assert!(tile.contains(r#"stroke-width="4""#), "{}", tile);
+        assert!(
+            tile.contains(r#"style="fill:red; stroke-width:2px""#),
+            "{}",
+            tile
+        );
+        assert!(tile.contains(r#"stroke-width="inherit""#), "{}", tile);
+        assert!(!tile.contains("vector-effect"), "{}", tile);
+        let tile = layer.tile(&top_left).as_element().to_string();
+        assert!(tile.contains(r#"stroke-width="1""#), "{}", tile);
+        assert!(tile.contains(r#"stroke-width:0.5px""#), "{}", tile);
+
+        let layer = layer.with_strokes(StrokeOptions {
+            scale: None,
+            non_scaling: true,
+        });
+        let tile = layer.tile(&whole).as_element().to_string();
+        assert_eq!(
+            2,
+            tile.matches(r#"vector-effect="non-scaling-stroke""#)
+                .count()
+        );
+        assert!(!tile.contains(r#"<g vector-effect"#), "{}", tile);
+    }
+
+    #[test]
+    fn stripped_metadata_shrinks_tiles() {
+        let svg_data = std::fs::read_to_string("tests/svg/metadata.svg").unwrap();
+        let bounds = BoundingSquare::new(Vector2::new(0., 0.), 200.);
+        let whole = TileCoords::new(Vector2::new(0, 0), 0);
+
+        let layer = Layer::new(&svg_data, bounds, &ParseOptions::default()).unwrap();
+        let original = layer.tile(&whole).as_element().to_string();
+        let layer = layer.with_stripped_metadata(true);
+        let stripped = layer.tile(&whole).as_element().to_string();
+        assert!(stripped.len() < original.len());
+        assert!(!stripped.contains("inkscape:label"));
+        assert!(stripped.contains(r#"id="room101""#));
+    }
+
+    #[test]
+    fn low_memory_tiles_match() {
+        let options = ParseOptions::default();
+        // `use` elements are inlined either way, including ones drawn far from where the
+        // elements they reference are
+        for (path, size) in [
+            ("tests/svg/metadata.svg", 200.),
+            ("tests/svg/use.svg", 400.),
+        ] {
+            let svg_data = std::fs::read_to_string(path).unwrap();
+            let bounds = BoundingSquare::new(Vector2::new(0., 0.), size);
+            let layer = Layer::new(&svg_data, bounds.clone(), &options)
+                .unwrap()
+                .with_overlap(Overlap::Units(2.))
+                .with_stripped_metadata(true);
+            let low_memory = Layer::new_low_memory(&svg_data, bounds, &options)
+                .unwrap()
+                .with_overlap(Overlap::Units(2.))
+                .with_stripped_metadata(true);
+
+            assert_eq!(layer.report().warnings, low_memory.report().warnings);
+            for zoom in 0..=2 {
+                for x in 0..1 << zoom {
+                    for y in 0..1 << zoom {
+                        let coords = TileCoords::new(Vector2::new(x, y), zoom);
+                        assert_eq!(
+                            layer.tile(&coords).to_document(),
+                            low_memory.tile(&coords).to_document(),
+                            "{} at {:?}",
+                            path,
+                            coords
+                        );
+                    }
+                }
+            }
+        }
+        let bounds = BoundingSquare::new(Vector2::new(0., 0.), 200.);
+        assert!(Layer::new_low_memory("<svg>", bounds, &options).is_err());
+    }
+
+    #[test]
+    fn tiles_with_precision() {
+        let svg_data = r#"<svg width="100" height="100">
+            <path id="left" d="M 10.123456 10 L 20.987654 20" />
+        </svg>"#;
+        let bounds = BoundingSquare::new(Vector2::new(0., 0.), 100. / 3.);
+        let coords = TileCoords::new(Vector2::new(0, 0), 0);
+
+        let layer = Layer::new(svg_data, bounds, &ParseOptions::default()).unwrap();
+        let tile = layer.tile(&coords).to_document();
+        assert!(tile.contains("10.123456"), "{}", tile);
+
+        let layer = layer.with_precision(Some(2));
+        let tile = layer.tile(&coords).to_document();
+        assert!(tile.contains(r#"d="M 10.12 10 L 20.99 20""#), "{}", tile);
+        assert!(tile.contains(r#"viewBox="0 0 33.33 33.33""#), "{}", tile);
+    }
+
+    #[test]
+    fn tile_bounds_include_layer_offset() {
+        let svg_data = r#"<svg width="100" height="100" />"#;
+        let bounds = BoundingSquare::new(Vector2::new(-50., 20.), 80.);
+        let layer = Layer::new(svg_data, bounds, &ParseOptions::default()).unwrap();
+
+        let tile = TileCoords::new(Vector2::new(1, 3), 2);
+        let tile_bounds = layer.tile_bounds(&tile);
+        assert_eq!(Vector2::new(-30., 80.), tile_bounds.get_top_left());
+        assert_eq!(Vector2::new(20., 20.), tile_bounds.get_size());
+    }
+
+    #[test]
+    fn tile_bounds_at_extreme_zoom_levels() {
+        let svg_data = r#"<svg width="100" height="100" />"#;
+        let bounds = BoundingSquare::new(Vector2::new(-1000., 500.), 4000.);
+        let layer = Layer::new(svg_data, bounds.clone(), &ParseOptions::default()).unwrap();
+        let layer_bottom_right = bounds.as_bounding_box().get_bottom_right();
+
+        for zoom in 0..=MAX_ZOOM {
+            let max_coords = TileCoords::tiles_per_side(zoom).unwrap() - 1;
+            let edge_length = 4000. / 2_f64.powi(zoom as i32);
+            for location in [
+                Vector2::new(0, 0),
+                Vector2::new(max_coords, 0),
+                Vector2::new(max_coords / 2, max_coords),
+                Vector2::new(max_coords, max_coords),
+            ] {
+                let tile_bounds = layer.tile_bounds(&TileCoords::new(location, zoom));
+                assert!(bounds.as_bounding_box().contains(&tile_bounds));
+                assert_eq!(
+                    Vector2::new(edge_length, edge_length),
+                    tile_bounds.get_size()
+                );
+            }
+
+            let last =
+                layer.tile_bounds(&TileCoords::new(Vector2::new(max_coords, max_coords), zoom));
+            assert!((last.get_bottom_right() - layer_bottom_right).norm() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn parse_overlap() {
+        assert_eq!(Overlap::Units(2.5), "2.5".parse().unwrap());
+        assert_eq!(Overlap::Fraction(0.1), "10%".parse().unwrap());
+        assert!("-1".parse::<Overlap>().is_err());
+        assert!("a%".parse::<Overlap>().is_err());
+    }
+}
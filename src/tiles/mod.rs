@@ -0,0 +1,341 @@
+//! Splitting SVG images into square tiles at several zoom levels, for maps that only load the
+//! part of a floor that is in view
+
+use std::ops::RangeInclusive;
+
+use serde::{Deserialize, Serialize};
+
+use crate::bounding_box::{BoundingBox, BoundingSquare};
+
+mod layer;
+mod service;
+mod tile;
+mod tile_iterator;
+
+pub use layer::{Layer, Overlap, StrokeOptions};
+pub use service::{LayerService, LayerServiceOptions, RenderedTile, TileError};
+pub use tile::{Tile, TileCoords, TileCoordsError, MAX_ZOOM};
+pub use tile_iterator::TileIterator;
+
+/// Which tiles of a layer [`split`] creates
+#[derive(Debug, Clone)]
+pub struct SplitOptions {
+    /// Zoom levels to create tiles for, which must be at most [`MAX_ZOOM`]
+    pub zoom_levels: RangeInclusive<u32>,
+    /// If given, only tiles overlapping this area of the SVG are created
+    pub region: Option<BoundingBox>,
+}
+
+impl Default for SplitOptions {
+    fn default() -> Self {
+        Self {
+            zoom_levels: 0..=0,
+            region: None,
+        }
+    }
+}
+
+impl SplitOptions {
+    /// Iterates over the coordinates of the tiles of `layer` to create, one zoom level at a time
+    ///
+    /// # Panics
+    ///
+    /// Panics if a zoom level is greater than [`MAX_ZOOM`]; check with [`TileCoords::validate`]
+    /// first.
+    pub fn tile_coords(&self, layer: &Layer) -> TileIterator {
+        TileIterator::over(
+            self.zoom_levels.clone(),
+            self.region.clone(),
+            layer.bounds(),
+        )
+    }
+}
+
+/// Description of a directory of tiles, written alongside them so that clients know which tiles
+/// there are and what they cover without being told separately
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Top left corner of the zoom level 0 tile, in the SVG coordinates of the tiled image
+    pub top_left: (f64, f64),
+    /// Length of the edges of the zoom level 0 tile
+    pub size: f64,
+    pub min_zoom: u32,
+    pub max_zoom: u32,
+    /// Path of each tile relative to the manifest, where `{z}`, `{x}`, and `{y}` stand for its
+    /// zoom level and coordinates
+    pub tile_path: String,
+    /// Number of tiles written
+    pub tiles: usize,
+}
+
+impl Manifest {
+    /// Tile paths used by the binaries, such as `2.1.3.svg` for the tile at (1, 3) at zoom level 2
+    pub const TILE_PATH: &'static str = "{z}.{x}.{y}.svg";
+
+    /// Returns the path of the tile at `coords` relative to the manifest
+    pub fn path_of(&self, coords: &TileCoords) -> String {
+        self.tile_path
+            .replace("{z}", &coords.zoom.to_string())
+            .replace("{x}", &coords.location[0].to_string())
+            .replace("{y}", &coords.location[1].to_string())
+    }
+}
+
+/// Finds the tiles at every zoom level in `zoom_range` that overlap `region`, where the zoom level
+/// 0 tile covers `layer_bounds`, such as the tiles to download for viewing part of a floor
+/// offline. Tiles only partly inside of `region` are included, but not tiles that only touch its
+/// edges, so a region lined up with the tiles of a zoom level has just the tiles inside of it
+/// there. Tiles are ordered as by [`TileIterator`].
+///
+/// # Panics
+///
+/// Panics if a zoom level is greater than [`MAX_ZOOM`]; check with [`TileCoords::validate`]
+/// first.
+pub fn tiles_covering(
+    region: &BoundingBox,
+    zoom_range: RangeInclusive<u32>,
+    layer_bounds: &BoundingSquare,
+) -> Vec<TileCoords> {
+    TileIterator::over_interior(zoom_range, region, layer_bounds).collect()
+}
+
+/// Creates the tiles of `layer` chosen by `options`, one zoom level at a time, and passes each to
+/// `sink` along with its coordinates. Stops at the first error returned by `sink`.
+///
+/// # Panics
+///
+/// Panics if a zoom level is greater than [`MAX_ZOOM`]; check with [`TileCoords::validate`]
+/// first.
+pub fn split<'a, E>(
+    layer: &'a Layer,
+    options: &SplitOptions,
+    mut sink: impl FnMut(TileCoords, Tile<'a>) -> Result<(), E>,
+) -> Result<(), E> {
+    for coords in options.tile_coords(layer) {
+        let tile = layer.tile(&coords);
+        sink(coords, tile)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::convert::Infallible;
+    use std::fs;
+
+    use nalgebra::Vector2;
+
+    use super::*;
+    use crate::bounding_box::BoundingSquare;
+    use crate::snapshot::assert_snapshot;
+    use crate::svg_parser::{ParseOptions, SvgElement};
+
+    #[test]
+    fn tile_snapshots() {
+        let svg_data = fs::read_to_string("tests/svg/tiles.svg").unwrap();
+        let bounds = BoundingSquare::new(Vector2::new(0., 0.), 100.);
+        let layer = Layer::new(&svg_data, bounds, &ParseOptions::default()).unwrap();
+        let options = SplitOptions {
+            zoom_levels: 0..=1,
+            ..SplitOptions::default()
+        };
+
+        let mut tiles = 0;
+        split(&layer, &options, |coords, tile| {
+            assert_snapshot(
+                &format!(
+                    "tiles.{}.{}.{}.svg",
+                    coords.zoom, coords.location[0], coords.location[1]
+                ),
+                &tile.to_document(),
+            );
+            tiles += 1;
+            Ok::<_, Infallible>(())
+        })
+        .unwrap();
+        assert_eq!(5, tiles);
+    }
+
+    /// IDs of `element` and its descendants, in document order
+    fn ids<'e>(element: &'e SvgElement) -> Vec<&'e str> {
+        element
+            .iter_descendants()
+            .filter_map(SvgElement::get_id)
+            .collect()
+    }
+
+    #[test]
+    fn split_fixture() {
+        let svg_data = fs::read_to_string("tests/svg/split.svg").unwrap();
+        let bounds = BoundingSquare::new(Vector2::new(0., 0.), 100.);
+        let layer = Layer::new(&svg_data, bounds, &ParseOptions::default()).unwrap();
+        let options = SplitOptions {
+            zoom_levels: 0..=2,
+            ..SplitOptions::default()
+        };
+
+        let mut tiles = HashMap::new();
+        split(&layer, &options, |coords, tile| {
+            let image = tile.get_image();
+            assert_eq!("svg", image.get_tag_name());
+            let view_box = image.get_attr("viewBox").unwrap().to_string();
+            let ids: Vec<_> = ids(image).into_iter().map(str::to_owned).collect();
+            let location = (coords.zoom, coords.location[0], coords.location[1]);
+            tiles.insert(location, (view_box, ids));
+            Ok::<_, Infallible>(())
+        })
+        .unwrap();
+        assert_eq!(1 + 4 + 16, tiles.len());
+
+        for ((zoom, x, y), (view_box, _ids)) in &tiles {
+            let size = 100. / 2_f64.powi(*zoom as i32);
+            let expected = format!(
+                "{} {} {} {}",
+                *x as f64 * size,
+                *y as f64 * size,
+                size,
+                size
+            );
+            assert_eq!(&expected, view_box, "tile ({}, {}) at zoom {}", x, y, zoom);
+        }
+
+        let spanning: &[&str] = &["spanning"];
+        let expected: HashMap<(u32, u32, u32), &[&str]> = HashMap::from([
+            (
+                (0, 0, 0),
+                &[
+                    "group",
+                    "inner",
+                    "nested",
+                    "transformed",
+                    "spanning",
+                    "corner",
+                ][..],
+            ),
+            ((1, 0, 0), &["group", "inner", "nested", "spanning"]),
+            ((1, 1, 0), &["transformed", "spanning"]),
+            ((1, 0, 1), spanning),
+            ((1, 1, 1), &["spanning", "corner"]),
+            ((2, 0, 0), &["group", "inner", "nested"]),
+            ((2, 2, 0), &["transformed"]),
+            ((2, 1, 1), spanning),
+            ((2, 2, 1), spanning),
+            ((2, 1, 2), spanning),
+            ((2, 2, 2), spanning),
+            ((2, 3, 3), &["corner"]),
+        ]);
+        for (location, (_view_box, ids)) in &tiles {
+            let expected = expected.get(location).copied().unwrap_or_default();
+            assert_eq!(expected, ids.as_slice(), "tile {:?}", location);
+        }
+    }
+
+    #[test]
+    fn identical_tiles_between_runs() {
+        let svg_data = fs::read_to_string("tests/svg/tiles.svg").unwrap();
+        let bounds = BoundingSquare::new(Vector2::new(0., 0.), 100.);
+        let options = SplitOptions {
+            zoom_levels: 0..=2,
+            ..SplitOptions::default()
+        };
+        // Each layer is parsed separately, so their attributes are stored in different orders
+        let split_tiles = || {
+            let layer = Layer::new(&svg_data, bounds.clone(), &ParseOptions::default()).unwrap();
+            let mut tiles = vec![];
+            split(&layer, &options, |coords, tile| {
+                tiles.push((coords, tile.to_document()));
+                Ok::<_, Infallible>(())
+            })
+            .unwrap();
+            tiles
+        };
+        assert_eq!(split_tiles(), split_tiles());
+    }
+
+    #[test]
+    fn tiles_covering_region() {
+        let layer_bounds = BoundingSquare::new(Vector2::new(0., 0.), 100.);
+        let locations = |x: f64, y: f64, size: f64, zoom: u32| {
+            let region = BoundingBox::new(Vector2::new(x, y), Vector2::new(size, size));
+            tiles_covering(&region, zoom..=zoom, &layer_bounds)
+                .into_iter()
+                .map(|coords| (coords.location[0], coords.location[1]))
+                .collect::<Vec<_>>()
+        };
+
+        // Tiles are 25 units at zoom level 2, so the region covers parts of four of them
+        assert_eq!(
+            vec![(1, 1), (2, 1), (1, 2), (2, 2)],
+            locations(30., 30., 30., 2)
+        );
+        // Tiles that only touch the edges of a region lined up with the tiles aren't included
+        assert_eq!(vec![(1, 1)], locations(25., 25., 25., 2));
+        assert_eq!(
+            vec![(1, 1), (2, 1), (1, 2), (2, 2)],
+            locations(25., 25., 50., 2)
+        );
+        // A region without any area still has the tile it's in
+        assert_eq!(vec![(1, 1)], locations(25., 25., 0., 2));
+        // Only the part of the region within the layer has tiles
+        assert_eq!(vec![(0, 0)], locations(-10., -10., 30., 2));
+        assert_eq!(vec![(3, 3)], locations(90., 90., 100., 2));
+        assert!(locations(110., 0., 10., 2).is_empty());
+        assert!(locations(-20., -20., 10., 2).is_empty());
+
+        let region = BoundingBox::new(Vector2::new(30., 30.), Vector2::new(30., 30.));
+        let coords = tiles_covering(&region, 1..=3, &layer_bounds);
+        let per_zoom: Vec<_> = (1..=3)
+            .map(|zoom| coords.iter().filter(|coords| coords.zoom == zoom).count())
+            .collect();
+        assert_eq!(vec![4, 4, 9], per_zoom);
+    }
+
+    #[test]
+    fn render_covering_tiles() {
+        let svg_data = fs::read_to_string("tests/svg/tiles.svg").unwrap();
+        let bounds = BoundingSquare::new(Vector2::new(0., 0.), 100.);
+        let layer = Layer::new(&svg_data, bounds.clone(), &ParseOptions::default()).unwrap();
+        let region = BoundingBox::new(Vector2::new(55., 55.), Vector2::new(10., 10.));
+        let coords = tiles_covering(&region, 1..=2, &bounds);
+
+        let rendered = layer.render_tiles(&coords);
+        assert_eq!(coords.len(), rendered.len());
+        for ((coords, document), expected_coords) in rendered.iter().zip(&coords) {
+            assert_eq!(expected_coords, coords);
+            assert_eq!(&layer.tile(coords).to_document(), document);
+        }
+    }
+
+    #[test]
+    fn split_region() {
+        let svg_data = fs::read_to_string("tests/svg/tiles.svg").unwrap();
+        let bounds = BoundingSquare::new(Vector2::new(0., 0.), 100.);
+        let layer = Layer::new(&svg_data, bounds, &ParseOptions::default()).unwrap();
+        let options = SplitOptions {
+            zoom_levels: 1..=2,
+            region: Some(BoundingBox::new(
+                Vector2::new(60., 60.),
+                Vector2::new(10., 10.),
+            )),
+        };
+
+        let mut coords = vec![];
+        let result = split(&layer, &options, |tile_coords, _tile| {
+            coords.push(tile_coords);
+            if coords.len() == 2 {
+                Err("stop")
+            } else {
+                Ok(())
+            }
+        });
+        assert_eq!(Err("stop"), result);
+        assert_eq!(
+            vec![
+                TileCoords::new(Vector2::new(1, 1), 1),
+                TileCoords::new(Vector2::new(2, 2), 2),
+            ],
+            coords
+        );
+    }
+}
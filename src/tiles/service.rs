@@ -0,0 +1,393 @@
+//! Rendering the tiles of a layer on a thread pool, for async servers that shouldn't block their
+//! executor on CPU-bound rendering
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll, Waker};
+
+use rayon::{ThreadPool, ThreadPoolBuildError, ThreadPoolBuilder};
+
+use crate::tiles::{Layer, TileCoords, TileCoordsError, MAX_ZOOM};
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum TileError {
+    #[error(transparent)]
+    InvalidCoords(#[from] TileCoordsError),
+    #[error("Rendering the tile ({x}, {y}) at zoom level {zoom} panicked")]
+    RenderPanicked { x: u32, y: u32, zoom: u32 },
+}
+
+/// A tile written as a standalone SVG document
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderedTile {
+    pub coords: TileCoords,
+    pub document: String,
+    /// Whether the tile has no elements, such as a tile past the edge of a floor, which servers
+    /// may answer with a shared blank tile instead
+    pub empty: bool,
+}
+
+/// How a [`LayerService`] renders and keeps tiles
+#[derive(Debug, Clone)]
+pub struct LayerServiceOptions {
+    /// Threads rendering tiles, or 0 for one per CPU
+    pub threads: usize,
+    /// Most tiles kept after they're rendered, with the least recently requested tiles dropped
+    /// first. Tiles aren't kept if this is 0.
+    pub cache_capacity: usize,
+    /// Tiles at higher zoom levels than this are rejected
+    pub max_zoom: u32,
+}
+
+impl Default for LayerServiceOptions {
+    fn default() -> Self {
+        Self {
+            threads: 0,
+            cache_capacity: 1024,
+            max_zoom: MAX_ZOOM,
+        }
+    }
+}
+
+/// Renders the tiles of a layer for async code, such as the handlers of an HTTP server. Tiles are
+/// rendered on the service's own thread pool, so awaiting them never blocks the executor, and
+/// concurrent requests for the same tile share a single render. Rendered tiles are kept in a
+/// cache of the most recently requested tiles.
+///
+/// The futures returned don't depend on any particular async runtime.
+pub struct LayerService {
+    shared: Arc<Shared>,
+    pool: ThreadPool,
+}
+
+/// What the service shares with the renders running on its pool
+struct Shared {
+    layer: Layer<'static>,
+    max_zoom: u32,
+    state: Mutex<State>,
+    renders: AtomicUsize,
+}
+
+struct State {
+    cache: TileCache,
+    /// Renders that haven't finished yet, which later requests for the same tile wait on
+    in_flight: HashMap<TileCoords, Arc<Render>>,
+}
+
+impl Shared {
+    fn state(&self) -> MutexGuard<'_, State> {
+        // The state is only changed while the lock is held, and nothing in between can panic
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl LayerService {
+    pub fn new(
+        layer: Layer<'static>,
+        options: &LayerServiceOptions,
+    ) -> Result<Self, ThreadPoolBuildError> {
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(options.threads)
+            .thread_name(|index| format!("tile-render-{}", index))
+            .build()?;
+        let shared = Shared {
+            layer,
+            max_zoom: options.max_zoom,
+            state: Mutex::new(State {
+                cache: TileCache::new(options.cache_capacity),
+                in_flight: HashMap::new(),
+            }),
+            renders: AtomicUsize::new(0),
+        };
+        Ok(Self {
+            shared: Arc::new(shared),
+            pool,
+        })
+    }
+
+    pub fn layer(&self) -> &Layer<'static> {
+        &self.shared.layer
+    }
+
+    /// Returns the tile at `coords`, from the cache if it's there, and otherwise by waiting for it
+    /// to be rendered. If the tile is already being rendered for another request, this waits for
+    /// that render instead of starting another.
+    pub async fn tile(&self, coords: TileCoords) -> Result<Arc<RenderedTile>, TileError> {
+        coords.validate(self.shared.max_zoom)?;
+        let render = {
+            let mut state = self.shared.state();
+            if let Some(tile) = state.cache.get(&coords) {
+                return Ok(tile);
+            }
+            match state.in_flight.get(&coords) {
+                Some(render) => Arc::clone(render),
+                None => {
+                    let render = Arc::new(Render::default());
+                    state.in_flight.insert(coords.clone(), Arc::clone(&render));
+                    self.spawn_render(coords, Arc::clone(&render));
+                    render
+                }
+            }
+        };
+        RenderFuture(render).await
+    }
+
+    /// Returns the number of tiles rendered so far, where a render shared by several requests
+    /// counts once
+    pub fn renders(&self) -> usize {
+        self.shared.renders.load(Ordering::Relaxed)
+    }
+
+    fn spawn_render(&self, coords: TileCoords, render: Arc<Render>) {
+        let shared = Arc::clone(&self.shared);
+        self.pool.spawn(move || {
+            shared.renders.fetch_add(1, Ordering::Relaxed);
+            let rendered = panic::catch_unwind(AssertUnwindSafe(|| {
+                let tile = shared.layer.tile(&coords);
+                RenderedTile {
+                    coords: coords.clone(),
+                    document: tile.to_document(),
+                    empty: tile.is_empty(),
+                }
+            }));
+            let result = rendered
+                .map(Arc::new)
+                .map_err(|_panic| TileError::RenderPanicked {
+                    x: coords.location[0],
+                    y: coords.location[1],
+                    zoom: coords.zoom,
+                });
+            {
+                let mut state = shared.state();
+                if let Ok(tile) = &result {
+                    state.cache.insert(coords.clone(), Arc::clone(tile));
+                }
+                state.in_flight.remove(&coords);
+            }
+            render.finish(result);
+        });
+    }
+}
+
+/// A render of a tile, which every request for the tile waits on until it finishes
+#[derive(Default)]
+struct Render(Mutex<RenderState>);
+
+#[derive(Default)]
+struct RenderState {
+    result: Option<Result<Arc<RenderedTile>, TileError>>,
+    waiting: Vec<Waker>,
+}
+
+impl Render {
+    fn state(&self) -> MutexGuard<'_, RenderState> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn finish(&self, result: Result<Arc<RenderedTile>, TileError>) {
+        let waiting = {
+            let mut state = self.state();
+            state.result = Some(result);
+            std::mem::take(&mut state.waiting)
+        };
+        for waker in waiting {
+            waker.wake();
+        }
+    }
+}
+
+struct RenderFuture(Arc<Render>);
+
+impl Future for RenderFuture {
+    type Output = Result<Arc<RenderedTile>, TileError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.0.state();
+        match &state.result {
+            Some(result) => Poll::Ready(result.clone()),
+            None => {
+                if !state
+                    .waiting
+                    .iter()
+                    .any(|waker| waker.will_wake(cx.waker()))
+                {
+                    state.waiting.push(cx.waker().clone());
+                }
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Rendered tiles, dropping the least recently used tile when full
+struct TileCache {
+    capacity: usize,
+    /// Each tile along with when it was last used
+    tiles: HashMap<TileCoords, (Arc<RenderedTile>, u64)>,
+    clock: u64,
+}
+
+impl TileCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            tiles: HashMap::new(),
+            clock: 0,
+        }
+    }
+
+    fn get(&mut self, coords: &TileCoords) -> Option<Arc<RenderedTile>> {
+        self.clock += 1;
+        let (tile, last_used) = self.tiles.get_mut(coords)?;
+        *last_used = self.clock;
+        Some(Arc::clone(tile))
+    }
+
+    fn insert(&mut self, coords: TileCoords, tile: Arc<RenderedTile>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.tiles.len() >= self.capacity && !self.tiles.contains_key(&coords) {
+            // Finding the least recently used tile is linear, but it's dwarfed by rendering
+            let least_recent = self
+                .tiles
+                .iter()
+                .min_by_key(|(_coords, (_tile, last_used))| *last_used)
+                .map(|(coords, _tile)| coords.clone());
+            if let Some(least_recent) = least_recent {
+                self.tiles.remove(&least_recent);
+            }
+        }
+        self.clock += 1;
+        self.tiles.insert(coords, (tile, self.clock));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::pin::pin;
+    use std::task::Wake;
+    use std::thread::{self, Thread};
+
+    use nalgebra::Vector2;
+
+    use super::*;
+    use crate::bounding_box::BoundingSquare;
+    use crate::svg_parser::ParseOptions;
+
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    /// Runs `future` to completion on the current thread, standing in for an async runtime
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+            thread::park();
+        }
+    }
+
+    fn service(options: &LayerServiceOptions) -> LayerService {
+        let svg_data = r#"<svg width="100" height="100">
+            <rect id="left" x="10" y="10" width="30" height="10" />
+            <rect id="right" x="60" y="60" width="30" height="30" />
+        </svg>"#
+            .to_owned();
+        let bounds = BoundingSquare::new(Vector2::new(0., 0.), 100.);
+        let layer = Layer::new(&svg_data, bounds, &ParseOptions::default())
+            .unwrap()
+            .into_owned();
+        LayerService::new(layer, options).unwrap()
+    }
+
+    #[test]
+    fn coalesce_concurrent_requests() {
+        let service = service(&LayerServiceOptions::default());
+        // Futures have to be `Send` for multithreaded runtimes to move them between threads
+        fn assert_send<T: Send>(_: &T) {}
+        assert_send(&service.tile(TileCoords::new(Vector2::new(0, 0), 0)));
+        let coords: Vec<_> = [(0, 0, 0), (0, 0, 1), (1, 0, 1), (0, 1, 1), (1, 1, 1)]
+            .into_iter()
+            .map(|(x, y, zoom)| TileCoords::new(Vector2::new(x, y), zoom))
+            .collect();
+
+        let tiles: Vec<_> = thread::scope(|scope| {
+            let requests: Vec<_> = (0..100)
+                .map(|i| {
+                    let (service, coords) = (&service, &coords[i % coords.len()]);
+                    scope.spawn(move || block_on(service.tile(coords.clone())).unwrap())
+                })
+                .collect();
+            requests
+                .into_iter()
+                .map(|request| request.join().unwrap())
+                .collect()
+        });
+        assert_eq!(5, service.renders());
+        for (i, tile) in tiles.iter().enumerate() {
+            let first = &tiles[i % coords.len()];
+            assert!(Arc::ptr_eq(first, tile));
+            assert_eq!(coords[i % coords.len()], tile.coords);
+        }
+        assert!(!tiles[0].empty);
+        assert!(tiles[0].document.contains(r#"id="left""#));
+        assert!(tiles[2].empty);
+    }
+
+    #[test]
+    fn evict_least_recently_used() {
+        let options = LayerServiceOptions {
+            cache_capacity: 2,
+            ..LayerServiceOptions::default()
+        };
+        let service = service(&options);
+        let tile = |x, y| block_on(service.tile(TileCoords::new(Vector2::new(x, y), 1))).unwrap();
+
+        tile(0, 0);
+        tile(1, 0);
+        tile(0, 0);
+        assert_eq!(2, service.renders());
+        // Drops (1, 0), which was used less recently than (0, 0)
+        tile(1, 1);
+        tile(0, 0);
+        assert_eq!(3, service.renders());
+        tile(1, 0);
+        assert_eq!(4, service.renders());
+    }
+
+    #[test]
+    fn reject_invalid_coords() {
+        let options = LayerServiceOptions {
+            max_zoom: 2,
+            ..LayerServiceOptions::default()
+        };
+        let service = service(&options);
+        let result = block_on(service.tile(TileCoords::new(Vector2::new(0, 0), 3)));
+        assert_eq!(
+            Err(TileError::InvalidCoords(TileCoordsError::ZoomTooHigh {
+                zoom: 3,
+                max_zoom: 2
+            })),
+            result
+        );
+        let result = block_on(service.tile(TileCoords::new(Vector2::new(2, 0), 1)));
+        assert!(matches!(
+            result,
+            Err(TileError::InvalidCoords(TileCoordsError::OutOfRange { .. }))
+        ));
+        assert_eq!(0, service.renders());
+    }
+}
@@ -0,0 +1,191 @@
+use nalgebra::Vector2;
+use svg::node::element::GenericElement;
+use svg::Document;
+
+use crate::svg_parser::SvgElement;
+use crate::trace::span;
+
+/// Highest zoom level whose tile coordinates fit in a `u32`
+pub const MAX_ZOOM: u32 = 31;
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum TileCoordsError {
+    #[error("The zoom level {zoom} is greater than the maximum of {max_zoom}")]
+    ZoomTooHigh { zoom: u32, max_zoom: u32 },
+    #[error("The tile ({x}, {y}) is outside of the {tiles_per_side}x{tiles_per_side} tiles at zoom level {zoom}")]
+    OutOfRange {
+        x: u32,
+        y: u32,
+        zoom: u32,
+        tiles_per_side: u32,
+    },
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct TileCoords {
+    pub location: Vector2<u32>,
+    pub zoom: u32,
+}
+
+impl TileCoords {
+    pub fn new(location: Vector2<u32>, zoom: u32) -> Self {
+        Self { location, zoom }
+    }
+
+    /// Returns the number of tiles along each edge of the grid at `zoom`, or `None` if it doesn't
+    /// fit in a `u32`
+    pub fn tiles_per_side(zoom: u32) -> Option<u32> {
+        2_u32.checked_pow(zoom)
+    }
+
+    /// Checks that the zoom level is at most `max_zoom` (and [`MAX_ZOOM`]), and that the location
+    /// is within the grid of tiles at that zoom level.
+    pub fn validate(&self, max_zoom: u32) -> Result<(), TileCoordsError> {
+        let max_zoom = max_zoom.min(MAX_ZOOM);
+        let tiles_per_side = Self::tiles_per_side(self.zoom)
+            .filter(|_| self.zoom <= max_zoom)
+            .ok_or(TileCoordsError::ZoomTooHigh {
+                zoom: self.zoom,
+                max_zoom,
+            })?;
+        if self.location.max() >= tiles_per_side {
+            return Err(TileCoordsError::OutOfRange {
+                x: self.location[0],
+                y: self.location[1],
+                zoom: self.zoom,
+                tiles_per_side,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct Tile<'a> {
+    image: SvgElement<'a>,
+    /// Digits after the decimal point that numbers in geometric attributes are rounded to when
+    /// the tile is written
+    precision: Option<usize>,
+}
+
+impl<'a> Tile<'a> {
+    pub fn new(image: SvgElement<'a>) -> Self {
+        Self {
+            image,
+            precision: None,
+        }
+    }
+
+    /// Rounds numbers in geometric attributes, such as path data, to `decimals` digits after the
+    /// decimal point when the tile is written
+    pub fn with_precision(self, decimals: Option<usize>) -> Self {
+        Self {
+            precision: decimals,
+            ..self
+        }
+    }
+
+    /// Returns the root `svg` element of the tile
+    pub fn get_image(&self) -> &SvgElement<'a> {
+        &self.image
+    }
+
+    /// Returns `true` if no elements overlap the tile
+    pub fn is_empty(&self) -> bool {
+        !self.image.has_children()
+    }
+
+    pub fn as_element(&self) -> GenericElement {
+        self.image.as_element_with_precision(self.precision)
+    }
+
+    /// Writes the tile as a standalone SVG document
+    pub fn to_document(&self) -> String {
+        span!("serialize_tile");
+        Document::new().add(self.as_element()).to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn validate_coords() {
+        assert_eq!(Ok(()), TileCoords::new(Vector2::new(0, 0), 0).validate(5));
+        assert_eq!(Ok(()), TileCoords::new(Vector2::new(31, 0), 5).validate(5));
+        assert_eq!(
+            Ok(()),
+            TileCoords::new(Vector2::new(u32::MAX >> 1, 0), 31).validate(u32::MAX)
+        );
+
+        assert_eq!(
+            Err(TileCoordsError::ZoomTooHigh {
+                zoom: 6,
+                max_zoom: 5
+            }),
+            TileCoords::new(Vector2::new(0, 0), 6).validate(5)
+        );
+        assert_eq!(
+            Err(TileCoordsError::ZoomTooHigh {
+                zoom: 32,
+                max_zoom: MAX_ZOOM
+            }),
+            TileCoords::new(Vector2::new(0, 0), 32).validate(u32::MAX)
+        );
+        assert_eq!(
+            Err(TileCoordsError::OutOfRange {
+                x: 0,
+                y: 32,
+                zoom: 5,
+                tiles_per_side: 32
+            }),
+            TileCoords::new(Vector2::new(0, 32), 5).validate(5)
+        );
+        assert!(TileCoords::new(Vector2::new(u32::MAX, 0), 31)
+            .validate(MAX_ZOOM)
+            .is_err());
+    }
+
+    /// Coordinates near the edges of the grids of tiles at the zoom levels around `MAX_ZOOM`, along
+    /// with any others
+    fn coordinate() -> impl Strategy<Value = u32> {
+        prop_oneof![
+            0..4_u32,
+            (u32::MAX >> 1) - 2..=(u32::MAX >> 1) + 2,
+            u32::MAX - 2..=u32::MAX,
+            any::<u32>(),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn validate_against_grid_size(
+            zoom in prop_oneof![0..=MAX_ZOOM + 2, any::<u32>()],
+            x in coordinate(),
+            y in coordinate(),
+            max_zoom in prop_oneof![Just(u32::MAX), 0..=MAX_ZOOM + 2],
+        ) {
+            let coords = TileCoords::new(Vector2::new(x, y), zoom);
+            let tiles_per_side = 1_u64.checked_shl(zoom).filter(|_| zoom <= max_zoom.min(MAX_ZOOM));
+            let expected = match tiles_per_side {
+                None => Err(TileCoordsError::ZoomTooHigh {
+                    zoom,
+                    max_zoom: max_zoom.min(MAX_ZOOM),
+                }),
+                Some(tiles_per_side) if u64::from(x.max(y)) >= tiles_per_side => {
+                    Err(TileCoordsError::OutOfRange {
+                        x,
+                        y,
+                        zoom,
+                        tiles_per_side: tiles_per_side as u32,
+                    })
+                }
+                Some(_) => Ok(()),
+            };
+            prop_assert_eq!(expected, coords.validate(max_zoom));
+        }
+    }
+}
@@ -0,0 +1,379 @@
+use std::ops::RangeInclusive;
+
+use nalgebra::Vector2;
+
+use crate::bounding_box::{BoundingBox, BoundingSquare};
+use crate::tiles::{TileCoords, MAX_ZOOM};
+
+/// Rectangle of tile locations at one zoom level, including `min` and `max`
+#[derive(Debug, Clone)]
+struct TileRange {
+    zoom: u32,
+    min: Vector2<u32>,
+    max: Vector2<u32>,
+}
+
+impl TileRange {
+    fn len(&self) -> usize {
+        let size = (self.max - self.min).add_scalar(1);
+        size[0] as usize * size[1] as usize
+    }
+}
+
+/// Iterates over tile coordinates one zoom level at a time, going across each row of tiles before
+/// moving down to the next.
+pub struct TileIterator {
+    /// Ranges still to be iterated over, in reverse order so that the current one is last
+    ranges: Vec<TileRange>,
+    /// Next location in the current range
+    location: Vector2<u32>,
+    remaining: usize,
+}
+
+impl TileIterator {
+    /// Iterates over every tile at `zoom_level`
+    pub fn new(zoom_level: u32) -> Self {
+        let max_coords = Self::max_coords_for_zoom_level(zoom_level);
+        Self::from_ranges(vec![TileRange {
+            zoom: zoom_level,
+            min: Vector2::new(0, 0),
+            max: Vector2::new(max_coords, max_coords),
+        }])
+    }
+
+    /// Iterates over the tiles at every zoom level in `zoom_range`, where the zoom level 0 tile
+    /// covers `layer_bounds`. If `bounds` is given, only tiles that overlap it (including tiles
+    /// that only touch its edges) are included.
+    pub fn over(
+        zoom_range: RangeInclusive<u32>,
+        bounds: Option<BoundingBox>,
+        layer_bounds: &BoundingSquare,
+    ) -> Self {
+        Self::over_with(zoom_range, bounds.as_ref(), layer_bounds, false)
+    }
+
+    /// Same as [`Self::over`] with bounds, but only includes tiles whose insides overlap `bounds`,
+    /// leaving out tiles that only touch its edges. Bounds without any area still have the tiles
+    /// they lie in.
+    pub fn over_interior(
+        zoom_range: RangeInclusive<u32>,
+        bounds: &BoundingBox,
+        layer_bounds: &BoundingSquare,
+    ) -> Self {
+        Self::over_with(zoom_range, Some(bounds), layer_bounds, true)
+    }
+
+    fn over_with(
+        zoom_range: RangeInclusive<u32>,
+        bounds: Option<&BoundingBox>,
+        layer_bounds: &BoundingSquare,
+        interior: bool,
+    ) -> Self {
+        let ranges = zoom_range
+            .filter_map(|zoom| {
+                let max_coords = Self::max_coords_for_zoom_level(zoom);
+                let (min, max) = match bounds {
+                    Some(bounds) => Self::overlapping_range(zoom, bounds, layer_bounds, interior)?,
+                    None => (Vector2::new(0, 0), Vector2::new(max_coords, max_coords)),
+                };
+                Some(TileRange { zoom, min, max })
+            })
+            .collect();
+        Self::from_ranges(ranges)
+    }
+
+    fn from_ranges(mut ranges: Vec<TileRange>) -> Self {
+        let remaining = ranges.iter().map(TileRange::len).sum();
+        ranges.reverse();
+        let location = ranges
+            .last()
+            .map_or_else(|| Vector2::new(0, 0), |range| range.min);
+        Self {
+            ranges,
+            location,
+            remaining,
+        }
+    }
+
+    /// Finds the first and last tile locations at `zoom` overlapping `bounds`, or `None` if no
+    /// tiles overlap it. With `interior`, tiles that only touch the edges of `bounds` don't
+    /// overlap it.
+    fn overlapping_range(
+        zoom: u32,
+        bounds: &BoundingBox,
+        layer_bounds: &BoundingSquare,
+        interior: bool,
+    ) -> Option<(Vector2<u32>, Vector2<u32>)> {
+        let max_coords = Self::max_coords_for_zoom_level(zoom) as f64;
+        // Inverse of `BoundingSquare::subdivide`, so a tile found here covers the point it was
+        // found for
+        let tiles_per_side = 2_f64.powi(zoom as i32);
+        let layer_top_left = layer_bounds.as_bounding_box().get_top_left();
+        let to_index = |point: Vector2<f64>| {
+            (point - layer_top_left) * tiles_per_side / layer_bounds.edge_length()
+        };
+        let start = to_index(bounds.get_top_left());
+        let end = to_index(bounds.get_bottom_right());
+
+        let (min, max) = if interior {
+            let min = start.map(f64::floor);
+            // Tiles that start exactly at the end of the bounds don't overlap them
+            let max = end.zip_map(&min, |end, min| (end.ceil() - 1.).max(min));
+            (
+                min.map(|min| min.max(0.)),
+                max.map(|max| max.min(max_coords)),
+            )
+        } else {
+            // A tile whose edge lies exactly on the start of the bounds still overlaps it
+            let min = start.map(|start| (start.ceil() - 1.).max(0.));
+            let max = end.map(|end| end.floor().min(max_coords));
+            (min, max)
+        };
+        if min[0] > max[0] || min[1] > max[1] {
+            None
+        } else {
+            Some((min.map(|min| min as u32), max.map(|max| max as u32)))
+        }
+    }
+
+    /// Returns the largest x- or y-coordinate of a tile at `zoom_level`. There is a single tile
+    /// at zoom level 0, so this is 0 there rather than underflowing.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `zoom_level` is greater than [`MAX_ZOOM`]; check with [`TileCoords::validate`]
+    /// first.
+    fn max_coords_for_zoom_level(zoom_level: u32) -> u32 {
+        TileCoords::tiles_per_side(zoom_level)
+            .and_then(|tiles_per_side| tiles_per_side.checked_sub(1))
+            .unwrap_or_else(|| panic!("Zoom level {} is greater than {}", zoom_level, MAX_ZOOM))
+    }
+}
+
+impl Iterator for TileIterator {
+    type Item = TileCoords;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let range = self.ranges.last()?;
+        let coords = TileCoords::new(self.location, range.zoom);
+
+        if self.location[0] < range.max[0] {
+            self.location[0] += 1;
+        } else if self.location[1] < range.max[1] {
+            self.location = Vector2::new(range.min[0], self.location[1] + 1);
+        } else {
+            self.ranges.pop();
+            if let Some(next_range) = self.ranges.last() {
+                self.location = next_range.min;
+            }
+        }
+        self.remaining -= 1;
+
+        Some(coords)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl ExactSizeIterator for TileIterator {}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+    use std::convert::TryFrom;
+
+    use proptest::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn extreme_zoom_levels() {
+        assert_eq!(
+            u32::MAX >> 1,
+            TileIterator::max_coords_for_zoom_level(MAX_ZOOM)
+        );
+        let mut tiles = TileIterator::new(MAX_ZOOM);
+        assert_eq!(1 << 62, tiles.len());
+        assert_eq!(
+            Some(TileCoords::new(Vector2::new(0, 0), MAX_ZOOM)),
+            tiles.next()
+        );
+        assert_eq!(
+            Some(TileCoords::new(Vector2::new(1, 0), MAX_ZOOM)),
+            tiles.next()
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn zoom_level_too_high() {
+        TileIterator::new(MAX_ZOOM + 1);
+    }
+
+    #[test]
+    fn zoom_level_0_has_one_tile() {
+        assert_eq!(0, TileIterator::max_coords_for_zoom_level(0));
+        let tiles: Vec<_> = TileIterator::new(0).collect();
+        assert_eq!(vec![TileCoords::new(Vector2::new(0, 0), 0)], tiles);
+    }
+
+    #[test]
+    fn every_tile_at_each_level() {
+        let layer_bounds = BoundingSquare::new(Vector2::new(0., 0.), 100.);
+        for zoom in 0..=6 {
+            let mut tiles = TileIterator::new(zoom);
+            let expected = 4_usize.pow(zoom);
+            assert_eq!(expected, tiles.len());
+            tiles.next();
+            assert_eq!(expected - 1, tiles.len());
+
+            let tiles: HashSet<_> = TileIterator::new(zoom).collect();
+            assert_eq!(expected, tiles.len());
+            assert!(tiles.iter().all(|coords| coords.zoom == zoom
+                && coords.location.max() <= TileIterator::max_coords_for_zoom_level(zoom)));
+
+            let over: Vec<_> = TileIterator::over(zoom..=zoom, None, &layer_bounds).collect();
+            assert_eq!(TileIterator::new(zoom).collect::<Vec<_>>(), over);
+        }
+
+        let tiles = TileIterator::over(2..=5, None, &layer_bounds);
+        assert_eq!(16 + 64 + 256 + 1024, tiles.len());
+        let zooms: Vec<_> = tiles.map(|coords| coords.zoom).collect();
+        assert!(zooms.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+
+    fn locations(tiles: TileIterator) -> Vec<Vector2<u32>> {
+        tiles.map(|coords| coords.location).collect()
+    }
+
+    #[test]
+    fn tiles_overlapping_bounds() {
+        let layer_bounds = BoundingSquare::new(Vector2::new(-50., 20.), 80.);
+
+        let bounds = BoundingBox::new(Vector2::new(-25., 25.), Vector2::new(10., 10.));
+        let tiles = TileIterator::over(2..=2, Some(bounds), &layer_bounds);
+        assert_eq!(1, tiles.len());
+        assert_eq!(vec![Vector2::new(1, 0)], locations(tiles));
+
+        // Tiles touching the edges of the bounds are included
+        let bounds = BoundingBox::new(Vector2::new(-30., 40.), Vector2::new(20., 0.));
+        let tiles = TileIterator::over(2..=2, Some(bounds), &layer_bounds);
+        assert_eq!(6, tiles.len());
+        assert_eq!(
+            vec![
+                Vector2::new(0, 0),
+                Vector2::new(1, 0),
+                Vector2::new(2, 0),
+                Vector2::new(0, 1),
+                Vector2::new(1, 1),
+                Vector2::new(2, 1),
+            ],
+            locations(tiles)
+        );
+
+        // Bounds extending past the layer are clamped to it, and levels without any overlapping
+        // tiles are skipped
+        let bounds = BoundingBox::new(Vector2::new(-100., 0.), Vector2::new(500., 500.));
+        let tiles = TileIterator::over(0..=3, Some(bounds), &layer_bounds);
+        assert_eq!(1 + 4 + 16 + 64, tiles.len());
+        let outside = BoundingBox::new(Vector2::new(100., 0.), Vector2::new(5., 5.));
+        assert_eq!(
+            0,
+            TileIterator::over(0..=3, Some(outside), &layer_bounds).len()
+        );
+    }
+
+    #[test]
+    fn bounded_tiles_match_brute_force() {
+        let layer_bounds = BoundingSquare::new(Vector2::new(10., -10.), 64.);
+        let bounds = BoundingBox::new(Vector2::new(13.5, 0.), Vector2::new(20., 33.25));
+        for zoom in 0..=5 {
+            let edge_length = 64. / 2_f64.powi(zoom as i32);
+            let expected: Vec<_> = TileIterator::new(zoom)
+                .filter(|coords| {
+                    let top_left = Vector2::new(10., -10.) + edge_length * coords.location.cast();
+                    BoundingBox::new(top_left, Vector2::new(edge_length, edge_length))
+                        .intersects(&bounds)
+                })
+                .map(|coords| coords.location)
+                .collect();
+            let tiles = TileIterator::over(zoom..=zoom, Some(bounds.clone()), &layer_bounds);
+            assert_eq!(expected.len(), tiles.len());
+            assert_eq!(expected, locations(tiles));
+        }
+    }
+
+    /// Coordinates in quarters of a unit, which the tile edges of the layers below are multiples
+    /// of, so that bounds can lie exactly on them and the brute force check is exact
+    fn quarters(range: std::ops::Range<i32>) -> impl Strategy<Value = f64> {
+        range.prop_map(|quarters| f64::from(quarters) / 4.)
+    }
+
+    /// Whether the tile from `start` to `start + edge_length` overlaps `min..=max` along one axis,
+    /// either including its edges or only its inside
+    fn axis_overlaps(start: f64, edge_length: f64, min: f64, max: f64, interior: bool) -> bool {
+        if interior {
+            start < max && min < start + edge_length
+        } else {
+            start <= max && min <= start + edge_length
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn tile_counts_near_max_zoom(zoom in prop_oneof![MAX_ZOOM - 3..=MAX_ZOOM + 3, any::<u32>()]) {
+            let expected = 1_u64.checked_shl(zoom).and_then(|count| u32::try_from(count).ok());
+            prop_assert_eq!(expected, TileCoords::tiles_per_side(zoom));
+            prop_assert_eq!(zoom <= MAX_ZOOM, expected.is_some());
+            if zoom <= MAX_ZOOM {
+                prop_assert_eq!(
+                    (1_u64 << zoom) - 1,
+                    u64::from(TileIterator::max_coords_for_zoom_level(zoom))
+                );
+            }
+        }
+
+        #[test]
+        fn overlapping_range_matches_brute_force(
+            zoom in 0..=6_u32,
+            layer_x in -64..64_i32,
+            layer_y in -64..64_i32,
+            x in quarters(-400..400),
+            y in quarters(-400..400),
+            width in prop_oneof![Just(0.), quarters(0..400)],
+            height in prop_oneof![Just(0.), quarters(0..400)],
+            interior: bool,
+        ) {
+            let layer_top_left = Vector2::new(f64::from(layer_x), f64::from(layer_y));
+            let layer_bounds = BoundingSquare::new(layer_top_left, 64.);
+            let bounds = BoundingBox::new(Vector2::new(x, y), Vector2::new(width, height));
+            let edge_length = 64. / 2_f64.powi(zoom as i32);
+
+            let tile_start = |index: u32, axis: usize| {
+                layer_top_left[axis] + edge_length * f64::from(index)
+            };
+            let min = bounds.get_top_left();
+            let max = bounds.get_bottom_right();
+            // Along an axis where the bounds have no size, the tile that starts where they lie
+            // stands in for the tiles that only touch them
+            let in_range = |index: u32, axis: usize| {
+                let start = tile_start(index, axis);
+                if interior && min[axis] == max[axis] {
+                    start <= min[axis] && min[axis] < start + edge_length
+                } else {
+                    axis_overlaps(start, edge_length, min[axis], max[axis], interior)
+                }
+            };
+            let expected: Vec<_> = TileIterator::new(zoom)
+                .map(|coords| coords.location)
+                .filter(|location| in_range(location[0], 0) && in_range(location[1], 1))
+                .collect();
+
+            let tiles = TileIterator::over_with(zoom..=zoom, Some(&bounds), &layer_bounds, interior);
+            prop_assert_eq!(expected.len(), tiles.len());
+            prop_assert_eq!(expected, locations(tiles));
+        }
+    }
+}
@@ -0,0 +1,13 @@
+//! Spans for timing parsing, compiling, and tiling with the `tracing` crate. Without the `tracing`
+//! feature, they expand to nothing.
+
+/// Enters an info level span until the end of the enclosing block, taking the same arguments as
+/// `tracing::info_span!`
+macro_rules! span {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!($($arg)*).entered();
+    };
+}
+
+pub(crate) use span;
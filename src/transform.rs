@@ -1,4 +1,7 @@
+use std::str::FromStr;
+
 use nalgebra::{Matrix3, Vector2};
+use serde::{Deserialize, Serialize};
 
 pub fn translate(translation: Vector2<f64>) -> Matrix3<f64> {
     Matrix3::new(1., 0., translation[0], 0., 1., translation[1], 0., 0., 1.)
@@ -26,3 +29,51 @@ pub fn rotate_deg_about(rotation: f64, about: Vector2<f64>) -> Matrix3<f64> {
 pub fn scale(factor: Vector2<f64>) -> Matrix3<f64> {
     Matrix3::new(factor[0], 0., 0., 0., factor[1], 0., 0., 0., 1.)
 }
+
+/// Convention used for map coordinates, which are found from the SVG coordinates of floor images
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum CoordinateSystem {
+    /// SVG coordinates are used unchanged, so the y-axis points down. Floors can't have offsets,
+    /// since outlines and vertices would no longer line up if they were ignored.
+    SvgNative,
+    /// The x-offset of the floor is subtracted from x, and y is subtracted from the y-offset of
+    /// the floor, so the y-axis points up
+    #[default]
+    YUpWithOffsets,
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("Unknown coordinate system `{0}`; expected `svg-native` or `y-up-with-offsets`")]
+pub struct UnknownCoordinateSystem(String);
+
+impl CoordinateSystem {
+    /// Converts SVG coordinates in an image of a floor with `offsets` to map coordinates
+    pub fn svg_to_map(&self, coords: (f64, f64), offsets: (f64, f64)) -> (f64, f64) {
+        match self {
+            CoordinateSystem::SvgNative => coords,
+            CoordinateSystem::YUpWithOffsets => (coords.0 - offsets.0, offsets.1 - coords.1),
+        }
+    }
+
+    /// Converts map coordinates to SVG coordinates in an image of a floor with `offsets`, undoing
+    /// [`Self::svg_to_map`]
+    pub fn map_to_svg(&self, coords: (f64, f64), offsets: (f64, f64)) -> (f64, f64) {
+        match self {
+            CoordinateSystem::SvgNative => coords,
+            CoordinateSystem::YUpWithOffsets => (coords.0 + offsets.0, offsets.1 - coords.1),
+        }
+    }
+}
+
+impl FromStr for CoordinateSystem {
+    type Err = UnknownCoordinateSystem;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "svg-native" => Ok(CoordinateSystem::SvgNative),
+            "y-up-with-offsets" => Ok(CoordinateSystem::YUpWithOffsets),
+            other => Err(UnknownCoordinateSystem(other.to_owned())),
+        }
+    }
+}
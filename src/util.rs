@@ -1,32 +1,387 @@
-use std::collections::HashSet;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashSet};
 use std::hash::Hash;
 
-pub fn shoelace_area(points: &[(f32, f32)]) -> f32 {
-    let this = points.iter();
-    let next = points.iter().cycle().skip(1);
-    let double_area: f32 = this
-        .zip(next)
-        .map(|((this_x, this_y), (next_x, next_y))| this_x * next_y - (next_x * this_y))
+use crate::geometry::{ring_centroid, ring_contains, ring_edges, ring_signed_area};
+
+#[deprecated(note = "use `Polygon::signed_area` instead")]
+pub fn shoelace_area(points: &[(f64, f64)]) -> f64 {
+    ring_signed_area(points)
+}
+
+#[deprecated(note = "use `Polygon::centroid` instead")]
+pub fn centroid(points: &[(f64, f64)]) -> (f64, f64) {
+    ring_centroid(points)
+}
+
+/// Finds the area of `outline` with `holes` cut out of it
+pub fn area_with_holes(outline: &[(f64, f64)], holes: &[impl AsRef<[(f64, f64)]>]) -> f64 {
+    let holes_area: f64 = holes
+        .iter()
+        .map(|hole| ring_signed_area(hole.as_ref()).abs())
         .sum();
-    0.5 * double_area
-}
-
-pub fn centroid(points: &[(f32, f32)]) -> (f32, f32) {
-    let this = points.iter();
-    let next = points.iter().cycle().skip(1);
-    let (center_x, center_y) = this
-        .zip(next)
-        .map(|((this_x, this_y), (next_x, next_y))| {
-            let diff = (this_x * next_y) - (next_x * this_y);
-            let x = (this_x + next_x) * diff;
-            let y = (this_y + next_y) * diff;
-            (x, y)
-        })
+    ring_signed_area(outline).abs() - holes_area
+}
+
+/// Finds the centroid of `outline` with `holes` cut out of it
+pub fn centroid_with_holes(
+    outline: &[(f64, f64)],
+    holes: &[impl AsRef<[(f64, f64)]>],
+) -> (f64, f64) {
+    if holes.is_empty() {
+        return ring_centroid(outline);
+    }
+
+    // Weight each ring's centroid by its area, with holes counting against the outline
+    let weighted = |ring: &[(f64, f64)]| {
+        let area = ring_signed_area(ring).abs();
+        let (x, y) = ring_centroid(ring);
+        (area * x, area * y)
+    };
+    let (outline_x, outline_y) = weighted(outline);
+    let (holes_x, holes_y) = holes
+        .iter()
+        .map(|hole| weighted(hole.as_ref()))
         .fold((0.0, 0.0), |(acc_x, acc_y), (x, y)| (acc_x + x, acc_y + y));
+    let area = area_with_holes(outline, holes);
+    ((outline_x - holes_x) / area, (outline_y - holes_y) / area)
+}
+
+/// Finds the distance from `point` to the line segment from `start` to `end`
+fn distance_to_segment(point: (f64, f64), start: (f64, f64), end: (f64, f64)) -> f64 {
+    let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+    let length_squared = dx * dx + dy * dy;
+    let t = if length_squared == 0. {
+        0.
+    } else {
+        (((point.0 - start.0) * dx + (point.1 - start.1) * dy) / length_squared).clamp(0., 1.)
+    };
+    (point.0 - (start.0 + t * dx)).hypot(point.1 - (start.1 + t * dy))
+}
+
+/// Finds the length of the segment from `start` to `end` that lies alongside the segment from
+/// `other_start` to `other_end`, if both ends of the other segment are within `tolerance` of the
+/// line through the first one. Segments that aren't nearly parallel don't lie alongside each
+/// other, even if they cross.
+fn length_alongside(
+    (start, end): ((f64, f64), (f64, f64)),
+    (other_start, other_end): ((f64, f64), (f64, f64)),
+    tolerance: f64,
+) -> f64 {
+    let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+    let length = dx.hypot(dy);
+    if length == 0. {
+        return 0.;
+    }
+    // Distance along the segment, and from the line through it
+    let project = |point: (f64, f64)| {
+        let (px, py) = (point.0 - start.0, point.1 - start.1);
+        (
+            (px * dx + py * dy) / length,
+            (px * dy - py * dx).abs() / length,
+        )
+    };
+    let (other_start_along, other_start_from) = project(other_start);
+    let (other_end_along, other_end_from) = project(other_end);
+    if other_start_from > tolerance || other_end_from > tolerance {
+        return 0.;
+    }
+    let overlap_start = other_start_along.min(other_end_along).max(0.);
+    let overlap_end = other_start_along.max(other_end_along).min(length);
+    (overlap_end - overlap_start).max(0.)
+}
+
+/// Determines if two polygons with holes share a wall: an edge of one runs alongside an edge of
+/// the other, within `tolerance` of it, for more than `tolerance`. Touching only at a corner
+/// doesn't count.
+pub fn polygons_share_wall(
+    outline: &[(f64, f64)],
+    holes: &[impl AsRef<[(f64, f64)]>],
+    other_outline: &[(f64, f64)],
+    other_holes: &[impl AsRef<[(f64, f64)]>],
+    tolerance: f64,
+) -> bool {
+    edges(outline, holes).any(|(start, end)| {
+        edges(other_outline, other_holes).any(|(other_start, other_end)| {
+            let (edge, other_edge) = ((start, end), (other_start, other_end));
+            length_alongside(edge, other_edge, tolerance) > tolerance
+                || length_alongside(other_edge, edge, tolerance) > tolerance
+        })
+    })
+}
+
+/// Marks the points strictly between `start` and `end` that the Ramer-Douglas-Peucker algorithm
+/// keeps
+fn mark_kept_points(
+    points: &[(f64, f64)],
+    start: usize,
+    end: usize,
+    tolerance: f64,
+    keep: &mut [bool],
+) {
+    let farthest = (start + 1..end)
+        .map(|i| {
+            (
+                i,
+                distance_to_segment(points[i], points[start], points[end]),
+            )
+        })
+        .max_by(|(_, a), (_, b)| a.total_cmp(b));
+    if let Some((i, distance)) = farthest {
+        if distance > tolerance {
+            keep[i] = true;
+            mark_kept_points(points, start, i, tolerance, keep);
+            mark_kept_points(points, i, end, tolerance, keep);
+        }
+    }
+}
+
+/// Simplifies a ring using the Ramer-Douglas-Peucker algorithm, leaving out points that are
+/// within `tolerance` of the line between the points kept on either side of them. A ring that
+/// would be left with fewer than three points is returned unchanged.
+pub fn simplify_ring(ring: &[(f64, f64)], tolerance: f64) -> Vec<(f64, f64)> {
+    if ring.len() <= 3 {
+        return ring.to_vec();
+    }
+
+    // Split the ring at its first point and the point farthest from it, and simplify both halves
+    let first = ring[0];
+    let farthest = (1..ring.len())
+        .max_by(|&a, &b| {
+            let distance = |i: usize| (ring[i].0 - first.0).hypot(ring[i].1 - first.1);
+            distance(a).total_cmp(&distance(b))
+        })
+        .unwrap();
+    let closed: Vec<_> = ring.iter().copied().chain([first]).collect();
+    let mut keep = vec![false; closed.len()];
+    keep[0] = true;
+    keep[farthest] = true;
+    mark_kept_points(&closed, 0, farthest, tolerance, &mut keep);
+    mark_kept_points(&closed, farthest, ring.len(), tolerance, &mut keep);
+
+    let simplified: Vec<_> = ring
+        .iter()
+        .zip(keep)
+        .filter(|(_, keep)| *keep)
+        .map(|(point, _)| *point)
+        .collect();
+    if simplified.len() < 3 {
+        ring.to_vec()
+    } else {
+        simplified
+    }
+}
+
+/// Determines if `point` is inside of `polygon` using the even-odd rule
+#[deprecated(note = "use `Polygon::contains` instead")]
+pub fn point_in_polygon(point: (f64, f64), polygon: &[(f64, f64)]) -> bool {
+    ring_contains(polygon, point)
+}
+
+/// Determines if `point` is inside of `outline`, but not inside of any of its `holes`
+pub fn point_in_polygon_with_holes(
+    point: (f64, f64),
+    outline: &[(f64, f64)],
+    holes: &[impl AsRef<[(f64, f64)]>],
+) -> bool {
+    ring_contains(outline, point) && !holes.iter().any(|hole| ring_contains(hole.as_ref(), point))
+}
+
+/// Iterates over the edges of every ring of a polygon with holes
+fn edges<'a, H: AsRef<[(f64, f64)]>>(
+    outline: &'a [(f64, f64)],
+    holes: &'a [H],
+) -> impl Iterator<Item = ((f64, f64), (f64, f64))> + 'a {
+    std::iter::once(outline)
+        .chain(holes.iter().map(AsRef::as_ref))
+        .flat_map(ring_edges)
+}
+
+/// Finds the distance from `point` to the nearest edge of `outline` or its `holes`, which is
+/// negative if the point is outside of the polygon
+fn signed_distance(
+    point: (f64, f64),
+    outline: &[(f64, f64)],
+    holes: &[impl AsRef<[(f64, f64)]>],
+) -> f64 {
+    let (x, y) = point;
+    let mut inside = false;
+    let mut min_distance_squared = f64::INFINITY;
+    for ((ax, ay), (bx, by)) in edges(outline, holes) {
+        if (ay > y) != (by > y) && x < (bx - ax) * (y - ay) / (by - ay) + ax {
+            inside = !inside;
+        }
 
-    let coefficient = 1.0 / (6.0 * shoelace_area(points));
+        // Closest point on the edge to `point`
+        let (dx, dy) = (bx - ax, by - ay);
+        let length_squared = dx * dx + dy * dy;
+        let t = if length_squared == 0. {
+            0.
+        } else {
+            (((x - ax) * dx + (y - ay) * dy) / length_squared).clamp(0., 1.)
+        };
+        let (px, py) = (ax + t * dx - x, ay + t * dy - y);
+        min_distance_squared = min_distance_squared.min(px * px + py * py);
+    }
 
-    (coefficient * center_x, coefficient * center_y)
+    let distance = min_distance_squared.sqrt();
+    if inside {
+        distance
+    } else {
+        -distance
+    }
+}
+
+/// Square area of a polygon being searched for its pole of inaccessibility
+struct Cell {
+    center: (f64, f64),
+    half_size: f64,
+    /// Distance from the center to the polygon's outline
+    distance: f64,
+    /// Largest distance any point in the cell could have to the polygon's outline
+    max_distance: f64,
+}
+
+impl Cell {
+    fn new(
+        center: (f64, f64),
+        half_size: f64,
+        outline: &[(f64, f64)],
+        holes: &[impl AsRef<[(f64, f64)]>],
+    ) -> Self {
+        let distance = signed_distance(center, outline, holes);
+        Self {
+            center,
+            half_size,
+            distance,
+            max_distance: distance + half_size * std::f64::consts::SQRT_2,
+        }
+    }
+}
+
+impl PartialEq for Cell {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Cell {}
+
+impl PartialOrd for Cell {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Cell {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.max_distance.total_cmp(&other.max_distance)
+    }
+}
+
+/// Finds the point inside of `outline` and outside of its `holes` farthest from any edge, to
+/// within `precision`. Unlike the centroid, this is always inside of the polygon, even for concave
+/// shapes like an L-shaped room, so it is a good place for a label.
+///
+/// This searches cells covering the polygon, subdividing those that could contain a point farther
+/// from the edges than the best found so far, as in Mapbox's polylabel.
+pub fn pole_of_inaccessibility(
+    outline: &[(f64, f64)],
+    holes: &[impl AsRef<[(f64, f64)]>],
+    precision: f64,
+) -> (f64, f64) {
+    let first = match outline.first() {
+        Some(&first) => first,
+        None => return (0., 0.),
+    };
+    let (min, max) = outline.iter().fold(
+        (first, first),
+        |((min_x, min_y), (max_x, max_y)), &(x, y)| {
+            ((min_x.min(x), min_y.min(y)), (max_x.max(x), max_y.max(y)))
+        },
+    );
+    let cell_size = (max.0 - min.0).min(max.1 - min.1);
+    if cell_size <= 0. {
+        return first;
+    }
+    let half_size = cell_size / 2.;
+
+    let mut cells = BinaryHeap::new();
+    let mut x = min.0;
+    while x < max.0 {
+        let mut y = min.1;
+        while y < max.1 {
+            let center = (x + half_size, y + half_size);
+            cells.push(Cell::new(center, half_size, outline, holes));
+            y += cell_size;
+        }
+        x += cell_size;
+    }
+
+    let centroid = centroid_with_holes(outline, holes);
+    let mut best = Cell::new(centroid, 0., outline, holes);
+    let center = ((min.0 + max.0) / 2., (min.1 + max.1) / 2.);
+    let center = Cell::new(center, 0., outline, holes);
+    if best.distance.is_nan() || center.distance > best.distance {
+        best = center;
+    }
+
+    while let Some(cell) = cells.pop() {
+        if cell.distance > best.distance {
+            best = Cell::new(cell.center, 0., outline, holes);
+        }
+        if cell.max_distance - best.distance <= precision {
+            continue;
+        }
+
+        let half_size = cell.half_size / 2.;
+        let (x, y) = cell.center;
+        for (dx, dy) in [(-1., -1.), (1., -1.), (-1., 1.), (1., 1.)] {
+            let center = (x + dx * half_size, y + dy * half_size);
+            cells.push(Cell::new(center, half_size, outline, holes));
+        }
+    }
+
+    best.center
+}
+
+/// Compares strings the way people expect room numbers to be ordered: runs of digits are compared
+/// by their values and letters are compared ignoring case, so `"9"` comes before `"10"`, and
+/// `"101a"` before `"101B"`. Strings that only differ by case or leading zeros are then ordered as
+/// by [`str::cmp`], so that only equal strings compare as equal.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    natural_key_cmp(a, b).then_with(|| a.cmp(b))
+}
+
+fn natural_key_cmp(mut a: &str, mut b: &str) -> Ordering {
+    loop {
+        let (Some(a_char), Some(b_char)) = (a.chars().next(), b.chars().next()) else {
+            // Whichever ran out first is a prefix of the other
+            return b.is_empty().cmp(&a.is_empty());
+        };
+        let ordering = if a_char.is_ascii_digit() && b_char.is_ascii_digit() {
+            let (a_digits, a_rest) = split_digits(a);
+            let (b_digits, b_rest) = split_digits(b);
+            (a, b) = (a_rest, b_rest);
+            let a_digits = a_digits.trim_start_matches('0');
+            let b_digits = b_digits.trim_start_matches('0');
+            a_digits
+                .len()
+                .cmp(&b_digits.len())
+                .then_with(|| a_digits.cmp(b_digits))
+        } else {
+            (a, b) = (&a[a_char.len_utf8()..], &b[b_char.len_utf8()..]);
+            a_char.to_lowercase().cmp(b_char.to_lowercase())
+        };
+        if ordering.is_ne() {
+            return ordering;
+        }
+    }
+}
+
+/// Splits the digits at the start of `s` from the rest of it
+fn split_digits(s: &str) -> (&str, &str) {
+    s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()))
 }
 
 pub fn max_f64(iter: impl Iterator<Item = f64>) -> Option<f64> {
@@ -67,10 +422,88 @@ pub fn undefined<'a, 'b, T: Eq + Hash + ?Sized>(
 
 #[cfg(test)]
 mod test {
+    use std::f64::consts::SQRT_2;
+
     use common_macros::hash_set;
+    use proptest::prelude::*;
 
     use super::*;
 
+    const NO_HOLES: &[Vec<(f64, f64)>] = &[];
+
+    #[test]
+    fn natural_ordering() {
+        let mut numbers = vec![
+            "10", "9", "101A", "B2", "101b", "a10", "101", "1", "a2", "009", "A2",
+        ];
+        numbers.sort_unstable_by(|a, b| natural_cmp(a, b));
+        assert_eq!(
+            vec!["1", "009", "9", "10", "101", "101A", "101b", "A2", "a2", "a10", "B2"],
+            numbers
+        );
+    }
+
+    /// Room numbers and the like, mixing digits with letters of both cases
+    fn alphanumeric() -> impl Strategy<Value = String> {
+        "[0-9a-cA-C-]{0,8}"
+    }
+
+    proptest! {
+        #[test]
+        fn natural_cmp_is_a_total_order(
+            a in alphanumeric(),
+            b in alphanumeric(),
+            c in alphanumeric(),
+        ) {
+            prop_assert_eq!(natural_cmp(&a, &b), natural_cmp(&b, &a).reverse());
+            prop_assert_eq!(a == b, natural_cmp(&a, &b).is_eq());
+            let mut sorted = [&a, &b, &c];
+            sorted.sort_by(|x, y| natural_cmp(x, y));
+            prop_assert!(natural_cmp(sorted[0], sorted[2]).is_le());
+        }
+
+        #[test]
+        fn natural_cmp_compares_numbers_by_value(
+            prefix in alphanumeric(),
+            x: u32,
+            y: u32,
+            suffix in "[a-c]{0,3}",
+        ) {
+            let a = format!("{}-{}{}", prefix, x, suffix);
+            let b = format!("{}-{}{}", prefix, y, suffix);
+            prop_assert_eq!(x.cmp(&y), natural_cmp(&a, &b));
+        }
+
+        #[test]
+        fn natural_cmp_puts_prefixes_first(prefix in alphanumeric(), rest in "[0-9a-c]{1,4}") {
+            let longer = format!("{}-{}", prefix, rest);
+            prop_assert_eq!(Ordering::Less, natural_cmp(&format!("{}-", prefix), &longer));
+        }
+    }
+
+    #[test]
+    fn simplify_rings() {
+        // A square with extra points along its edges, one of them slightly off of the edge
+        let ring = vec![
+            (0., 0.),
+            (5., 0.),
+            (10., 0.),
+            (10.05, 5.),
+            (10., 10.),
+            (5., 10.),
+            (0., 10.),
+        ];
+        let square = vec![(0., 0.), (10., 0.), (10., 10.), (0., 10.)];
+        assert_eq!(square, simplify_ring(&ring, 0.1));
+        assert_eq!(
+            vec![(0., 0.), (10., 0.), (10.05, 5.), (10., 10.), (0., 10.)],
+            simplify_ring(&ring, 0.)
+        );
+        // Rings too small to simplify, or that would collapse, are kept as they are
+        assert_eq!(square[..3], simplify_ring(&square[..3], 100.));
+        assert_eq!(square, simplify_ring(&square, 100.));
+    }
+
     #[test]
     fn unique_items() {
         let expected = hash_set!["hello", "world", "testing", "643tbu346y", "u34i6"];
@@ -99,4 +532,89 @@ mod test {
         let actual = undefined(items, &defined).unwrap_err();
         assert_eq!("xy", actual);
     }
+
+    const L_SHAPE: [(f64, f64); 6] = [
+        (0., 0.),
+        (100., 0.),
+        (100., 20.),
+        (20., 20.),
+        (20., 100.),
+        (0., 100.),
+    ];
+    const U_SHAPE: [(f64, f64); 8] = [
+        (0., 0.),
+        (90., 0.),
+        (90., 100.),
+        (70., 100.),
+        (70., 20.),
+        (20., 20.),
+        (20., 100.),
+        (0., 100.),
+    ];
+
+    #[test]
+    #[allow(deprecated)]
+    fn points_in_polygons() {
+        assert!(point_in_polygon((10., 50.), &L_SHAPE));
+        assert!(point_in_polygon((50., 10.), &L_SHAPE));
+        assert!(!point_in_polygon((50., 50.), &L_SHAPE));
+        assert!(!point_in_polygon((-1., 10.), &L_SHAPE));
+        assert!(point_in_polygon((80., 60.), &U_SHAPE));
+        assert!(!point_in_polygon((45., 60.), &U_SHAPE));
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn pole_of_inaccessibility_inside_concave_polygons() {
+        for polygon in [&L_SHAPE[..], &U_SHAPE[..]] {
+            assert!(!point_in_polygon(centroid(polygon), polygon));
+
+            let pole = pole_of_inaccessibility(polygon, NO_HOLES, 0.1);
+            assert!(point_in_polygon(pole, polygon), "{:?}", pole);
+            // Both shapes are 20 units wide, but have more room at their inside corners
+            let distance = signed_distance(pole, polygon, NO_HOLES);
+            let corner_distance = 20. * SQRT_2 / (1. + SQRT_2);
+            assert!((distance - corner_distance).abs() <= 0.1, "{}", distance);
+        }
+    }
+
+    #[test]
+    fn pole_of_inaccessibility_of_rectangle() {
+        let rectangle = [(10., 10.), (10., 30.), (50., 30.), (50., 10.)];
+        let (x, y) = pole_of_inaccessibility(&rectangle, NO_HOLES, 0.01);
+        assert!((y - 20.).abs() <= 0.01);
+        assert!((10.0..=50.).contains(&x));
+
+        assert_eq!((0., 0.), pole_of_inaccessibility(&[], NO_HOLES, 1.));
+        assert_eq!(
+            (1., 1.),
+            pole_of_inaccessibility(&[(1., 1.), (3., 1.)], NO_HOLES, 1.)
+        );
+    }
+
+    #[test]
+    fn polygons_with_holes() {
+        let outline = [(0., 0.), (0., 100.), (100., 100.), (100., 0.)];
+        // A column in the middle, and a small one in a corner
+        let holes = vec![
+            vec![(30., 30.), (70., 30.), (70., 70.), (30., 70.)],
+            vec![(85., 85.), (95., 85.), (95., 95.), (85., 95.)],
+        ];
+
+        assert_eq!(10000. - 1600. - 100., area_with_holes(&outline, &holes));
+        assert_eq!(10000., area_with_holes(&outline, NO_HOLES));
+        let (x, y) = centroid_with_holes(&outline, &holes[..1]);
+        assert!((x - 50.).abs() < 1e-3 && (y - 50.).abs() < 1e-3);
+        let (x, _) = centroid_with_holes(&outline, &holes);
+        assert!(x < 50.);
+
+        assert!(!point_in_polygon_with_holes((50., 50.), &outline, &holes));
+        assert!(point_in_polygon_with_holes((10., 50.), &outline, &holes));
+        assert!(!point_in_polygon_with_holes((90., 90.), &outline, &holes));
+
+        let pole = pole_of_inaccessibility(&outline, &holes, 0.1);
+        assert!(point_in_polygon_with_holes(pole, &outline, &holes));
+        let distance = signed_distance(pole, &outline, &holes);
+        assert!(distance >= 15. - 0.1, "{}", distance);
+    }
 }
@@ -0,0 +1,197 @@
+//! Exit codes and error output of the binaries, which scripts rely on to tell errors apart
+#![cfg(any(feature = "compile_map_json", feature = "svg_splitter"))]
+
+use std::path::PathBuf;
+
+use assert_cmd::Command;
+
+/// Returns an empty directory for the output of `test`
+fn output_dir(test: &str) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(test);
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Parses the JSON error written to standard error by a binary run with `--error-format json`
+fn json_error(output: &std::process::Output) -> serde_json::Value {
+    serde_json::from_slice(&output.stderr).unwrap()
+}
+
+#[cfg(feature = "compile_map_json")]
+fn compile_map_json(input: &str, test: &str) -> Command {
+    let mut command = Command::cargo_bin("compile_map_json").unwrap();
+    command
+        .arg(input)
+        .arg(output_dir(test).join("compiled.json"))
+        .args(["--error-format", "json"]);
+    command
+}
+
+#[cfg(feature = "compile_map_json")]
+#[test]
+fn compile_map_json_exit_codes() {
+    let graph = output_dir("compile_graph").join("graph.json");
+    compile_map_json("tests/json/compile.json", "compile_ok")
+        .arg("--emit-graph")
+        .arg(&graph)
+        .assert()
+        .success();
+    let graph: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(graph).unwrap()).unwrap();
+    assert!(graph["edge_starts"].is_array());
+
+    Command::cargo_bin("compile_map_json")
+        .unwrap()
+        .arg("tests/json/compile.json")
+        .assert()
+        .code(1);
+
+    let output = compile_map_json("tests/json/malformed_edge.json", "compile_parse")
+        .assert()
+        .code(2)
+        .get_output()
+        .clone();
+    let error = json_error(&output);
+    assert_eq!("parse", error["kind"]);
+    assert_eq!("tests/json/malformed_edge.json", error["file"]);
+
+    let output = compile_map_json(
+        "tests/json/undefined_vertex_id_edge.json",
+        "compile_invalid",
+    )
+    .assert()
+    .code(3)
+    .get_output()
+    .clone();
+    let error = json_error(&output);
+    assert_eq!("validation", error["kind"]);
+    assert_eq!("tests/json/undefined_vertex_id_edge.json", error["file"]);
+    assert!(error["id"].is_string());
+
+    // The floor image of simple.json doesn't exist
+    let output = compile_map_json("tests/json/simple.json", "compile_missing_image")
+        .assert()
+        .code(4)
+        .get_output()
+        .clone();
+    let error = json_error(&output);
+    assert_eq!("io", error["kind"]);
+    assert_eq!("1", error["id"]);
+
+    compile_map_json("tests/json/does_not_exist.json", "compile_missing_input")
+        .assert()
+        .code(4);
+
+    compile_map_json("tests/json/id_patterns.json", "compile_id_prefixes")
+        .args(["--id-prefix", "room", "--id-prefix", "r"])
+        .assert()
+        .success();
+    compile_map_json("tests/json/id_patterns.json", "compile_id_pattern")
+        .args(["--id-pattern", "room("])
+        .assert()
+        .code(1);
+}
+
+#[cfg(all(feature = "compile_map_json", feature = "map_drawer"))]
+#[test]
+fn map_drawer_exit_codes() {
+    use indoor_map_lib::tiles::{Manifest, TileCoords};
+    use nalgebra::Vector2;
+
+    let dir = output_dir("map_drawer");
+    let compiled = dir.join("compiled.json");
+    Command::cargo_bin("compile_map_json")
+        .unwrap()
+        .arg("tests/json/compile.json")
+        .arg(&compiled)
+        .assert()
+        .success();
+
+    let map_drawer = |floor: &str| {
+        let mut command = Command::cargo_bin("map_drawer").unwrap();
+        command
+            .arg(&compiled)
+            .arg(&dir)
+            .arg(floor)
+            .args(["--schematic", "--error-format", "json"]);
+        command
+    };
+    map_drawer("1").assert().success();
+    assert!(dir.join("base.svg").exists());
+
+    // Tiles and the manifest go in a directory named after the floor
+    map_drawer("1")
+        .args(["--tile", "--max-zoom", "1"])
+        .assert()
+        .success();
+    let manifest: Manifest =
+        serde_json::from_str(&std::fs::read_to_string(dir.join("1/manifest.json")).unwrap())
+            .unwrap();
+    assert_eq!(
+        (0, 1, 5),
+        (manifest.min_zoom, manifest.max_zoom, manifest.tiles)
+    );
+    // The tiles cover the view box of the drawing, not just what's drawn in it
+    let base = std::fs::read_to_string(dir.join("base.svg")).unwrap();
+    let view_box = base.split("viewBox=\"").nth(1).unwrap();
+    let view_box =
+        indoor_map_lib::numbers::parse_number_list(&view_box[..view_box.find('"').unwrap()])
+            .unwrap();
+    assert_eq!(
+        ((view_box[0], view_box[1]), view_box[2].max(view_box[3])),
+        (manifest.top_left, manifest.size)
+    );
+    for coords in [
+        TileCoords::new(Vector2::new(0, 0), 0),
+        TileCoords::new(Vector2::new(1, 1), 1),
+    ] {
+        assert!(dir.join("1").join(manifest.path_of(&coords)).exists());
+    }
+    map_drawer("1")
+        .args(["--tile", "--min-zoom-level", "2", "--max-zoom", "1"])
+        .assert()
+        .code(1);
+    let output = map_drawer("9").assert().code(1).get_output().clone();
+    let error = json_error(&output);
+    assert_eq!("usage", error["kind"]);
+    assert_eq!("9", error["id"]);
+
+    Command::cargo_bin("map_drawer")
+        .unwrap()
+        .args(["tests/json/malformed_edge.json", dir.to_str().unwrap(), "1"])
+        .assert()
+        .code(2);
+}
+
+#[cfg(feature = "svg_splitter")]
+#[test]
+fn svg_splitter_exit_codes() {
+    let svg_splitter = |input: &str, test: &str| {
+        let mut command = Command::cargo_bin("svg_splitter").unwrap();
+        command
+            .arg(input)
+            .arg(output_dir(test))
+            .args(["--error-format", "json"]);
+        command
+    };
+    svg_splitter("tests/svg/tiles.svg", "split_ok")
+        .assert()
+        .success();
+    svg_splitter("tests/json/simple.json", "split_parse")
+        .assert()
+        .code(2);
+    svg_splitter("tests/svg/tiles.svg", "split_zoom")
+        .args(["--zoom-level", "40"])
+        .assert()
+        .code(1);
+
+    let output = svg_splitter("tests/svg/does_not_exist.svg", "split_missing")
+        .assert()
+        .code(4)
+        .get_output()
+        .clone();
+    let error = json_error(&output);
+    assert_eq!("io", error["kind"]);
+    assert_eq!("tests/svg/does_not_exist.svg", error["file"]);
+}